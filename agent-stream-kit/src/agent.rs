@@ -2,12 +2,14 @@ use std::any::Any;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use im::Vector;
 use serde_json::Value;
 
 use crate::askit::ASKit;
 use crate::config::AgentConfigs;
 use crate::context::AgentContext;
 use crate::error::AgentError;
+use crate::llm::{Message, ToolCall};
 use crate::runtime::runtime;
 use crate::spec::AgentSpec;
 use crate::value::AgentValue;
@@ -17,6 +19,7 @@ pub enum AgentStatus {
     #[default]
     Init,
     Start,
+    Paused,
     Stop,
 }
 
@@ -33,6 +36,8 @@ pub enum AgentMessage {
     Configs {
         configs: AgentConfigs,
     },
+    Pause,
+    Resume,
     Stop,
 }
 
@@ -69,10 +74,26 @@ pub trait Agent: Send + Sync + 'static {
 
     fn set_stream_id(&mut self, stream_id: String);
 
+    /// The ids of the subflow agents the agent is inside of, outermost first.
+    fn subflow_path(&self) -> &[String] {
+        &[]
+    }
+
+    fn set_subflow_path(&mut self, _path: Vec<String>) {}
+
     async fn start(&mut self) -> Result<(), AgentError>;
 
     async fn stop(&mut self) -> Result<(), AgentError>;
 
+    /// Called when the agent is paused. Inputs stay queued until it is resumed.
+    async fn pause(&mut self) -> Result<(), AgentError> {
+        Ok(())
+    }
+
+    async fn resume(&mut self) -> Result<(), AgentError> {
+        Ok(())
+    }
+
     async fn process(
         &mut self,
         ctx: AgentContext,
@@ -114,6 +135,10 @@ pub struct AgentData {
     /// Empty string when the agent does not belong to any stream.
     pub stream_id: String,
 
+    /// The ids of the subflow agents the agent is inside of, outermost first.
+    /// Empty for the agents of the stream itself.
+    pub subflow_path: Vec<String>,
+
     /// The current status of the agent.
     pub status: AgentStatus,
 }
@@ -125,6 +150,7 @@ impl AgentData {
             id,
             spec,
             stream_id: String::new(),
+            subflow_path: Vec::new(),
             status: AgentStatus::Init,
         }
     }
@@ -154,6 +180,15 @@ pub trait AsAgent: HasAgentData + Send + Sync + 'static {
         Ok(())
     }
 
+    /// Called when the agent is paused. Inputs are held in the queue until it is resumed.
+    async fn pause(&mut self) -> Result<(), AgentError> {
+        Ok(())
+    }
+
+    async fn resume(&mut self) -> Result<(), AgentError> {
+        Ok(())
+    }
+
     async fn process(
         &mut self,
         _ctx: AgentContext,
@@ -162,6 +197,36 @@ pub trait AsAgent: HasAgentData + Send + Sync + 'static {
     ) -> Result<(), AgentError> {
         Ok(())
     }
+
+    /// Call a tool visible from the stream of the agent.
+    ///
+    /// Unlike `ASKit::call_tool`, it is not rejected while ASKit is draining for shutdown.
+    async fn call_tool(
+        &self,
+        ctx: AgentContext,
+        name: &str,
+        args: AgentValue,
+    ) -> Result<AgentValue, AgentError> {
+        let data = self.data();
+        data.askit
+            .call_tool_internal(ctx, Some(&data.stream_id), name, args)
+            .await
+    }
+
+    /// Call tools visible from the stream of the agent, and returns their responses as tool
+    /// messages.
+    ///
+    /// Unlike `ASKit::call_tools`, it is not rejected while ASKit is draining for shutdown.
+    async fn call_tools(
+        &self,
+        ctx: &AgentContext,
+        tool_calls: &Vector<ToolCall>,
+    ) -> Result<Vector<Message>, AgentError> {
+        let data = self.data();
+        data.askit
+            .call_tools_internal(ctx, Some(&data.stream_id), tool_calls)
+            .await
+    }
 }
 
 #[async_trait]
@@ -225,6 +290,14 @@ impl<T: AsAgent> Agent for T {
         self.mut_data().stream_id = stream_id;
     }
 
+    fn subflow_path(&self) -> &[String] {
+        &self.data().subflow_path
+    }
+
+    fn set_subflow_path(&mut self, path: Vec<String>) {
+        self.mut_data().subflow_path = path;
+    }
+
     async fn start(&mut self) -> Result<(), AgentError> {
         self.mut_data().status = AgentStatus::Start;
 
@@ -244,6 +317,18 @@ impl<T: AsAgent> Agent for T {
         Ok(())
     }
 
+    async fn pause(&mut self) -> Result<(), AgentError> {
+        self.pause().await?;
+        self.mut_data().status = AgentStatus::Paused;
+        Ok(())
+    }
+
+    async fn resume(&mut self) -> Result<(), AgentError> {
+        self.resume().await?;
+        self.mut_data().status = AgentStatus::Start;
+        Ok(())
+    }
+
     async fn process(
         &mut self,
         ctx: AgentContext,
@@ -293,7 +378,7 @@ pub fn new_agent_boxed<T: Agent>(
 pub fn agent_new(
     askit: ASKit,
     agent_id: String,
-    spec: AgentSpec,
+    mut spec: AgentSpec,
 ) -> Result<Box<dyn Agent>, AgentError> {
     let def;
     {
@@ -305,6 +390,8 @@ pub fn agent_new(
             .clone();
     }
 
+    def.upgrade_spec(&mut spec)?;
+
     if let Some(new_boxed) = def.new_boxed {
         return new_boxed(askit, agent_id, spec);
     }
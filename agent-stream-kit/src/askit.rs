@@ -1,22 +1,38 @@
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+use im::Vector;
 use serde_json::Value;
-use tokio::sync::{Mutex as AsyncMutex, broadcast, broadcast::error::RecvError, mpsc};
+use tokio::sync::{Mutex as AsyncMutex, Notify, broadcast, broadcast::error::RecvError, mpsc};
 
-use crate::FnvIndexMap;
 use crate::agent::{Agent, AgentMessage, AgentStatus, agent_new};
+use crate::board_history::BoardHistory;
+use crate::board_pattern::BoardPattern;
+use crate::board_store::BoardStoreWriter;
 use crate::config::{AgentConfigs, AgentConfigsMap};
 use crate::context::AgentContext;
 use crate::definition::{AgentConfigSpecs, AgentDefinition, AgentDefinitions};
+use crate::diff::{self, StreamSpecDiff};
 use crate::error::AgentError;
 use crate::id::{new_id, update_ids};
+use crate::llm::{Message, ToolCall};
+use crate::mailbox::{
+    self, DEFAULT_PAUSE_CAPACITY, DEFAULT_QUEUE_CAPACITY, MailboxReceiver, MailboxSender, Sent,
+};
 use crate::message::{self, AgentEventMessage};
+use crate::metrics::{self, Metrics};
+use crate::recorder::Recorder;
 use crate::registry;
-use crate::spec::{AgentSpec, AgentStreamSpec, ChannelSpec};
+use crate::shutdown::{self, ShutdownReport};
+use crate::spec::{AgentSpec, AgentStreamSpec, AgentStreamSpecs, ChannelSpec};
 use crate::stream::{AgentStream, AgentStreamInfo, AgentStreams};
+use crate::supervisor;
+use crate::tool::{self, Tool, ToolInfo, ToolRegistry};
+use crate::trace::{self, SpanExporter};
 use crate::value::AgentValue;
+use crate::{FnvIndexMap, FnvIndexSet};
 
-const MESSAGE_LIMIT: usize = 1024;
 const EVENT_CHANNEL_CAPACITY: usize = 256;
 
 #[derive(Clone)]
@@ -25,14 +41,32 @@ pub struct ASKit {
     pub(crate) agents: Arc<Mutex<FnvIndexMap<String, Arc<AsyncMutex<Box<dyn Agent>>>>>>,
 
     // agent id -> sender
-    pub(crate) agent_txs: Arc<Mutex<FnvIndexMap<String, mpsc::Sender<AgentMessage>>>>,
+    pub(crate) agent_txs: Arc<Mutex<FnvIndexMap<String, MailboxSender>>>,
 
     // board name -> [board out agent id]
     pub(crate) board_out_agents: Arc<Mutex<FnvIndexMap<String, Vec<String>>>>,
 
+    // Var-> agent id -> the boards its variable is looked up in, innermost scope first
+    pub(crate) var_out_boards: Arc<Mutex<FnvIndexMap<String, Vec<String>>>>,
+
+    // [(board name pattern, board out agent id)]
+    pub(crate) board_pattern_agents: Arc<Mutex<Vec<(BoardPattern, String)>>>,
+
     // board name -> value
     pub(crate) board_value: Arc<Mutex<FnvIndexMap<String, AgentValue>>>,
 
+    // writes the values of the persistent boards to the board store when set
+    pub(crate) board_store: Arc<RwLock<Option<BoardStoreWriter>>>,
+
+    // names of the boards whose values are kept in the board store
+    pub(crate) persistent_boards: Arc<Mutex<FnvIndexSet<String>>>,
+
+    // names of the boards whose current values are emitted to new Board-> agents
+    pub(crate) retained_boards: Arc<Mutex<FnvIndexSet<String>>>,
+
+    // board name -> kept values, for the boards with a retention policy
+    pub(crate) board_histories: Arc<Mutex<FnvIndexMap<String, BoardHistory>>>,
+
     // source agent id -> [target agent id / source handle / target handle]
     pub(crate) channels: Arc<Mutex<FnvIndexMap<String, Vec<(String, String, String)>>>>,
 
@@ -42,14 +76,52 @@ pub struct ASKit {
     // agent streams (stream id -> stream)
     pub(crate) streams: Arc<Mutex<AgentStreams>>,
 
+    // stream id -> stream name, kept while a stream is taken out of `streams` to be started
+    // or stopped, and reserved before the agents of a new stream are added
+    pub(crate) stream_names: Arc<Mutex<FnvIndexMap<String, String>>>,
+
+    // notified when a stream is put into `streams` or its name is released
+    pub(crate) streams_changed: Arc<Notify>,
+
+    // subflow agent id -> id of the stream it embeds, while the subflow is started
+    pub(crate) subflow_streams: Arc<Mutex<FnvIndexMap<String, String>>>,
+
+    // stream templates (template name -> spec)
+    pub(crate) templates: Arc<Mutex<AgentStreamSpecs>>,
+
     // agent def name -> config
     pub(crate) global_configs_map: Arc<Mutex<FnvIndexMap<String, AgentConfigs>>>,
 
+    // tools owned by this instance
+    pub(crate) tools: Arc<RwLock<ToolRegistry>>,
+
+    // stream id -> tools scoped to the stream
+    pub(crate) stream_tools: Arc<RwLock<FnvIndexMap<String, ToolRegistry>>>,
+
+    // connections to the MCP servers of the tools owned by this instance
+    #[cfg(feature = "mcp")]
+    pub(crate) mcp_connections: crate::mcp::SharedConnectionPool,
+
+    // whether external writes are accepted
+    pub(crate) accepting: Arc<AtomicBool>,
+
+    // messages sent to the main loop and not processed yet
+    pub(crate) in_flight: Arc<AtomicUsize>,
+
     // message sender
     pub(crate) tx: Arc<Mutex<Option<mpsc::Sender<AgentEventMessage>>>>,
 
     // observers
     pub(crate) observers: broadcast::Sender<ASKitEvent>,
+
+    // records the messages of the main loop when set
+    pub(crate) recorder: Arc<Mutex<Option<Recorder>>>,
+
+    // receives the spans when tracing
+    pub(crate) span_exporter: Arc<RwLock<Option<Arc<dyn SpanExporter>>>>,
+
+    // counters of what is happening
+    pub(crate) metrics: Arc<Mutex<Metrics>>,
 }
 
 impl ASKit {
@@ -59,13 +131,32 @@ impl ASKit {
             agents: Default::default(),
             agent_txs: Default::default(),
             board_out_agents: Default::default(),
+            var_out_boards: Default::default(),
+            board_pattern_agents: Default::default(),
             board_value: Default::default(),
+            board_store: Default::default(),
+            persistent_boards: Default::default(),
+            retained_boards: Default::default(),
+            board_histories: Default::default(),
             channels: Default::default(),
             defs: Default::default(),
             streams: Default::default(),
+            stream_names: Default::default(),
+            streams_changed: Default::default(),
+            subflow_streams: Default::default(),
+            templates: Default::default(),
             global_configs_map: Default::default(),
+            tools: Default::default(),
+            stream_tools: Default::default(),
+            #[cfg(feature = "mcp")]
+            mcp_connections: Arc::new(AsyncMutex::new(crate::mcp::MCPConnectionPool::new())),
+            accepting: Arc::new(AtomicBool::new(true)),
+            in_flight: Default::default(),
             tx: Arc::new(Mutex::new(None)),
             observers: tx,
+            recorder: Default::default(),
+            span_exporter: Default::default(),
+            metrics: Default::default(),
         }
     }
 
@@ -94,6 +185,16 @@ impl ASKit {
         Ok(())
     }
 
+    /// Shut down ASKit gracefully.
+    ///
+    /// Stops accepting external writes, new streams and tool calls, drains in-flight messages, stops all agents
+    /// in reverse topological order, shuts down the MCP servers of the tools registered into this instance,
+    /// stops recording, waits for the board store, and then quits. The MCP servers of other instances and of the global registry are left running.
+    /// Anything abandoned because of the timeout is reported.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        shutdown::shutdown(self, timeout).await
+    }
+
     /// Quit ASKit.
     pub fn quit(&self) {
         let mut tx_lock = self.tx.lock().unwrap();
@@ -214,6 +315,39 @@ impl ASKit {
         Ok(())
     }
 
+    /// Change a stream to the given spec, touching only what differs.
+    ///
+    /// Agents are matched by id. Agents only in the new spec are added with fresh ids, and
+    /// removed ones are stopped and removed. Agents are recreated only when their definition,
+    /// pins, queue, supervisor or disabled flag changed; config changes are sent to the running
+    /// agents, which keep their state, and agents with no configs in the new spec keep theirs.
+    /// Channels are rewired at once.
+    ///
+    /// A spec which cannot be applied changes nothing. Agents failing to start are logged,
+    /// as when starting a stream.
+    /// Returns the applied changes.
+    pub async fn apply_stream_spec(
+        &self,
+        stream_id: &str,
+        spec: AgentStreamSpec,
+    ) -> Result<StreamSpecDiff, AgentError> {
+        diff::apply_stream_spec(self, stream_id, spec).await
+    }
+
+    /// Replace the spec of the stream, and returns whether it is running and paused.
+    pub(crate) fn set_agent_stream_spec(
+        &self,
+        stream_id: &str,
+        spec: AgentStreamSpec,
+    ) -> Result<(bool, bool), AgentError> {
+        let mut streams = self.streams.lock().unwrap();
+        let Some(stream) = streams.get_mut(stream_id) else {
+            return Err(AgentError::StreamNotFound(stream_id.to_string()));
+        };
+        stream.set_spec(spec);
+        Ok((stream.running(), stream.paused()))
+    }
+
     /// Create a new agent stream with the given name.
     /// If the name already exists, a unique name will be generated by appending a number suffix.
     /// Returns the id of the new agent stream.
@@ -228,50 +362,142 @@ impl ASKit {
     }
 
     /// Rename an existing agent stream.
+    ///
+    /// The vars of the stream, which are addressed by its name, are moved to the new name
+    /// along with whether they are retained or persistent, and its `Var->` agents follow them.
     pub fn rename_agent_stream(&self, id: &str, new_name: &str) -> Result<String, AgentError> {
         if !is_valid_stream_name(new_name) {
             return Err(AgentError::InvalidStreamName(new_name.into()));
         }
 
-        // check if the new name is already used
-        let new_name = self.unique_stream_name(new_name);
-
         let mut streams = self.streams.lock().unwrap();
-
-        // remove the original stream
-        let Some(mut stream) = streams.swap_remove(id) else {
+        let Some(stream) = streams.get_mut(id) else {
             return Err(AgentError::RenameStreamFailed(id.into()));
         };
 
-        // insert renamed stream
+        // check if the new name is already used
+        let new_name = {
+            let mut stream_names = self.stream_names.lock().unwrap();
+            let new_name = unique_name(&stream_names, new_name, Some(id));
+            stream_names.insert(id.to_string(), new_name.clone());
+            new_name
+        };
+
+        let old_name = stream.name().to_string();
         stream.set_name(new_name.clone());
-        streams.insert(stream.id().to_string(), stream);
+        drop(streams);
+
+        self.rename_stream_vars(&old_name, &new_name);
         Ok(new_name)
     }
 
+    // templates
+
+    /// Register a stream template under the given name, replacing any template of the same name.
+    ///
+    /// The parameters are declared in the `parameters` extension of the spec.
+    pub fn add_stream_template(&self, name: &str, spec: AgentStreamSpec) -> Result<(), AgentError> {
+        spec.template_params()?;
+        let mut templates = self.templates.lock().unwrap();
+        templates.insert(name.to_string(), spec);
+        Ok(())
+    }
+
+    /// Remove a stream template.
+    pub fn remove_stream_template(&self, name: &str) -> Option<AgentStreamSpec> {
+        let mut templates = self.templates.lock().unwrap();
+        templates.shift_remove(name)
+    }
+
+    /// Get a stream template by name.
+    pub fn get_stream_template(&self, name: &str) -> Option<AgentStreamSpec> {
+        let templates = self.templates.lock().unwrap();
+        templates.get(name).cloned()
+    }
+
+    /// Get the names of all stream templates.
+    pub fn get_stream_template_names(&self) -> Vec<String> {
+        let templates = self.templates.lock().unwrap();
+        templates.keys().cloned().collect()
+    }
+
+    /// Add a new agent stream from the template with the given params, and returns its id.
+    ///
+    /// The stream is named after the template, with a number suffix if needed.
+    pub fn instantiate_stream_template(
+        &self,
+        name: &str,
+        params: &FnvIndexMap<String, AgentValue>,
+    ) -> Result<String, AgentError> {
+        let Some(template) = self.get_stream_template(name) else {
+            return Err(AgentError::TemplateNotFound(name.to_string()));
+        };
+        let spec = template.instantiate(params)?;
+        let stream_name = self.unique_stream_name(name);
+        self.add_agent_stream(stream_name, spec)
+    }
+
     /// Generate a unique stream name by appending a number suffix if needed.
     pub fn unique_stream_name(&self, name: &str) -> String {
-        let mut new_name = name.trim().to_string();
-        let mut i = 2;
-        let streams = self.streams.lock().unwrap();
-        while streams.values().any(|stream| stream.name() == new_name) {
-            new_name = format!("{}{}", name, i);
-            i += 1;
-        }
-        new_name
+        let stream_names = self.stream_names.lock().unwrap();
+        unique_name(&stream_names, name, None)
+    }
+
+    /// Add an agent stream, like `add_agent_stream`, but only when the spec has no validation errors
+    /// and the name is not used by another stream.
+    ///
+    /// Warnings are logged. See `AgentStreamSpec::validate`.
+    pub fn add_agent_stream_strict(
+        &self,
+        name: String,
+        mut spec: AgentStreamSpec,
+    ) -> Result<String, AgentError> {
+        self.migrate_stream_spec(&mut spec);
+        spec.check(self, &name)?;
+        self.add_agent_stream_with(name, spec, true)
     }
 
     /// Add a new agent stream with the given name and spec, and returns the id of the new agent stream.
     ///
     /// The ids of the given spec, including agents and channels, are changed to new unique ids.
+    /// Channels between incompatible pins are logged and not connected.
+    ///
+    /// Streams of the same name share their vars, since the vars of a stream are addressed
+    /// by its name. See `unique_stream_name`.
     pub fn add_agent_stream(
         &self,
         name: String,
         spec: AgentStreamSpec,
     ) -> Result<String, AgentError> {
+        self.add_agent_stream_with(name, spec, false)
+    }
+
+    fn add_agent_stream_with(
+        &self,
+        name: String,
+        mut spec: AgentStreamSpec,
+        unique: bool,
+    ) -> Result<String, AgentError> {
+        self.check_accepting()?;
+        if !is_valid_stream_name(&name) {
+            return Err(AgentError::InvalidStreamName(name));
+        }
+        self.migrate_stream_spec(&mut spec);
         let stream = AgentStream::new(name, spec);
         let id = stream.id().to_string();
 
+        // reserve the id and the name before anything of the stream is added
+        {
+            let mut stream_names = self.stream_names.lock().unwrap();
+            if stream_names.contains_key(&id) {
+                return Err(AgentError::DuplicateId(id));
+            }
+            if unique && stream_names.values().any(|name| name == stream.name()) {
+                return Err(AgentError::DuplicateStreamName(stream.name().to_string()));
+            }
+            stream_names.insert(id.clone(), stream.name().to_string());
+        }
+
         // add agents
         for agent in &stream.spec().agents {
             if let Err(e) = self.add_agent_internal(id.clone(), agent.clone()) {
@@ -281,18 +507,15 @@ impl ASKit {
 
         // add channels
         for channel in &stream.spec().channels {
-            self.add_channel_internal(channel.clone())
+            self.check_pin_types(stream.spec(), channel)
+                .and_then(|_| self.add_channel_internal(channel.clone()))
                 .unwrap_or_else(|e| {
                     log::error!("Failed to add_channel {}: {}", channel.source, e);
                 });
         }
 
         // add the given stream into streams
-        let mut streams = self.streams.lock().unwrap();
-        if streams.contains_key(&id) {
-            return Err(AgentError::DuplicateId(id.into()));
-        }
-        streams.insert(id.to_string(), stream);
+        self.put_stream(&id, stream);
 
         Ok(id)
     }
@@ -323,11 +546,22 @@ impl ASKit {
             self.remove_channel_internal(channel);
         }
 
+        self.stream_tools.write().unwrap().swap_remove(id);
+        self.stream_names.lock().unwrap().swap_remove(id);
+        self.streams_changed.notify_waiters();
+
         Ok(())
     }
 
+    /// Put a stream into the streams, waking the subflows waiting for it.
+    fn put_stream(&self, id: &str, stream: AgentStream) {
+        self.streams.lock().unwrap().insert(id.to_string(), stream);
+        self.streams_changed.notify_waiters();
+    }
+
     /// Start an agent stream by id.
     pub async fn start_agent_stream(&self, id: &str) -> Result<(), AgentError> {
+        self.check_accepting()?;
         let mut stream = {
             let mut streams = self.streams.lock().unwrap();
             let Some(stream) = streams.swap_remove(id) else {
@@ -336,10 +570,51 @@ impl ASKit {
             stream
         };
 
-        stream.start(self).await?;
+        let started = stream.start(self).await;
 
-        let mut streams = self.streams.lock().unwrap();
-        streams.insert(id.to_string(), stream);
+        self.put_stream(id, stream);
+        started
+    }
+
+    /// Pause a running agent stream by id.
+    ///
+    /// Agents keep their state, and their inputs are held until the stream is resumed.
+    pub async fn pause_agent_stream(&self, id: &str) -> Result<(), AgentError> {
+        let mut stream = {
+            let mut streams = self.streams.lock().unwrap();
+            let Some(stream) = streams.swap_remove(id) else {
+                return Err(AgentError::StreamNotFound(id.to_string()));
+            };
+            stream
+        };
+
+        let paused = stream.pause(self).await;
+
+        self.put_stream(id, stream);
+
+        if paused? {
+            self.emit_agent_stream_paused(id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Resume a paused agent stream by id.
+    pub async fn resume_agent_stream(&self, id: &str) -> Result<(), AgentError> {
+        let mut stream = {
+            let mut streams = self.streams.lock().unwrap();
+            let Some(stream) = streams.swap_remove(id) else {
+                return Err(AgentError::StreamNotFound(id.to_string()));
+            };
+            stream
+        };
+
+        let resumed = stream.resume(self).await;
+
+        self.put_stream(id, stream);
+
+        if resumed? {
+            self.emit_agent_stream_resumed(id.to_string());
+        }
         Ok(())
     }
 
@@ -353,11 +628,10 @@ impl ASKit {
             stream
         };
 
-        stream.stop(self).await?;
+        let stopped = stream.stop(self).await;
 
-        let mut streams = self.streams.lock().unwrap();
-        streams.insert(id.to_string(), stream);
-        Ok(())
+        self.put_stream(id, stream);
+        stopped
     }
 
     // Agents
@@ -372,6 +646,7 @@ impl ASKit {
 
     /// Add an agent to the specified stream, and returns the id of the newly added agent.
     pub fn add_agent(&self, stream_id: String, mut spec: AgentSpec) -> Result<String, AgentError> {
+        self.migrate_agent_spec(&mut spec);
         let mut streams = self.streams.lock().unwrap();
         let Some(stream) = streams.get_mut(&stream_id) else {
             return Err(AgentError::StreamNotFound(stream_id.to_string()));
@@ -383,7 +658,21 @@ impl ASKit {
         Ok(id)
     }
 
-    fn add_agent_internal(&self, stream_id: String, spec: AgentSpec) -> Result<(), AgentError> {
+    pub(crate) fn add_agent_internal(
+        &self,
+        stream_id: String,
+        spec: AgentSpec,
+    ) -> Result<(), AgentError> {
+        self.add_subflow_agent_internal(stream_id, Vec::new(), spec)
+    }
+
+    /// Add an interior agent of the subflow agents of the path, outermost first.
+    pub(crate) fn add_subflow_agent_internal(
+        &self,
+        stream_id: String,
+        subflow_path: Vec<String>,
+        spec: AgentSpec,
+    ) -> Result<(), AgentError> {
         let mut agents = self.agents.lock().unwrap();
         if agents.contains_key(&spec.id) {
             return Err(AgentError::AgentAlreadyExists(spec.id.to_string()));
@@ -391,6 +680,7 @@ impl ASKit {
         let spec_id = spec.id.clone();
         let mut agent = agent_new(self.clone(), spec_id.clone(), spec)?;
         agent.set_stream_id(stream_id);
+        agent.set_subflow_path(subflow_path);
         agents.insert(spec_id, Arc::new(AsyncMutex::new(agent)));
         Ok(())
     }
@@ -426,12 +716,41 @@ impl ASKit {
         let Some(stream) = streams.get_mut(stream_id) else {
             return Err(AgentError::StreamNotFound(stream_id.to_string()));
         };
+        self.check_pin_types(stream.spec(), &channel)?;
         stream.add_channel(channel.clone());
         self.add_channel_internal(channel)?;
         Ok(())
     }
 
-    fn add_channel_internal(&self, channel: ChannelSpec) -> Result<(), AgentError> {
+    /// Check that the output pin of the channel can be sent to its input pin.
+    pub(crate) fn check_pin_types(
+        &self,
+        spec: &AgentStreamSpec,
+        channel: &ChannelSpec,
+    ) -> Result<(), AgentError> {
+        let def_of = |agent_id: &str| {
+            spec.agents
+                .iter()
+                .find(|a| a.id == agent_id)
+                .and_then(|a| self.get_agent_definition(&a.def_name))
+        };
+        let (Some(source_def), Some(target_def)) =
+            (def_of(&channel.source), def_of(&channel.target))
+        else {
+            return Ok(());
+        };
+        let source_type = source_def.output_pin_type(&channel.source_handle);
+        let target_type = target_def.input_pin_type(&channel.target_handle);
+        if !target_type.accepts(source_type) {
+            return Err(AgentError::IncompatiblePinTypes(
+                format!("{} ({})", channel.source_handle, source_type),
+                format!("{} ({})", channel.target_handle, target_type),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_channel_internal(&self, channel: ChannelSpec) -> Result<(), AgentError> {
         let mut channels = self.channels.lock().unwrap();
         if let Some(targets) = channels.get_mut(&channel.source) {
             if targets
@@ -471,6 +790,13 @@ impl ASKit {
             return Err(AgentError::StreamNotFound(stream_id.to_string()));
         };
 
+        // check the channels before adding anything
+        let mut spec = stream.spec().clone();
+        spec.agents.extend(agents.iter().cloned());
+        for channel in &channels {
+            self.check_pin_types(&spec, channel)?;
+        }
+
         for agent in &agents {
             self.add_agent_internal(stream_id.to_string(), agent.clone())?;
             stream.add_agent(agent.clone());
@@ -501,7 +827,7 @@ impl ASKit {
         Ok(())
     }
 
-    async fn remove_agent_internal(&self, agent_id: &str) -> Result<(), AgentError> {
+    pub(crate) async fn remove_agent_internal(&self, agent_id: &str) -> Result<(), AgentError> {
         self.stop_agent(agent_id).await?;
 
         // remove from channels
@@ -525,6 +851,7 @@ impl ASKit {
             let mut agents = self.agents.lock().unwrap();
             agents.swap_remove(agent_id);
         }
+        self.remove_agent_metrics(agent_id);
 
         Ok(())
     }
@@ -540,20 +867,46 @@ impl ASKit {
         };
 
         let Some(channel) = stream.remove_channel(channel) else {
-            let mut streams = self.streams.lock().unwrap();
-            streams.insert(stream_id.to_string(), stream);
+            self.put_stream(stream_id, stream);
             return Err(AgentError::ChannelNotFound(format!(
                 "{}:{}->{}:{}",
                 channel.source, channel.source_handle, channel.target, channel.target_handle
             )));
         };
-        let mut streams = self.streams.lock().unwrap();
-        streams.insert(stream_id.to_string(), stream);
+        self.put_stream(stream_id, stream);
 
         self.remove_channel_internal(&channel);
         Ok(())
     }
 
+    /// Remove and add channels while holding the lock, so no message sees a half-rewired stream.
+    pub(crate) fn rewire_channels(&self, removed: &[ChannelSpec], added: &[ChannelSpec]) {
+        let mut channels = self.channels.lock().unwrap();
+        for channel in removed {
+            if let Some(targets) = channels.get_mut(&channel.source) {
+                targets.retain(|(target, source_handle, target_handle)| {
+                    *target != channel.target
+                        || *source_handle != channel.source_handle
+                        || *target_handle != channel.target_handle
+                });
+                if targets.is_empty() {
+                    channels.swap_remove(&channel.source);
+                }
+            }
+        }
+        for channel in added {
+            let entry = (
+                channel.target.clone(),
+                channel.source_handle.clone(),
+                channel.target_handle.clone(),
+            );
+            let targets = channels.entry(channel.source.clone()).or_default();
+            if !targets.contains(&entry) {
+                targets.push(entry);
+            }
+        }
+    }
+
     fn remove_channel_internal(&self, channel: &ChannelSpec) {
         let mut channels = self.channels.lock().unwrap();
         if let Some(targets) = channels.get_mut(&channel.source) {
@@ -581,77 +934,46 @@ impl ASKit {
             let agent = agent.lock().await;
             agent.def_name().to_string()
         };
-        let uses_native_thread = {
+        let (uses_native_thread, def_capacity, def_policy, def_pause_capacity) = {
             let defs = self.defs.lock().unwrap();
             let Some(def) = defs.get(&def_name) else {
                 return Err(AgentError::AgentDefinitionNotFound(agent_id.to_string()));
             };
-            def.native_thread
+            (
+                def.native_thread,
+                def.queue_capacity,
+                def.delivery_policy,
+                def.pause_capacity,
+            )
         };
-        let agent_status = {
+        let (agent_status, capacity, policy, pause_capacity, supervisor) = {
             // This will not block since the agent is not started yet.
             let agent = agent.lock().await;
-            agent.status().clone()
+            let spec = agent.spec();
+            (
+                agent.status().clone(),
+                spec.queue_capacity
+                    .or(def_capacity)
+                    .unwrap_or(DEFAULT_QUEUE_CAPACITY),
+                spec.delivery_policy.or(def_policy).unwrap_or_default(),
+                spec.pause_capacity
+                    .or(def_pause_capacity)
+                    .unwrap_or(DEFAULT_PAUSE_CAPACITY),
+                spec.supervisor.clone(),
+            )
         };
         if agent_status == AgentStatus::Init {
             log::info!("Starting agent {}", agent_id);
 
-            let (tx, mut rx) = mpsc::channel(MESSAGE_LIMIT);
+            let (tx, rx) = mailbox::mailbox(capacity, pause_capacity, policy);
 
             {
                 let mut agent_txs = self.agent_txs.lock().unwrap();
-                agent_txs.insert(agent_id.to_string(), tx.clone());
+                agent_txs.insert(agent_id.to_string(), tx);
             };
 
-            let agent_clone = agent.clone();
-            let agent_id_clone = agent_id.to_string();
-
-            let agent_loop = async move {
-                {
-                    let mut agent_guard = agent_clone.lock().await;
-                    if let Err(e) = agent_guard.start().await {
-                        log::error!("Failed to start agent {}: {}", agent_id_clone, e);
-                        return;
-                    }
-                }
-
-                while let Some(message) = rx.recv().await {
-                    match message {
-                        AgentMessage::Input { ctx, pin, value } => {
-                            agent_clone
-                                .lock()
-                                .await
-                                .process(ctx, pin, value)
-                                .await
-                                .unwrap_or_else(|e| {
-                                    log::error!("Process Error {}: {}", agent_id_clone, e);
-                                });
-                        }
-                        AgentMessage::Config { key, value } => {
-                            agent_clone
-                                .lock()
-                                .await
-                                .set_config(key, value)
-                                .unwrap_or_else(|e| {
-                                    log::error!("Config Error {}: {}", agent_id_clone, e);
-                                });
-                        }
-                        AgentMessage::Configs { configs } => {
-                            agent_clone
-                                .lock()
-                                .await
-                                .set_configs(configs)
-                                .unwrap_or_else(|e| {
-                                    log::error!("Configs Error {}: {}", agent_id_clone, e);
-                                });
-                        }
-                        AgentMessage::Stop => {
-                            rx.close();
-                            break;
-                        }
-                    }
-                }
-            };
+            let agent_loop =
+                supervisor::run_agent(self.clone(), agent_id.to_string(), agent, rx, supervisor);
 
             if uses_native_thread {
                 std::thread::spawn(move || {
@@ -688,7 +1010,10 @@ impl ASKit {
             a.clone()
         };
         let mut agent_guard = agent.lock().await;
-        if *agent_guard.status() == AgentStatus::Start {
+        if matches!(
+            agent_guard.status(),
+            AgentStatus::Start | AgentStatus::Paused
+        ) {
             log::info!("Stopping agent {}", agent_id);
             agent_guard.stop().await?;
         }
@@ -696,6 +1021,57 @@ impl ASKit {
         Ok(())
     }
 
+    /// Whether the running mailbox of the agent is the one of the given receiver.
+    pub(crate) fn is_agent_mailbox(&self, agent_id: &str, rx: &MailboxReceiver) -> bool {
+        let agent_txs = self.agent_txs.lock().unwrap();
+        agent_txs
+            .get(agent_id)
+            .is_some_and(|tx| tx.is_connected_to(rx))
+    }
+
+    /// Remove the sender of the agent if it belongs to the given receiver.
+    pub(crate) fn remove_agent_mailbox(&self, agent_id: &str, rx: &MailboxReceiver) {
+        let mut agent_txs = self.agent_txs.lock().unwrap();
+        if agent_txs
+            .get(agent_id)
+            .is_some_and(|tx| tx.is_connected_to(rx))
+        {
+            agent_txs.swap_remove(agent_id);
+        }
+    }
+
+    /// Pause an agent by id.
+    ///
+    /// The agent keeps its state, and its inputs are held in the queue until it is resumed.
+    /// With the default `DeliveryPolicy::Block`, the queue holds up to the pause capacity of the
+    /// agent, and the inputs past it are dropped. The other policies drop inputs from a full
+    /// queue as usual.
+    pub async fn pause_agent(&self, agent_id: &str) -> Result<(), AgentError> {
+        self.send_agent_control(agent_id, AgentMessage::Pause).await
+    }
+
+    /// Resume a paused agent by id.
+    pub async fn resume_agent(&self, agent_id: &str) -> Result<(), AgentError> {
+        self.send_agent_control(agent_id, AgentMessage::Resume)
+            .await
+    }
+
+    async fn send_agent_control(
+        &self,
+        agent_id: &str,
+        message: AgentMessage,
+    ) -> Result<(), AgentError> {
+        let tx = {
+            let agent_txs = self.agent_txs.lock().unwrap();
+            agent_txs.get(agent_id).cloned()
+        };
+        let Some(tx) = tx else {
+            return Err(AgentError::AgentTxNotFound(agent_id.to_string()));
+        };
+        tx.send(message).await?;
+        Ok(())
+    }
+
     /// Set configs for an agent by id.
     pub async fn set_agent_configs(
         &self,
@@ -800,11 +1176,27 @@ impl ASKit {
             }
             return Ok(());
         };
-        tx.send(message).await.map_err(|_| {
+        let is_input = matches!(message, AgentMessage::Input { .. });
+        let sent = tx.send(message).await.map_err(|_| {
             AgentError::SendMessageFailed("Failed to send input message".to_string())
         })?;
+        let queued = matches!(sent, Sent::Queued(_));
+        if is_input && queued {
+            self.count_agent_input(&agent_id, &pin);
+        }
 
-        self.emit_agent_input(agent_id.to_string(), pin);
+        if let Some(AgentMessage::Input {
+            pin: dropped_pin, ..
+        }) = sent.dropped()
+        {
+            log::debug!("Dropped input {} of agent {}", dropped_pin, agent_id);
+            self.count_dropped_input(&agent_id);
+            self.emit_agent_input_dropped(agent_id.clone(), dropped_pin);
+        }
+
+        if queued {
+            self.emit_agent_input(agent_id.to_string(), pin);
+        }
 
         Ok(())
     }
@@ -837,19 +1229,66 @@ impl ASKit {
         name: String,
         value: AgentValue,
     ) -> Result<(), AgentError> {
-        self.send_board_out(name, AgentContext::new(), value).await
+        self.check_accepting()?;
+        let ctx = self.trace_board_write(&name, AgentContext::new());
+        message::send_board_out(self, name, ctx, value, true).await
     }
 
-    /// Write a value to the variable board.
+    /// Write a value to the variable of the stream, addressed as from the agents of the stream.
+    /// See `VarScope`.
     pub async fn write_var_value(
         &self,
         stream_id: &str,
         name: &str,
         value: AgentValue,
     ) -> Result<(), AgentError> {
-        let var_name = format!("%{}/{}", stream_id, name);
-        self.send_board_out(var_name, AgentContext::new(), value)
-            .await
+        self.check_accepting()?;
+        let var_name = self.var_board_name(stream_id, name)?;
+        let ctx = self.trace_board_write(&var_name, AgentContext::new());
+        message::send_board_out(self, var_name, ctx, value, true).await
+    }
+
+    /// Set whether the board is retained. `Board->` agents emit the current value of
+    /// a retained board when they start or are set to the board, as with the retained
+    /// messages of MQTT, so that they do not depend on the order streams are started in.
+    pub fn set_board_retained(&self, name: &str, retained: bool) {
+        let mut retained_boards = self.retained_boards.lock().unwrap();
+        if retained {
+            retained_boards.insert(name.to_string());
+        } else {
+            retained_boards.shift_remove(name);
+        }
+    }
+
+    /// Set whether the variable of the stream is retained, for `Var->` agents.
+    pub fn set_var_retained(
+        &self,
+        stream_id: &str,
+        name: &str,
+        retained: bool,
+    ) -> Result<(), AgentError> {
+        let var_name = self.var_board_name(stream_id, name)?;
+        self.set_board_retained(&var_name, retained);
+        Ok(())
+    }
+
+    pub fn is_board_retained(&self, name: &str) -> bool {
+        self.retained_boards.lock().unwrap().contains(name)
+    }
+
+    /// The current value of the board, if it is retained.
+    pub(crate) fn retained_value(&self, name: &str) -> Option<AgentValue> {
+        if !self.is_board_retained(name) {
+            return None;
+        }
+        self.read_board_value(name)
+    }
+
+    fn check_accepting(&self) -> Result<(), AgentError> {
+        if !self.accepting.load(Ordering::Acquire) {
+            return Err(AgentError::ShuttingDown);
+        }
+        Ok(())
     }
 
     pub(crate) async fn send_board_out(
@@ -858,7 +1297,7 @@ impl ASKit {
         ctx: AgentContext,
         value: AgentValue,
     ) -> Result<(), AgentError> {
-        message::send_board_out(self, name, ctx, value).await
+        message::send_board_out(self, name, ctx, value, false).await
     }
 
     async fn spawn_message_loop(&self) -> Result<(), AgentError> {
@@ -875,6 +1314,7 @@ impl ASKit {
             while let Some(message) = rx.recv().await {
                 use AgentEventMessage::*;
 
+                askit.record(&message);
                 match message {
                     AgentOut {
                         agent,
@@ -884,10 +1324,13 @@ impl ASKit {
                     } => {
                         message::agent_out(&askit, agent, ctx, pin, value).await;
                     }
-                    BoardOut { name, ctx, value } => {
+                    BoardOut {
+                        name, ctx, value, ..
+                    } => {
                         message::board_out(&askit, name, ctx, value).await;
                     }
                 }
+                askit.in_flight.fetch_sub(1, Ordering::AcqRel);
             }
         });
 
@@ -896,6 +1339,213 @@ impl ASKit {
         Ok(())
     }
 
+    // Tools
+
+    /// Register a tool owned by this instance.
+    pub fn register_tool<T: Tool + Send + Sync + 'static>(&self, tool: T) {
+        self.tools.write().unwrap().register_tool(tool);
+    }
+
+    /// Unregister a tool owned by this instance.
+    pub fn unregister_tool(&self, name: &str) {
+        self.tools.write().unwrap().unregister_tool(name);
+    }
+
+    /// Register a tool visible only to the agents of the specified stream.
+    pub fn register_stream_tool<T: Tool + Send + Sync + 'static>(&self, stream_id: &str, tool: T) {
+        self.stream_tools
+            .write()
+            .unwrap()
+            .entry(stream_id.to_string())
+            .or_default()
+            .register_tool(tool);
+    }
+
+    /// Register a tool of the stream, or of this instance without a stream, unless a tool
+    /// of the same name is already registered there, and unregister the `old` (stream id,
+    /// name) tool it replaces.
+    ///
+    /// Both are done under the registry locks, so nothing changes when the name is taken.
+    pub(crate) fn replace_unique_tool<T: Tool + Send + Sync + 'static>(
+        &self,
+        old: Option<(Option<&str>, &str)>,
+        stream_id: Option<&str>,
+        tool: T,
+    ) -> Result<(), AgentError> {
+        let name = tool.info().name.to_string();
+        let mut stream_tools = self.stream_tools.write().unwrap();
+        let mut tools = self.tools.write().unwrap();
+
+        let taken = match stream_id {
+            Some(stream_id) => stream_tools
+                .get(stream_id)
+                .is_some_and(|tools| tools.contains(&name)),
+            None => tools.contains(&name),
+        };
+        if taken && old != Some((stream_id, name.as_str())) {
+            return Err(AgentError::DuplicateToolName(name));
+        }
+
+        match old {
+            Some((Some(old_stream_id), old_name)) => {
+                if let Some(old_tools) = stream_tools.get_mut(old_stream_id) {
+                    old_tools.unregister_tool(old_name);
+                    if old_tools.is_empty() {
+                        stream_tools.swap_remove(old_stream_id);
+                    }
+                }
+            }
+            Some((None, old_name)) => tools.unregister_tool(old_name),
+            None => {}
+        }
+        match stream_id {
+            Some(stream_id) => stream_tools
+                .entry(stream_id.to_string())
+                .or_default()
+                .register_tool(tool),
+            None => tools.register_tool(tool),
+        }
+        Ok(())
+    }
+
+    /// Unregister a tool of the specified stream.
+    pub fn unregister_stream_tool(&self, stream_id: &str, name: &str) {
+        let mut stream_tools = self.stream_tools.write().unwrap();
+        if let Some(tools) = stream_tools.get_mut(stream_id) {
+            tools.unregister_tool(name);
+            if tools.is_empty() {
+                stream_tools.swap_remove(stream_id);
+            }
+        }
+    }
+
+    /// Get a tool by name.
+    ///
+    /// The tool is looked up in the stream, this instance, and the global registry, in that order.
+    pub fn get_tool(
+        &self,
+        stream_id: Option<&str>,
+        name: &str,
+    ) -> Option<Arc<Box<dyn Tool + Send + Sync>>> {
+        if let Some(stream_id) = stream_id {
+            let stream_tools = self.stream_tools.read().unwrap();
+            if let Some(tool) = stream_tools.get(stream_id).and_then(|t| t.get_tool(name)) {
+                return Some(tool);
+            }
+        }
+        if let Some(tool) = self.tools.read().unwrap().get_tool(name) {
+            return Some(tool);
+        }
+        tool::get_tool(name)
+    }
+
+    /// List the infos of the tools visible from the stream.
+    ///
+    /// A tool shadows tools with the same name in the later scopes (stream, instance, global).
+    pub fn list_tool_infos(&self, stream_id: Option<&str>) -> Vec<ToolInfo> {
+        self.collect_tool_infos(stream_id, None)
+    }
+
+    /// List the infos of the tools visible from the stream, filtered by newline separated regex patterns.
+    pub fn list_tool_infos_patterns(
+        &self,
+        stream_id: Option<&str>,
+        patterns: &str,
+    ) -> Result<Vec<ToolInfo>, regex::Error> {
+        let reg_set = tool::patterns_to_regex_set(patterns)?;
+        Ok(self.collect_tool_infos(stream_id, Some(&reg_set)))
+    }
+
+    fn collect_tool_infos(
+        &self,
+        stream_id: Option<&str>,
+        reg_set: Option<&regex::RegexSet>,
+    ) -> Vec<ToolInfo> {
+        let mut infos = FnvIndexMap::default();
+        let mut add = |tool_infos: Vec<ToolInfo>| {
+            for info in tool_infos {
+                if !infos.contains_key(&info.name) {
+                    infos.insert(info.name.clone(), info);
+                }
+            }
+        };
+        if let Some(stream_id) = stream_id
+            && let Some(tools) = self.stream_tools.read().unwrap().get(stream_id)
+        {
+            add(tools.tool_infos(reg_set));
+        }
+        add(self.tools.read().unwrap().tool_infos(reg_set));
+        add(tool::registry().read().unwrap().tool_infos(reg_set));
+        infos.into_values().collect()
+    }
+
+    /// Call a tool visible from the stream.
+    ///
+    /// This is an external input, and is rejected while shutting down. Agents call tools with
+    /// `AsAgent::call_tool` instead, so that the messages in flight still complete.
+    pub async fn call_tool(
+        &self,
+        ctx: AgentContext,
+        stream_id: Option<&str>,
+        name: &str,
+        args: AgentValue,
+    ) -> Result<AgentValue, AgentError> {
+        self.check_accepting()?;
+        self.call_tool_internal(ctx, stream_id, name, args).await
+    }
+
+    /// Call tools visible from the stream, and returns their responses as tool messages.
+    ///
+    /// Rejected while shutting down, like `call_tool`.
+    pub async fn call_tools(
+        &self,
+        ctx: &AgentContext,
+        stream_id: Option<&str>,
+        tool_calls: &Vector<ToolCall>,
+    ) -> Result<Vector<Message>, AgentError> {
+        self.check_accepting()?;
+        self.call_tools_internal(ctx, stream_id, tool_calls).await
+    }
+
+    pub(crate) async fn call_tool_internal(
+        &self,
+        ctx: AgentContext,
+        stream_id: Option<&str>,
+        name: &str,
+        args: AgentValue,
+    ) -> Result<AgentValue, AgentError> {
+        tool::call_tool_with(ctx, name, args, |name| {
+            self.get_tool_to_call(stream_id, name)
+        })
+        .await
+    }
+
+    pub(crate) async fn call_tools_internal(
+        &self,
+        ctx: &AgentContext,
+        stream_id: Option<&str>,
+        tool_calls: &Vector<ToolCall>,
+    ) -> Result<Vector<Message>, AgentError> {
+        tool::call_tools_with(ctx, tool_calls, |name| {
+            self.get_tool_to_call(stream_id, name)
+        })
+        .await
+    }
+
+    // the tool, metered, and traced when tracing
+    fn get_tool_to_call(
+        &self,
+        stream_id: Option<&str>,
+        name: &str,
+    ) -> Option<Arc<Box<dyn Tool + Send + Sync>>> {
+        let tool = self.get_tool(stream_id, name)?;
+        let tool = metrics::metered_tool(tool, self, stream_id);
+        match self.span_exporter() {
+            Some(exporter) => Some(trace::traced_tool(tool, exporter)),
+            None => Some(tool),
+        }
+    }
+
     /// Subscribe to all ASKit events.
     pub fn subscribe(&self) -> broadcast::Receiver<ASKitEvent> {
         self.observers.subscribe()
@@ -954,10 +1604,46 @@ impl ASKit {
         self.notify_observers(ASKitEvent::AgentIn(agent_id, pin));
     }
 
+    pub(crate) fn emit_agent_started(&self, agent_id: String) {
+        self.notify_observers(ASKitEvent::AgentStarted(agent_id));
+    }
+
+    pub(crate) fn emit_agent_crashed(&self, agent_id: String, message: String) {
+        self.notify_observers(ASKitEvent::AgentCrashed(agent_id, message));
+    }
+
+    pub(crate) fn emit_agent_restarted(&self, agent_id: String, attempt: u32) {
+        self.notify_observers(ASKitEvent::AgentRestarted(agent_id, attempt));
+    }
+
+    pub(crate) fn emit_agent_paused(&self, agent_id: String) {
+        self.notify_observers(ASKitEvent::AgentPaused(agent_id));
+    }
+
+    pub(crate) fn emit_agent_resumed(&self, agent_id: String) {
+        self.notify_observers(ASKitEvent::AgentResumed(agent_id));
+    }
+
+    pub(crate) fn emit_agent_stream_paused(&self, stream_id: String) {
+        self.notify_observers(ASKitEvent::AgentStreamPaused(stream_id));
+    }
+
+    pub(crate) fn emit_agent_stream_resumed(&self, stream_id: String) {
+        self.notify_observers(ASKitEvent::AgentStreamResumed(stream_id));
+    }
+
+    pub(crate) fn emit_agent_input_dropped(&self, agent_id: String, pin: String) {
+        self.notify_observers(ASKitEvent::AgentInDropped(agent_id, pin));
+    }
+
     pub(crate) fn emit_agent_spec_updated(&self, agent_id: String) {
         self.notify_observers(ASKitEvent::AgentSpecUpdated(agent_id));
     }
 
+    pub(crate) fn emit_stream_file_error(&self, path: String, message: String) {
+        self.notify_observers(ASKitEvent::StreamFileError(path, message));
+    }
+
     pub(crate) fn emit_board(&self, name: String, value: AgentValue) {
         // // ignore variables
         // if name.starts_with('%') {
@@ -971,7 +1657,28 @@ impl ASKit {
     }
 }
 
-fn is_valid_stream_name(new_name: &str) -> bool {
+// the trimmed name, with a number suffix if it is used by a stream other than `except`
+fn unique_name(
+    stream_names: &FnvIndexMap<String, String>,
+    name: &str,
+    except: Option<&str>,
+) -> String {
+    let name = name.trim();
+    let used = |new_name: &str| {
+        stream_names
+            .iter()
+            .any(|(id, n)| Some(id.as_str()) != except && n == new_name)
+    };
+    let mut new_name = name.to_string();
+    let mut i = 2;
+    while used(&new_name) {
+        new_name = format!("{}{}", name, i);
+        i += 1;
+    }
+    new_name
+}
+
+pub(crate) fn is_valid_stream_name(new_name: &str) -> bool {
     // Check if the name is empty
     if new_name.trim().is_empty() {
         return false;
@@ -1006,8 +1713,17 @@ fn is_valid_stream_name(new_name: &str) -> bool {
 #[derive(Clone, Debug)]
 pub enum ASKitEvent {
     AgentConfigUpdated(String, String, AgentValue), // (agent_id, key, value)
+    AgentCrashed(String, String),                   // (agent_id, message)
     AgentError(String, String),                     // (agent_id, message)
     AgentIn(String, String),                        // (agent_id, pin)
+    AgentInDropped(String, String),                 // (agent_id, pin)
+    AgentPaused(String),                            // (agent_id)
+    AgentRestarted(String, u32),                    // (agent_id, attempt)
+    AgentResumed(String),                           // (agent_id)
     AgentSpecUpdated(String),                       // (agent_id)
+    AgentStarted(String),                           // (agent_id)
+    AgentStreamPaused(String),                      // (stream_id)
+    AgentStreamResumed(String),                     // (stream_id)
     Board(String, AgentValue),                      // (board name, value)
+    StreamFileError(String, String),                // (path, message)
 }
@@ -1,21 +1,30 @@
+use std::time::Duration;
 use std::vec;
 
 use async_trait::async_trait;
 
 use askit_macros::askit_agent;
 
-use crate::agent::{Agent, AgentData, AsAgent};
+use crate::agent::{Agent, AgentData, AgentStatus, AsAgent};
 use crate::askit::ASKit;
+use crate::board_pattern::{BOARD_NAME_VAR, BoardPattern};
 use crate::context::AgentContext;
 use crate::error::AgentError;
+use crate::output::AgentOutput;
 use crate::spec::AgentSpec;
 use crate::value::AgentValue;
+use crate::var_scope::STREAM_VAR_PREFIX;
 
 const CATEGORY: &str = "Core/Board";
 
+const PIN_TRIGGER: &str = "trigger";
 const PIN_VALUE: &str = "value";
+const PIN_VALUES: &str = "values";
 
 const CONFIG_NAME: &str = "name";
+const CONFIG_PATTERN: &str = "pattern";
+const CONFIG_TIMESTAMPS: &str = "timestamps";
+const CONFIG_WINDOW: &str = "window";
 
 #[askit_agent(
     kind = "Board",
@@ -69,6 +78,11 @@ impl AsAgent for BoardInAgent {
     }
 }
 
+/// Emits the values written to the board of the name.
+///
+/// With the `pattern` config set, the name is a `BoardPattern`, and the name of the board
+/// each value was written to is set as the `BOARD_NAME_VAR` context var. Otherwise the name
+/// is matched exactly, even when it contains `*` or `#` segments or starts with `re:`.
 #[askit_agent(
     kind = "Board",
     title = "Board->",
@@ -76,11 +90,81 @@ impl AsAgent for BoardInAgent {
     outputs = [PIN_VALUE],
     string_config(
         name = CONFIG_NAME,
+    ),
+    boolean_config(
+        name = CONFIG_PATTERN,
+        description = "Match the name as a pattern, such as sensors/* or re:sensors/\\d+",
     )
 )]
 struct BoardOutAgent {
     data: AgentData,
     board_name: Option<String>,
+    pattern: bool,
+}
+
+impl BoardOutAgent {
+    fn board_pattern(&self, board_name: &str) -> Result<BoardPattern, AgentError> {
+        if self.pattern {
+            BoardPattern::parse(board_name)
+        } else {
+            Ok(BoardPattern::exact(board_name))
+        }
+    }
+
+    fn subscribe(&self, board_name: &str) -> Result<(), AgentError> {
+        let pattern = self.board_pattern(board_name)?;
+        let askit = self.askit();
+        if !pattern.is_exact() {
+            let mut board_pattern_agents = askit.board_pattern_agents.lock().unwrap();
+            board_pattern_agents.push((pattern, self.data.id.clone()));
+            return Ok(());
+        }
+        let mut board_out_agents = askit.board_out_agents.lock().unwrap();
+        if let Some(nodes) = board_out_agents.get_mut(board_name) {
+            nodes.push(self.data.id.clone());
+        } else {
+            board_out_agents.insert(board_name.to_string(), vec![self.data.id.clone()]);
+        }
+        Ok(())
+    }
+
+    fn unsubscribe(&self, board_name: &str) {
+        let askit = self.askit();
+        {
+            let mut board_out_agents = askit.board_out_agents.lock().unwrap();
+            if let Some(nodes) = board_out_agents.get_mut(board_name) {
+                nodes.retain(|x| x != &self.data.id);
+            }
+        }
+        let mut board_pattern_agents = askit.board_pattern_agents.lock().unwrap();
+        board_pattern_agents.retain(|(_, id)| id != &self.data.id);
+    }
+
+    // the current values of the retained boards of the name, with their contexts
+    fn retained_values(&self, board_name: &str) -> Vec<(AgentContext, AgentValue)> {
+        let askit = self.askit();
+        let pattern = match self.board_pattern(board_name) {
+            Ok(pattern) if !pattern.is_exact() => pattern,
+            _ => {
+                return askit
+                    .retained_value(board_name)
+                    .map(|value| (AgentContext::new(), value))
+                    .into_iter()
+                    .collect();
+            }
+        };
+        askit
+            .list_boards()
+            .into_iter()
+            .filter(|name| pattern.matches(name))
+            .filter_map(|name| {
+                let value = askit.retained_value(&name)?;
+                let ctx =
+                    AgentContext::new().with_var(BOARD_NAME_VAR.into(), AgentValue::string(name));
+                Some((ctx, value))
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -90,61 +174,142 @@ impl AsAgent for BoardOutAgent {
             .configs
             .as_ref()
             .and_then(|c| c.get_string(CONFIG_NAME).ok());
+        let pattern = spec
+            .configs
+            .as_ref()
+            .is_some_and(|c| c.get_bool_or_default(CONFIG_PATTERN));
         Ok(Self {
             data: AgentData::new(askit, id, spec),
             board_name,
+            pattern,
         })
     }
 
     async fn start(&mut self) -> Result<(), AgentError> {
-        if let Some(board_name) = &self.board_name {
-            let askit = self.askit();
-            let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-            if let Some(nodes) = board_out_agents.get_mut(board_name) {
-                nodes.push(self.data.id.clone());
-            } else {
-                board_out_agents.insert(board_name.clone(), vec![self.data.id.clone()]);
-            }
+        let Some(board_name) = &self.board_name else {
+            return Ok(());
+        };
+        self.subscribe(board_name)?;
+        for (ctx, value) in self.retained_values(board_name) {
+            self.output(ctx, PIN_VALUE, value).await?;
         }
         Ok(())
     }
 
     async fn stop(&mut self) -> Result<(), AgentError> {
         if let Some(board_name) = &self.board_name {
-            let askit = self.askit();
-            let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-            if let Some(nodes) = board_out_agents.get_mut(board_name) {
-                nodes.retain(|x| x != &self.data.id);
-            }
+            self.unsubscribe(board_name);
         }
         Ok(())
     }
 
     fn configs_changed(&mut self) -> Result<(), AgentError> {
-        let board_name = self.configs()?.get_string(CONFIG_NAME).ok();
-        if self.board_name != board_name {
+        let configs = self.configs()?;
+        let board_name = configs.get_string(CONFIG_NAME).ok();
+        let pattern = configs.get_bool_or_default(CONFIG_PATTERN);
+        if self.board_name != board_name || self.pattern != pattern {
             if let Some(board_name) = &self.board_name {
-                let askit = self.askit();
-                let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-                if let Some(nodes) = board_out_agents.get_mut(board_name) {
-                    nodes.retain(|x| x != &self.data.id);
-                }
+                self.unsubscribe(board_name);
+            }
+            self.board_name = board_name;
+            self.pattern = pattern;
+            // subscribed on start otherwise
+            if !matches!(self.status(), AgentStatus::Start | AgentStatus::Paused) {
+                return Ok(());
             }
-            if let Some(board_name) = &board_name {
-                let askit = self.askit();
-                let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-                if let Some(nodes) = board_out_agents.get_mut(board_name) {
-                    nodes.push(self.data.id.clone());
-                } else {
-                    board_out_agents.insert(board_name.clone(), vec![self.data.id.clone()]);
+            let Some(board_name) = &self.board_name else {
+                return Ok(());
+            };
+            self.subscribe(board_name)?;
+            // the agent is running, so the retained values are emitted now instead of on start
+            if *self.status() == AgentStatus::Start {
+                for (ctx, value) in self.retained_values(board_name) {
+                    self.try_output(ctx, PIN_VALUE, value)?;
                 }
             }
-            self.board_name = board_name;
         }
         Ok(())
     }
 }
 
+/// Emits the kept values of a board as an array, oldest first, on each input.
+///
+/// The board needs a retention policy that keeps its history, set with
+/// `ASKit::set_board_retention`.
+#[askit_agent(
+    kind = "Board",
+    title = "Board History->",
+    category = CATEGORY,
+    inputs = [PIN_TRIGGER],
+    outputs = [PIN_VALUES],
+    string_config(
+        name = CONFIG_NAME,
+    ),
+    integer_config(
+        name = CONFIG_WINDOW,
+        description = "Seconds of history to emit, or 0 for all of it",
+    ),
+    boolean_config(
+        name = CONFIG_TIMESTAMPS,
+        description = "Emit each value as {timestamp, value}",
+    )
+)]
+struct BoardHistoryAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for BoardHistoryAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        let configs = self.configs()?;
+        let board_name = configs.get_string_or_default(CONFIG_NAME);
+        if board_name.is_empty() {
+            return Ok(());
+        }
+        let window = configs.get_integer_or_default(CONFIG_WINDOW);
+        let timestamps = configs.get_bool_or_default(CONFIG_TIMESTAMPS);
+
+        let askit = self.askit();
+        let entries = if window > 0 {
+            askit.read_board_window(&board_name, Duration::from_secs(window as u64))
+        } else {
+            askit.read_board_history(&board_name)
+        };
+        let values = entries
+            .into_iter()
+            .map(|entry| {
+                if !timestamps {
+                    return entry.value;
+                }
+                AgentValue::object(
+                    [
+                        (
+                            "timestamp".to_string(),
+                            AgentValue::integer(entry.timestamp as i64),
+                        ),
+                        ("value".to_string(), entry.value),
+                    ]
+                    .into_iter()
+                    .collect(),
+                )
+            })
+            .collect();
+        self.output(ctx, PIN_VALUES, AgentValue::array(values))
+            .await
+    }
+}
+
 #[askit_agent(
     kind = "Board",
     title = "->Var",
@@ -188,16 +353,17 @@ impl AsAgent for VarInAgent {
             // if var_name is not set, stop processing
             return Ok(());
         }
-        let board_name = board_name_for_var(self.stream_id(), &var_name);
         let askit = self.askit();
-        askit
-            .send_board_out(board_name.clone(), ctx, value.clone())
-            .await?;
+        let (scope, var_name) = askit.agent_var_scope(self)?.resolve(&var_name);
+        let board_name = scope.board_name(var_name);
+        askit.send_board_out(board_name, ctx, value).await?;
 
         Ok(())
     }
 }
 
+/// Emits the values written to the variable of the name, or to the variable of the name in
+/// an enclosing scope while the inner ones have no value. See `VarScope` for the names.
 #[askit_agent(
     kind = "Board",
     title = "Var->",
@@ -212,6 +378,19 @@ struct VarOutAgent {
     var_name: Option<String>,
 }
 
+impl VarOutAgent {
+    // subscribe to the boards the variable is looked up in, and return their names
+    fn subscribe(&self, var_name: &str) -> Result<Vec<String>, AgentError> {
+        let askit = self.askit();
+        let scope = askit.agent_var_scope(self)?;
+        Ok(askit.subscribe_var(&self.data.id, &scope, var_name))
+    }
+
+    fn unsubscribe(&self) {
+        self.askit().unsubscribe_var(&self.data.id);
+    }
+}
+
 #[async_trait]
 impl AsAgent for VarOutAgent {
     fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
@@ -226,58 +405,92 @@ impl AsAgent for VarOutAgent {
     }
 
     async fn start(&mut self) -> Result<(), AgentError> {
-        if let Some(var_name) = &self.var_name {
-            let board_name = board_name_for_var(self.stream_id(), var_name);
-            let askit = self.askit();
-            let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-            if let Some(nodes) = board_out_agents.get_mut(&board_name) {
-                nodes.push(self.data.id.clone());
-            } else {
-                board_out_agents.insert(board_name.clone(), vec![self.data.id.clone()]);
-            }
+        let Some(var_name) = self.var_name.clone() else {
+            return Ok(());
+        };
+        let boards = self.subscribe(&var_name)?;
+        if let Some(value) = self.askit().retained_var_value(&boards) {
+            self.output(AgentContext::new(), PIN_VALUE, value).await?;
         }
         Ok(())
     }
 
     async fn stop(&mut self) -> Result<(), AgentError> {
-        if let Some(var_name) = &self.var_name {
-            let board_name = board_name_for_var(self.stream_id(), var_name);
-            let askit = self.askit();
-            let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-            if let Some(nodes) = board_out_agents.get_mut(&board_name) {
-                nodes.retain(|x| x != &self.data.id);
-            }
-        }
+        self.unsubscribe();
         Ok(())
     }
 
     fn configs_changed(&mut self) -> Result<(), AgentError> {
         let new_var_name = self.configs()?.get_string(CONFIG_NAME).ok();
         if self.var_name != new_var_name {
-            if let Some(var_name) = &self.var_name {
-                let board_name = board_name_for_var(self.stream_id(), var_name);
-                let askit = self.askit();
-                let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-                if let Some(nodes) = board_out_agents.get_mut(&board_name) {
-                    nodes.retain(|x| x != &self.data.id);
-                }
+            self.unsubscribe();
+            self.var_name = new_var_name;
+            // subscribed on start otherwise
+            if !matches!(self.status(), AgentStatus::Start | AgentStatus::Paused) {
+                return Ok(());
             }
-            if let Some(var_name) = &new_var_name {
-                let board_name = board_name_for_var(self.stream_id(), var_name);
-                let askit = self.askit();
-                let mut board_out_agents = askit.board_out_agents.lock().unwrap();
-                if let Some(nodes) = board_out_agents.get_mut(&board_name) {
-                    nodes.push(self.data.id.clone());
-                } else {
-                    board_out_agents.insert(board_name.clone(), vec![self.data.id.clone()]);
-                }
+            let Some(var_name) = self.var_name.clone() else {
+                return Ok(());
+            };
+            let boards = self.subscribe(&var_name)?;
+            if *self.status() == AgentStatus::Start
+                && let Some(value) = self.askit().retained_var_value(&boards)
+            {
+                self.try_output(AgentContext::new(), PIN_VALUE, value)?;
             }
-            self.var_name = new_var_name;
         }
         Ok(())
     }
 }
 
-fn board_name_for_var(flow_id: &str, var_name: &str) -> String {
-    format!("%{}/{}", flow_id, var_name)
+/// Whether the agent is a Board-> or Var-> agent, which emits the values of a board.
+pub(crate) fn reads_board(spec: &AgentSpec) -> bool {
+    matches!(
+        spec.def_name.as_str(),
+        BoardOutAgent::DEF_NAME | VarOutAgent::DEF_NAME
+    )
+}
+
+/// The board or variable a Board/Var agent of a spec is connected to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BoardLink {
+    /// Whether `name` is a variable, rather than a board.
+    pub var: bool,
+
+    /// The name of the board, or of the variable, without a `stream:` prefix, since the
+    /// agents of a stream spec are all in the scope of the stream.
+    pub name: String,
+
+    /// Whether the agent writes to the board, rather than reading from it.
+    pub writes: bool,
+
+    /// Whether `name` is a `BoardPattern` of the boards the agent reads.
+    pub pattern: bool,
+}
+
+/// The board link of the agent, if it is a Board/Var agent with a name.
+pub(crate) fn board_link(spec: &AgentSpec) -> Option<BoardLink> {
+    let (var, writes) = match spec.def_name.as_str() {
+        BoardInAgent::DEF_NAME => (false, true),
+        BoardOutAgent::DEF_NAME | BoardHistoryAgent::DEF_NAME => (false, false),
+        VarInAgent::DEF_NAME => (true, true),
+        VarOutAgent::DEF_NAME => (true, false),
+        _ => return None,
+    };
+    let configs = spec.configs.as_ref()?;
+    let mut name = configs.get_string(CONFIG_NAME).ok()?;
+    if var && let Some(stream_name) = name.strip_prefix(STREAM_VAR_PREFIX) {
+        name = stream_name.to_string();
+    }
+    if name.is_empty() {
+        return None;
+    }
+    let pattern =
+        spec.def_name == BoardOutAgent::DEF_NAME && configs.get_bool_or_default(CONFIG_PATTERN);
+    Some(BoardLink {
+        var,
+        name,
+        writes,
+        pattern,
+    })
 }
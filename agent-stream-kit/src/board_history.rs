@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::askit::ASKit;
+use crate::recorder::now_millis;
+use crate::value::AgentValue;
+
+/// How the values written to a board are kept, set with `ASKit::set_board_retention`.
+///
+/// The history is kept when `max_len` or `max_age` is set. Both can be set, in which case
+/// a value is dropped when either limit is exceeded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BoardRetention {
+    /// Keep at most this many of the latest values in the history.
+    pub max_len: Option<usize>,
+
+    /// Keep the values written within this duration in the history.
+    pub max_age: Option<Duration>,
+
+    /// Forget the current value of the board this long after it was written,
+    /// as if it had never been written. The value is removed from the board store too.
+    ///
+    /// A value written at an unknown time, such as one restored from the board store,
+    /// is counted from when it was restored or the policy was set.
+    pub ttl: Option<Duration>,
+}
+
+impl BoardRetention {
+    /// Keep the last `n` values.
+    pub fn last(n: usize) -> Self {
+        Self {
+            max_len: Some(n),
+            ..Default::default()
+        }
+    }
+
+    /// Keep the values written within the duration.
+    pub fn within(max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..Default::default()
+        }
+    }
+
+    /// Keep no history, and forget the current value after `ttl`.
+    pub fn expire_after(ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_len(mut self, n: usize) -> Self {
+        self.max_len = Some(n);
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn keeps_history(&self) -> bool {
+        self.max_len.is_some() || self.max_age.is_some()
+    }
+}
+
+/// A value in the history of a board.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoardHistoryEntry {
+    /// When the value was written, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    pub value: AgentValue,
+}
+
+/// The kept values of a board with a retention policy.
+#[derive(Default)]
+pub(crate) struct BoardHistory {
+    retention: BoardRetention,
+
+    // oldest first
+    entries: VecDeque<BoardHistoryEntry>,
+
+    // when the current value was written
+    written_at: Option<u64>,
+}
+
+impl BoardHistory {
+    fn push(&mut self, timestamp: u64, value: AgentValue) {
+        self.written_at = Some(timestamp);
+        if self.retention.keeps_history() {
+            self.entries
+                .push_back(BoardHistoryEntry { timestamp, value });
+        }
+        self.prune(timestamp);
+    }
+
+    fn prune(&mut self, now: u64) {
+        if let Some(max_len) = self.retention.max_len {
+            while self.entries.len() > max_len {
+                self.entries.pop_front();
+            }
+        }
+        if let Some(max_age) = self.retention.max_age {
+            let oldest = now.saturating_sub(max_age.as_millis() as u64);
+            while self.entries.front().is_some_and(|e| e.timestamp < oldest) {
+                self.entries.pop_front();
+            }
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        match (self.retention.ttl, self.written_at) {
+            (Some(ttl), Some(written_at)) => now >= written_at + ttl.as_millis() as u64,
+            _ => false,
+        }
+    }
+
+    fn entries_since(&mut self, since: u64, now: u64) -> Vec<BoardHistoryEntry> {
+        self.prune(now);
+        self.entries
+            .iter()
+            .filter(|e| e.timestamp >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+impl ASKit {
+    /// Set how the values written to the board are kept.
+    ///
+    /// Values are kept from the next write. Changing the policy of a board prunes the values
+    /// it already holds.
+    pub fn set_board_retention(&self, name: &str, retention: BoardRetention) {
+        let has_value = self.board_value.lock().unwrap().contains_key(name);
+        let mut histories = self.board_histories.lock().unwrap();
+        let history = histories.entry(name.to_string()).or_default();
+        if !retention.keeps_history() {
+            history.entries.clear();
+        }
+        history.retention = retention;
+        let now = now_millis();
+        if has_value && history.written_at.is_none() {
+            history.written_at = Some(now);
+        }
+        history.prune(now);
+    }
+
+    /// Stop keeping the history of the board, and stop its current value from expiring.
+    pub fn remove_board_retention(&self, name: &str) {
+        self.board_histories.lock().unwrap().shift_remove(name);
+    }
+
+    pub fn board_retention(&self, name: &str) -> Option<BoardRetention> {
+        let histories = self.board_histories.lock().unwrap();
+        histories.get(name).map(|h| h.retention.clone())
+    }
+
+    /// The kept values of the board, oldest first. Empty when the board keeps no history.
+    pub fn read_board_history(&self, name: &str) -> Vec<BoardHistoryEntry> {
+        let mut histories = self.board_histories.lock().unwrap();
+        let Some(history) = histories.get_mut(name) else {
+            return Vec::new();
+        };
+        history.entries_since(0, now_millis())
+    }
+
+    /// The kept values of the board written within the last `window`, oldest first.
+    pub fn read_board_window(&self, name: &str, window: Duration) -> Vec<BoardHistoryEntry> {
+        let mut histories = self.board_histories.lock().unwrap();
+        let Some(history) = histories.get_mut(name) else {
+            return Vec::new();
+        };
+        let now = now_millis();
+        history.entries_since(now.saturating_sub(window.as_millis() as u64), now)
+    }
+
+    /// Keep the value written to the board, if it has a retention policy.
+    pub(crate) fn record_board_history(&self, name: &str, value: &AgentValue) {
+        let mut histories = self.board_histories.lock().unwrap();
+        if let Some(history) = histories.get_mut(name) {
+            history.push(now_millis(), value.clone());
+        }
+    }
+
+    /// Count the TTL of a value restored to the board from now.
+    pub(crate) fn record_board_restored(&self, name: &str) {
+        let mut histories = self.board_histories.lock().unwrap();
+        if let Some(history) = histories.get_mut(name)
+            && history.written_at.is_none()
+        {
+            history.written_at = Some(now_millis());
+        }
+    }
+
+    /// Remove the current value of the board, from the board store too, if it has outlived
+    /// its TTL. Returns whether it was removed.
+    pub(crate) fn expire_board(&self, name: &str) -> bool {
+        // a write records its history before its value, so that a new value is not removed
+        let mut histories = self.board_histories.lock().unwrap();
+        let Some(history) = histories.get_mut(name) else {
+            return false;
+        };
+        if !history.is_expired(now_millis()) {
+            return false;
+        }
+        history.written_at = None;
+        self.board_value.lock().unwrap().shift_remove(name);
+        if self.is_board_persistent(name) {
+            self.write_board_store(name, None);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(retention: BoardRetention) -> BoardHistory {
+        BoardHistory {
+            retention,
+            ..Default::default()
+        }
+    }
+
+    fn values(history: &BoardHistory) -> Vec<i64> {
+        history
+            .entries
+            .iter()
+            .filter_map(|e| e.value.as_i64())
+            .collect()
+    }
+
+    #[test]
+    fn test_max_len() {
+        let mut history = history(BoardRetention::last(2));
+        for i in 1..=3 {
+            history.push(i as u64, AgentValue::integer(i));
+        }
+        assert_eq!(values(&history), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let mut history = history(BoardRetention::within(Duration::from_millis(100)));
+        history.push(1000, AgentValue::integer(1));
+        history.push(1050, AgentValue::integer(2));
+        history.push(1120, AgentValue::integer(3));
+        assert_eq!(values(&history), vec![2, 3]);
+
+        // pruned when read
+        let entries = history.entries_since(0, 1160);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].timestamp, 1120);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let mut history = history(BoardRetention::expire_after(Duration::from_millis(10)));
+        assert!(!history.is_expired(1000));
+        history.push(1000, AgentValue::integer(1));
+        assert!(!history.is_expired(1009));
+        assert!(history.is_expired(1010));
+        // no history is kept
+        assert!(history.entries.is_empty());
+    }
+
+    #[test]
+    fn test_entries_since() {
+        let mut history = history(BoardRetention::last(10));
+        for i in 1..=5 {
+            history.push(i * 10, AgentValue::integer(i as i64));
+        }
+        let entries = history.entries_since(30, 50);
+        let timestamps: Vec<u64> = entries.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![30, 40, 50]);
+    }
+}
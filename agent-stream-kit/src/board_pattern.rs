@@ -0,0 +1,172 @@
+use regex::Regex;
+use tokio::sync::mpsc;
+
+use crate::askit::{ASKit, ASKitEvent};
+use crate::error::AgentError;
+use crate::value::AgentValue;
+
+/// Context var holding the name of the board a value was written to, set on the values
+/// a `Board->` agent emits for a pattern.
+pub const BOARD_NAME_VAR: &str = "board_name";
+
+const REGEX_PREFIX: &str = "re:";
+
+/// A pattern of board names, for `ASKit::subscribe_board` and the `Board->` agents with
+/// the `pattern` config set.
+///
+/// Names are split into segments at `/`. As in MQTT topic filters, a `*` segment matches any
+/// one segment, and a `#` segment at the end matches the rest of the name, including nothing,
+/// so `sensors/#` matches `sensors` and `sensors/room1/temp`. A pattern starting with `re:`
+/// is a regular expression matched against the whole name. Any other name matches only itself.
+///
+/// Names are only read as patterns where patterns are asked for, so a `Board->` agent without
+/// the `pattern` config matches a name such as `alerts/*` exactly. A pattern matching such a
+/// name exactly is a `re:` pattern of the escaped name.
+///
+/// As with the `$` topics of MQTT, wildcards and regular expressions do not match variables,
+/// whose board names start with `%`.
+#[derive(Clone, Debug)]
+pub enum BoardPattern {
+    Exact(String),
+    Wildcard(Vec<String>),
+    Regex(Regex),
+}
+
+impl BoardPattern {
+    pub fn parse(pattern: &str) -> Result<Self, AgentError> {
+        if let Some(re) = pattern.strip_prefix(REGEX_PREFIX) {
+            let re = Regex::new(&format!("^(?:{})$", re))
+                .map_err(|e| AgentError::InvalidBoardPattern(format!("{}: {}", pattern, e)))?;
+            return Ok(BoardPattern::Regex(re));
+        }
+        let segments: Vec<String> = pattern.split('/').map(str::to_string).collect();
+        if let Some(i) = segments.iter().position(|s| s == "#")
+            && i != segments.len() - 1
+        {
+            return Err(AgentError::InvalidBoardPattern(format!(
+                "{}: # must be the last segment",
+                pattern
+            )));
+        }
+        if segments.iter().any(|s| s == "*" || s == "#") {
+            return Ok(BoardPattern::Wildcard(segments));
+        }
+        Ok(BoardPattern::Exact(pattern.to_string()))
+    }
+
+    /// The pattern matching only the name, whatever it contains.
+    pub fn exact(name: &str) -> Self {
+        BoardPattern::Exact(name.to_string())
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, BoardPattern::Exact(_))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            BoardPattern::Exact(exact) => exact == name,
+            _ if name.starts_with('%') => false,
+            BoardPattern::Wildcard(segments) => {
+                let mut names = name.split('/');
+                for segment in segments {
+                    if segment == "#" {
+                        return true;
+                    }
+                    let Some(name) = names.next() else {
+                        return false;
+                    };
+                    if segment != "*" && segment != name {
+                        return false;
+                    }
+                }
+                names.next().is_none()
+            }
+            BoardPattern::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+impl ASKit {
+    /// Subscribe to the values written to the boards matching the pattern,
+    /// as pairs of the board name and the value.
+    pub fn subscribe_board(
+        &self,
+        pattern: &str,
+    ) -> Result<mpsc::UnboundedReceiver<(String, AgentValue)>, AgentError> {
+        let pattern = BoardPattern::parse(pattern)?;
+        Ok(self.subscribe_to_event(move |event| match event {
+            ASKitEvent::Board(name, value) if pattern.matches(&name) => Some((name, value)),
+            _ => None,
+        }))
+    }
+
+    /// Ids of the `Board->` agents subscribed with a pattern matching the board.
+    pub(crate) fn board_pattern_agents_for(&self, name: &str) -> Vec<String> {
+        let board_pattern_agents = self.board_pattern_agents.lock().unwrap();
+        board_pattern_agents
+            .iter()
+            .filter(|(pattern, _)| pattern.matches(name))
+            .map(|(_, agent_id)| agent_id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        BoardPattern::parse(pattern).unwrap().matches(name)
+    }
+
+    #[test]
+    fn test_exact() {
+        assert!(BoardPattern::parse("sensors/temp").unwrap().is_exact());
+        assert!(matches("sensors/temp", "sensors/temp"));
+        assert!(!matches("sensors/temp", "sensors/temp2"));
+        // not a whole segment
+        assert!(matches("sensors*", "sensors*"));
+        assert!(!matches("sensors*", "sensors1"));
+
+        let exact = BoardPattern::exact("sensors/*");
+        assert!(exact.matches("sensors/*"));
+        assert!(!exact.matches("sensors/temp"));
+        assert!(BoardPattern::exact("re:x").matches("re:x"));
+    }
+
+    #[test]
+    fn test_single_segment_wildcard() {
+        assert!(matches("sensors/*", "sensors/temp"));
+        assert!(!matches("sensors/*", "sensors"));
+        assert!(!matches("sensors/*", "sensors/room1/temp"));
+        assert!(matches("sensors/*/temp", "sensors/room1/temp"));
+        assert!(!matches("sensors/*/temp", "sensors/room1/humidity"));
+    }
+
+    #[test]
+    fn test_multi_segment_wildcard() {
+        assert!(matches("sensors/#", "sensors"));
+        assert!(matches("sensors/#", "sensors/temp"));
+        assert!(matches("sensors/#", "sensors/room1/temp"));
+        assert!(!matches("sensors/#", "actuators/temp"));
+        assert!(matches("#", "anything/at/all"));
+        assert!(!matches("#", "%1/var"));
+        assert!(matches!(
+            BoardPattern::parse("sensors/#/temp"),
+            Err(AgentError::InvalidBoardPattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_regex() {
+        assert!(matches(r"re:sensors/\d+", "sensors/12"));
+        assert!(!matches(r"re:sensors/\d+", "sensors/12/temp"));
+        assert!(!matches(r"re:sensors/\d+", "old/sensors/12"));
+        assert!(!matches("re:.*", "%1/var"));
+        assert!(matches!(
+            BoardPattern::parse("re:("),
+            Err(AgentError::InvalidBoardPattern(_))
+        ));
+    }
+}
@@ -0,0 +1,621 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::FnvIndexMap;
+use crate::askit::ASKit;
+use crate::error::AgentError;
+use crate::value::AgentValue;
+
+// how long the writer waits for more values before writing them to the store
+const STORE_WRITE_DELAY: Duration = Duration::from_millis(50);
+
+// how long the writer keeps values of boards written all the time
+const STORE_WRITE_MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Keeps the values of persistent boards, set with `ASKit::set_board_store`.
+///
+/// The ASKit writes to the store from a background thread, in batches of the last values
+/// written to the boards within a short time.
+pub trait BoardStore: Send + Sync {
+    /// All the stored values with the names they were saved under, in the order they were
+    /// first saved.
+    fn load(&self) -> Result<Vec<(String, AgentValue)>, AgentError>;
+
+    fn save(&self, name: &str, value: &AgentValue) -> Result<(), AgentError>;
+
+    fn remove(&self, name: &str) -> Result<(), AgentError>;
+
+    /// Save the values, or remove the boards of `None`. Calls `save` and `remove` by default.
+    fn write_batch(&self, changes: &[(String, Option<AgentValue>)]) -> Result<(), AgentError> {
+        for (name, value) in changes {
+            match value {
+                Some(value) => self.save(name, value)?,
+                None => self.remove(name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: BoardStore + ?Sized> BoardStore for Arc<T> {
+    fn load(&self) -> Result<Vec<(String, AgentValue)>, AgentError> {
+        (**self).load()
+    }
+
+    fn save(&self, name: &str, value: &AgentValue) -> Result<(), AgentError> {
+        (**self).save(name, value)
+    }
+
+    fn remove(&self, name: &str) -> Result<(), AgentError> {
+        (**self).remove(name)
+    }
+
+    fn write_batch(&self, changes: &[(String, Option<AgentValue>)]) -> Result<(), AgentError> {
+        (**self).write_batch(changes)
+    }
+}
+
+/// Keeps the values in memory, for tests and for passing values between ASKit instances.
+#[derive(Default)]
+pub struct MemoryBoardStore {
+    values: Mutex<FnvIndexMap<String, AgentValue>>,
+}
+
+impl MemoryBoardStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BoardStore for MemoryBoardStore {
+    fn load(&self) -> Result<Vec<(String, AgentValue)>, AgentError> {
+        let values = self.values.lock().unwrap();
+        Ok(values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect())
+    }
+
+    fn save(&self, name: &str, value: &AgentValue) -> Result<(), AgentError> {
+        let mut values = self.values.lock().unwrap();
+        values.insert(name.to_string(), value.clone());
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), AgentError> {
+        let mut values = self.values.lock().unwrap();
+        values.shift_remove(name);
+        Ok(())
+    }
+}
+
+/// Keeps the values in a JSON file, as an object of the values by name.
+///
+/// The whole file is rewritten on each save, and once for each batch written by the ASKit,
+/// so this suits boards written now and then, such as settings and last-known states.
+pub struct JsonFileBoardStore {
+    path: PathBuf,
+    values: Mutex<FnvIndexMap<String, AgentValue>>,
+}
+
+impl JsonFileBoardStore {
+    /// Open the file, which is created on the first save if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let path = path.as_ref();
+        let values = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| AgentError::JsonParseError(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => FnvIndexMap::default(),
+            Err(e) => {
+                return Err(AgentError::IoError(format!(
+                    "Failed to read {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            values: Mutex::new(values),
+        })
+    }
+
+    // write to a temporary file first, so that a crash does not leave a broken file
+    fn write(&self, values: &FnvIndexMap<String, AgentValue>) -> Result<(), AgentError> {
+        let content = serde_json::to_string_pretty(values)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, &self.path))
+            .map_err(|e| {
+                AgentError::IoError(format!("Failed to write {}: {}", self.path.display(), e))
+            })
+    }
+}
+
+impl BoardStore for JsonFileBoardStore {
+    fn load(&self) -> Result<Vec<(String, AgentValue)>, AgentError> {
+        let values = self.values.lock().unwrap();
+        Ok(values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect())
+    }
+
+    fn save(&self, name: &str, value: &AgentValue) -> Result<(), AgentError> {
+        let mut values = self.values.lock().unwrap();
+        values.insert(name.to_string(), value.clone());
+        self.write(&values)
+    }
+
+    fn remove(&self, name: &str) -> Result<(), AgentError> {
+        let mut values = self.values.lock().unwrap();
+        if values.shift_remove(name).is_none() {
+            return Ok(());
+        }
+        self.write(&values)
+    }
+
+    fn write_batch(&self, changes: &[(String, Option<AgentValue>)]) -> Result<(), AgentError> {
+        let mut values = self.values.lock().unwrap();
+        let mut changed = false;
+        for (name, value) in changes {
+            match value {
+                Some(value) => {
+                    values.insert(name.clone(), value.clone());
+                    changed = true;
+                }
+                None => changed |= values.shift_remove(name).is_some(),
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        self.write(&values)
+    }
+}
+
+/// Keeps the values in a table of an SQLite database, each value as JSON text.
+#[cfg(feature = "sqlite")]
+pub struct SqliteBoardStore {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteBoardStore {
+    /// Open the database, creating it and the `boards` table if they do not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let conn = rusqlite::Connection::open(path).map_err(sqlite_error)?;
+        Self::with_connection(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self, AgentError> {
+        let conn = rusqlite::Connection::open_in_memory().map_err(sqlite_error)?;
+        Self::with_connection(conn)
+    }
+
+    fn with_connection(conn: rusqlite::Connection) -> Result<Self, AgentError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS boards (name TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            (),
+        )
+        .map_err(sqlite_error)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl BoardStore for SqliteBoardStore {
+    fn load(&self) -> Result<Vec<(String, AgentValue)>, AgentError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT name, value FROM boards ORDER BY rowid")
+            .map_err(sqlite_error)?;
+        let rows = stmt
+            .query_map((), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_error)?;
+        let mut values = Vec::new();
+        for row in rows {
+            let (name, json) = row.map_err(sqlite_error)?;
+            let value = serde_json::from_str(&json)
+                .map_err(|e| AgentError::JsonParseError(format!("board {}: {}", name, e)))?;
+            values.push((name, value));
+        }
+        Ok(values)
+    }
+
+    fn save(&self, name: &str, value: &AgentValue) -> Result<(), AgentError> {
+        let json = serde_json::to_string(value)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO boards (name, value) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+            (name, json),
+        )
+        .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), AgentError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM boards WHERE name = ?1", (name,))
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_error(e: rusqlite::Error) -> AgentError {
+    AgentError::BoardStoreError(e.to_string())
+}
+
+enum StoreOp {
+    // the value to save, or None to remove the board
+    Write(String, Option<AgentValue>),
+
+    // write the pending values, and tell when done
+    Flush(mpsc::Sender<()>),
+}
+
+/// Writes to the board store from a background thread, so that the main loop does not wait
+/// on the disk.
+///
+/// Only the last value of each board is written, once no value comes for a while.
+/// Dropping the writer writes the rest.
+pub(crate) struct BoardStoreWriter {
+    tx: Option<mpsc::Sender<StoreOp>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BoardStoreWriter {
+    pub(crate) fn new(store: Arc<dyn BoardStore>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || store_write_loop(store, rx));
+        Self {
+            tx: Some(tx),
+            handle: Some(handle),
+        }
+    }
+
+    /// Save the value of the board, or remove the board with `None`.
+    pub(crate) fn write(&self, name: &str, value: Option<&AgentValue>) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(StoreOp::Write(name.to_string(), value.cloned()));
+        }
+    }
+
+    /// Write the pending values now. The receiver gets a message when they are written.
+    pub(crate) fn flush(&self) -> mpsc::Receiver<()> {
+        let (done_tx, done_rx) = mpsc::channel();
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(StoreOp::Flush(done_tx));
+        }
+        done_rx
+    }
+}
+
+impl Drop for BoardStoreWriter {
+    fn drop(&mut self) {
+        // closing the channel makes the thread write the rest and exit
+        self.tx.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn store_write_loop(store: Arc<dyn BoardStore>, rx: mpsc::Receiver<StoreOp>) {
+    // board name -> last value
+    let mut pending: FnvIndexMap<String, Option<AgentValue>> = FnvIndexMap::default();
+    let mut pending_since = Instant::now();
+    loop {
+        let op = if pending.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(STORE_WRITE_DELAY)
+        };
+        match op {
+            Ok(StoreOp::Write(name, value)) => {
+                if pending.is_empty() {
+                    pending_since = Instant::now();
+                }
+                pending.insert(name, value);
+                if pending_since.elapsed() >= STORE_WRITE_MAX_DELAY {
+                    write_pending(&*store, &mut pending);
+                }
+            }
+            Ok(StoreOp::Flush(done)) => {
+                write_pending(&*store, &mut pending);
+                let _ = done.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => write_pending(&*store, &mut pending),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    write_pending(&*store, &mut pending);
+}
+
+fn write_pending(store: &dyn BoardStore, pending: &mut FnvIndexMap<String, Option<AgentValue>>) {
+    if pending.is_empty() {
+        return;
+    }
+    let changes: Vec<(String, Option<AgentValue>)> = pending.drain(..).collect();
+    if let Err(e) = store.write_batch(&changes) {
+        log::error!("Failed to write boards to the store: {}", e);
+    }
+}
+
+impl ASKit {
+    /// The last value written to the board, unless it has expired.
+    pub fn read_board_value(&self, name: &str) -> Option<AgentValue> {
+        if self.expire_board(name) {
+            return None;
+        }
+        let board_value = self.board_value.lock().unwrap();
+        board_value.get(name).cloned()
+    }
+
+    /// The last value written to the variable of the stream, addressed as from the agents
+    /// of the stream. See `VarScope`.
+    pub fn read_var_value(&self, stream_id: &str, name: &str) -> Option<AgentValue> {
+        let var_name = self.var_board_name(stream_id, name).ok()?;
+        self.read_board_value(&var_name)
+    }
+
+    /// Names of the boards with an unexpired value, in the order they were first written.
+    ///
+    /// Variables are listed under their board names. See `VarScope`.
+    pub fn list_boards(&self) -> Vec<String> {
+        let names: Vec<String> = {
+            let board_value = self.board_value.lock().unwrap();
+            board_value.keys().cloned().collect()
+        };
+        names
+            .into_iter()
+            .filter(|name| !self.expire_board(name))
+            .collect()
+    }
+
+    /// Keep the values of persistent boards in the store, and restore the values it holds.
+    ///
+    /// Restored boards are persistent, and keep the values written since the ASKit started.
+    /// Variables are stored under their board names, which name streams rather than their ids,
+    /// so they are restored for the streams of the same names whenever these are loaded.
+    ///
+    /// The values are saved from a background thread. See `flush_board_store`.
+    pub fn set_board_store(&self, store: impl BoardStore + 'static) -> Result<(), AgentError> {
+        let stored = store.load()?;
+        let writer = BoardStoreWriter::new(Arc::new(store));
+        let previous = self.board_store.write().unwrap().replace(writer);
+        drop(previous);
+        for (name, value) in stored {
+            self.restore_board(name, value);
+        }
+        Ok(())
+    }
+
+    /// Stop storing values, once the values written so far are saved. The values already
+    /// stored are kept in the store.
+    pub fn remove_board_store(&self) {
+        let writer = self.board_store.write().unwrap().take();
+        drop(writer);
+    }
+
+    /// Wait until the values written to persistent boards so far are saved in the store.
+    pub fn flush_board_store(&self) {
+        let done = self.board_store.read().unwrap().as_ref().map(|w| w.flush());
+        if let Some(done) = done {
+            let _ = done.recv();
+        }
+    }
+
+    /// Set whether the values of the board are kept in the board store.
+    ///
+    /// The current value is saved when the board becomes persistent, and removed from the store
+    /// when it stops being persistent.
+    pub fn set_board_persistent(&self, name: &str, persistent: bool) -> Result<(), AgentError> {
+        if persistent {
+            self.persistent_boards
+                .lock()
+                .unwrap()
+                .insert(name.to_string());
+        } else {
+            self.persistent_boards.lock().unwrap().shift_remove(name);
+        }
+
+        if !persistent {
+            self.write_board_store(name, None);
+        } else if let Some(value) = self.read_board_value(name) {
+            self.write_board_store(name, Some(&value));
+        }
+        Ok(())
+    }
+
+    /// Set whether the values of the variable of the stream are kept in the board store.
+    pub fn set_var_persistent(
+        &self,
+        stream_id: &str,
+        name: &str,
+        persistent: bool,
+    ) -> Result<(), AgentError> {
+        let var_name = self.var_board_name(stream_id, name)?;
+        self.set_board_persistent(&var_name, persistent)
+    }
+
+    pub fn is_board_persistent(&self, name: &str) -> bool {
+        self.persistent_boards.lock().unwrap().contains(name)
+    }
+
+    /// Save the value of the board to the store in the background, or remove it with `None`.
+    pub(crate) fn write_board_store(&self, name: &str, value: Option<&AgentValue>) {
+        if let Some(writer) = self.board_store.read().unwrap().as_ref() {
+            writer.write(name, value);
+        }
+    }
+
+    fn restore_board(&self, name: String, value: AgentValue) {
+        self.persistent_boards.lock().unwrap().insert(name.clone());
+        let restored = {
+            let mut board_value = self.board_value.lock().unwrap();
+            if board_value.contains_key(&name) {
+                false
+            } else {
+                board_value.insert(name.clone(), value);
+                true
+            }
+        };
+        if restored {
+            self.record_board_restored(&name);
+        }
+    }
+
+    /// Save the value written to the board, if it is persistent.
+    pub(crate) fn persist_board(&self, name: &str, value: &AgentValue) {
+        if self.is_board_persistent(name) {
+            self.write_board_store(name, Some(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("askit_{}_{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn check_store(store: &dyn BoardStore) {
+        store.save("a", &AgentValue::integer(1)).unwrap();
+        store.save("b", &AgentValue::string("x")).unwrap();
+        store.save("a", &AgentValue::integer(2)).unwrap();
+        store.remove("b").unwrap();
+        store.remove("missing").unwrap();
+        store.save("c", &AgentValue::boolean(true)).unwrap();
+        assert_eq!(
+            store.load().unwrap(),
+            vec![
+                ("a".to_string(), AgentValue::integer(2)),
+                ("c".to_string(), AgentValue::boolean(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(&MemoryBoardStore::new());
+    }
+
+    #[test]
+    fn test_json_file_store() {
+        let path = temp_path("boards.json");
+        check_store(&JsonFileBoardStore::open(&path).unwrap());
+
+        // reopened
+        let store = JsonFileBoardStore::open(&path).unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_json_file_store_write_batch() {
+        let path = temp_path("batch_boards.json");
+        let store = JsonFileBoardStore::open(&path).unwrap();
+        store.save("a", &AgentValue::integer(1)).unwrap();
+        store
+            .write_batch(&[
+                ("a".to_string(), None),
+                ("b".to_string(), Some(AgentValue::integer(2))),
+            ])
+            .unwrap();
+
+        let store = JsonFileBoardStore::open(&path).unwrap();
+        assert_eq!(
+            store.load().unwrap(),
+            vec![("b".to_string(), AgentValue::integer(2))]
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    // keeps the sizes of the batches written
+    #[derive(Default)]
+    struct BatchStore {
+        store: MemoryBoardStore,
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl BoardStore for BatchStore {
+        fn load(&self) -> Result<Vec<(String, AgentValue)>, AgentError> {
+            self.store.load()
+        }
+
+        fn save(&self, name: &str, value: &AgentValue) -> Result<(), AgentError> {
+            self.store.save(name, value)
+        }
+
+        fn remove(&self, name: &str) -> Result<(), AgentError> {
+            self.store.remove(name)
+        }
+
+        fn write_batch(&self, changes: &[(String, Option<AgentValue>)]) -> Result<(), AgentError> {
+            self.batches.lock().unwrap().push(changes.len());
+            self.store.write_batch(changes)
+        }
+    }
+
+    #[test]
+    fn test_writer_keeps_last_values() {
+        let store = Arc::new(BatchStore::default());
+        let writer = BoardStoreWriter::new(store.clone());
+        for i in 0..100 {
+            writer.write("a", Some(&AgentValue::integer(i)));
+        }
+        writer.write("b", Some(&AgentValue::integer(1)));
+        writer.write("b", None);
+        writer.flush().recv().unwrap();
+        assert_eq!(
+            store.load().unwrap(),
+            vec![("a".to_string(), AgentValue::integer(99))]
+        );
+        assert_eq!(*store.batches.lock().unwrap(), vec![2]);
+
+        // the rest is written when dropped
+        writer.write("c", Some(&AgentValue::integer(3)));
+        drop(writer);
+        assert_eq!(store.load().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_json_file_store_broken_file() {
+        let path = temp_path("broken_boards.json");
+        fs::write(&path, "{").unwrap();
+        assert!(matches!(
+            JsonFileBoardStore::open(&path),
+            Err(AgentError::JsonParseError(_))
+        ));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store() {
+        check_store(&SqliteBoardStore::open_in_memory().unwrap());
+
+        let path = temp_path("boards.db");
+        check_store(&SqliteBoardStore::open(&path).unwrap());
+        let store = SqliteBoardStore::open(&path).unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -7,7 +7,7 @@ use crate::value::{AgentValue, AgentValueMap};
 
 pub type AgentConfigsMap = FnvIndexMap<String, AgentConfigs>;
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
 pub struct AgentConfigs(FnvIndexMap<String, AgentValue>);
 
 impl AgentConfigs {
@@ -19,6 +19,10 @@ impl AgentConfigs {
         self.0.insert(key, value);
     }
 
+    pub fn remove(&mut self, key: &str) -> Option<AgentValue> {
+        self.0.shift_remove(key)
+    }
+
     pub fn contains_key(&self, key: &str) -> bool {
         self.0.contains_key(key)
     }
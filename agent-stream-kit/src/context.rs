@@ -3,6 +3,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use serde::{Deserialize, Serialize};
 
 use crate::error::AgentError;
+use crate::trace::SpanContext;
 use crate::value::AgentValue;
 
 /// Event-scoped context that identifies a single flow across agents and carries auxiliary metadata.
@@ -24,6 +25,10 @@ pub struct AgentContext {
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     frames: Option<im::Vector<Frame>>,
+
+    /// Span of the agent handling the context, when tracing.
+    #[serde(skip)]
+    span: Option<SpanContext>,
 }
 
 pub const FRAME_MAP: &str = "map";
@@ -37,6 +42,7 @@ impl AgentContext {
             id: new_id(),
             vars: None,
             frames: None,
+            span: None,
         }
     }
 
@@ -45,6 +51,19 @@ impl AgentContext {
         self.id
     }
 
+    /// Returns the span of the agent handling this context, when tracing.
+    pub fn span(&self) -> Option<SpanContext> {
+        self.span
+    }
+
+    /// Returns a new context handled in the given span.
+    pub(crate) fn with_span(&self, span: SpanContext) -> Self {
+        Self {
+            span: Some(span),
+            ..self.clone()
+        }
+    }
+
     // Variables
 
     /// Retrieves an immutable reference to a stored variable, if present.
@@ -64,6 +83,7 @@ impl AgentContext {
             id: self.id,
             vars: Some(vars),
             frames: self.frames.clone(),
+            span: self.span,
         }
     }
 }
@@ -139,6 +159,7 @@ impl AgentContext {
             id: self.id,
             vars: self.vars.clone(),
             frames: Some(frames),
+            span: self.span,
         }
     }
 
@@ -240,6 +261,7 @@ impl AgentContext {
                     id: self.id,
                     vars: self.vars.clone(),
                     frames: new_frames,
+                    span: self.span,
                 },
             );
         }
@@ -1,12 +1,17 @@
 use std::ops::Not;
 
+use semver::Version;
 use serde::{Deserialize, Serialize};
 
 use crate::FnvIndexMap;
 use crate::agent::Agent;
 use crate::askit::ASKit;
+use crate::config::AgentConfigs;
 use crate::error::AgentError;
 use crate::id::new_id;
+use crate::mailbox::DeliveryPolicy;
+use crate::migration::AgentMigrations;
+use crate::pin::{PinSpec, PinSpecs, PinType};
 use crate::spec::AgentSpec;
 use crate::value::AgentValue;
 
@@ -18,6 +23,10 @@ pub struct AgentDefinition {
 
     pub name: String,
 
+    /// Semver version of the definition, recorded in the specs created from it.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
 
@@ -36,6 +45,14 @@ pub struct AgentDefinition {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub outputs: Option<Vec<String>>,
 
+    /// Descriptions of the input pins, by name.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub input_pins: Option<PinSpecs>,
+
+    /// Descriptions of the output pins, by name.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub output_pins: Option<PinSpecs>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub configs: Option<AgentConfigSpecs>,
 
@@ -45,6 +62,26 @@ pub struct AgentDefinition {
     #[serde(default, skip_serializing_if = "<&bool>::not")]
     pub native_thread: bool,
 
+    /// Capacity of the input queue of each agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_capacity: Option<usize>,
+
+    /// Delivery policy of the input queue of each agent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery_policy: Option<DeliveryPolicy>,
+
+    /// Number of inputs the `Block` queue of each agent holds while the agent is paused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause_capacity: Option<usize>,
+
+    /// Renames of the definition, its configs and pins, applied to saved specs.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub migrations: Option<AgentMigrations>,
+
+    /// Upgrades the configs of specs created with an older major version.
+    #[serde(skip)]
+    pub upgrade: Option<AgentUpgradeFn>,
+
     #[serde(skip)]
     pub new_boxed: Option<AgentNewBoxedFn>,
 }
@@ -81,6 +118,9 @@ pub struct AgentConfigSpec {
 pub type AgentNewBoxedFn =
     fn(askit: ASKit, id: String, spec: AgentSpec) -> Result<Box<dyn Agent>, AgentError>;
 
+/// Upgrades the configs of a spec created with the version `from` to the current version.
+pub type AgentUpgradeFn = fn(from: &Version, configs: &mut AgentConfigs) -> Result<(), AgentError>;
+
 impl AgentDefinition {
     pub fn new(
         kind: impl Into<String>,
@@ -95,6 +135,18 @@ impl AgentDefinition {
         }
     }
 
+    /// Set the semver version of the definition.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the hook that upgrades specs created with an older major version.
+    pub fn upgrade(mut self, upgrade: AgentUpgradeFn) -> Self {
+        self.upgrade = Some(upgrade);
+        self
+    }
+
     pub fn title(mut self, title: &str) -> Self {
         self.title = Some(title.into());
         self
@@ -125,6 +177,54 @@ impl AgentDefinition {
         self
     }
 
+    // Pin Spec
+
+    /// Declare a typed input pin. The pin is added to `inputs` if not listed yet.
+    pub fn input_pin(self, name: &str, type_: PinType) -> Self {
+        self.input_pin_with(name, type_, |pin| pin)
+    }
+
+    pub fn input_pin_with<F>(mut self, name: &str, type_: PinType, f: F) -> Self
+    where
+        F: FnOnce(PinSpec) -> PinSpec,
+    {
+        insert_pin(
+            &mut self.inputs,
+            &mut self.input_pins,
+            name,
+            f(PinSpec::new(type_)),
+        );
+        self
+    }
+
+    /// Declare a typed output pin. The pin is added to `outputs` if not listed yet.
+    pub fn output_pin(self, name: &str, type_: PinType) -> Self {
+        self.output_pin_with(name, type_, |pin| pin)
+    }
+
+    pub fn output_pin_with<F>(mut self, name: &str, type_: PinType, f: F) -> Self
+    where
+        F: FnOnce(PinSpec) -> PinSpec,
+    {
+        insert_pin(
+            &mut self.outputs,
+            &mut self.output_pins,
+            name,
+            f(PinSpec::new(type_)),
+        );
+        self
+    }
+
+    /// Type of the input pin, `Any` when the pin is not typed.
+    pub fn input_pin_type(&self, name: &str) -> &PinType {
+        pin_type(&self.input_pins, name)
+    }
+
+    /// Type of the output pin, `Any` when the pin is not typed.
+    pub fn output_pin_type(&self, name: &str) -> &PinType {
+        pin_type(&self.output_pins, name)
+    }
+
     // Config Spec
 
     pub fn configs(mut self, configs: Vec<(&str, AgentConfigSpec)>) -> Self {
@@ -442,10 +542,64 @@ impl AgentDefinition {
         self
     }
 
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    pub fn delivery_policy(mut self, policy: DeliveryPolicy) -> Self {
+        self.delivery_policy = Some(policy);
+        self
+    }
+
+    pub fn pause_capacity(mut self, capacity: usize) -> Self {
+        self.pause_capacity = Some(capacity);
+        self
+    }
+
+    // Migrations
+
+    /// Declare a former name of the definition. Specs using it are moved to this definition.
+    pub fn renamed_from(mut self, name: &str) -> Self {
+        self.migrations
+            .get_or_insert_with(Default::default)
+            .renamed_from
+            .push(name.into());
+        self
+    }
+
+    /// Declare that the config `from` was renamed to `to`.
+    pub fn config_renamed(mut self, from: &str, to: &str) -> Self {
+        self.migrations
+            .get_or_insert_with(Default::default)
+            .configs
+            .insert(from.into(), to.into());
+        self
+    }
+
+    /// Declare that the input pin `from` was renamed to `to`.
+    pub fn input_renamed(mut self, from: &str, to: &str) -> Self {
+        self.migrations
+            .get_or_insert_with(Default::default)
+            .inputs
+            .insert(from.into(), to.into());
+        self
+    }
+
+    /// Declare that the output pin `from` was renamed to `to`.
+    pub fn output_renamed(mut self, from: &str, to: &str) -> Self {
+        self.migrations
+            .get_or_insert_with(Default::default)
+            .outputs
+            .insert(from.into(), to.into());
+        self
+    }
+
     pub fn to_spec(&self) -> AgentSpec {
         AgentSpec {
             id: new_id(),
             def_name: self.name.clone(),
+            def_version: self.version.clone(),
             inputs: self.inputs.clone(),
             outputs: self.outputs.clone(),
             configs: self.configs.as_ref().map(|cfgs| {
@@ -457,11 +611,91 @@ impl AgentDefinition {
             #[allow(deprecated)]
             enabled: false,
             disabled: false,
+            queue_capacity: None,
+            delivery_policy: None,
+            pause_capacity: None,
+            supervisor: None,
             extensions: FnvIndexMap::default(),
         }
     }
 }
 
+impl AgentDefinition {
+    /// Check that the spec was created with a compatible version of the definition.
+    ///
+    /// Specs of the same major version are compatible. Specs of an older major version need
+    /// the `upgrade` hook, and are refused when there is none. Specs without a version
+    /// predate versioning and are taken as they are.
+    /// Returns the version of the spec when it has to be upgraded.
+    pub(crate) fn check_version(&self, spec: &AgentSpec) -> Result<Option<Version>, AgentError> {
+        let (Some(def_version), Some(spec_version)) = (&self.version, &spec.def_version) else {
+            return Ok(None);
+        };
+        let current = parse_version(def_version)?;
+        let from = parse_version(spec_version)?;
+        if from.major == current.major {
+            return Ok(None);
+        }
+        if from.major > current.major || self.upgrade.is_none() {
+            return Err(AgentError::IncompatibleVersion(
+                self.name.clone(),
+                def_version.clone(),
+                spec_version.clone(),
+            ));
+        }
+        Ok(Some(from))
+    }
+
+    /// Upgrade the spec if needed, and record the version of the definition in it.
+    pub(crate) fn upgrade_spec(&self, spec: &mut AgentSpec) -> Result<(), AgentError> {
+        if let Some(from) = self.check_version(spec)?
+            && let Some(upgrade) = self.upgrade
+        {
+            log::info!(
+                "Agent {}: upgrading {} from version {} to {}",
+                spec.id,
+                self.name,
+                from,
+                self.version.as_deref().unwrap_or_default()
+            );
+            upgrade(&from, spec.configs.get_or_insert_with(Default::default))?;
+            // the saved config specs are those of the old version
+            if spec.config_specs.is_some() {
+                spec.config_specs = self.configs.clone();
+            }
+        }
+        if self.version.is_some() {
+            spec.def_version = self.version.clone();
+        }
+        Ok(())
+    }
+}
+
+fn parse_version(version: &str) -> Result<Version, AgentError> {
+    Version::parse(version).map_err(|e| AgentError::InvalidVersion(format!("{}: {}", version, e)))
+}
+
+fn insert_pin(
+    names: &mut Option<Vec<String>>,
+    pins: &mut Option<PinSpecs>,
+    name: &str,
+    pin: PinSpec,
+) {
+    let names = names.get_or_insert_with(Vec::new);
+    if !names.iter().any(|n| n == name) {
+        names.push(name.into());
+    }
+    pins.get_or_insert_with(FnvIndexMap::default)
+        .insert(name.into(), pin);
+}
+
+fn pin_type<'a>(pins: &'a Option<PinSpecs>, name: &str) -> &'a PinType {
+    pins.as_ref()
+        .and_then(|pins| pins.get(name))
+        .map(|pin| pin.pin_type())
+        .unwrap_or(&PinType::Any)
+}
+
 impl AgentConfigSpec {
     pub fn new<V: Into<AgentValue>>(value: V, type_: &str) -> Self {
         Self {
@@ -576,6 +810,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialize_pins() {
+        let def = AgentDefinition::new("test", "caption", None)
+            .inputs(vec!["in"])
+            .input_pin_with("image", PinType::Image, |pin| pin.required())
+            .output_pin("captions", PinType::array(PinType::String));
+        let json = serde_json::to_string(&def).unwrap();
+        assert_eq!(
+            json,
+            r#"{"kind":"test","name":"caption","inputs":["in","image"],"outputs":["captions"],"input_pins":{"image":{"type":"image","required":true}},"output_pins":{"captions":{"type":"array<string>"}}}"#
+        );
+        let def: AgentDefinition = serde_json::from_str(&json).unwrap();
+        assert_eq!(def.input_pin_type("image"), &PinType::Image);
+        assert_eq!(def.input_pin_type("in"), &PinType::Any);
+    }
+
     #[test]
     fn test_deserialize_echo_agent_definition() {
         let json = r#"{"kind":"test","name":"echo","title":"Echo","category":"Test","inputs":["in"],"outputs":["out"],"configs":{"value":{"value":"abc","type":"string","title":"display_title","description":"display_description","readonly":true},"hide_title_value":{"value":1,"type":"integer","hide_title":true,"readonly":true}}}"#;
@@ -0,0 +1,311 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::FnvIndexMap;
+use crate::agent::agent_new;
+use crate::askit::ASKit;
+use crate::config::AgentConfigs;
+use crate::error::AgentError;
+use crate::id::new_id;
+use crate::spec::{AgentSpec, AgentStreamSpec, ChannelSpec};
+
+/// Changes between two specs of a stream. Agents are matched by id.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StreamSpecDiff {
+    /// Agents only in the new spec.
+    pub added_agents: Vec<AgentSpec>,
+
+    /// Ids of the agents only in the current spec.
+    pub removed_agents: Vec<String>,
+
+    /// Agents which have to be recreated, because their definition or its version, pins, queue,
+    /// supervisor or disabled flag changed.
+    pub replaced_agents: Vec<AgentSpec>,
+
+    /// Agents whose configs changed. (agent id, new configs)
+    ///
+    /// An agent whose new spec has no configs keeps its current configs, so it is not listed.
+    pub updated_configs: Vec<(String, AgentConfigs)>,
+
+    /// Agents whose extensions changed. (agent id, new extensions)
+    pub updated_extensions: Vec<(String, FnvIndexMap<String, Value>)>,
+
+    /// Channels only in the new spec.
+    pub added_channels: Vec<ChannelSpec>,
+
+    /// Channels only in the current spec.
+    pub removed_channels: Vec<ChannelSpec>,
+}
+
+impl StreamSpecDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_agents.is_empty()
+            && self.removed_agents.is_empty()
+            && self.replaced_agents.is_empty()
+            && self.updated_configs.is_empty()
+            && self.updated_extensions.is_empty()
+            && self.added_channels.is_empty()
+            && self.removed_channels.is_empty()
+    }
+}
+
+impl AgentStreamSpec {
+    /// Compute the changes needed to turn this spec into the given one.
+    pub fn diff(&self, new: &AgentStreamSpec) -> StreamSpecDiff {
+        let mut diff = StreamSpecDiff::default();
+
+        for agent in &self.agents {
+            if !new.agents.iter().any(|a| a.id == agent.id) {
+                diff.removed_agents.push(agent.id.clone());
+            }
+        }
+        for agent in &new.agents {
+            let Some(current) = self.agents.iter().find(|a| a.id == agent.id) else {
+                diff.added_agents.push(agent.clone());
+                continue;
+            };
+            if needs_replace(current, agent) {
+                diff.replaced_agents.push(agent.clone());
+                continue;
+            }
+            if current.configs != agent.configs
+                && let Some(configs) = &agent.configs
+            {
+                diff.updated_configs
+                    .push((agent.id.clone(), configs.clone()));
+            }
+            if current.extensions != agent.extensions {
+                diff.updated_extensions
+                    .push((agent.id.clone(), agent.extensions.clone()));
+            }
+        }
+
+        for channel in &self.channels {
+            if !new.channels.contains(channel) {
+                diff.removed_channels.push(channel.clone());
+            }
+        }
+        for channel in &new.channels {
+            if !self.channels.contains(channel) {
+                diff.added_channels.push(channel.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+fn needs_replace(current: &AgentSpec, new: &AgentSpec) -> bool {
+    // a spec of another version goes through the version check of `agent_new`
+    current.def_name != new.def_name
+        || current.def_version != new.def_version
+        || current.inputs != new.inputs
+        || current.outputs != new.outputs
+        || current.disabled != new.disabled
+        || current.queue_capacity != new.queue_capacity
+        || current.delivery_policy != new.delivery_policy
+        || current.pause_capacity != new.pause_capacity
+        || current.supervisor != new.supervisor
+}
+
+/// Give fresh ids to the agents which are not in the current spec, and update the channels.
+fn assign_new_ids(current: &AgentStreamSpec, mut spec: AgentStreamSpec) -> AgentStreamSpec {
+    let mut id_map = FnvIndexMap::default();
+    for agent in spec.agents.iter_mut() {
+        if agent.id.is_empty() || !current.agents.iter().any(|a| a.id == agent.id) {
+            let id = new_id();
+            id_map.insert(agent.id.clone(), id.clone());
+            agent.id = id;
+        }
+    }
+    for channel in spec.channels.iter_mut() {
+        if let Some(id) = id_map.get(&channel.source) {
+            channel.source = id.clone();
+        }
+        if let Some(id) = id_map.get(&channel.target) {
+            channel.target = id.clone();
+        }
+    }
+    spec
+}
+
+pub(crate) async fn apply_stream_spec(
+    askit: &ASKit,
+    stream_id: &str,
+    spec: AgentStreamSpec,
+) -> Result<StreamSpecDiff, AgentError> {
+    // configs are compared with the live ones, which may have been changed at runtime
+    let Some(current) = askit.get_agent_stream_spec(stream_id).await else {
+        return Err(AgentError::StreamNotFound(stream_id.to_string()));
+    };
+    let mut spec = assign_new_ids(&current, spec);
+    askit.migrate_stream_spec(&mut spec);
+    let diff = current.diff(&spec);
+    if diff.is_empty() {
+        askit.set_agent_stream_spec(stream_id, spec)?;
+        return Ok(diff);
+    }
+
+    // Create the agents and check the new channels first, so that a failure changes nothing.
+    let mut new_agents = Vec::new();
+    for agent in diff.added_agents.iter().chain(&diff.replaced_agents) {
+        let mut new_agent = agent_new(askit.clone(), agent.id.clone(), agent.clone())?;
+        new_agent.set_stream_id(stream_id.to_string());
+        new_agents.push((agent.id.clone(), new_agent));
+    }
+    for channel in &diff.added_channels {
+        if !spec.agents.iter().any(|a| a.id == channel.source) {
+            return Err(AgentError::SourceAgentNotFound(channel.source.clone()));
+        }
+        if !spec.agents.iter().any(|a| a.id == channel.target) {
+            return Err(AgentError::AgentNotFound(channel.target.clone()));
+        }
+        askit.check_pin_types(&spec, channel)?;
+    }
+
+    // Then update the kept agents, restoring them on a failure.
+    if let Err(e) = update_agents(askit, &diff).await {
+        if let Err(e) = update_agents(askit, &spec.diff(&current)).await {
+            log::error!("Failed to restore agents of stream {}: {}", stream_id, e);
+        }
+        return Err(e);
+    }
+
+    // Nothing fails from here on, so that the stream is not left half-applied.
+    for agent_id in &diff.removed_agents {
+        askit
+            .remove_agent_internal(agent_id)
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to remove agent {}: {}", agent_id, e);
+            });
+    }
+    for agent in &diff.replaced_agents {
+        askit.stop_agent(&agent.id).await.unwrap_or_else(|e| {
+            log::error!("Failed to stop agent {}: {}", agent.id, e);
+        });
+    }
+    {
+        let mut agents = askit.agents.lock().unwrap();
+        for (agent_id, agent) in new_agents {
+            agents.insert(agent_id, Arc::new(AsyncMutex::new(agent)));
+        }
+    }
+
+    askit.rewire_channels(&diff.removed_channels, &diff.added_channels);
+
+    let (running, paused) = askit.set_agent_stream_spec(stream_id, spec)?;
+    if running {
+        for agent in diff.added_agents.iter().chain(&diff.replaced_agents) {
+            if agent.disabled {
+                continue;
+            }
+            // as when starting the stream, an agent failing to start is only logged
+            if let Err(e) = askit.start_agent(&agent.id).await {
+                log::error!("Failed to start agent {}: {}", agent.id, e);
+                continue;
+            }
+            if paused && let Err(e) = askit.pause_agent(&agent.id).await {
+                log::error!("Failed to pause agent {}: {}", agent.id, e);
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+// Update the configs and extensions of the agents kept by the diff.
+async fn update_agents(askit: &ASKit, diff: &StreamSpecDiff) -> Result<(), AgentError> {
+    for (agent_id, configs) in &diff.updated_configs {
+        askit
+            .set_agent_configs(agent_id.clone(), configs.clone())
+            .await?;
+    }
+    for (agent_id, extensions) in &diff.updated_extensions {
+        if let Some(agent) = askit.get_agent(agent_id) {
+            let value = Value::Object(extensions.clone().into_iter().collect());
+            agent.lock().await.update_spec(&value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::AgentValue;
+
+    fn agent(id: &str, def_name: &str) -> AgentSpec {
+        serde_json::from_value(serde_json::json!({ "id": id, "def_name": def_name })).unwrap()
+    }
+
+    fn channel(source: &str, target: &str) -> ChannelSpec {
+        ChannelSpec {
+            source: source.into(),
+            source_handle: "out".into(),
+            target: target.into(),
+            target_handle: "in".into(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut current = AgentStreamSpec::default();
+        current.add_agent(agent("a", "A"));
+        current.add_agent(agent("b", "B"));
+        current.add_agent(agent("c", "C"));
+        current.add_channel(channel("a", "b"));
+
+        let mut new = AgentStreamSpec::default();
+        let mut a = agent("a", "A");
+        a.configs = Some(
+            [("n".to_string(), AgentValue::integer(1))]
+                .into_iter()
+                .collect(),
+        );
+        new.add_agent(a);
+        new.add_agent(agent("b", "B2"));
+        new.add_agent(agent("d", "D"));
+        new.add_channel(channel("a", "d"));
+
+        let diff = current.diff(&new);
+        assert_eq!(diff.added_agents.len(), 1);
+        assert_eq!(diff.added_agents[0].id, "d");
+        assert_eq!(diff.removed_agents, vec!["c"]);
+        assert_eq!(diff.replaced_agents.len(), 1);
+        assert_eq!(diff.replaced_agents[0].id, "b");
+        assert_eq!(diff.updated_configs.len(), 1);
+        assert_eq!(diff.updated_configs[0].0, "a");
+        assert!(diff.updated_extensions.is_empty());
+        assert_eq!(diff.added_channels, vec![channel("a", "d")]);
+        assert_eq!(diff.removed_channels, vec![channel("a", "b")]);
+
+        assert!(new.diff(&new).is_empty());
+
+        // no configs keeps the current ones
+        let mut current = AgentStreamSpec::default();
+        current.add_agent(new.agents[0].clone());
+        let mut new = AgentStreamSpec::default();
+        new.add_agent(agent("a", "A"));
+        assert!(current.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn test_assign_new_ids() {
+        let mut current = AgentStreamSpec::default();
+        current.add_agent(agent("a", "A"));
+
+        let mut new = AgentStreamSpec::default();
+        new.add_agent(agent("a", "A"));
+        new.add_agent(agent("x", "X"));
+        new.add_channel(channel("a", "x"));
+
+        let new = assign_new_ids(&current, new);
+        assert_eq!(new.agents[0].id, "a");
+        assert_ne!(new.agents[1].id, "x");
+        assert_eq!(new.channels[0].target, new.agents[1].id);
+    }
+}
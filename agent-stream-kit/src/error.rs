@@ -17,6 +17,12 @@ pub enum AgentError {
     #[error("Invalid agent stream name: {0}")]
     InvalidStreamName(String),
 
+    #[error("Invalid agent stream spec: {0}")]
+    InvalidStreamSpec(String),
+
+    #[error("Invalid stream template: {0}")]
+    InvalidTemplate(String),
+
     #[error("Invalid {0} value")]
     InvalidValue(String),
 
@@ -26,6 +32,12 @@ pub enum AgentError {
     #[error("Failed to rename agent stream: {0}")]
     RenameStreamFailed(String),
 
+    #[error("Agent definition \"{0}\" is version {1}, but the spec was created with version {2}")]
+    IncompatibleVersion(String, String, String),
+
+    #[error("Invalid version: {0}")]
+    InvalidVersion(String),
+
     #[error("Unknown agent def kind: {0}")]
     UnknownDefKind(String),
 
@@ -50,6 +62,9 @@ pub enum AgentError {
     #[error("Duplicate id: {0}")]
     DuplicateId(String),
 
+    #[error("Tool {0} is already registered")]
+    DuplicateToolName(String),
+
     #[error("Source handle is empty")]
     EmptySourceHandle,
 
@@ -65,6 +80,9 @@ pub enum AgentError {
     #[error("Agent stream {0} not found")]
     StreamNotFound(String),
 
+    #[error("Stream template {0} not found")]
+    TemplateNotFound(String),
+
     #[error("Agent {0} definition not found")]
     AgentDefinitionNotFound(String),
 
@@ -86,8 +104,8 @@ pub enum AgentError {
     #[error("JSON parsing error: {0}")]
     JsonParseError(String),
 
-    #[error("Invalid file extension: expected JSON")]
-    InvalidFileExtension,
+    #[error("Invalid file extension: {0}")]
+    InvalidFileExtension(String),
 
     #[error("Empty file name")]
     EmptyFileName,
@@ -110,6 +128,18 @@ pub enum AgentError {
     #[error("Pin not found: {0}")]
     PinNotFound(String),
 
+    #[error("Incompatible pin types: {0} -> {1}")]
+    IncompatiblePinTypes(String, String),
+
+    #[error("ASKit is shutting down")]
+    ShuttingDown,
+
+    #[error("Board store error: {0}")]
+    BoardStoreError(String),
+
+    #[error("Invalid board pattern: {0}")]
+    InvalidBoardPattern(String),
+
     #[error("Agent error: {0}")]
     Other(String),
 }
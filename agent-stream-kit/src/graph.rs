@@ -0,0 +1,227 @@
+use std::fmt::Write;
+
+use crate::FnvIndexMap;
+use crate::askit::ASKit;
+use crate::board_agent::board_link;
+use crate::board_pattern::BoardPattern;
+use crate::spec::{AgentSpec, AgentStreamSpec};
+use crate::value::AgentValue;
+
+// longest config value shown in an agent label, in chars
+const MAX_CONFIG_VALUE_LEN: usize = 24;
+
+struct Node {
+    id: String,
+    lines: Vec<String>,
+}
+
+struct Edge {
+    source: usize,
+    target: usize,
+    label: String,
+
+    /// Data flowing through a board or variable, not a channel.
+    implicit: bool,
+}
+
+impl AgentStreamSpec {
+    /// Render the stream as a Graphviz DOT digraph.
+    ///
+    /// Agents are labelled with the title of their definition and their scalar configs,
+    /// and channels with their source and target handles. Board/Var agents writing
+    /// and reading the same name are linked with dashed edges, as are board writers
+    /// and readers whose pattern matches the written name.
+    pub fn to_dot(&self, askit: &ASKit) -> String {
+        let (nodes, edges) = self.graph(askit);
+        let mut dot = String::from("digraph {\n  rankdir=LR;\n  node [shape=box];\n");
+        for node in &nodes {
+            let label: Vec<String> = node.lines.iter().map(|l| dot_escape(l)).collect();
+            let _ = writeln!(
+                dot,
+                "  \"{}\" [label=\"{}\"];",
+                dot_escape(&node.id),
+                label.join("\\n")
+            );
+        }
+        for edge in &edges {
+            let style = if edge.implicit { ", style=dashed" } else { "" };
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                dot_escape(&nodes[edge.source].id),
+                dot_escape(&nodes[edge.target].id),
+                dot_escape(&edge.label),
+                style
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the stream as a Mermaid flowchart, in the same way as `to_dot`.
+    ///
+    /// Agents are named `a0`, `a1`, ... in the order of the spec, since their ids
+    /// may contain characters Mermaid does not accept.
+    pub fn to_mermaid(&self, askit: &ASKit) -> String {
+        let (nodes, edges) = self.graph(askit);
+        let mut mermaid = String::from("flowchart LR\n");
+        for (i, node) in nodes.iter().enumerate() {
+            let label: Vec<String> = node.lines.iter().map(|l| mermaid_escape(l)).collect();
+            let _ = writeln!(mermaid, "  a{}[\"{}\"]", i, label.join("<br/>"));
+        }
+        for edge in &edges {
+            let arrow = if edge.implicit { "-.->" } else { "-->" };
+            let _ = writeln!(
+                mermaid,
+                "  a{} {}|\"{}\"| a{}",
+                edge.source,
+                arrow,
+                mermaid_escape(&edge.label),
+                edge.target
+            );
+        }
+        mermaid
+    }
+
+    fn graph(&self, askit: &ASKit) -> (Vec<Node>, Vec<Edge>) {
+        let mut indices = FnvIndexMap::default();
+        let nodes: Vec<Node> = self
+            .agents
+            .iter()
+            .enumerate()
+            .map(|(i, agent)| {
+                indices.entry(agent.id.as_str()).or_insert(i);
+                Node {
+                    id: agent.id.clone(),
+                    lines: agent_lines(askit, agent),
+                }
+            })
+            .collect();
+
+        // channels between agents missing from the spec are left out
+        let mut edges: Vec<Edge> = self
+            .channels
+            .iter()
+            .filter_map(|c| {
+                Some(Edge {
+                    source: *indices.get(c.source.as_str())?,
+                    target: *indices.get(c.target.as_str())?,
+                    label: format!("{} -> {}", c.source_handle, c.target_handle),
+                    implicit: false,
+                })
+            })
+            .collect();
+
+        let links: Vec<_> = self.agents.iter().map(board_link).collect();
+        for (source, writer) in links.iter().enumerate() {
+            let Some(writer) = writer.as_ref().filter(|l| l.writes) else {
+                continue;
+            };
+            for (target, reader) in links.iter().enumerate() {
+                let Some(reader) = reader.as_ref().filter(|l| !l.writes) else {
+                    continue;
+                };
+                let linked = if reader.var || writer.var {
+                    reader.var == writer.var && reader.name == writer.name
+                } else if reader.pattern {
+                    BoardPattern::parse(&reader.name).is_ok_and(|p| p.matches(&writer.name))
+                } else {
+                    reader.name == writer.name
+                };
+                if linked {
+                    edges.push(Edge {
+                        source,
+                        target,
+                        label: writer.name.clone(),
+                        implicit: true,
+                    });
+                }
+            }
+        }
+
+        (nodes, edges)
+    }
+}
+
+// the title of the definition, followed by the configs worth showing
+fn agent_lines(askit: &ASKit, agent: &AgentSpec) -> Vec<String> {
+    let def = askit.get_agent_definition(&agent.def_name);
+    let title = def
+        .as_ref()
+        .and_then(|def| def.title.clone())
+        .unwrap_or_else(|| {
+            let name = agent.def_name.rsplit("::").next().unwrap_or_default();
+            name.to_string()
+        });
+    let mut lines = vec![title];
+
+    let Some(configs) = &agent.configs else {
+        return lines;
+    };
+    let config_specs = def.and_then(|def| def.configs);
+    for (key, value) in configs {
+        let hidden = config_specs
+            .as_ref()
+            .and_then(|specs| specs.get(key))
+            .is_some_and(|spec| spec.hidden);
+        if hidden {
+            continue;
+        }
+        if let Some(value) = config_label(value) {
+            lines.push(format!("{}: {}", key, value));
+        }
+    }
+    lines
+}
+
+// scalar values only, with long or multi-line strings shortened
+fn config_label(value: &AgentValue) -> Option<String> {
+    match value {
+        AgentValue::Boolean(b) => Some(b.to_string()),
+        AgentValue::Integer(i) => Some(i.to_string()),
+        AgentValue::Number(n) => Some(n.to_string()),
+        AgentValue::String(s) if !s.is_empty() => {
+            let line = s.lines().next().unwrap_or_default();
+            let mut label: String = line.chars().take(MAX_CONFIG_VALUE_LEN).collect();
+            if label.len() < s.len() {
+                label.push('…');
+            }
+            Some(label)
+        }
+        _ => None,
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_label() {
+        assert_eq!(config_label(&AgentValue::integer(3)).as_deref(), Some("3"));
+        assert_eq!(
+            config_label(&AgentValue::string("first\nsecond")).as_deref(),
+            Some("first…")
+        );
+        assert_eq!(
+            config_label(&AgentValue::string("x".repeat(30))),
+            Some(format!("{}…", "x".repeat(MAX_CONFIG_VALUE_LEN)))
+        );
+        assert_eq!(config_label(&AgentValue::string("")), None);
+        assert_eq!(config_label(&AgentValue::array_default()), None);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(dot_escape(r#"say "hi" \o/"#), r#"say \"hi\" \\o/"#);
+        assert_eq!(mermaid_escape(r#"say "hi""#), "say #quot;hi#quot;");
+    }
+}
@@ -8,24 +8,46 @@
 mod agent;
 mod askit;
 mod board_agent;
+mod board_history;
+mod board_pattern;
+mod board_store;
 mod config;
 mod context;
 mod definition;
+mod diff;
 mod error;
+mod graph;
 mod id;
 mod llm;
+mod mailbox;
 mod message;
+mod metrics;
+mod migration;
 mod output;
+mod pin;
+mod recorder;
 mod registry;
+mod replay;
 mod runtime;
+mod shutdown;
 mod spec;
 mod stream;
+mod stream_dir;
+mod subflow;
+mod supervisor;
+mod template;
 pub mod tool;
+pub mod trace;
+mod validation;
 mod value;
+mod var_scope;
 
 #[cfg(feature = "mcp")]
 pub mod mcp;
 
+#[cfg(feature = "server")]
+pub mod server;
+
 #[cfg(feature = "test-utils")]
 pub mod test_utils;
 
@@ -36,6 +58,10 @@ pub use async_trait::async_trait;
 #[cfg(feature = "image")]
 pub use photon_rs::{self, PhotonImage};
 
+// re-export axum for composing the server router
+#[cfg(feature = "server")]
+pub use axum;
+
 // Re-export the crate under its canonical name for proc-macros.
 pub extern crate self as agent_stream_kit;
 pub use inventory;
@@ -46,18 +72,45 @@ pub use indexmap;
 pub type FnvIndexMap<K, V> = indexmap::IndexMap<K, V, fnv::FnvBuildHasher>;
 pub type FnvIndexSet<T> = indexmap::IndexSet<T, fnv::FnvBuildHasher>;
 
+// re-export semver for agent versions
+pub use semver;
+
 // Re-exports askit_macros
 pub use askit_macros::askit_agent;
 
 pub use agent::{Agent, AgentData, AgentStatus, AsAgent, HasAgentData, agent_new, new_agent_boxed};
 pub use askit::{ASKit, ASKitEvent};
+pub use board_history::{BoardHistoryEntry, BoardRetention};
+pub use board_pattern::{BOARD_NAME_VAR, BoardPattern};
+#[cfg(feature = "sqlite")]
+pub use board_store::SqliteBoardStore;
+pub use board_store::{BoardStore, JsonFileBoardStore, MemoryBoardStore};
 pub use config::{AgentConfigs, AgentConfigsMap};
 pub use context::AgentContext;
-pub use definition::{AgentConfigSpec, AgentConfigSpecs, AgentDefinition, AgentDefinitions};
+pub use definition::{
+    AgentConfigSpec, AgentConfigSpecs, AgentDefinition, AgentDefinitions, AgentUpgradeFn,
+};
+pub use diff::StreamSpecDiff;
 pub use error::AgentError;
 pub use llm::{Message, ToolCall, ToolCallFunction};
+pub use mailbox::DeliveryPolicy;
+pub use metrics::{
+    AgentMetrics, Histogram, LATENCY_BUCKETS, MetricsSnapshot, StreamMetrics, ToolMetrics,
+};
+pub use migration::{AgentMigrations, STREAM_SPEC_VERSION};
 pub use output::AgentOutput;
+pub use pin::{PinSpec, PinSpecs, PinType};
+pub use recorder::RecordedEvent;
 pub use registry::AgentRegistration;
+pub use replay::{ReplayMismatch, ReplayReport, Replayer};
+pub use shutdown::ShutdownReport;
 pub use spec::{AgentSpec, AgentStreamSpec, AgentStreamSpecs, ChannelSpec};
 pub use stream::{AgentStream, AgentStreamInfo, AgentStreams};
+pub use stream_dir::StreamDirectory;
+#[cfg(feature = "watch")]
+pub use stream_dir::StreamDirectoryWatcher;
+pub use supervisor::{RestartPolicy, SupervisorSpec};
+pub use template::{TEMPLATE_PARAMETERS_KEY, TemplateParam, TemplateParams};
+pub use validation::{DiagnosticLevel, ValidationDiagnostic};
 pub use value::{AgentValue, AgentValueMap};
+pub use var_scope::{GLOBAL_VAR_PREFIX, STREAM_VAR_PREFIX, VarScope};
@@ -0,0 +1,539 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::agent::AgentMessage;
+use crate::error::AgentError;
+
+/// Default capacity of an agent input queue.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// Default number of inputs a paused agent with a `Block` queue holds.
+pub const DEFAULT_PAUSE_CAPACITY: usize = 8 * DEFAULT_QUEUE_CAPACITY;
+
+/// How an agent input queue behaves when it is full.
+///
+/// Only `AgentMessage::Input` messages are subject to the policy.
+/// Control messages (configs, stop, ...) are always queued.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryPolicy {
+    /// Wait until there is room in the queue.
+    /// While the agent is paused, inputs are queued beyond the capacity instead, up to the
+    /// pause capacity, so that senders do not wait on an agent that is not handling its inputs.
+    /// Inputs past the pause capacity are dropped. Once the agent is resumed, senders wait
+    /// until its queue is below the capacity again.
+    #[default]
+    Block,
+
+    /// Drop the oldest queued input to make room for the new one.
+    DropOldest,
+
+    /// Drop the new input.
+    DropNewest,
+
+    /// Replace a queued input for the same pin with the new one.
+    /// When there is no such input and the queue is full, the oldest input is dropped.
+    CoalesceLatest,
+}
+
+struct MailboxInner {
+    queue: Mutex<MessageQueue>,
+    capacity: usize,
+    // inputs held by a paused Block queue
+    pause_capacity: usize,
+    policy: DeliveryPolicy,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+    // true while the agent is paused
+    paused: AtomicBool,
+    // true while the receiver is handling the last received message
+    busy: AtomicBool,
+    // notified when a message is pushed or the mailbox is closed
+    recv_ready: Notify,
+    // notified when an input is popped or the mailbox is closed
+    send_ready: Notify,
+}
+
+impl MailboxInner {
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.recv_ready.notify_one();
+        self.send_ready.notify_waiters();
+    }
+}
+
+// queued messages, with the number of inputs among them
+#[derive(Default)]
+struct MessageQueue {
+    messages: VecDeque<AgentMessage>,
+    inputs: usize,
+}
+
+impl MessageQueue {
+    fn is_input(message: &AgentMessage) -> bool {
+        matches!(message, AgentMessage::Input { .. })
+    }
+
+    fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn push_back(&mut self, message: AgentMessage) {
+        if Self::is_input(&message) {
+            self.inputs += 1;
+        }
+        self.messages.push_back(message);
+    }
+
+    fn pop_front(&mut self) -> Option<AgentMessage> {
+        self.remove(0)
+    }
+
+    fn remove(&mut self, index: usize) -> Option<AgentMessage> {
+        let message = self.messages.remove(index)?;
+        if Self::is_input(&message) {
+            self.inputs -= 1;
+        }
+        Some(message)
+    }
+
+    fn remove_first_input<F>(&mut self, f: F) -> Option<AgentMessage>
+    where
+        F: Fn(&String) -> bool,
+    {
+        let index = self
+            .messages
+            .iter()
+            .position(|m| matches!(m, AgentMessage::Input { pin, .. } if f(pin)))?;
+        self.remove(index)
+    }
+}
+
+/// Create a new mailbox with the given capacities and delivery policy.
+///
+/// The pause capacity is never below the capacity.
+pub(crate) fn mailbox(
+    capacity: usize,
+    pause_capacity: usize,
+    policy: DeliveryPolicy,
+) -> (MailboxSender, MailboxReceiver) {
+    let capacity = capacity.max(1);
+    let inner = Arc::new(MailboxInner {
+        queue: Mutex::new(MessageQueue::default()),
+        capacity,
+        pause_capacity: pause_capacity.max(capacity),
+        policy,
+        senders: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+        paused: AtomicBool::new(false),
+        busy: AtomicBool::new(false),
+        recv_ready: Notify::new(),
+        send_ready: Notify::new(),
+    });
+    (
+        MailboxSender {
+            inner: inner.clone(),
+        },
+        MailboxReceiver { inner },
+    )
+}
+
+/// Sending half of an agent mailbox.
+pub(crate) struct MailboxSender {
+    inner: Arc<MailboxInner>,
+}
+
+impl Clone for MailboxSender {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl Drop for MailboxSender {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.close();
+        }
+    }
+}
+
+impl MailboxSender {
+    /// Whether this sender belongs to the same mailbox as the receiver.
+    pub(crate) fn is_connected_to(&self, rx: &MailboxReceiver) -> bool {
+        Arc::ptr_eq(&self.inner, &rx.inner)
+    }
+
+    /// Number of queued inputs.
+    pub(crate) fn input_len(&self) -> usize {
+        self.inner.queue.lock().unwrap().inputs
+    }
+
+    /// Whether the receiver is not handling a message and has nothing to receive.
+    ///
+    /// The inputs held while the agent is paused are not received, so they do not count.
+    pub(crate) fn is_settled(&self) -> bool {
+        if self.inner.busy.load(Ordering::Acquire) {
+            return false;
+        }
+        let queue = self.inner.queue.lock().unwrap();
+        if self.inner.paused.load(Ordering::Acquire) {
+            queue.inputs == queue.len()
+        } else {
+            queue.is_empty()
+        }
+    }
+
+    /// Send a message.
+    pub(crate) async fn send(&self, message: AgentMessage) -> Result<Sent, AgentError> {
+        let mut message = message;
+        loop {
+            let notified = self.inner.send_ready.notified();
+            match self.push(message)? {
+                Push::Done(sent) => return Ok(sent),
+                Push::Full(m) => message = m,
+            }
+            notified.await;
+        }
+    }
+
+    /// Send a message without waiting.
+    ///
+    /// Fails when the policy is `Block` and the queue of the running agent is full.
+    pub(crate) fn try_send(&self, message: AgentMessage) -> Result<Sent, AgentError> {
+        match self.push(message)? {
+            Push::Done(sent) => Ok(sent),
+            Push::Full(_) => Err(AgentError::SendMessageFailed(
+                "Agent input queue is full".to_string(),
+            )),
+        }
+    }
+
+    fn push(&self, message: AgentMessage) -> Result<Push, AgentError> {
+        if self.inner.closed.load(Ordering::Acquire) {
+            return Err(AgentError::SendMessageFailed(
+                "Agent mailbox is closed".to_string(),
+            ));
+        }
+
+        let mut queue = self.inner.queue.lock().unwrap();
+
+        let AgentMessage::Input { pin, .. } = &message else {
+            queue.push_back(message);
+            drop(queue);
+            self.inner.recv_ready.notify_one();
+            return Ok(Push::Done(Sent::Queued(None)));
+        };
+
+        let mut dropped = None;
+        match self.inner.policy {
+            DeliveryPolicy::Block => {
+                let count = queue.inputs;
+                if self.inner.paused.load(Ordering::Acquire) {
+                    if count >= self.inner.pause_capacity {
+                        return Ok(Push::Done(Sent::Dropped(message)));
+                    }
+                } else if count >= self.inner.capacity {
+                    return Ok(Push::Full(message));
+                }
+            }
+            DeliveryPolicy::DropNewest => {
+                if queue.inputs >= self.inner.capacity {
+                    return Ok(Push::Done(Sent::Dropped(message)));
+                }
+            }
+            DeliveryPolicy::DropOldest => {
+                if queue.inputs >= self.inner.capacity {
+                    dropped = queue.remove_first_input(|_| true);
+                }
+            }
+            DeliveryPolicy::CoalesceLatest => {
+                let pin = pin.clone();
+                dropped = queue.remove_first_input(|p| *p == pin);
+                if dropped.is_none() && queue.inputs >= self.inner.capacity {
+                    dropped = queue.remove_first_input(|_| true);
+                }
+            }
+        }
+        queue.push_back(message);
+        drop(queue);
+        self.inner.recv_ready.notify_one();
+        Ok(Push::Done(Sent::Queued(dropped)))
+    }
+}
+
+/// The outcome of sending a message.
+pub(crate) enum Sent {
+    /// The message was queued, with the input dropped by the delivery policy to make room for it, if any.
+    Queued(Option<AgentMessage>),
+
+    /// The message was dropped by the delivery policy.
+    Dropped(AgentMessage),
+}
+
+impl Sent {
+    /// The input dropped by the delivery policy, if any.
+    pub(crate) fn dropped(self) -> Option<AgentMessage> {
+        match self {
+            Sent::Queued(dropped) => dropped,
+            Sent::Dropped(message) => Some(message),
+        }
+    }
+}
+
+enum Push {
+    Done(Sent),
+    Full(AgentMessage),
+}
+
+/// Receiving half of an agent mailbox.
+pub(crate) struct MailboxReceiver {
+    inner: Arc<MailboxInner>,
+}
+
+impl MailboxReceiver {
+    /// Receive the next message.
+    ///
+    /// Returns `None` when the mailbox is closed and empty.
+    pub(crate) async fn recv(&mut self) -> Option<AgentMessage> {
+        self.inner.busy.store(false, Ordering::Release);
+        loop {
+            let notified = self.inner.recv_ready.notified();
+            {
+                let mut queue = self.inner.queue.lock().unwrap();
+                if let Some(message) = queue.pop_front() {
+                    self.inner.busy.store(true, Ordering::Release);
+                    drop(queue);
+                    self.inner.send_ready.notify_one();
+                    return Some(message);
+                }
+                if self.inner.closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Receive the next control message, leaving queued inputs in place.
+    ///
+    /// Returns `None` when the mailbox is closed and has no control messages.
+    pub(crate) async fn recv_control(&mut self) -> Option<AgentMessage> {
+        self.inner.busy.store(false, Ordering::Release);
+        loop {
+            let notified = self.inner.recv_ready.notified();
+            {
+                let mut queue = self.inner.queue.lock().unwrap();
+                let control = if queue.inputs < queue.len() {
+                    queue
+                        .messages
+                        .iter()
+                        .position(|m| !MessageQueue::is_input(m))
+                } else {
+                    None
+                };
+                if let Some(index) = control {
+                    self.inner.busy.store(true, Ordering::Release);
+                    return queue.remove(index);
+                }
+                if self.inner.closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Mark the agent as paused or resumed.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.inner.paused.store(paused, Ordering::Release);
+        if paused {
+            // senders waiting for room queue their inputs instead
+            self.inner.send_ready.notify_waiters();
+        }
+    }
+
+    /// Close the mailbox. Queued messages can still be received.
+    pub(crate) fn close(&mut self) {
+        self.inner.busy.store(false, Ordering::Release);
+        self.inner.close();
+    }
+}
+
+impl Drop for MailboxReceiver {
+    fn drop(&mut self) {
+        self.inner.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::AgentContext;
+    use crate::value::AgentValue;
+
+    fn input(pin: &str, n: i64) -> AgentMessage {
+        AgentMessage::Input {
+            ctx: AgentContext::new(),
+            pin: pin.to_string(),
+            value: AgentValue::integer(n),
+        }
+    }
+
+    fn input_value(message: Option<AgentMessage>) -> Option<(String, i64)> {
+        match message {
+            Some(AgentMessage::Input { pin, value, .. }) => Some((pin, value.as_i64().unwrap())),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_room() {
+        let (tx, mut rx) = mailbox(1, 1, DeliveryPolicy::Block);
+        tx.send(input("in", 1)).await.unwrap();
+        assert!(tx.try_send(input("in", 2)).is_err());
+
+        let tx2 = tx.clone();
+        let handle = tokio::spawn(async move { tx2.send(input("in", 2)).await });
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 1)));
+        assert!(handle.await.unwrap().unwrap().dropped().is_none());
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 2)));
+    }
+
+    #[tokio::test]
+    async fn block_holds_while_paused() {
+        let (tx, mut rx) = mailbox(1, 3, DeliveryPolicy::Block);
+        tx.send(input("in", 1)).await.unwrap();
+
+        let tx2 = tx.clone();
+        let handle = tokio::spawn(async move { tx2.send(input("in", 2)).await });
+        tokio::task::yield_now().await;
+        rx.set_paused(true);
+        assert!(handle.await.unwrap().unwrap().dropped().is_none());
+        assert!(tx.try_send(input("in", 3)).unwrap().dropped().is_none());
+
+        // past the pause capacity
+        let sent = tx.try_send(input("in", 4)).unwrap();
+        assert!(matches!(sent, Sent::Dropped(_)));
+        assert_eq!(input_value(sent.dropped()), Some(("in".into(), 4)));
+
+        rx.set_paused(false);
+        assert!(tx.try_send(input("in", 5)).is_err());
+        for n in 1..=3 {
+            assert_eq!(input_value(rx.recv().await), Some(("in".into(), n)));
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_and_newest() {
+        let (tx, mut rx) = mailbox(2, 2, DeliveryPolicy::DropOldest);
+        for n in 1..=3 {
+            tx.send(input("in", n)).await.unwrap();
+        }
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 2)));
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 3)));
+
+        let (tx, mut rx) = mailbox(2, 2, DeliveryPolicy::DropNewest);
+        tx.send(input("in", 1)).await.unwrap();
+        tx.send(input("in", 2)).await.unwrap();
+        let sent = tx.send(input("in", 3)).await.unwrap();
+        assert!(matches!(sent, Sent::Dropped(_)));
+        assert_eq!(input_value(sent.dropped()), Some(("in".into(), 3)));
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 1)));
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 2)));
+    }
+
+    #[tokio::test]
+    async fn coalesce_latest_per_pin() {
+        let (tx, mut rx) = mailbox(8, 8, DeliveryPolicy::CoalesceLatest);
+        tx.send(input("a", 1)).await.unwrap();
+        tx.send(input("b", 1)).await.unwrap();
+        let dropped = tx.send(input("a", 2)).await.unwrap().dropped();
+        assert_eq!(input_value(dropped), Some(("a".into(), 1)));
+        assert_eq!(input_value(rx.recv().await), Some(("b".into(), 1)));
+        assert_eq!(input_value(rx.recv().await), Some(("a".into(), 2)));
+    }
+
+    #[tokio::test]
+    async fn control_messages_bypass_capacity() {
+        let (tx, mut rx) = mailbox(1, 1, DeliveryPolicy::Block);
+        tx.send(input("in", 1)).await.unwrap();
+        assert!(tx.try_send(AgentMessage::Stop).unwrap().dropped().is_none());
+        assert!(input_value(rx.recv().await).is_some());
+        assert!(matches!(rx.recv().await, Some(AgentMessage::Stop)));
+
+        drop(tx);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn settled_after_handling() {
+        let (tx, mut rx) = mailbox(8, 8, DeliveryPolicy::Block);
+        assert!(tx.is_settled());
+        tx.send(input("in", 1)).await.unwrap();
+        assert!(!tx.is_settled());
+        assert_eq!(tx.input_len(), 1);
+        rx.recv().await.unwrap();
+        // still handling the received message
+        assert!(!tx.is_settled());
+        assert_eq!(tx.input_len(), 0);
+        rx.close();
+        assert!(tx.is_settled());
+    }
+
+    #[tokio::test]
+    async fn settled_while_paused_with_inputs() {
+        let (tx, rx) = mailbox(8, 8, DeliveryPolicy::Block);
+        rx.set_paused(true);
+        tx.send(input("in", 1)).await.unwrap();
+        assert!(tx.is_settled());
+        assert_eq!(tx.input_len(), 1);
+
+        // control messages are still received while paused
+        tx.send(AgentMessage::Resume).await.unwrap();
+        assert!(!tx.is_settled());
+    }
+
+    #[tokio::test]
+    async fn recv_control_holds_inputs() {
+        let (tx, mut rx) = mailbox(8, 8, DeliveryPolicy::Block);
+        tx.send(input("in", 1)).await.unwrap();
+        tx.send(AgentMessage::Resume).await.unwrap();
+        tx.send(input("in", 2)).await.unwrap();
+        assert!(matches!(
+            rx.recv_control().await,
+            Some(AgentMessage::Resume)
+        ));
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 1)));
+        assert_eq!(input_value(rx.recv().await), Some(("in".into(), 2)));
+    }
+
+    #[tokio::test]
+    async fn input_len_follows_queue() {
+        let (tx, mut rx) = mailbox(2, 2, DeliveryPolicy::CoalesceLatest);
+        tx.send(input("a", 1)).await.unwrap();
+        tx.send(AgentMessage::Resume).await.unwrap();
+        tx.send(input("a", 2)).await.unwrap();
+        tx.send(input("b", 1)).await.unwrap();
+        tx.send(input("c", 1)).await.unwrap();
+        assert_eq!(tx.input_len(), 2);
+
+        assert!(matches!(
+            rx.recv_control().await,
+            Some(AgentMessage::Resume)
+        ));
+        assert_eq!(tx.input_len(), 2);
+        assert_eq!(input_value(rx.recv().await), Some(("b".into(), 1)));
+        assert_eq!(tx.input_len(), 1);
+    }
+}
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, OnceLock};
 
-use agent_stream_kit::{AgentContext, AgentError, AgentValue, async_trait};
+use agent_stream_kit::{ASKit, AgentContext, AgentError, AgentValue, async_trait};
 use rmcp::{
     model::{CallToolRequestParam, CallToolResult},
     service::ServiceExt,
@@ -18,6 +18,7 @@ use crate::tool::{Tool, ToolInfo, register_tool};
 
 /// MCP Tool with connection pool support
 struct MCPTool {
+    pool: SharedConnectionPool,
     server_name: String,
     server_config: MCPServerConfig,
     tool: rmcp::model::Tool,
@@ -26,6 +27,7 @@ struct MCPTool {
 
 impl MCPTool {
     fn new(
+        pool: SharedConnectionPool,
         name: String,
         server_name: String,
         server_config: MCPServerConfig,
@@ -37,6 +39,7 @@ impl MCPTool {
             parameters: serde_json::to_value(&tool.input_schema).ok(),
         };
         Self {
+            pool,
             server_name,
             server_config,
             tool,
@@ -51,7 +54,7 @@ impl MCPTool {
     ) -> Result<AgentValue, AgentError> {
         // Get or create connection from pool
         let conn = {
-            let mut pool = connection_pool().lock().await;
+            let mut pool = self.pool.lock().await;
             pool.get_or_create(&self.server_name, &self.server_config)
                 .await?
         };
@@ -125,12 +128,14 @@ struct MCPConnection {
 }
 
 /// Connection pool for MCP servers
-struct MCPConnectionPool {
+pub(crate) struct MCPConnectionPool {
     connections: HashMap<String, Arc<AsyncMutex<MCPConnection>>>,
 }
 
+pub(crate) type SharedConnectionPool = Arc<AsyncMutex<MCPConnectionPool>>;
+
 impl MCPConnectionPool {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             connections: HashMap::new(),
         }
@@ -214,14 +219,18 @@ impl MCPConnectionPool {
     }
 }
 
-// Global connection pool
-static CONNECTION_POOL: OnceLock<AsyncMutex<MCPConnectionPool>> = OnceLock::new();
+// Global connection pool, for the tools in the global registry
+static CONNECTION_POOL: OnceLock<SharedConnectionPool> = OnceLock::new();
 
-fn connection_pool() -> &'static AsyncMutex<MCPConnectionPool> {
-    CONNECTION_POOL.get_or_init(|| AsyncMutex::new(MCPConnectionPool::new()))
+fn connection_pool() -> &'static SharedConnectionPool {
+    CONNECTION_POOL.get_or_init(|| Arc::new(AsyncMutex::new(MCPConnectionPool::new())))
 }
 
-/// Shuts down all MCP server connections in the pool
+/// Shuts down all MCP server connections in the global pool
+///
+/// These are the connections of the tools registered with [`register_tools_from_mcp_json`].
+/// The connections of the tools registered into an ASKit instance are shut down by
+/// `ASKit::shutdown` of that instance.
 pub async fn shutdown_all_mcp_connections() -> Result<(), AgentError> {
     log::info!("Shutting down all MCP server connections");
     connection_pool().lock().await.shutdown_all().await?;
@@ -229,23 +238,35 @@ pub async fn shutdown_all_mcp_connections() -> Result<(), AgentError> {
     Ok(())
 }
 
+/// Shuts down the MCP server connections of the ASKit instance
+pub(crate) async fn shutdown_mcp_connections(askit: &ASKit) -> Result<(), AgentError> {
+    log::info!("Shutting down MCP server connections of the ASKit instance");
+    askit.mcp_connections.lock().await.shutdown_all().await
+}
+
 /// Registers tools from a single MCP server
 ///
 /// # Arguments
+/// * `askit` - ASKit instance owning the tools, or `None` for the global registry
 /// * `server_name` - Name of the MCP server
 /// * `server_config` - Configuration for the MCP server
 ///
 /// # Returns
 /// A vector of registered tool names in the format "server_name::tool_name"
 async fn register_tools_from_server(
+    askit: Option<&ASKit>,
     server_name: String,
     server_config: MCPServerConfig,
 ) -> Result<Vec<String>, AgentError> {
     log::debug!("Registering tools from MCP server '{}'", server_name);
 
-    // Get or create connection from pool
+    // Get or create connection from the pool of the owner
+    let pool = match askit {
+        Some(askit) => askit.mcp_connections.clone(),
+        None => connection_pool().clone(),
+    };
     let conn = {
-        let mut pool = connection_pool().lock().await;
+        let mut pool = pool.lock().await;
         pool.get_or_create(&server_name, &server_config).await?
     };
 
@@ -276,12 +297,17 @@ async fn register_tools_from_server(
         let mcp_tool_name = format!("{}::{}", server_name, tool_info.name);
         registered_tool_names.push(mcp_tool_name.clone());
 
-        register_tool(MCPTool::new(
+        let tool = MCPTool::new(
+            pool.clone(),
             mcp_tool_name.clone(),
             server_name.clone(),
             server_config.clone(),
             tool_info,
-        ));
+        );
+        match askit {
+            Some(askit) => askit.register_tool(tool),
+            None => register_tool(tool),
+        }
         log::debug!("Registered MCP tool '{}'", mcp_tool_name);
     }
 
@@ -316,7 +342,28 @@ async fn register_tools_from_server(
 pub async fn register_tools_from_mcp_json<P: AsRef<Path>>(
     json_path: P,
 ) -> Result<Vec<String>, AgentError> {
-    let path = json_path.as_ref();
+    register_tools_from_mcp_json_impl(None, json_path.as_ref()).await
+}
+
+/// Loads MCP configuration from a JSON file and registers all tools into the ASKit instance
+///
+/// Unlike [`register_tools_from_mcp_json`], the tools are owned by `askit` and are not visible
+/// from other ASKit instances. Their MCP servers are started for `askit` alone, and are shut down
+/// by `ASKit::shutdown`.
+///
+/// # Returns
+/// A vector of registered tool names in the format "server_name::tool_name"
+pub async fn register_tools_from_mcp_json_into<P: AsRef<Path>>(
+    askit: &ASKit,
+    json_path: P,
+) -> Result<Vec<String>, AgentError> {
+    register_tools_from_mcp_json_impl(Some(askit), json_path.as_ref()).await
+}
+
+async fn register_tools_from_mcp_json_impl(
+    askit: Option<&ASKit>,
+    path: &Path,
+) -> Result<Vec<String>, AgentError> {
     log::info!("Loading MCP configuration from: {}", path.display());
 
     // Read the JSON file
@@ -337,7 +384,7 @@ pub async fn register_tools_from_mcp_json<P: AsRef<Path>>(
 
     // Iterate through each MCP server
     for (server_name, server_config) in config.mcp_servers {
-        let tools = register_tools_from_server(server_name, server_config).await?;
+        let tools = register_tools_from_server(askit, server_name, server_config).await?;
         registered_tool_names.extend(tools);
     }
 
@@ -1,4 +1,7 @@
+use std::sync::atomic::Ordering;
+
 use crate::askit::ASKit;
+use crate::board_pattern::BOARD_NAME_VAR;
 use crate::context::AgentContext;
 use crate::error::AgentError;
 use crate::value::AgentValue;
@@ -15,6 +18,8 @@ pub enum AgentEventMessage {
         name: String,
         ctx: AgentContext,
         value: AgentValue,
+        // written from outside of the agents
+        external: bool,
     },
 }
 
@@ -25,16 +30,19 @@ pub async fn send_agent_out(
     pin: String,
     value: AgentValue,
 ) -> Result<(), AgentError> {
-    askit
-        .tx()?
-        .send(AgentEventMessage::AgentOut {
-            agent,
-            ctx,
-            pin,
-            value,
-        })
-        .await
-        .map_err(|_| AgentError::SendMessageFailed("Failed to send AgentOut message".to_string()))
+    let tx = askit.tx()?;
+    askit.in_flight.fetch_add(1, Ordering::AcqRel);
+    tx.send(AgentEventMessage::AgentOut {
+        agent,
+        ctx,
+        pin,
+        value,
+    })
+    .await
+    .map_err(|_| {
+        askit.in_flight.fetch_sub(1, Ordering::AcqRel);
+        AgentError::SendMessageFailed("Failed to send AgentOut message".to_string())
+    })
 }
 
 pub fn try_send_agent_out(
@@ -44,17 +52,18 @@ pub fn try_send_agent_out(
     pin: String,
     value: AgentValue,
 ) -> Result<(), AgentError> {
-    askit
-        .tx()?
-        .try_send(AgentEventMessage::AgentOut {
-            agent,
-            ctx,
-            pin,
-            value,
-        })
-        .map_err(|_| {
-            AgentError::SendMessageFailed("Failed to try_send AgentOut message".to_string())
-        })
+    let tx = askit.tx()?;
+    askit.in_flight.fetch_add(1, Ordering::AcqRel);
+    tx.try_send(AgentEventMessage::AgentOut {
+        agent,
+        ctx,
+        pin,
+        value,
+    })
+    .map_err(|_| {
+        askit.in_flight.fetch_sub(1, Ordering::AcqRel);
+        AgentError::SendMessageFailed("Failed to try_send AgentOut message".to_string())
+    })
 }
 
 pub async fn send_board_out(
@@ -62,14 +71,21 @@ pub async fn send_board_out(
     name: String,
     ctx: AgentContext,
     value: AgentValue,
+    external: bool,
 ) -> Result<(), AgentError> {
-    askit
-        .tx()?
-        .send(AgentEventMessage::BoardOut { name, ctx, value })
-        .await
-        .map_err(|_| {
-            AgentError::SendMessageFailed("Failed to try_send BoardOut message".to_string())
-        })
+    let tx = askit.tx()?;
+    askit.in_flight.fetch_add(1, Ordering::AcqRel);
+    tx.send(AgentEventMessage::BoardOut {
+        name,
+        ctx,
+        value,
+        external,
+    })
+    .await
+    .map_err(|_| {
+        askit.in_flight.fetch_sub(1, Ordering::AcqRel);
+        AgentError::SendMessageFailed("Failed to try_send BoardOut message".to_string())
+    })
 }
 
 // Processing AgentOut message
@@ -80,6 +96,8 @@ pub async fn agent_out(
     pin: String,
     value: AgentValue,
 ) {
+    askit.count_agent_output(&source_agent, &pin);
+
     let targets;
     {
         let env_edges = askit.channels.lock().unwrap();
@@ -114,11 +132,36 @@ pub async fn agent_out(
     }
 }
 
+// send the value along the channels of a Board-> agent
+async fn board_out_node(askit: &ASKit, node: &str, ctx: &AgentContext, value: &AgentValue) {
+    let edges;
+    {
+        let env_edges = askit.channels.lock().unwrap();
+        edges = env_edges.get(node).cloned();
+    }
+    let Some(edges) = edges else {
+        // edges not found
+        return;
+    };
+    for (target_agent, _source_pin, target_pin) in edges {
+        askit
+            .agent_input(target_agent.clone(), ctx.clone(), target_pin, value.clone())
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to send message to {}: {}", target_agent, e);
+            });
+    }
+}
+
 pub async fn board_out(askit: &ASKit, name: String, ctx: AgentContext, value: AgentValue) {
+    askit.count_board_write(&name);
+    // before the value, so that the value is not taken for the expired one
+    askit.record_board_history(&name, &value);
     {
         let mut board_value = askit.board_value.lock().unwrap();
         board_value.insert(name.clone(), value.clone());
     }
+    askit.persist_board(&name, &value);
     let board_nodes;
     {
         let env_board_nodes = askit.board_out_agents.lock().unwrap();
@@ -126,25 +169,20 @@ pub async fn board_out(askit: &ASKit, name: String, ctx: AgentContext, value: Ag
     }
     if let Some(board_nodes) = board_nodes {
         for node in board_nodes {
-            // Perhaps we could process this by send_message_to BoardOutAgent
-
-            let edges;
-            {
-                let env_edges = askit.channels.lock().unwrap();
-                edges = env_edges.get(&node).cloned();
-            }
-            let Some(edges) = edges else {
-                // edges not found
+            if askit.is_var_shadowed(&node, &name) {
                 continue;
-            };
-            for (target_agent, _source_pin, target_pin) in edges {
-                askit
-                    .agent_input(target_agent.clone(), ctx.clone(), target_pin, value.clone())
-                    .await
-                    .unwrap_or_else(|e| {
-                        log::error!("Failed to send message to {}: {}", target_agent, e);
-                    });
             }
+            // Perhaps we could process this by send_message_to BoardOutAgent
+            board_out_node(askit, &node, &ctx, &value).await;
+        }
+    }
+
+    // agents subscribed with a pattern are told which board was written
+    let pattern_nodes = askit.board_pattern_agents_for(&name);
+    if !pattern_nodes.is_empty() {
+        let ctx = ctx.with_var(BOARD_NAME_VAR.into(), AgentValue::string(name.clone()));
+        for node in pattern_nodes {
+            board_out_node(askit, &node, &ctx, &value).await;
         }
     }
 
@@ -0,0 +1,635 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::FnvIndexMap;
+use crate::askit::ASKit;
+use crate::async_trait;
+use crate::context::AgentContext;
+use crate::error::AgentError;
+use crate::tool::{Tool, ToolInfo};
+use crate::value::AgentValue;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+///
+/// The buckets of the Prometheus client libraries, extended for LLM calls.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// A latency histogram.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// Number of observations in each of `LATENCY_BUCKETS`, followed by the ones above them.
+    pub counts: Vec<u64>,
+
+    /// Sum of the observations, in seconds.
+    pub sum: f64,
+
+    /// Number of observations.
+    pub count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; LATENCY_BUCKETS.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub(crate) fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    /// Mean of the observations, in seconds.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+}
+
+/// Metrics of an agent.
+#[derive(Clone, Debug, Default)]
+pub struct AgentMetrics {
+    pub def_name: String,
+
+    pub stream_id: String,
+
+    /// Inputs queued for the agent. (pin -> count)
+    pub inputs: FnvIndexMap<String, u64>,
+
+    /// Outputs sent from the agent. (pin -> count)
+    pub outputs: FnvIndexMap<String, u64>,
+
+    /// Inputs dropped by the delivery policy.
+    pub dropped_inputs: u64,
+
+    /// Inputs whose `process` returned an error or panicked.
+    pub errors: u64,
+
+    /// Time taken by `process`.
+    pub process_latency: Histogram,
+
+    /// Inputs waiting in the queue of the agent.
+    pub mailbox_depth: usize,
+}
+
+/// Metrics of a stream, the sums of its agents and the tools called from it.
+#[derive(Clone, Debug, Default)]
+pub struct StreamMetrics {
+    pub name: String,
+
+    pub inputs: u64,
+
+    pub outputs: u64,
+
+    pub dropped_inputs: u64,
+
+    pub errors: u64,
+
+    pub mailbox_depth: usize,
+
+    /// Tools called with the stream id. (tool name -> metrics)
+    pub tools: FnvIndexMap<String, ToolMetrics>,
+}
+
+/// Metrics of a tool.
+#[derive(Clone, Debug, Default)]
+pub struct ToolMetrics {
+    pub calls: u64,
+
+    pub errors: u64,
+
+    pub latency: Histogram,
+}
+
+impl ToolMetrics {
+    fn merge(&mut self, other: &ToolMetrics) {
+        self.calls += other.calls;
+        self.errors += other.errors;
+        self.latency.merge(&other.latency);
+    }
+}
+
+/// Snapshot of the metrics of an ASKit, taken by `ASKit::metrics`.
+#[derive(Clone, Debug, Default)]
+pub struct MetricsSnapshot {
+    /// Messages waiting in the main loop queue.
+    pub main_queue_depth: usize,
+
+    /// Values written to each board. (board name -> count)
+    pub board_writes: FnvIndexMap<String, u64>,
+
+    /// agent id -> metrics
+    pub agents: FnvIndexMap<String, AgentMetrics>,
+
+    /// stream id -> metrics
+    pub streams: FnvIndexMap<String, StreamMetrics>,
+
+    /// All calls of each tool, with or without a stream. (tool name -> metrics)
+    pub tools: FnvIndexMap<String, ToolMetrics>,
+}
+
+impl MetricsSnapshot {
+    /// Render in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        gauge(
+            &mut out,
+            "askit_main_queue_depth",
+            "Messages waiting in the main loop queue.",
+            [(Vec::new(), self.main_queue_depth as u64)],
+        );
+        counter(
+            &mut out,
+            "askit_board_writes_total",
+            "Values written to the board.",
+            self.board_writes
+                .iter()
+                .map(|(name, count)| (vec![("board", name.as_str())], *count)),
+        );
+
+        counter(
+            &mut out,
+            "askit_agent_inputs_total",
+            "Inputs queued for the agent.",
+            self.agents.iter().flat_map(|(id, agent)| {
+                agent.inputs.iter().map(move |(pin, count)| {
+                    let mut labels = agent_labels(id, agent);
+                    labels.push(("pin", pin.as_str()));
+                    (labels, *count)
+                })
+            }),
+        );
+        counter(
+            &mut out,
+            "askit_agent_outputs_total",
+            "Outputs sent from the agent.",
+            self.agents.iter().flat_map(|(id, agent)| {
+                agent.outputs.iter().map(move |(pin, count)| {
+                    let mut labels = agent_labels(id, agent);
+                    labels.push(("pin", pin.as_str()));
+                    (labels, *count)
+                })
+            }),
+        );
+        counter(
+            &mut out,
+            "askit_agent_dropped_inputs_total",
+            "Inputs dropped by the delivery policy of the agent.",
+            self.agents
+                .iter()
+                .map(|(id, agent)| (agent_labels(id, agent), agent.dropped_inputs)),
+        );
+        counter(
+            &mut out,
+            "askit_agent_errors_total",
+            "Inputs whose processing failed.",
+            self.agents
+                .iter()
+                .map(|(id, agent)| (agent_labels(id, agent), agent.errors)),
+        );
+        gauge(
+            &mut out,
+            "askit_agent_mailbox_depth",
+            "Inputs waiting in the queue of the agent.",
+            self.agents
+                .iter()
+                .map(|(id, agent)| (agent_labels(id, agent), agent.mailbox_depth as u64)),
+        );
+        histogram(
+            &mut out,
+            "askit_agent_process_duration_seconds",
+            "Time taken to process an input.",
+            self.agents
+                .iter()
+                .map(|(id, agent)| (agent_labels(id, agent), &agent.process_latency)),
+        );
+
+        counter(
+            &mut out,
+            "askit_stream_inputs_total",
+            "Inputs queued for the agents of the stream.",
+            self.streams
+                .iter()
+                .map(|(id, stream)| (stream_labels(id, stream), stream.inputs)),
+        );
+        counter(
+            &mut out,
+            "askit_stream_outputs_total",
+            "Outputs sent from the agents of the stream.",
+            self.streams
+                .iter()
+                .map(|(id, stream)| (stream_labels(id, stream), stream.outputs)),
+        );
+        counter(
+            &mut out,
+            "askit_stream_dropped_inputs_total",
+            "Inputs dropped by the delivery policies of the agents of the stream.",
+            self.streams
+                .iter()
+                .map(|(id, stream)| (stream_labels(id, stream), stream.dropped_inputs)),
+        );
+        counter(
+            &mut out,
+            "askit_stream_errors_total",
+            "Inputs whose processing failed in the stream.",
+            self.streams
+                .iter()
+                .map(|(id, stream)| (stream_labels(id, stream), stream.errors)),
+        );
+        gauge(
+            &mut out,
+            "askit_stream_mailbox_depth",
+            "Inputs waiting in the queues of the agents of the stream.",
+            self.streams
+                .iter()
+                .map(|(id, stream)| (stream_labels(id, stream), stream.mailbox_depth as u64)),
+        );
+        counter(
+            &mut out,
+            "askit_stream_tool_calls_total",
+            "Tools called from the stream.",
+            self.streams.iter().flat_map(|(id, stream)| {
+                stream.tools.iter().map(move |(tool, metrics)| {
+                    let mut labels = stream_labels(id, stream);
+                    labels.push(("tool", tool.as_str()));
+                    (labels, metrics.calls)
+                })
+            }),
+        );
+
+        counter(
+            &mut out,
+            "askit_tool_calls_total",
+            "Calls of the tool.",
+            self.tools
+                .iter()
+                .map(|(tool, metrics)| (vec![("tool", tool.as_str())], metrics.calls)),
+        );
+        counter(
+            &mut out,
+            "askit_tool_errors_total",
+            "Calls of the tool which returned an error.",
+            self.tools
+                .iter()
+                .map(|(tool, metrics)| (vec![("tool", tool.as_str())], metrics.errors)),
+        );
+        histogram(
+            &mut out,
+            "askit_tool_duration_seconds",
+            "Time taken by a call of the tool.",
+            self.tools
+                .iter()
+                .map(|(tool, metrics)| (vec![("tool", tool.as_str())], &metrics.latency)),
+        );
+
+        out
+    }
+}
+
+fn agent_labels<'a>(id: &'a str, agent: &'a AgentMetrics) -> Vec<(&'a str, &'a str)> {
+    vec![
+        ("agent_id", id),
+        ("def_name", agent.def_name.as_str()),
+        ("stream_id", agent.stream_id.as_str()),
+    ]
+}
+
+fn stream_labels<'a>(id: &'a str, stream: &'a StreamMetrics) -> Vec<(&'a str, &'a str)> {
+    vec![("stream_id", id), ("stream_name", stream.name.as_str())]
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        out.push('{');
+        for (i, (key, value)) in labels.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", key, escape_label(value));
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn counter<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (Vec<(&'a str, &'a str)>, u64)>,
+) {
+    header(out, name, help, "counter");
+    for (labels, value) in samples {
+        sample(out, name, &labels, value);
+    }
+}
+
+fn gauge<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (Vec<(&'a str, &'a str)>, u64)>,
+) {
+    header(out, name, help, "gauge");
+    for (labels, value) in samples {
+        sample(out, name, &labels, value);
+    }
+}
+
+fn histogram<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (Vec<(&'a str, &'a str)>, &'a Histogram)>,
+) {
+    header(out, name, help, "histogram");
+    let bucket_name = format!("{}_bucket", name);
+    for (labels, histogram) in samples {
+        let mut cumulative = 0;
+        for (i, count) in histogram.counts.iter().enumerate() {
+            cumulative += count;
+            let le = LATENCY_BUCKETS
+                .get(i)
+                .map(|bound| bound.to_string())
+                .unwrap_or_else(|| "+Inf".to_string());
+            let mut labels = labels.clone();
+            labels.push(("le", le.as_str()));
+            sample(out, &bucket_name, &labels, cumulative);
+        }
+        sample(out, &format!("{}_sum", name), &labels, histogram.sum);
+        sample(out, &format!("{}_count", name), &labels, histogram.count);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counters updated as the ASKit runs.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    // agent id -> metrics, without the labels and the mailbox depth
+    agents: FnvIndexMap<String, AgentMetrics>,
+
+    board_writes: FnvIndexMap<String, u64>,
+
+    // (stream id or "", tool name) -> metrics
+    tools: FnvIndexMap<(String, String), ToolMetrics>,
+}
+
+impl Metrics {
+    fn agent(&mut self, agent_id: &str) -> &mut AgentMetrics {
+        self.agents.entry(agent_id.to_string()).or_default()
+    }
+}
+
+impl ASKit {
+    /// Take a snapshot of the metrics.
+    ///
+    /// Counters start when the ASKit is created, and those of an agent are reset when it
+    /// is removed.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let mut snapshot = {
+            let metrics = self.metrics.lock().unwrap();
+            let mut tools: FnvIndexMap<String, ToolMetrics> = FnvIndexMap::default();
+            for ((_, name), metrics) in metrics.tools.iter() {
+                tools.entry(name.clone()).or_default().merge(metrics);
+            }
+            MetricsSnapshot {
+                board_writes: metrics.board_writes.clone(),
+                agents: metrics.agents.clone(),
+                tools,
+                ..Default::default()
+            }
+        };
+
+        snapshot.main_queue_depth = self
+            .tx
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tx| tx.max_capacity() - tx.capacity())
+            .unwrap_or_default();
+
+        // every agent, labelled by the specs of the streams
+        for agent_id in self.agents.lock().unwrap().keys() {
+            snapshot.agents.entry(agent_id.clone()).or_default();
+        }
+        for (stream_id, stream) in self.streams.lock().unwrap().iter() {
+            snapshot.streams.insert(
+                stream_id.clone(),
+                StreamMetrics {
+                    name: stream.name().to_string(),
+                    ..Default::default()
+                },
+            );
+            for spec in stream.spec().agents.iter() {
+                if let Some(agent) = snapshot.agents.get_mut(&spec.id) {
+                    agent.def_name = spec.def_name.clone();
+                    agent.stream_id = stream_id.clone();
+                }
+            }
+        }
+        {
+            let agent_txs = self.agent_txs.lock().unwrap();
+            for (agent_id, tx) in agent_txs.iter() {
+                if let Some(agent) = snapshot.agents.get_mut(agent_id) {
+                    agent.mailbox_depth = tx.input_len();
+                }
+            }
+        }
+
+        for agent in snapshot.agents.values() {
+            let Some(stream) = snapshot.streams.get_mut(&agent.stream_id) else {
+                continue;
+            };
+            stream.inputs += agent.inputs.values().sum::<u64>();
+            stream.outputs += agent.outputs.values().sum::<u64>();
+            stream.dropped_inputs += agent.dropped_inputs;
+            stream.errors += agent.errors;
+            stream.mailbox_depth += agent.mailbox_depth;
+        }
+        {
+            let metrics = self.metrics.lock().unwrap();
+            for ((stream_id, name), tool) in metrics.tools.iter() {
+                if let Some(stream) = snapshot.streams.get_mut(stream_id) {
+                    stream.tools.insert(name.clone(), tool.clone());
+                }
+            }
+        }
+
+        snapshot
+    }
+
+    pub(crate) fn count_agent_input(&self, agent_id: &str, pin: &str) {
+        let mut metrics = self.metrics.lock().unwrap();
+        *metrics
+            .agent(agent_id)
+            .inputs
+            .entry(pin.to_string())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn count_dropped_input(&self, agent_id: &str) {
+        self.metrics.lock().unwrap().agent(agent_id).dropped_inputs += 1;
+    }
+
+    pub(crate) fn count_agent_output(&self, agent_id: &str, pin: &str) {
+        let mut metrics = self.metrics.lock().unwrap();
+        *metrics
+            .agent(agent_id)
+            .outputs
+            .entry(pin.to_string())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn observe_process(&self, agent_id: &str, duration: Duration, failed: bool) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let agent = metrics.agent(agent_id);
+        agent.process_latency.observe(duration);
+        if failed {
+            agent.errors += 1;
+        }
+    }
+
+    pub(crate) fn count_board_write(&self, name: &str) {
+        let mut metrics = self.metrics.lock().unwrap();
+        *metrics.board_writes.entry(name.to_string()).or_default() += 1;
+    }
+
+    pub(crate) fn remove_agent_metrics(&self, agent_id: &str) {
+        self.metrics.lock().unwrap().agents.swap_remove(agent_id);
+    }
+}
+
+/// A tool whose calls are counted.
+struct MeteredTool {
+    tool: Arc<Box<dyn Tool + Send + Sync>>,
+    metrics: Arc<Mutex<Metrics>>,
+    stream_id: String,
+}
+
+#[async_trait]
+impl Tool for MeteredTool {
+    fn info(&self) -> &ToolInfo {
+        self.tool.info()
+    }
+
+    async fn call(&self, ctx: AgentContext, args: AgentValue) -> Result<AgentValue, AgentError> {
+        let start = Instant::now();
+        let result = self.tool.call(ctx, args).await;
+        let mut metrics = self.metrics.lock().unwrap();
+        let tool = metrics
+            .tools
+            .entry((self.stream_id.clone(), self.tool.info().name.clone()))
+            .or_default();
+        tool.calls += 1;
+        if result.is_err() {
+            tool.errors += 1;
+        }
+        tool.latency.observe(start.elapsed());
+        result
+    }
+}
+
+pub(crate) fn metered_tool(
+    tool: Arc<Box<dyn Tool + Send + Sync>>,
+    askit: &ASKit,
+    stream_id: Option<&str>,
+) -> Arc<Box<dyn Tool + Send + Sync>> {
+    Arc::new(Box::new(MeteredTool {
+        tool,
+        metrics: askit.metrics.clone(),
+        stream_id: stream_id.unwrap_or_default().to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(200));
+        histogram.observe(Duration::from_secs(100));
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.counts[0], 1);
+        assert_eq!(histogram.counts[5], 1);
+        assert_eq!(histogram.counts[LATENCY_BUCKETS.len()], 1);
+        assert!((histogram.mean().unwrap() - 100.203 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let mut snapshot = MetricsSnapshot::default();
+        snapshot.board_writes.insert("say \"hi\"".into(), 2);
+        let mut agent = AgentMetrics {
+            def_name: "Counter".into(),
+            stream_id: "s1".into(),
+            ..Default::default()
+        };
+        agent.inputs.insert("in".into(), 3);
+        agent.process_latency.observe(Duration::from_millis(20));
+        snapshot.agents.insert("a1".into(), agent);
+        snapshot.streams.insert(
+            "s1".into(),
+            StreamMetrics {
+                name: "main".into(),
+                dropped_inputs: 4,
+                ..Default::default()
+            },
+        );
+
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("# TYPE askit_board_writes_total counter\n"));
+        assert!(text.contains("askit_board_writes_total{board=\"say \\\"hi\\\"\"} 2\n"));
+        assert!(text.contains(
+            "askit_agent_inputs_total{agent_id=\"a1\",def_name=\"Counter\",stream_id=\"s1\",pin=\"in\"} 3\n"
+        ));
+        assert!(text.contains(
+            "askit_agent_process_duration_seconds_bucket{agent_id=\"a1\",def_name=\"Counter\",stream_id=\"s1\",le=\"0.01\"} 0\n"
+        ));
+        assert!(text.contains(
+            "askit_agent_process_duration_seconds_bucket{agent_id=\"a1\",def_name=\"Counter\",stream_id=\"s1\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "askit_agent_process_duration_seconds_count{agent_id=\"a1\",def_name=\"Counter\",stream_id=\"s1\"} 1\n"
+        ));
+        assert!(text.contains(
+            "askit_stream_dropped_inputs_total{stream_id=\"s1\",stream_name=\"main\"} 4\n"
+        ));
+        assert!(text.contains("askit_main_queue_depth 0\n"));
+    }
+}
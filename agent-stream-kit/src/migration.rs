@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::FnvIndexMap;
+use crate::askit::ASKit;
+use crate::error::AgentError;
+use crate::spec::{AgentSpec, AgentStreamSpec, ChannelSpec};
+
+/// Version of the stream spec schema written by this crate.
+pub const STREAM_SPEC_VERSION: u32 = SPEC_MIGRATIONS.len() as u32;
+
+type SpecMigrationFn = fn(&mut Value) -> Result<(), AgentError>;
+
+// SPEC_MIGRATIONS[n] upgrades a spec from version n to n + 1.
+const SPEC_MIGRATIONS: &[SpecMigrationFn] = &[migrate_v0_to_v1];
+
+/// Upgrade a stream spec in JSON form to `STREAM_SPEC_VERSION`, one version at a time.
+///
+/// A spec without `version` is version 0.
+pub(crate) fn migrate_spec_value(value: &mut Value) -> Result<(), AgentError> {
+    let Some(obj) = value.as_object() else {
+        return Ok(());
+    };
+    let version = match obj.get("version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| AgentError::InvalidStreamSpec(format!("invalid version {}", v)))?
+            as u32,
+    };
+    if version > STREAM_SPEC_VERSION {
+        return Err(AgentError::InvalidStreamSpec(format!(
+            "version {} is newer than the supported version {}",
+            version, STREAM_SPEC_VERSION
+        )));
+    }
+    for (from, migrate) in SPEC_MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migrate(value)?;
+        log::debug!("Migrated stream spec from version {} to {}", from, from + 1);
+    }
+    value["version"] = STREAM_SPEC_VERSION.into();
+    Ok(())
+}
+
+// The deprecated `enabled` flag of agents is replaced by `disabled`.
+fn migrate_v0_to_v1(value: &mut Value) -> Result<(), AgentError> {
+    let Some(agents) = value.get_mut("agents").and_then(|v| v.as_array_mut()) else {
+        return Ok(());
+    };
+    for agent in agents.iter_mut() {
+        migrate_enabled(agent);
+    }
+    Ok(())
+}
+
+/// Replace the deprecated `enabled` flag of an agent spec in JSON form with `disabled`.
+///
+/// Agent specs have no version, so this is applied to every agent spec deserialized on its own.
+pub(crate) fn migrate_enabled(agent: &mut Value) {
+    let Some(agent) = agent.as_object_mut() else {
+        return;
+    };
+    let Some(enabled) = agent.remove("enabled") else {
+        return;
+    };
+    if !agent.contains_key("disabled") {
+        let disabled = !enabled.as_bool().unwrap_or(false);
+        agent.insert("disabled".into(), disabled.into());
+    }
+}
+
+/// Renames made to an agent definition, so that specs saved with the old names keep loading.
+///
+/// Renames are applied in the order they were declared, so `a -> b` followed by `b -> c`
+/// upgrades both `a` and `b`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentMigrations {
+    /// Former names of the definition.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed_from: Vec<String>,
+
+    /// Renamed config keys. (old key -> new key)
+    #[serde(default, skip_serializing_if = "FnvIndexMap::is_empty")]
+    pub configs: FnvIndexMap<String, String>,
+
+    /// Renamed input pins. (old pin -> new pin)
+    #[serde(default, skip_serializing_if = "FnvIndexMap::is_empty")]
+    pub inputs: FnvIndexMap<String, String>,
+
+    /// Renamed output pins. (old pin -> new pin)
+    #[serde(default, skip_serializing_if = "FnvIndexMap::is_empty")]
+    pub outputs: FnvIndexMap<String, String>,
+}
+
+impl ASKit {
+    /// Apply the renames declared by the agent definitions to the spec.
+    ///
+    /// Agents whose `def_name` is a former name of a definition are moved to that definition,
+    /// and their config keys and pins, including the handles of their channels, are renamed.
+    /// Streams are migrated when they are added, so this is only needed to inspect a spec
+    /// before that.
+    pub fn migrate_stream_spec(&self, spec: &mut AgentStreamSpec) {
+        for agent in spec.agents.iter_mut() {
+            let Some(migrations) = self.migrate_def_name(agent) else {
+                continue;
+            };
+            migrate_agent(agent, &migrations);
+            for channel in spec.channels.iter_mut() {
+                migrate_channel(channel, &agent.id, &migrations);
+            }
+        }
+    }
+
+    /// Apply the renames to a single agent spec, without channels.
+    pub(crate) fn migrate_agent_spec(&self, agent: &mut AgentSpec) {
+        if let Some(migrations) = self.migrate_def_name(agent) {
+            migrate_agent(agent, &migrations);
+        }
+    }
+
+    // Move the agent to the definition it was renamed to, and returns the renames of the definition.
+    fn migrate_def_name(&self, agent: &mut AgentSpec) -> Option<AgentMigrations> {
+        let defs = self.defs.lock().unwrap();
+        let def = match defs.get(&agent.def_name) {
+            Some(def) => def,
+            None => {
+                let def = defs.values().find(|def| {
+                    def.migrations
+                        .as_ref()
+                        .is_some_and(|m| m.renamed_from.contains(&agent.def_name))
+                })?;
+                log::info!(
+                    "Agent {}: definition {} is renamed to {}",
+                    agent.id,
+                    agent.def_name,
+                    def.name
+                );
+                agent.def_name = def.name.clone();
+                def
+            }
+        };
+        def.migrations.clone()
+    }
+}
+
+fn migrate_agent(agent: &mut AgentSpec, migrations: &AgentMigrations) {
+    if let Some(configs) = agent.configs.as_mut() {
+        for (old, new) in &migrations.configs {
+            if let Some(value) = configs.remove(old)
+                && !configs.contains_key(new)
+            {
+                configs.set(new.clone(), value);
+            }
+        }
+    }
+    if let Some(config_specs) = agent.config_specs.as_mut() {
+        for (old, new) in &migrations.configs {
+            if let Some(config_spec) = config_specs.shift_remove(old)
+                && !config_specs.contains_key(new)
+            {
+                config_specs.insert(new.clone(), config_spec);
+            }
+        }
+    }
+    if let Some(inputs) = agent.inputs.as_mut() {
+        rename_pins(inputs, &migrations.inputs);
+    }
+    if let Some(outputs) = agent.outputs.as_mut() {
+        rename_pins(outputs, &migrations.outputs);
+    }
+}
+
+fn rename_pins(pins: &mut [String], renames: &FnvIndexMap<String, String>) {
+    for pin in pins.iter_mut() {
+        for (old, new) in renames {
+            if pin == old {
+                *pin = new.clone();
+            }
+        }
+    }
+}
+
+fn migrate_channel(channel: &mut ChannelSpec, agent_id: &str, migrations: &AgentMigrations) {
+    if channel.source == agent_id {
+        for (old, new) in &migrations.outputs {
+            if channel.source_handle == *old {
+                channel.source_handle = new.clone();
+            }
+        }
+    }
+    if channel.target == agent_id {
+        for (old, new) in &migrations.inputs {
+            if channel.target_handle == *old {
+                channel.target_handle = new.clone();
+            }
+        }
+        // channels into a config
+        for (old, new) in &migrations.configs {
+            if channel.target_handle.strip_prefix("config:") == Some(old.as_str()) {
+                channel.target_handle = format!("config:{}", new);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate_enabled() {
+        let mut value = json!({
+            "agents": [
+                { "id": "1", "def_name": "a", "enabled": true },
+                { "id": "2", "def_name": "a", "enabled": false },
+                { "id": "3", "def_name": "a", "disabled": true },
+            ],
+            "channels": [],
+        });
+        migrate_spec_value(&mut value).unwrap();
+        assert_eq!(value["version"], STREAM_SPEC_VERSION);
+        assert_eq!(
+            value["agents"][0],
+            json!({ "id": "1", "def_name": "a", "disabled": false })
+        );
+        assert_eq!(
+            value["agents"][1],
+            json!({ "id": "2", "def_name": "a", "disabled": true })
+        );
+        assert_eq!(
+            value["agents"][2],
+            json!({ "id": "3", "def_name": "a", "disabled": true })
+        );
+
+        // already up to date
+        let migrated = value.clone();
+        migrate_spec_value(&mut value).unwrap();
+        assert_eq!(value, migrated);
+    }
+
+    #[test]
+    fn test_migrate_enabled_of_agent_spec() {
+        let agent: AgentSpec =
+            serde_json::from_value(json!({ "id": "1", "def_name": "a", "enabled": false }))
+                .unwrap();
+        assert!(agent.disabled);
+        #[allow(deprecated)]
+        let enabled = agent.enabled;
+        assert!(!enabled);
+        assert!(agent.extensions.is_empty());
+        assert_eq!(
+            serde_json::to_value(&agent).unwrap(),
+            json!({ "id": "1", "def_name": "a", "disabled": true })
+        );
+    }
+
+    #[test]
+    fn test_migrate_newer_version() {
+        let mut value = json!({ "version": STREAM_SPEC_VERSION + 1, "agents": [], "channels": [] });
+        assert!(matches!(
+            migrate_spec_value(&mut value),
+            Err(AgentError::InvalidStreamSpec(_))
+        ));
+    }
+
+    #[test]
+    fn test_migrate_channel() {
+        let migrations = AgentMigrations {
+            configs: [("old".to_string(), "new".to_string())]
+                .into_iter()
+                .collect(),
+            inputs: [("in".to_string(), "input".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let mut channel = ChannelSpec {
+            source: "1".into(),
+            source_handle: "in".into(),
+            target: "2".into(),
+            target_handle: "config:old".into(),
+        };
+        migrate_channel(&mut channel, "2", &migrations);
+        assert_eq!(channel.target_handle, "config:new");
+        // only the handles of the migrated agent are renamed
+        assert_eq!(channel.source_handle, "in");
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+use std::ops::Not;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::FnvIndexMap;
+use crate::error::AgentError;
+
+pub type PinSpecs = FnvIndexMap<String, PinSpec>;
+
+/// Type of the values which flow through a pin.
+///
+/// Serialized as a string, such as `"message"` or `"array<string>"`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum PinType {
+    /// Any value.
+    #[default]
+    Any,
+    Unit,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Image,
+    Array(Box<PinType>),
+    Object,
+    Tensor,
+    Message,
+    Error,
+}
+
+impl PinType {
+    pub fn array(item: PinType) -> Self {
+        PinType::Array(Box::new(item))
+    }
+
+    /// Whether values from a pin of the `source` type can be sent to a pin of this type.
+    ///
+    /// `Any` on either side is compatible with everything, and integers are accepted as numbers.
+    pub fn accepts(&self, source: &PinType) -> bool {
+        match (self, source) {
+            (PinType::Any, _) | (_, PinType::Any) => true,
+            (PinType::Number, PinType::Integer) => true,
+            (PinType::Array(target), PinType::Array(source)) => target.accepts(source),
+            (target, source) => target == source,
+        }
+    }
+}
+
+impl fmt::Display for PinType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinType::Any => write!(f, "any"),
+            PinType::Unit => write!(f, "unit"),
+            PinType::Boolean => write!(f, "boolean"),
+            PinType::Integer => write!(f, "integer"),
+            PinType::Number => write!(f, "number"),
+            PinType::String => write!(f, "string"),
+            PinType::Image => write!(f, "image"),
+            PinType::Array(item) => write!(f, "array<{}>", item),
+            PinType::Object => write!(f, "object"),
+            PinType::Tensor => write!(f, "tensor"),
+            PinType::Message => write!(f, "message"),
+            PinType::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl FromStr for PinType {
+    type Err = AgentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(item) = s
+            .strip_prefix("array<")
+            .and_then(|rest| rest.strip_suffix('>'))
+        {
+            return Ok(PinType::array(item.parse()?));
+        }
+        match s {
+            "any" => Ok(PinType::Any),
+            "unit" => Ok(PinType::Unit),
+            "boolean" => Ok(PinType::Boolean),
+            "integer" => Ok(PinType::Integer),
+            "number" => Ok(PinType::Number),
+            "string" => Ok(PinType::String),
+            "image" => Ok(PinType::Image),
+            "array" => Ok(PinType::array(PinType::Any)),
+            "object" => Ok(PinType::Object),
+            "tensor" => Ok(PinType::Tensor),
+            "message" => Ok(PinType::Message),
+            "error" => Ok(PinType::Error),
+            _ => Err(AgentError::InvalidValue(format!("pin type \"{}\"", s))),
+        }
+    }
+}
+
+impl Serialize for PinType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PinType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Description of an input or output pin.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PinSpec {
+    /// Type of the values. A pin without type accepts and emits any value.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none", default)]
+    pub type_: Option<PinType>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub description: Option<String>,
+
+    /// Whether the pin needs to be connected for the agent to work.
+    #[serde(default, skip_serializing_if = "<&bool>::not")]
+    pub required: bool,
+}
+
+impl PinSpec {
+    pub fn new(type_: PinType) -> Self {
+        Self {
+            type_: Some(type_),
+            ..Default::default()
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Type of the pin, `Any` when not given.
+    pub fn pin_type(&self) -> &PinType {
+        self.type_.as_ref().unwrap_or(&PinType::Any)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_type_from_str() {
+        assert_eq!("message".parse::<PinType>().unwrap(), PinType::Message);
+        assert_eq!(
+            "array<array<string>>".parse::<PinType>().unwrap(),
+            PinType::array(PinType::array(PinType::String))
+        );
+        assert_eq!(
+            "array".parse::<PinType>().unwrap(),
+            PinType::array(PinType::Any)
+        );
+        assert!("array<foo>".parse::<PinType>().is_err());
+        assert!("".parse::<PinType>().is_err());
+    }
+
+    #[test]
+    fn test_pin_type_display_roundtrip() {
+        for t in [
+            PinType::Any,
+            PinType::Image,
+            PinType::array(PinType::Message),
+            PinType::array(PinType::array(PinType::Integer)),
+        ] {
+            assert_eq!(t.to_string().parse::<PinType>().unwrap(), t);
+        }
+    }
+
+    #[test]
+    fn test_pin_type_accepts() {
+        assert!(PinType::Message.accepts(&PinType::Message));
+        assert!(!PinType::Message.accepts(&PinType::Image));
+        assert!(PinType::Any.accepts(&PinType::Image));
+        assert!(PinType::Image.accepts(&PinType::Any));
+        assert!(PinType::Number.accepts(&PinType::Integer));
+        assert!(!PinType::Integer.accepts(&PinType::Number));
+        assert!(PinType::array(PinType::Number).accepts(&PinType::array(PinType::Integer)));
+        assert!(!PinType::array(PinType::String).accepts(&PinType::array(PinType::Message)));
+        assert!(!PinType::array(PinType::String).accepts(&PinType::String));
+    }
+
+    #[test]
+    fn test_serialize_pin_spec() {
+        let pin = PinSpec::new(PinType::array(PinType::String))
+            .description("lines")
+            .required();
+        let json = serde_json::to_string(&pin).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"array<string>","description":"lines","required":true}"#
+        );
+        let pin2: PinSpec = serde_json::from_str(&json).unwrap();
+        assert_eq!(pin, pin2);
+
+        let pin: PinSpec = serde_json::from_str("{}").unwrap();
+        assert_eq!(pin.pin_type(), &PinType::Any);
+    }
+}
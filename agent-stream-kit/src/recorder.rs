@@ -0,0 +1,295 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::askit::ASKit;
+use crate::context::AgentContext;
+use crate::error::AgentError;
+use crate::message::AgentEventMessage;
+use crate::spec::AgentStreamSpec;
+use crate::value::AgentValue;
+
+// how long the file writer waits for more events before flushing
+const RECORD_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A line of a recording.
+///
+/// A recording starts with a `Stream` for each stream at the time, followed by the messages
+/// of the main loop in the order they were processed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    /// A stream which existed when the recording started.
+    Stream {
+        timestamp: u64,
+        stream_id: String,
+        name: String,
+        running: bool,
+        spec: AgentStreamSpec,
+    },
+
+    /// A value sent from an output pin of an agent.
+    AgentOut {
+        seq: u64,
+        timestamp: u64,
+        agent: String,
+        pin: String,
+        ctx: AgentContext,
+        value: AgentValue,
+    },
+
+    /// A value written to a board.
+    BoardOut {
+        seq: u64,
+        timestamp: u64,
+        name: String,
+        ctx: AgentContext,
+        value: AgentValue,
+
+        /// Whether the value was written from outside of the agents, such as by
+        /// `ASKit::write_board_value`. These are the inputs fed back by `Replayer`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        external: bool,
+    },
+}
+
+impl RecordedEvent {
+    /// Context of the message. `None` for `Stream`.
+    pub fn ctx(&self) -> Option<&AgentContext> {
+        match self {
+            RecordedEvent::Stream { .. } => None,
+            RecordedEvent::AgentOut { ctx, .. } | RecordedEvent::BoardOut { ctx, .. } => Some(ctx),
+        }
+    }
+
+    /// Whether this is a board value written from outside of the agents.
+    pub fn is_external(&self) -> bool {
+        matches!(self, RecordedEvent::BoardOut { external: true, .. })
+    }
+
+    /// Read a recording written by `ASKit::start_recording`.
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<RecordedEvent>, AgentError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            AgentError::IoError(format!("Failed to open {}: {}", path.display(), e))
+        })?;
+        let mut events = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| AgentError::IoError(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = serde_json::from_str(&line).map_err(|e| {
+                AgentError::SerializationError(format!("{}:{}: {}", path.display(), i + 1, e))
+            })?;
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+// where the recorded events go
+enum RecordSink {
+    // append-only JSON lines
+    File(RecordWriter),
+
+    // kept for `Replayer`
+    Memory(Vec<RecordedEvent>),
+}
+
+pub(crate) struct Recorder {
+    seq: u64,
+    sink: RecordSink,
+}
+
+impl Recorder {
+    fn write(&mut self, event: RecordedEvent) {
+        match &mut self.sink {
+            RecordSink::File(writer) => writer.write(event),
+            RecordSink::Memory(events) => events.push(event),
+        }
+    }
+}
+
+/// Writes the recorded events to a file from a background thread, so that the main loop does
+/// not wait on the disk.
+///
+/// The file is flushed when no event comes for a while. Dropping the writer writes the rest.
+struct RecordWriter {
+    tx: Option<mpsc::Sender<RecordedEvent>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RecordWriter {
+    fn new(file: File) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || record_write_loop(BufWriter::new(file), rx));
+        Self {
+            tx: Some(tx),
+            handle: Some(handle),
+        }
+    }
+
+    fn write(&self, event: RecordedEvent) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(event);
+        }
+    }
+}
+
+impl Drop for RecordWriter {
+    fn drop(&mut self) {
+        // closing the channel makes the thread write the rest and exit
+        self.tx.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn record_write_loop(mut file: BufWriter<File>, rx: mpsc::Receiver<RecordedEvent>) {
+    loop {
+        let event = match rx.recv_timeout(RECORD_FLUSH_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(e) = file.flush() {
+                    log::error!("Failed to write recorded events: {}", e);
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to serialize recorded event: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = writeln!(file, "{}", line) {
+            log::error!("Failed to write recorded event: {}", e);
+        }
+    }
+    if let Err(e) = file.flush() {
+        log::error!("Failed to write recorded events: {}", e);
+    }
+}
+
+impl ASKit {
+    /// Record the messages of the main loop to the file, as JSON lines.
+    ///
+    /// The file is appended to, and starts with the specs of the current streams.
+    /// A recording already in progress is replaced.
+    ///
+    /// The events are written from a background thread, and are all in the file once the
+    /// recording is stopped.
+    pub async fn start_recording(&self, path: impl AsRef<Path>) -> Result<(), AgentError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                AgentError::IoError(format!("Failed to open {}: {}", path.display(), e))
+            })?;
+        self.start_recorder(RecordSink::File(RecordWriter::new(file)))
+            .await;
+        Ok(())
+    }
+
+    /// Stop recording, and wait until the recorded events are written.
+    pub fn stop_recording(&self) {
+        let recorder = self.recorder.lock().unwrap().take();
+        drop(recorder);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.lock().unwrap().is_some()
+    }
+
+    /// Start recording into memory, to be read with `take_recorded_events`.
+    pub(crate) async fn start_memory_recording(&self) {
+        self.start_recorder(RecordSink::Memory(Vec::new())).await;
+    }
+
+    /// Take the events recorded in memory so far.
+    pub(crate) fn take_recorded_events(&self) -> Vec<RecordedEvent> {
+        let mut recorder = self.recorder.lock().unwrap();
+        match recorder.as_mut().map(|r| &mut r.sink) {
+            Some(RecordSink::Memory(events)) => std::mem::take(events),
+            _ => Vec::new(),
+        }
+    }
+
+    async fn start_recorder(&self, sink: RecordSink) {
+        let mut streams = Vec::new();
+        for info in self.get_agent_stream_infos() {
+            if let Some(spec) = self.get_agent_stream_spec(&info.id).await {
+                streams.push(RecordedEvent::Stream {
+                    timestamp: now_millis(),
+                    stream_id: info.id,
+                    name: info.name,
+                    running: info.running,
+                    spec,
+                });
+            }
+        }
+        let mut recorder = Recorder { seq: 0, sink };
+        for event in streams {
+            recorder.write(event);
+        }
+        let previous = self.recorder.lock().unwrap().replace(recorder);
+        drop(previous);
+    }
+
+    pub(crate) fn record(&self, message: &AgentEventMessage) {
+        let mut recorder = self.recorder.lock().unwrap();
+        let Some(recorder) = recorder.as_mut() else {
+            return;
+        };
+        let seq = recorder.seq;
+        recorder.seq += 1;
+        let event = match message {
+            AgentEventMessage::AgentOut {
+                agent,
+                ctx,
+                pin,
+                value,
+            } => RecordedEvent::AgentOut {
+                seq,
+                timestamp: now_millis(),
+                agent: agent.clone(),
+                pin: pin.clone(),
+                ctx: ctx.clone(),
+                value: value.clone(),
+            },
+            AgentEventMessage::BoardOut {
+                name,
+                ctx,
+                value,
+                external,
+            } => RecordedEvent::BoardOut {
+                seq,
+                timestamp: now_millis(),
+                name: name.clone(),
+                ctx: ctx.clone(),
+                value: value.clone(),
+                external: *external,
+            },
+        };
+        recorder.write(event);
+    }
+}
+
+// milliseconds since the Unix epoch
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,244 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::FnvIndexMap;
+use crate::askit::ASKit;
+use crate::context::AgentContext;
+use crate::error::AgentError;
+use crate::message;
+use crate::recorder::RecordedEvent;
+use crate::shutdown;
+use crate::var_scope::map_subflow_ids;
+
+const DEFAULT_REPLAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Feeds the external inputs of a recording into an ASKit, and compares the outputs with
+/// the recorded ones.
+///
+/// The outputs of an input are the agent outputs and board values in its context, so
+/// values sent from contexts created by the agents themselves, such as timers, are not
+/// compared. Inputs are fed one at a time, each after the previous one has settled, so
+/// the order of outputs from parallel branches does not matter.
+pub struct Replayer {
+    events: Vec<RecordedEvent>,
+    timeout: Duration,
+}
+
+/// Result of `Replayer::replay`.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayReport {
+    /// Number of inputs fed.
+    pub inputs: usize,
+
+    /// Inputs whose outputs differ from the recording.
+    pub mismatches: Vec<ReplayMismatch>,
+}
+
+impl ReplayReport {
+    /// Whether all outputs were the same as recorded.
+    pub fn is_match(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// An input whose outputs differ from the recording.
+#[derive(Clone, Debug)]
+pub struct ReplayMismatch {
+    /// The recorded input.
+    pub input: RecordedEvent,
+
+    /// Outputs of the input in the recording.
+    pub expected: Vec<RecordedEvent>,
+
+    /// Outputs of the input in the replay, with the agent and stream ids of the recording.
+    pub actual: Vec<RecordedEvent>,
+}
+
+impl Replayer {
+    pub fn new(events: Vec<RecordedEvent>) -> Self {
+        Self {
+            events,
+            timeout: DEFAULT_REPLAY_TIMEOUT,
+        }
+    }
+
+    /// Load a recording written by `ASKit::start_recording`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        Ok(Self::new(RecordedEvent::read_file(path)?))
+    }
+
+    /// Time to wait for each input to settle. Defaults to 5 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Add the recorded streams to the ASKit, and feed the recorded inputs into them.
+    ///
+    /// The ASKit should be fresh and ready, with the definitions used by the streams
+    /// registered. Streams which were running when the recording started are started.
+    pub async fn replay(&self, askit: &ASKit) -> Result<ReplayReport, AgentError> {
+        if askit.is_recording() {
+            return Err(AgentError::Other(
+                "Cannot replay into an ASKit which is recording".to_string(),
+            ));
+        }
+
+        let mut ids = IdMap::default();
+        for event in &self.events {
+            let RecordedEvent::Stream {
+                name,
+                running,
+                spec,
+                ..
+            } = event
+            else {
+                continue;
+            };
+            let new_stream_id = askit.add_agent_stream(name.clone(), spec.clone())?;
+            // agent ids are renewed in order
+            if let Some(new_spec) = askit.get_agent_stream_spec(&new_stream_id).await {
+                for (agent, new_agent) in spec.agents.iter().zip(new_spec.agents.iter()) {
+                    ids.agents.insert(agent.id.clone(), new_agent.id.clone());
+                }
+            }
+            if *running {
+                askit.start_agent_stream(&new_stream_id).await?;
+            }
+        }
+
+        askit.start_memory_recording().await;
+        let result = self.feed_inputs(askit, &ids).await;
+        askit.stop_recording();
+        result
+    }
+
+    async fn feed_inputs(&self, askit: &ASKit, ids: &IdMap) -> Result<ReplayReport, AgentError> {
+        let mut report = ReplayReport::default();
+        askit.take_recorded_events();
+
+        for input in self.events.iter().filter(|e| e.is_external()) {
+            let RecordedEvent::BoardOut {
+                name, ctx, value, ..
+            } = input
+            else {
+                continue;
+            };
+            report.inputs += 1;
+
+            let expected: Vec<RecordedEvent> = self
+                .events
+                .iter()
+                .filter(|e| !e.is_external() && e.ctx().is_some_and(|c| c.id() == ctx.id()))
+                .cloned()
+                .collect();
+
+            let ctx = AgentContext::new();
+            let ctx_id = ctx.id();
+            message::send_board_out(askit, ids.to_replay_board(name), ctx, value.clone(), true)
+                .await?;
+            if !shutdown::drain(askit, Instant::now() + self.timeout).await {
+                log::warn!("Replayed input to {} did not settle", name);
+            }
+
+            let actual: Vec<RecordedEvent> = askit
+                .take_recorded_events()
+                .into_iter()
+                .filter(|e| !e.is_external() && e.ctx().is_some_and(|c| c.id() == ctx_id))
+                .map(|e| ids.to_recorded(e))
+                .collect();
+
+            if comparable(&expected) != comparable(&actual) {
+                report.mismatches.push(ReplayMismatch {
+                    input: input.clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+// ids in the recording -> ids in the replay
+#[derive(Default)]
+struct IdMap {
+    agents: FnvIndexMap<String, String>,
+}
+
+impl IdMap {
+    // Variables are named after the streams, which keep their names, and the subflow agents,
+    // which do not. See `VarScope`.
+    fn to_replay_board(&self, name: &str) -> String {
+        map_subflow_ids(name, |id| self.agents.get(id).cloned())
+    }
+
+    fn to_recorded(&self, event: RecordedEvent) -> RecordedEvent {
+        match event {
+            RecordedEvent::AgentOut {
+                seq,
+                timestamp,
+                agent,
+                pin,
+                ctx,
+                value,
+            } => RecordedEvent::AgentOut {
+                seq,
+                timestamp,
+                agent: find_key(&self.agents, &agent).unwrap_or(agent),
+                pin,
+                ctx,
+                value,
+            },
+            RecordedEvent::BoardOut {
+                seq,
+                timestamp,
+                name,
+                ctx,
+                value,
+                external,
+            } => RecordedEvent::BoardOut {
+                seq,
+                timestamp,
+                name: map_subflow_ids(&name, |id| find_key(&self.agents, id)),
+                ctx,
+                value,
+                external,
+            },
+            event => event,
+        }
+    }
+}
+
+fn find_key(map: &FnvIndexMap<String, String>, value: &str) -> Option<String> {
+    map.iter()
+        .find(|(_, v)| *v == value)
+        .map(|(k, _)| k.clone())
+}
+
+// The outputs without sequence numbers, timestamps and context ids, in a stable order.
+fn comparable(events: &[RecordedEvent]) -> Vec<String> {
+    let mut values: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            RecordedEvent::AgentOut {
+                agent,
+                pin,
+                ctx,
+                value,
+                ..
+            } => Some(json!(["agent_out", agent, pin, ctx.frames(), value])),
+            RecordedEvent::BoardOut {
+                name, ctx, value, ..
+            } => Some(json!(["board_out", name, ctx.frames(), value])),
+            RecordedEvent::Stream { .. } => None,
+        })
+        .map(|value| value.to_string())
+        .collect();
+    values.sort();
+    values
+}
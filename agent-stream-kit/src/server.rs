@@ -0,0 +1,470 @@
+#![cfg(feature = "server")]
+
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::askit::{ASKit, ASKitEvent};
+use crate::config::AgentConfigs;
+use crate::error::AgentError;
+use crate::spec::AgentStreamSpec;
+use crate::value::AgentValue;
+
+/// Routes of the control API, as JSON over HTTP.
+///
+/// | Method | Path | |
+/// |---|---|---|
+/// | GET | `/definitions` | agent definitions |
+/// | GET | `/definitions/{def_name}` | an agent definition |
+/// | GET, POST | `/streams` | stream infos, add a stream from `{"name", "spec"?}` |
+/// | GET, PATCH, DELETE | `/streams/{id}` | stream info, rename with `{"name"}`, remove |
+/// | GET, PUT, PATCH | `/streams/{id}/spec` | spec, apply a whole spec, update the spec properties |
+/// | POST | `/streams/{id}/start`, `stop`, `pause`, `resume` | |
+/// | GET, PUT | `/streams/{id}/vars/{name}` | value of a variable, write a value to it |
+/// | GET, PATCH | `/agents/{id}/spec` | agent spec, update the spec properties |
+/// | PUT | `/agents/{id}/configs` | set agent configs |
+/// | GET | `/configs` | global configs of all definitions |
+/// | GET, PUT | `/configs/{def_name}` | global configs of a definition |
+/// | GET | `/boards` | names of the boards with a value |
+/// | GET, PUT | `/boards/{name}` | value of a board, write a value to it |
+/// | GET | `/boards/{name}/history` | kept values of a board, as `[{"timestamp", "value"}]` |
+/// | GET | `/metrics` | metrics in the Prometheus text format |
+/// | GET | `/events` | WebSocket of `ASKitEvent`s |
+///
+/// Errors are returned as `{"error": message}`. The WebSocket sends each event as a JSON
+/// object with a `type`, such as `{"type": "board", "name": "out", "value": 1}`, and accepts
+/// `{"type": "write_board", "name", "value"}` and
+/// `{"type": "write_var", "stream_id", "name", "value"}`.
+pub fn router(askit: ASKit) -> Router {
+    Router::new()
+        .route("/definitions", get(get_definitions))
+        .route("/definitions/{def_name}", get(get_definition))
+        .route("/streams", get(get_streams).post(add_stream))
+        .route(
+            "/streams/{id}",
+            get(get_stream).patch(rename_stream).delete(remove_stream),
+        )
+        .route(
+            "/streams/{id}/spec",
+            get(get_stream_spec)
+                .put(apply_stream_spec)
+                .patch(update_stream_spec),
+        )
+        .route("/streams/{id}/start", post(start_stream))
+        .route("/streams/{id}/stop", post(stop_stream))
+        .route("/streams/{id}/pause", post(pause_stream))
+        .route("/streams/{id}/resume", post(resume_stream))
+        .route("/streams/{id}/vars/{name}", get(read_var).put(write_var))
+        .route(
+            "/agents/{id}/spec",
+            get(get_agent_spec).patch(update_agent_spec),
+        )
+        .route("/agents/{id}/configs", put(set_agent_configs))
+        .route("/configs", get(get_global_configs_map))
+        .route(
+            "/configs/{def_name}",
+            get(get_global_configs).put(set_global_configs),
+        )
+        .route("/boards", get(list_boards))
+        .route("/boards/{name}", get(read_board).put(write_board))
+        .route("/boards/{name}/history", get(read_board_history))
+        .route("/metrics", get(get_metrics))
+        .route("/events", get(events))
+        .with_state(askit)
+}
+
+/// Serve the control API on the listener. Returns only when the server fails.
+pub async fn serve(askit: ASKit, listener: TcpListener) -> Result<(), AgentError> {
+    axum::serve(listener, router(askit))
+        .await
+        .map_err(|e| AgentError::IoError(e.to_string()))
+}
+
+struct ApiError(StatusCode, String);
+
+impl From<AgentError> for ApiError {
+    fn from(e: AgentError) -> Self {
+        use AgentError::*;
+        let status = match &e {
+            AgentNotFound(_)
+            | AgentDefinitionNotFound(_)
+            | StreamNotFound(_)
+            | TemplateNotFound(_)
+            | ChannelNotFound(_)
+            | UnknownDefName(_) => StatusCode::NOT_FOUND,
+            DuplicateStreamName(_) | AgentAlreadyExists(_) | DuplicateId(_) => StatusCode::CONFLICT,
+            ShuttingDown | TxNotInitialized => StatusCode::SERVICE_UNAVAILABLE,
+            Other(_) | IoError(_) | SendMessageFailed(_) | AgentTxNotFound(_)
+            | BoardStoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        ApiError(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+fn not_found(what: &str, id: &str) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("{} {} not found", what, id))
+}
+
+async fn get_definitions(State(askit): State<ASKit>) -> Response {
+    Json(askit.get_agent_definitions()).into_response()
+}
+
+async fn get_definition(
+    State(askit): State<ASKit>,
+    Path(def_name): Path<String>,
+) -> ApiResult<Response> {
+    let def = askit
+        .get_agent_definition(&def_name)
+        .ok_or_else(|| not_found("Agent definition", &def_name))?;
+    Ok(Json(def).into_response())
+}
+
+async fn get_streams(State(askit): State<ASKit>) -> Response {
+    Json(askit.get_agent_stream_infos()).into_response()
+}
+
+#[derive(Deserialize)]
+struct NewStream {
+    name: String,
+    spec: Option<AgentStreamSpec>,
+}
+
+async fn add_stream(State(askit): State<ASKit>, Json(new): Json<NewStream>) -> ApiResult<Response> {
+    let id = match new.spec {
+        Some(spec) => askit.add_agent_stream(askit.unique_stream_name(&new.name), spec)?,
+        None => askit.new_agent_stream(&new.name)?,
+    };
+    Ok((StatusCode::CREATED, Json(json!({ "id": id }))).into_response())
+}
+
+async fn get_stream(State(askit): State<ASKit>, Path(id): Path<String>) -> ApiResult<Response> {
+    let info = askit
+        .get_agent_stream_info(&id)
+        .ok_or_else(|| not_found("Agent stream", &id))?;
+    Ok(Json(info).into_response())
+}
+
+#[derive(Deserialize)]
+struct Rename {
+    name: String,
+}
+
+async fn rename_stream(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+    Json(rename): Json<Rename>,
+) -> ApiResult<Response> {
+    let name = askit.rename_agent_stream(&id, &rename.name)?;
+    Ok(Json(json!({ "name": name })).into_response())
+}
+
+async fn remove_stream(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    askit.remove_agent_stream(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_stream_spec(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+) -> ApiResult<Response> {
+    let spec = askit
+        .get_agent_stream_spec(&id)
+        .await
+        .ok_or_else(|| not_found("Agent stream", &id))?;
+    Ok(Json(spec).into_response())
+}
+
+async fn apply_stream_spec(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+    Json(spec): Json<AgentStreamSpec>,
+) -> ApiResult<Response> {
+    let diff = askit.apply_stream_spec(&id, spec).await?;
+    Ok(Json(diff).into_response())
+}
+
+async fn update_stream_spec(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+    Json(value): Json<Value>,
+) -> ApiResult<StatusCode> {
+    askit.update_agent_stream_spec(&id, &value)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn start_stream(State(askit): State<ASKit>, Path(id): Path<String>) -> ApiResult<StatusCode> {
+    askit.start_agent_stream(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn stop_stream(State(askit): State<ASKit>, Path(id): Path<String>) -> ApiResult<StatusCode> {
+    askit.stop_agent_stream(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn pause_stream(State(askit): State<ASKit>, Path(id): Path<String>) -> ApiResult<StatusCode> {
+    askit.pause_agent_stream(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn resume_stream(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    askit.resume_agent_stream(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn read_var(
+    State(askit): State<ASKit>,
+    Path((id, name)): Path<(String, String)>,
+) -> ApiResult<Response> {
+    let value = askit
+        .read_var_value(&id, &name)
+        .ok_or_else(|| not_found("Variable", &name))?;
+    Ok(Json(value).into_response())
+}
+
+async fn write_var(
+    State(askit): State<ASKit>,
+    Path((id, name)): Path<(String, String)>,
+    Json(value): Json<Value>,
+) -> ApiResult<StatusCode> {
+    askit
+        .write_var_value(&id, &name, AgentValue::from_json(value)?)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_agent_spec(State(askit): State<ASKit>, Path(id): Path<String>) -> ApiResult<Response> {
+    let spec = askit
+        .get_agent_spec(&id)
+        .await
+        .ok_or_else(|| not_found("Agent", &id))?;
+    Ok(Json(spec).into_response())
+}
+
+async fn update_agent_spec(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+    Json(value): Json<Value>,
+) -> ApiResult<StatusCode> {
+    askit.update_agent_spec(&id, &value).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_agent_configs(
+    State(askit): State<ASKit>,
+    Path(id): Path<String>,
+    Json(configs): Json<AgentConfigs>,
+) -> ApiResult<StatusCode> {
+    askit.set_agent_configs(id, configs).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_global_configs_map(State(askit): State<ASKit>) -> Response {
+    Json(askit.get_global_configs_map()).into_response()
+}
+
+async fn get_global_configs(
+    State(askit): State<ASKit>,
+    Path(def_name): Path<String>,
+) -> ApiResult<Response> {
+    let configs = askit
+        .get_global_configs(&def_name)
+        .ok_or_else(|| not_found("Global configs of", &def_name))?;
+    Ok(Json(configs).into_response())
+}
+
+async fn set_global_configs(
+    State(askit): State<ASKit>,
+    Path(def_name): Path<String>,
+    Json(configs): Json<AgentConfigs>,
+) -> StatusCode {
+    askit.set_global_configs(def_name, configs);
+    StatusCode::NO_CONTENT
+}
+
+async fn list_boards(State(askit): State<ASKit>) -> Response {
+    Json(askit.list_boards()).into_response()
+}
+
+async fn read_board(State(askit): State<ASKit>, Path(name): Path<String>) -> ApiResult<Response> {
+    let value = askit
+        .read_board_value(&name)
+        .ok_or_else(|| not_found("Board", &name))?;
+    Ok(Json(value).into_response())
+}
+
+async fn read_board_history(State(askit): State<ASKit>, Path(name): Path<String>) -> Response {
+    Json(askit.read_board_history(&name)).into_response()
+}
+
+async fn write_board(
+    State(askit): State<ASKit>,
+    Path(name): Path<String>,
+    Json(value): Json<Value>,
+) -> ApiResult<StatusCode> {
+    askit
+        .write_board_value(name, AgentValue::from_json(value)?)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_metrics(State(askit): State<ASKit>) -> Response {
+    (
+        [("content-type", "text/plain; version=0.0.4")],
+        askit.metrics().to_prometheus(),
+    )
+        .into_response()
+}
+
+async fn events(State(askit): State<ASKit>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| handle_events(askit, socket))
+}
+
+// messages from WebSocket clients
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    WriteBoard {
+        name: String,
+        value: Value,
+    },
+    WriteVar {
+        stream_id: String,
+        name: String,
+        value: Value,
+    },
+}
+
+async fn handle_events(askit: ASKit, mut socket: WebSocket) {
+    let mut rx = askit.subscribe();
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("WebSocket client lagged behind by {} events", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let text = event_json(&event).to_string();
+                if socket.send(WsMessage::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(WsMessage::Text(text))) => text,
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                if let Err(e) = handle_client_message(&askit, &text).await {
+                    let error = json!({ "type": "error", "message": e.to_string() });
+                    if socket.send(WsMessage::Text(error.to_string().into())).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn handle_client_message(askit: &ASKit, text: &str) -> Result<(), AgentError> {
+    let message: ClientMessage =
+        serde_json::from_str(text).map_err(|e| AgentError::JsonParseError(e.to_string()))?;
+    match message {
+        ClientMessage::WriteBoard { name, value } => {
+            askit
+                .write_board_value(name, AgentValue::from_json(value)?)
+                .await
+        }
+        ClientMessage::WriteVar {
+            stream_id,
+            name,
+            value,
+        } => {
+            askit
+                .write_var_value(&stream_id, &name, AgentValue::from_json(value)?)
+                .await
+        }
+    }
+}
+
+/// JSON sent to WebSocket clients for the event.
+fn event_json(event: &ASKitEvent) -> Value {
+    use ASKitEvent::*;
+    match event {
+        AgentConfigUpdated(agent_id, key, value) => json!({
+            "type": "agent_config_updated",
+            "agent_id": agent_id,
+            "key": key,
+            "value": value,
+        }),
+        AgentCrashed(agent_id, message) => json!({
+            "type": "agent_crashed",
+            "agent_id": agent_id,
+            "message": message,
+        }),
+        AgentError(agent_id, message) => json!({
+            "type": "agent_error",
+            "agent_id": agent_id,
+            "message": message,
+        }),
+        AgentIn(agent_id, pin) => json!({
+            "type": "agent_in",
+            "agent_id": agent_id,
+            "pin": pin,
+        }),
+        AgentInDropped(agent_id, pin) => json!({
+            "type": "agent_in_dropped",
+            "agent_id": agent_id,
+            "pin": pin,
+        }),
+        AgentPaused(agent_id) => json!({ "type": "agent_paused", "agent_id": agent_id }),
+        AgentRestarted(agent_id, attempt) => json!({
+            "type": "agent_restarted",
+            "agent_id": agent_id,
+            "attempt": attempt,
+        }),
+        AgentResumed(agent_id) => json!({ "type": "agent_resumed", "agent_id": agent_id }),
+        AgentSpecUpdated(agent_id) => {
+            json!({ "type": "agent_spec_updated", "agent_id": agent_id })
+        }
+        AgentStarted(agent_id) => json!({ "type": "agent_started", "agent_id": agent_id }),
+        AgentStreamPaused(stream_id) => {
+            json!({ "type": "agent_stream_paused", "stream_id": stream_id })
+        }
+        AgentStreamResumed(stream_id) => {
+            json!({ "type": "agent_stream_resumed", "stream_id": stream_id })
+        }
+        Board(name, value) => json!({ "type": "board", "name": name, "value": value }),
+        StreamFileError(path, message) => json!({
+            "type": "stream_file_error",
+            "path": path,
+            "message": message,
+        }),
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::FnvIndexMap;
+use crate::askit::ASKit;
+
+// interval between checks for quiescence
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// time given to each agent to stop, even when the drain used up the timeout
+const MIN_STOP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What was left behind by `ASKit::shutdown`.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownReport {
+    /// Whether all in-flight messages were processed before the timeout.
+    ///
+    /// The inputs held by paused agents are not processed, and do not hold up the drain.
+    pub drained: bool,
+
+    /// Number of messages abandoned in the main loop.
+    pub pending_messages: usize,
+
+    /// Number of inputs abandoned in each agent queue, including the inputs held by paused agents.
+    /// (agent id -> count)
+    pub pending_inputs: FnvIndexMap<String, usize>,
+
+    /// Number of agents stopped.
+    pub stopped_agents: usize,
+
+    /// Agents which failed or timed out to stop. (agent id, message)
+    pub stop_failures: Vec<(String, String)>,
+
+    /// Error while shutting down MCP connections.
+    pub mcp_error: Option<String>,
+}
+
+impl ShutdownReport {
+    /// Whether nothing was abandoned.
+    pub fn is_clean(&self) -> bool {
+        self.drained
+            && self.pending_messages == 0
+            && self.pending_inputs.is_empty()
+            && self.stop_failures.is_empty()
+            && self.mcp_error.is_none()
+    }
+}
+
+pub(crate) async fn shutdown(askit: &ASKit, timeout: Duration) -> ShutdownReport {
+    let deadline = Instant::now() + timeout;
+    let mut report = ShutdownReport::default();
+
+    log::info!("Shutting down ASKit");
+    askit.accepting.store(false, Ordering::Release);
+
+    report.drained = drain(askit, deadline).await;
+    {
+        let agent_txs = askit.agent_txs.lock().unwrap();
+        for (agent_id, tx) in agent_txs.iter() {
+            let len = tx.input_len();
+            if len > 0 {
+                report.pending_inputs.insert(agent_id.clone(), len);
+            }
+        }
+    }
+    if !report.drained {
+        report.pending_messages = askit.in_flight.load(Ordering::Acquire);
+        log::warn!(
+            "Shutdown timed out while draining: {} messages and {} agent queues pending",
+            report.pending_messages,
+            report.pending_inputs.len()
+        );
+    }
+
+    stop_agents(askit, deadline, &mut report).await;
+
+    #[cfg(feature = "mcp")]
+    if let Err(e) = crate::mcp::shutdown_mcp_connections(askit).await {
+        report.mcp_error = Some(e.to_string());
+    }
+
+    // write out the rest of the recording and of the board values
+    askit.stop_recording();
+    askit.flush_board_store();
+
+    askit.quit();
+    log::info!("ASKit shut down");
+
+    report
+}
+
+/// Wait until no message is in flight, apart from the inputs held by paused agents.
+/// Returns false on timeout.
+pub(crate) async fn drain(askit: &ASKit, deadline: Instant) -> bool {
+    // An agent may be between receiving a message and sending its output,
+    // so the graph must be seen quiescent twice in a row.
+    let mut quiescent_once = false;
+    loop {
+        if is_quiescent(askit) {
+            if quiescent_once {
+                return true;
+            }
+            quiescent_once = true;
+        } else {
+            quiescent_once = false;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+}
+
+fn is_quiescent(askit: &ASKit) -> bool {
+    if askit.in_flight.load(Ordering::Acquire) > 0 {
+        return false;
+    }
+    let agent_txs = askit.agent_txs.lock().unwrap();
+    agent_txs.values().all(|tx| tx.is_settled())
+}
+
+async fn stop_agents(askit: &ASKit, deadline: Instant, report: &mut ShutdownReport) {
+    for agent_id in stop_order(askit) {
+        let running = askit.agent_txs.lock().unwrap().contains_key(&agent_id);
+        if !running {
+            continue;
+        }
+        let timeout = deadline
+            .saturating_duration_since(Instant::now())
+            .max(MIN_STOP_TIMEOUT);
+        match tokio::time::timeout(timeout, askit.stop_agent(&agent_id)).await {
+            Ok(Ok(())) => report.stopped_agents += 1,
+            Ok(Err(e)) => report.stop_failures.push((agent_id, e.to_string())),
+            Err(_) => report
+                .stop_failures
+                .push((agent_id, "timed out".to_string())),
+        }
+    }
+
+    let mut streams = askit.streams.lock().unwrap();
+    for stream in streams.values_mut() {
+        stream.set_stopped();
+    }
+}
+
+/// Agent ids in reverse topological order of the channels, so that downstream agents stop first.
+///
+/// Agents in cycles, which have no topological order, are stopped first.
+fn stop_order(askit: &ASKit) -> Vec<String> {
+    let agent_ids: Vec<String> = askit.agents.lock().unwrap().keys().cloned().collect();
+    let channels = askit.channels.lock().unwrap().clone();
+
+    let mut in_degree: FnvIndexMap<&str, usize> =
+        agent_ids.iter().map(|id| (id.as_str(), 0)).collect();
+    for targets in channels.values() {
+        for (target, _, _) in targets {
+            if let Some(d) = in_degree.get_mut(target.as_str()) {
+                *d += 1;
+            }
+        }
+    }
+
+    let mut queue: VecDeque<&str> = in_degree
+        .iter()
+        .filter(|(_, d)| **d == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    let mut order: Vec<String> = Vec::with_capacity(agent_ids.len());
+    while let Some(id) = queue.pop_front() {
+        order.push(id.to_string());
+        let Some(targets) = channels.get(id) else {
+            continue;
+        };
+        for (target, _, _) in targets {
+            if let Some(d) = in_degree.get_mut(target.as_str()) {
+                *d -= 1;
+                if *d == 0 {
+                    queue.push_back(target.as_str());
+                }
+            }
+        }
+    }
+    for id in &agent_ids {
+        if !order.contains(id) {
+            order.push(id.clone());
+        }
+    }
+
+    order.reverse();
+    order
+}
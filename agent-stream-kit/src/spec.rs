@@ -1,4 +1,5 @@
 use std::ops::Not;
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -7,11 +8,21 @@ use crate::FnvIndexMap;
 use crate::config::AgentConfigs;
 use crate::definition::AgentConfigSpecs;
 use crate::error::AgentError;
+use crate::mailbox::DeliveryPolicy;
+use crate::migration::{STREAM_SPEC_VERSION, migrate_enabled, migrate_spec_value};
+use crate::supervisor::SupervisorSpec;
 
 pub type AgentStreamSpecs = FnvIndexMap<String, AgentStreamSpec>;
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+/// Spec of an agent stream.
+///
+/// Specs of older versions are migrated to `STREAM_SPEC_VERSION` when they are deserialized.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "Value")]
 pub struct AgentStreamSpec {
+    /// Version of the schema.
+    pub version: u32,
+
     pub agents: Vec<AgentSpec>,
 
     pub channels: Vec<ChannelSpec>,
@@ -20,6 +31,47 @@ pub struct AgentStreamSpec {
     pub extensions: FnvIndexMap<String, Value>,
 }
 
+impl Default for AgentStreamSpec {
+    fn default() -> Self {
+        Self {
+            version: STREAM_SPEC_VERSION,
+            agents: Vec::new(),
+            channels: Vec::new(),
+            extensions: FnvIndexMap::default(),
+        }
+    }
+}
+
+// the fields of AgentStreamSpec, read after the migration
+#[derive(Deserialize)]
+struct AgentStreamSpecFields {
+    #[serde(default)]
+    version: u32,
+
+    agents: Vec<AgentSpec>,
+
+    channels: Vec<ChannelSpec>,
+
+    #[serde(flatten)]
+    extensions: FnvIndexMap<String, Value>,
+}
+
+impl TryFrom<Value> for AgentStreamSpec {
+    type Error = AgentError;
+
+    fn try_from(mut value: Value) -> Result<Self, Self::Error> {
+        migrate_spec_value(&mut value)?;
+        let fields: AgentStreamSpecFields = serde_json::from_value(value)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        Ok(Self {
+            version: fields.version,
+            agents: fields.agents,
+            channels: fields.channels,
+            extensions: fields.extensions,
+        })
+    }
+}
+
 impl AgentStreamSpec {
     pub fn add_agent(&mut self, agent: AgentSpec) {
         self.agents.push(agent);
@@ -51,10 +103,81 @@ impl AgentStreamSpec {
             .map_err(|e| AgentError::SerializationError(e.to_string()))?;
         Ok(stream)
     }
+
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> Result<String, AgentError> {
+        // multi-line strings are written as block scalars
+        let yaml = serde_yaml_ng::to_string(self)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        Ok(yaml)
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(yaml_str: &str) -> Result<Self, AgentError> {
+        let stream: AgentStreamSpec = serde_yaml_ng::from_str(yaml_str)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        Ok(stream)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> Result<String, AgentError> {
+        // multi-line strings are written as multi-line literal strings
+        let toml = toml::to_string_pretty(self)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        Ok(toml)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml_str: &str) -> Result<Self, AgentError> {
+        let stream: AgentStreamSpec =
+            toml::from_str(toml_str).map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        Ok(stream)
+    }
+
+    /// Parse a spec in the format for the file extension: `json`, and `yaml`, `yml` or `toml`
+    /// when the feature of the format is enabled.
+    pub fn from_str_with_extension(s: &str, extension: &str) -> Result<Self, AgentError> {
+        match extension {
+            "json" => Self::from_json(s),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Self::from_yaml(s),
+            #[cfg(feature = "toml")]
+            "toml" => Self::from_toml(s),
+            _ => Err(AgentError::InvalidFileExtension(extension.to_string())),
+        }
+    }
+
+    /// Read a spec from a file, in the format for its extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        if !is_stream_file_extension(extension) {
+            return Err(AgentError::InvalidFileExtension(extension.to_string()));
+        }
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| AgentError::IoError(format!("Failed to read stream file: {}", e)))?;
+        Self::from_str_with_extension(&s, extension)
+    }
+}
+
+/// Whether files with the extension can be read as stream specs.
+pub(crate) fn is_stream_file_extension(extension: &str) -> bool {
+    match extension {
+        "json" => true,
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => true,
+        #[cfg(feature = "toml")]
+        "toml" => true,
+        _ => false,
+    }
 }
 
 /// Information held by each agent.
+///
+/// The deprecated `enabled` flag of older specs is migrated to `disabled` when the spec is
+/// deserialized.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Value")]
 pub struct AgentSpec {
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub id: String,
@@ -63,6 +186,10 @@ pub struct AgentSpec {
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub def_name: String,
 
+    /// Version of the AgentDefinition the spec was created with.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub def_version: Option<String>,
+
     /// List of input pin names.
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub inputs: Option<Vec<String>>,
@@ -86,10 +213,94 @@ pub struct AgentSpec {
     #[serde(default, skip_serializing_if = "<&bool>::not")]
     pub disabled: bool,
 
+    /// Capacity of the input queue. Overrides the definition's capacity.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub queue_capacity: Option<usize>,
+
+    /// Delivery policy of the input queue. Overrides the definition's policy.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub delivery_policy: Option<DeliveryPolicy>,
+
+    /// Number of inputs a paused `Block` queue holds. Overrides the definition's pause capacity.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pause_capacity: Option<usize>,
+
+    /// How the agent is restarted when it crashes.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub supervisor: Option<SupervisorSpec>,
+
     #[serde(flatten)]
     pub extensions: FnvIndexMap<String, serde_json::Value>,
 }
 
+// the fields of AgentSpec, read after the migration
+#[derive(Deserialize)]
+struct AgentSpecFields {
+    #[serde(default)]
+    id: String,
+
+    #[serde(default)]
+    def_name: String,
+
+    #[serde(default)]
+    def_version: Option<String>,
+
+    #[serde(default)]
+    inputs: Option<Vec<String>>,
+
+    #[serde(default)]
+    outputs: Option<Vec<String>>,
+
+    configs: Option<AgentConfigs>,
+
+    config_specs: Option<AgentConfigSpecs>,
+
+    #[serde(default)]
+    disabled: bool,
+
+    #[serde(default)]
+    queue_capacity: Option<usize>,
+
+    #[serde(default)]
+    delivery_policy: Option<DeliveryPolicy>,
+
+    #[serde(default)]
+    pause_capacity: Option<usize>,
+
+    #[serde(default)]
+    supervisor: Option<SupervisorSpec>,
+
+    #[serde(flatten)]
+    extensions: FnvIndexMap<String, serde_json::Value>,
+}
+
+impl TryFrom<Value> for AgentSpec {
+    type Error = AgentError;
+
+    fn try_from(mut value: Value) -> Result<Self, Self::Error> {
+        migrate_enabled(&mut value);
+        let fields: AgentSpecFields = serde_json::from_value(value)
+            .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+        #[allow(deprecated)]
+        Ok(Self {
+            id: fields.id,
+            def_name: fields.def_name,
+            def_version: fields.def_version,
+            inputs: fields.inputs,
+            outputs: fields.outputs,
+            configs: fields.configs,
+            config_specs: fields.config_specs,
+            enabled: false,
+            disabled: fields.disabled,
+            queue_capacity: fields.queue_capacity,
+            delivery_policy: fields.delivery_policy,
+            pause_capacity: fields.pause_capacity,
+            supervisor: fields.supervisor,
+            extensions: fields.extensions,
+        })
+    }
+}
+
 impl AgentSpec {
     pub fn update(&mut self, value: &Value) -> Result<(), AgentError> {
         let update_map = value
@@ -108,6 +319,9 @@ impl AgentSpec {
                         self.def_name = def_name_str.to_string();
                     }
                 }
+                "def_version" => {
+                    self.def_version = v.as_str().map(|s| s.to_string());
+                }
                 "inputs" => {
                     if let Some(inputs_array) = v.as_array() {
                         self.inputs = Some(
@@ -138,6 +352,22 @@ impl AgentSpec {
                         self.disabled = disabled_bool;
                     }
                 }
+                "queue_capacity" => {
+                    self.queue_capacity = v.as_u64().map(|n| n as usize);
+                }
+                "delivery_policy" => {
+                    let policy: Option<DeliveryPolicy> = serde_json::from_value(v.clone())
+                        .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+                    self.delivery_policy = policy;
+                }
+                "pause_capacity" => {
+                    self.pause_capacity = v.as_u64().map(|n| n as usize);
+                }
+                "supervisor" => {
+                    let supervisor: Option<SupervisorSpec> = serde_json::from_value(v.clone())
+                        .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+                    self.supervisor = supervisor;
+                }
                 _ => {
                     // Update extensions
                     self.extensions.insert(k.clone(), v.clone());
@@ -158,3 +388,78 @@ pub struct ChannelSpec {
     pub target: String,
     pub target_handle: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPT: &str = "You are a helpful assistant.\nAnswer briefly.\n";
+
+    fn sample_spec() -> AgentStreamSpec {
+        serde_json::from_value(serde_json::json!({
+            "agents": [
+                {
+                    "id": "1",
+                    "def_name": "std::LLMAgent",
+                    "configs": { "prompt": PROMPT, "limit": 3 },
+                },
+                { "id": "2", "def_name": "std::Sink" },
+            ],
+            "channels": [
+                { "source": "1", "source_handle": "out", "target": "2", "target_handle": "in" },
+            ],
+            "description": "sample",
+        }))
+        .unwrap()
+    }
+
+    fn assert_same(a: &AgentStreamSpec, b: &AgentStreamSpec) {
+        assert_eq!(
+            serde_json::to_value(a).unwrap(),
+            serde_json::to_value(b).unwrap()
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_round_trip() {
+        let spec = sample_spec();
+        let yaml = spec.to_yaml().unwrap();
+        // readable as a block scalar
+        assert!(yaml.contains("You are a helpful assistant.\n"));
+        assert!(yaml.contains("description: sample"));
+        assert_same(&AgentStreamSpec::from_yaml(&yaml).unwrap(), &spec);
+        assert_same(
+            &AgentStreamSpec::from_str_with_extension(&yaml, "yml").unwrap(),
+            &spec,
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_round_trip() {
+        let spec = sample_spec();
+        let toml = spec.to_toml().unwrap();
+        assert!(toml.contains("You are a helpful assistant.\n"));
+        assert!(toml.contains("description = \"sample\""));
+        assert_same(&AgentStreamSpec::from_toml(&toml).unwrap(), &spec);
+        assert_same(
+            &AgentStreamSpec::from_str_with_extension(&toml, "toml").unwrap(),
+            &spec,
+        );
+    }
+
+    #[test]
+    fn test_from_str_with_extension() {
+        let spec = sample_spec();
+        let json = spec.to_json().unwrap();
+        assert_same(
+            &AgentStreamSpec::from_str_with_extension(&json, "json").unwrap(),
+            &spec,
+        );
+        assert!(matches!(
+            AgentStreamSpec::from_str_with_extension(&json, "txt"),
+            Err(AgentError::InvalidFileExtension(ext)) if ext == "txt"
+        ));
+    }
+}
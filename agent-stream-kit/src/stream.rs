@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::askit::ASKit;
+use crate::board_agent;
 use crate::error::AgentError;
 use crate::id::{new_id, update_ids};
 use crate::spec::AgentStreamSpec;
@@ -16,6 +17,8 @@ pub struct AgentStream {
 
     running: bool,
 
+    paused: bool,
+
     spec: AgentStreamSpec,
 }
 
@@ -32,6 +35,7 @@ impl AgentStream {
             id: new_id(),
             name,
             running: false,
+            paused: false,
             spec,
         }
     }
@@ -44,6 +48,11 @@ impl AgentStream {
         &self.spec
     }
 
+    /// Replace the whole spec. The ids are kept as they are.
+    pub(crate) fn set_spec(&mut self, spec: AgentStreamSpec) {
+        self.spec = spec;
+    }
+
     pub fn update_spec(&mut self, value: &Value) -> Result<(), AgentError> {
         let update_map = value
             .as_object()
@@ -94,6 +103,10 @@ impl AgentStream {
         self.running
     }
 
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
     pub async fn start(&mut self, askit: &ASKit) -> Result<(), AgentError> {
         if self.running {
             // Already running
@@ -101,10 +114,15 @@ impl AgentStream {
         }
         self.running = true;
 
-        for agent in self.spec.agents.iter() {
-            if agent.disabled {
-                continue;
-            }
+        // Board-> and Var-> agents start last, so that the retained values they emit
+        // on start reach running agents
+        let (readers, others): (Vec<_>, Vec<_>) = self
+            .spec
+            .agents
+            .iter()
+            .filter(|agent| !agent.disabled)
+            .partition(|agent| board_agent::reads_board(agent));
+        for agent in others.into_iter().chain(readers) {
             askit.start_agent(&agent.id).await.unwrap_or_else(|e| {
                 log::error!("Failed to start agent {}: {}", agent.id, e);
             });
@@ -120,8 +138,53 @@ impl AgentStream {
             });
         }
         self.running = false;
+        self.paused = false;
         Ok(())
     }
+
+    /// Mark the stream as stopped after its agents were stopped individually.
+    pub(crate) fn set_stopped(&mut self) {
+        self.running = false;
+        self.paused = false;
+    }
+
+    /// Pause all agents of the running stream.
+    ///
+    /// Returns false when the stream was not paused because it is not running or already paused.
+    pub async fn pause(&mut self, askit: &ASKit) -> Result<bool, AgentError> {
+        if !self.running || self.paused {
+            return Ok(false);
+        }
+        for agent in self.spec.agents.iter() {
+            if agent.disabled {
+                continue;
+            }
+            askit.pause_agent(&agent.id).await.unwrap_or_else(|e| {
+                log::error!("Failed to pause agent {}: {}", agent.id, e);
+            });
+        }
+        self.paused = true;
+        Ok(true)
+    }
+
+    /// Resume all agents of the paused stream.
+    ///
+    /// Returns false when the stream was not paused.
+    pub async fn resume(&mut self, askit: &ASKit) -> Result<bool, AgentError> {
+        if !self.paused {
+            return Ok(false);
+        }
+        for agent in self.spec.agents.iter() {
+            if agent.disabled {
+                continue;
+            }
+            askit.resume_agent(&agent.id).await.unwrap_or_else(|e| {
+                log::error!("Failed to resume agent {}: {}", agent.id, e);
+            });
+        }
+        self.paused = false;
+        Ok(true)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -129,6 +192,8 @@ pub struct AgentStreamInfo {
     pub id: String,
     pub name: String,
     pub running: bool,
+    #[serde(default)]
+    pub paused: bool,
 }
 
 impl From<&AgentStream> for AgentStreamInfo {
@@ -137,6 +202,7 @@ impl From<&AgentStream> for AgentStreamInfo {
             id: stream.id.clone(),
             name: stream.name.clone(),
             running: stream.running,
+            paused: stream.paused,
         }
     }
 }
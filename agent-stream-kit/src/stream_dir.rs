@@ -0,0 +1,408 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::FnvIndexMap;
+use crate::askit::{ASKit, is_valid_stream_name};
+use crate::error::AgentError;
+use crate::id::new_id;
+use crate::spec::{AgentStreamSpec, is_stream_file_extension};
+
+#[cfg(feature = "watch")]
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Keeps agent streams in sync with a directory of stream files.
+///
+/// Each file is loaded as a stream named after its path relative to the directory, without
+/// the extension, so `billing/invoices.json` becomes the stream `billing/invoices`.
+/// The format is chosen by the extension, as in `AgentStreamSpec::from_file`.
+/// On every `sync`, new files are added, changed files are applied to their running stream with
+/// `ASKit::apply_stream_spec`, and the streams of deleted files are removed.
+///
+/// Problems with single files are emitted as `ASKitEvent::StreamFileError` and do not stop
+/// the other files from loading.
+#[derive(Clone)]
+pub struct StreamDirectory {
+    askit: ASKit,
+    dir: PathBuf,
+    auto_start: bool,
+    files: Arc<AsyncMutex<FnvIndexMap<PathBuf, StreamFile>>>,
+}
+
+// a stream loaded from a file
+struct StreamFile {
+    name: String,
+
+    stream_id: String,
+
+    // contents when it was loaded last
+    content: String,
+
+    // agent id in the file -> agent id in the running stream
+    agent_ids: FnvIndexMap<String, String>,
+}
+
+impl StreamDirectory {
+    pub fn new(askit: ASKit, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            askit,
+            dir: dir.into(),
+            auto_start: true,
+            files: Default::default(),
+        }
+    }
+
+    /// Whether streams are started when they are added. Defaults to true.
+    pub fn auto_start(mut self, auto_start: bool) -> Self {
+        self.auto_start = auto_start;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Id of the stream loaded from the file with the given stream name.
+    pub async fn stream_id(&self, name: &str) -> Option<String> {
+        let files = self.files.lock().await;
+        files
+            .values()
+            .find(|file| file.name == name)
+            .map(|file| file.stream_id.clone())
+    }
+
+    /// Bring the streams up to date with the files in the directory.
+    ///
+    /// Fails only when the directory itself cannot be read.
+    pub async fn sync(&self) -> Result<(), AgentError> {
+        // the file system is read off the runtime
+        let dir = self.dir.clone();
+        let scan = tokio::task::spawn_blocking(move || scan(&dir))
+            .await
+            .map_err(|e| {
+                AgentError::IoError(format!("Failed to scan stream directory: {}", e))
+            })??;
+        for (dir, e) in scan.dir_errors.iter() {
+            self.emit_error(dir, e.clone());
+        }
+
+        let mut files = self.files.lock().await;
+
+        // the streams of files in unreadable directories are kept
+        let removed: Vec<PathBuf> = files
+            .keys()
+            .filter(|path| {
+                !scan.files.iter().any(|(p, _)| p == *path)
+                    && !scan.dir_errors.iter().any(|(dir, _)| path.starts_with(dir))
+            })
+            .cloned()
+            .collect();
+        for path in removed {
+            let Some(file) = files.swap_remove(&path) else {
+                continue;
+            };
+            log::info!("Removing stream of {}", path.display());
+            if let Err(e) = self.askit.remove_agent_stream(&file.stream_id).await {
+                self.emit_error(&path, e);
+            }
+        }
+
+        for (path, content) in scan.files {
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    self.emit_error(&path, e);
+                    continue;
+                }
+            };
+            if let Some(file) = files.get_mut(&path) {
+                if file.content == content {
+                    continue;
+                }
+                log::info!("Updating stream of {}", path.display());
+                if let Err(e) = self.update_stream(&path, file, content).await {
+                    self.emit_error(&path, e);
+                }
+            } else {
+                log::info!("Adding stream of {}", path.display());
+                let name = match self.stream_name(&path) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        self.emit_error(&path, e);
+                        continue;
+                    }
+                };
+                // such as `a.json` and `a.yaml`
+                if files.values().any(|file| file.name == name) {
+                    self.emit_error(&path, AgentError::DuplicateStreamName(name));
+                    continue;
+                }
+                let file = match self.add_stream(&path, name, content) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        self.emit_error(&path, e);
+                        continue;
+                    }
+                };
+                let stream_id = file.stream_id.clone();
+                files.insert(path.clone(), file);
+                if self.auto_start
+                    && let Err(e) = self.askit.start_agent_stream(&stream_id).await
+                {
+                    self.emit_error(&path, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sync now, and again whenever something in the directory changes.
+    ///
+    /// Watching stops when the returned watcher is dropped.
+    #[cfg(feature = "watch")]
+    pub async fn watch(&self) -> Result<StreamDirectoryWatcher, AgentError> {
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        self.sync().await?;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        })
+        .map_err(|e| AgentError::IoError(format!("Failed to watch stream directory: {}", e)))?;
+        watcher
+            .watch(&self.dir, RecursiveMode::Recursive)
+            .map_err(|e| AgentError::IoError(format!("Failed to watch stream directory: {}", e)))?;
+
+        let stream_dir = self.clone();
+        let task = tokio::spawn(async move {
+            while let Some(res) = rx.recv().await {
+                match res {
+                    // reading the files while syncing must not trigger another sync
+                    Ok(notify::Event {
+                        kind: EventKind::Access(_),
+                        ..
+                    }) => continue,
+                    Ok(_) => {}
+                    Err(e) => {
+                        let path = stream_dir.dir.clone();
+                        stream_dir.emit_error(&path, AgentError::IoError(e.to_string()));
+                        continue;
+                    }
+                }
+                // let a burst of changes settle, such as an editor saving a file
+                tokio::time::sleep(WATCH_DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
+                if let Err(e) = stream_dir.sync().await {
+                    let path = stream_dir.dir.clone();
+                    stream_dir.emit_error(&path, e);
+                }
+            }
+        });
+
+        Ok(StreamDirectoryWatcher {
+            _watcher: watcher,
+            task,
+        })
+    }
+
+    fn add_stream(
+        &self,
+        path: &Path,
+        name: String,
+        content: String,
+    ) -> Result<StreamFile, AgentError> {
+        let spec = parse_stream_file(path, &content)?;
+        let file_ids: Vec<String> = spec.agents.iter().map(|a| a.id.clone()).collect();
+
+        let stream_id = self.askit.add_agent_stream_strict(name.clone(), spec)?;
+
+        // the ids are changed in order when the stream is added
+        let live_ids: Vec<String> = {
+            let streams = self.askit.streams.lock().unwrap();
+            streams
+                .get(&stream_id)
+                .map(|stream| stream.spec().agents.iter().map(|a| a.id.clone()).collect())
+                .unwrap_or_default()
+        };
+        let agent_ids = file_ids.into_iter().zip(live_ids).collect();
+
+        Ok(StreamFile {
+            name,
+            stream_id,
+            content,
+            agent_ids,
+        })
+    }
+
+    async fn update_stream(
+        &self,
+        path: &Path,
+        file: &mut StreamFile,
+        content: String,
+    ) -> Result<(), AgentError> {
+        let mut spec = parse_stream_file(path, &content)?;
+        self.askit.migrate_stream_spec(&mut spec);
+        spec.check(&self.askit, &file.name)?;
+
+        // Map the ids in the file to the running agents. Agents new to the file get fresh ids,
+        // which never clash with the running ones.
+        let file_ids: Vec<String> = spec.agents.iter().map(|a| a.id.clone()).collect();
+        let mut id_map = file.agent_ids.clone();
+        for id in &file_ids {
+            if !id_map.contains_key(id) {
+                id_map.insert(id.clone(), new_id());
+            }
+        }
+        for agent in spec.agents.iter_mut() {
+            agent.id = id_map[&agent.id].clone();
+        }
+        for channel in spec.channels.iter_mut() {
+            if let Some(id) = id_map.get(&channel.source) {
+                channel.source = id.clone();
+            }
+            if let Some(id) = id_map.get(&channel.target) {
+                channel.target = id.clone();
+            }
+        }
+
+        let diff = self.askit.apply_stream_spec(&file.stream_id, spec).await?;
+
+        // added agents are in the same order as in the file
+        let mut added = diff.added_agents.iter().map(|a| a.id.clone());
+        let mut agent_ids = FnvIndexMap::default();
+        for id in file_ids {
+            let live_id = match file.agent_ids.get(&id) {
+                Some(live_id) => live_id.clone(),
+                None => added.next().unwrap_or_default(),
+            };
+            agent_ids.insert(id, live_id);
+        }
+        file.agent_ids = agent_ids;
+        file.content = content;
+        Ok(())
+    }
+
+    fn stream_name(&self, path: &Path) -> Result<String, AgentError> {
+        let relative = path
+            .strip_prefix(&self.dir)
+            .unwrap_or(path)
+            .with_extension("");
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if !is_valid_stream_name(&name) {
+            return Err(AgentError::InvalidStreamName(name));
+        }
+        Ok(name)
+    }
+
+    fn emit_error(&self, path: &Path, e: AgentError) {
+        log::error!("{}: {}", path.display(), e);
+        self.askit
+            .emit_stream_file_error(path.display().to_string(), e.to_string());
+    }
+}
+
+fn parse_stream_file(path: &Path, content: &str) -> Result<AgentStreamSpec, AgentError> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    AgentStreamSpec::from_str_with_extension(content, extension)
+}
+
+// what a scan of the directory found
+#[derive(Default)]
+struct Scan {
+    // stream files sorted by path, with their contents
+    files: Vec<(PathBuf, Result<String, AgentError>)>,
+
+    // subdirectories which could not be read
+    dir_errors: Vec<(PathBuf, AgentError)>,
+}
+
+/// Collect the stream files under the directory, recursively, and read them.
+fn scan(dir: &Path) -> Result<Scan, AgentError> {
+    let mut paths = Vec::new();
+    let mut scan = Scan::default();
+    scan_dir(dir, &mut paths, &mut scan.dir_errors)?;
+    paths.sort();
+    scan.files = paths
+        .into_iter()
+        .map(|path| {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| AgentError::IoError(format!("Failed to read stream file: {}", e)));
+            (path, content)
+        })
+        .collect();
+    Ok(scan)
+}
+
+fn scan_dir(
+    dir: &Path,
+    paths: &mut Vec<PathBuf>,
+    dir_errors: &mut Vec<(PathBuf, AgentError)>,
+) -> Result<(), AgentError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        AgentError::IoError(format!("Failed to read directory {}: {}", dir.display(), e))
+    })?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            // an unreadable subdirectory does not stop the others from loading
+            if let Err(e) = scan_dir(&path, paths, dir_errors) {
+                dir_errors.push((path, e));
+            }
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(is_stream_file_extension)
+        {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Watches a `StreamDirectory` until dropped.
+#[cfg(feature = "watch")]
+pub struct StreamDirectoryWatcher {
+    _watcher: notify::RecommendedWatcher,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "watch")]
+impl Drop for StreamDirectoryWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_name() {
+        let stream_dir = StreamDirectory::new(ASKit::new(), "/streams");
+        assert_eq!(
+            stream_dir
+                .stream_name(Path::new("/streams/billing/invoices.json"))
+                .unwrap(),
+            "billing/invoices"
+        );
+        assert_eq!(
+            stream_dir
+                .stream_name(Path::new("/streams/a.json"))
+                .unwrap(),
+            "a"
+        );
+        assert!(
+            stream_dir
+                .stream_name(Path::new("/streams/bad:name.json"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use std::pin::pin;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use askit_macros::askit_agent;
+
+use crate::FnvIndexMap;
+use crate::agent::{Agent, AgentData, AsAgent};
+use crate::askit::ASKit;
+use crate::config::AgentConfigs;
+use crate::context::AgentContext;
+use crate::error::AgentError;
+use crate::id::update_ids;
+use crate::output::AgentOutput;
+use crate::spec::{AgentSpec, AgentStreamSpec, ChannelSpec};
+use crate::value::AgentValue;
+
+const CATEGORY: &str = "Core/Subflow";
+
+const CONFIG_STREAM: &str = "stream";
+const CONFIG_SPEC: &str = "spec";
+const CONFIG_INPUT_MAP: &str = "input_map";
+const CONFIG_OUTPUT_MAP: &str = "output_map";
+
+// target handle prefix of the channels from the interior agents to the subflow
+const OUTPUT_PREFIX: &str = "%out/";
+
+// limit of nested subflows
+const MAX_DEPTH: usize = 16;
+
+// how long to wait for an embedded stream that is being started or stopped
+const STREAM_WAIT: Duration = Duration::from_secs(1);
+
+/// Embeds another agent stream as a single agent.
+///
+/// The interior stream is given by the name of a loaded stream in `stream`, or inline in `spec`.
+/// `input_map` and `output_map` expose pins of interior agents as pins of the subflow:
+///
+/// ```json
+/// { "in": { "agent": "<interior agent id>", "pin": "value" } }
+/// ```
+///
+/// A copy of the interior stream with fresh ids is created every time the subflow is started.
+/// A subflow cannot embed the stream it is in, nor a stream embedding it through other subflows.
+/// The interior agents belong to the stream of the subflow agent, and their vars are in the
+/// `VarScope::Subflow` of the subflow agent.
+#[askit_agent(
+    kind = "Subflow",
+    title = "Subflow",
+    category = CATEGORY,
+    string_config(name = CONFIG_STREAM, title = "Stream"),
+    object_config(name = CONFIG_SPEC, title = "Spec", hidden),
+    object_config(name = CONFIG_INPUT_MAP, title = "Inputs"),
+    object_config(name = CONFIG_OUTPUT_MAP, title = "Outputs"),
+)]
+struct SubflowAgent {
+    data: AgentData,
+
+    // ids of the interior agents
+    interior: Vec<String>,
+
+    // exposed input pin -> (interior agent id, pin)
+    inputs: FnvIndexMap<String, (String, String)>,
+}
+
+#[async_trait]
+impl AsAgent for SubflowAgent {
+    fn new(askit: ASKit, id: String, mut spec: AgentSpec) -> Result<Self, AgentError> {
+        set_exposed_pins(&mut spec)?;
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+            interior: Vec::new(),
+            inputs: FnvIndexMap::default(),
+        })
+    }
+
+    fn configs_changed(&mut self) -> Result<(), AgentError> {
+        // takes effect when the subflow is started next time
+        set_exposed_pins(&mut self.data.spec)
+    }
+
+    async fn start(&mut self) -> Result<(), AgentError> {
+        if let Err(e) = self.instantiate().await {
+            self.teardown().await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), AgentError> {
+        self.teardown().await;
+        Ok(())
+    }
+
+    async fn pause(&mut self) -> Result<(), AgentError> {
+        for agent_id in &self.interior {
+            self.askit()
+                .pause_agent(agent_id)
+                .await
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to pause interior agent {}: {}", agent_id, e);
+                });
+        }
+        Ok(())
+    }
+
+    async fn resume(&mut self) -> Result<(), AgentError> {
+        for agent_id in &self.interior {
+            self.askit()
+                .resume_agent(agent_id)
+                .await
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to resume interior agent {}: {}", agent_id, e);
+                });
+        }
+        Ok(())
+    }
+
+    async fn process(
+        &mut self,
+        ctx: AgentContext,
+        pin: String,
+        value: AgentValue,
+    ) -> Result<(), AgentError> {
+        if let Some(exposed) = pin.strip_prefix(OUTPUT_PREFIX) {
+            return self.output(ctx, exposed, value).await;
+        }
+        let Some((agent_id, interior_pin)) = self.inputs.get(&pin) else {
+            return Err(AgentError::PinNotFound(pin));
+        };
+        self.askit()
+            .agent_input(agent_id.clone(), ctx, interior_pin.clone(), value)
+            .await
+    }
+}
+
+impl SubflowAgent {
+    /// Create and start a copy of the interior stream.
+    async fn instantiate(&mut self) -> Result<(), AgentError> {
+        if self.subflow_path().len() >= MAX_DEPTH {
+            return Err(AgentError::InvalidConfig(
+                "Subflows are nested too deeply".into(),
+            ));
+        }
+
+        let mut spec = self.interior_spec().await?;
+        self.askit().migrate_stream_spec(&mut spec);
+        let configs = self.configs()?.clone();
+        let input_map = pin_map(&configs, CONFIG_INPUT_MAP)?;
+        let output_map = pin_map(&configs, CONFIG_OUTPUT_MAP)?;
+
+        let (agents, channels) = update_ids(&spec.agents, &spec.channels);
+        let new_ids: FnvIndexMap<&str, &str> = spec
+            .agents
+            .iter()
+            .zip(&agents)
+            .map(|(old, new)| (old.id.as_str(), new.id.as_str()))
+            .collect();
+        let interior_id = |agent_id: &str| {
+            new_ids
+                .get(agent_id)
+                .map(|id| id.to_string())
+                .ok_or_else(|| AgentError::AgentNotFound(agent_id.to_string()))
+        };
+
+        // check the channels before adding anything
+        let mut checked = AgentStreamSpec::default();
+        checked.agents.extend(agents.iter().cloned());
+        checked.agents.push(self.spec().clone());
+        let mut outputs = Vec::new();
+        for (exposed, (agent_id, pin)) in output_map {
+            outputs.push(ChannelSpec {
+                source: interior_id(&agent_id)?,
+                source_handle: pin,
+                target: self.id().to_string(),
+                target_handle: format!("{}{}", OUTPUT_PREFIX, exposed),
+            });
+        }
+        for channel in channels.iter().chain(&outputs) {
+            self.askit().check_pin_types(&checked, channel)?;
+        }
+
+        let askit = self.askit().clone();
+        let stream_id = self.stream_id().to_string();
+        let mut subflow_path = self.subflow_path().to_vec();
+        subflow_path.push(self.id().to_string());
+        for agent in &agents {
+            askit.add_subflow_agent_internal(
+                stream_id.clone(),
+                subflow_path.clone(),
+                agent.clone(),
+            )?;
+            self.interior.push(agent.id.clone());
+        }
+        for channel in channels {
+            askit
+                .add_channel_internal(channel.clone())
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to add_channel {}: {}", channel.source, e);
+                });
+        }
+
+        self.inputs.clear();
+        for (exposed, (agent_id, pin)) in input_map {
+            self.inputs.insert(exposed, (interior_id(&agent_id)?, pin));
+        }
+        for channel in outputs {
+            askit.add_channel_internal(channel)?;
+        }
+
+        for agent in &agents {
+            if agent.disabled {
+                continue;
+            }
+            askit.start_agent(&agent.id).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop and remove the interior agents.
+    async fn teardown(&mut self) {
+        for agent_id in self.interior.drain(..) {
+            self.data
+                .askit
+                .remove_agent_internal(&agent_id)
+                .await
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to remove interior agent {}: {}", agent_id, e);
+                });
+        }
+        self.inputs.clear();
+        self.askit()
+            .subflow_streams
+            .lock()
+            .unwrap()
+            .swap_remove(self.id());
+    }
+
+    async fn interior_spec(&self) -> Result<AgentStreamSpec, AgentError> {
+        let configs = self.configs()?;
+        if let Ok(spec) = configs.get(CONFIG_SPEC)
+            && spec.as_object().is_some_and(|spec| !spec.is_empty())
+        {
+            return serde_json::from_value(spec.to_json())
+                .map_err(|e| AgentError::InvalidConfig(format!("{}: {}", CONFIG_SPEC, e)));
+        }
+
+        let name = configs.get_string_or_default(CONFIG_STREAM);
+        if name.is_empty() {
+            return Err(AgentError::InvalidConfig(
+                "Subflow has neither a stream nor a spec".into(),
+            ));
+        }
+        let stream_id = {
+            let stream_names = self.askit().stream_names.lock().unwrap();
+            stream_names
+                .iter()
+                .find(|(_, stream_name)| **stream_name == name)
+                .map(|(id, _)| id.clone())
+        };
+        let Some(stream_id) = stream_id else {
+            return Err(AgentError::StreamNotFound(name));
+        };
+        self.embed_stream(&stream_id, &name)?;
+
+        // the stream is taken out of the streams while it is started or stopped
+        let askit = self.askit();
+        let wait = async {
+            loop {
+                let mut changed = pin!(askit.streams_changed.notified());
+                changed.as_mut().enable();
+                if let Some(spec) = askit.get_agent_stream_spec(&stream_id).await {
+                    return Ok(spec);
+                }
+                if !askit.stream_names.lock().unwrap().contains_key(&stream_id) {
+                    return Err(AgentError::StreamNotFound(name.clone()));
+                }
+                changed.await;
+            }
+        };
+        tokio::time::timeout(STREAM_WAIT, wait)
+            .await
+            .unwrap_or_else(|_| {
+                Err(AgentError::InvalidConfig(format!(
+                    "Stream {} is still being started or stopped",
+                    name
+                )))
+            })
+    }
+
+    /// Record the stream embedded by this subflow, unless the subflow is inside of it.
+    ///
+    /// The check only reads the recorded streams, since the agents of the stream and the
+    /// enclosing subflows may be locked while they are started.
+    fn embed_stream(&self, stream_id: &str, name: &str) -> Result<(), AgentError> {
+        let mut subflow_streams = self.askit().subflow_streams.lock().unwrap();
+        let inside = stream_id == self.stream_id()
+            || self
+                .subflow_path()
+                .iter()
+                .any(|id| subflow_streams.get(id).is_some_and(|s| s == stream_id));
+        if inside {
+            return Err(AgentError::InvalidConfig(format!(
+                "Subflow cannot embed the stream {} it is inside of",
+                name
+            )));
+        }
+        subflow_streams.insert(self.id().to_string(), stream_id.to_string());
+        Ok(())
+    }
+}
+
+/// Set the inputs and outputs of the spec to the exposed pins.
+fn set_exposed_pins(spec: &mut AgentSpec) -> Result<(), AgentError> {
+    let Some(configs) = &spec.configs else {
+        return Ok(());
+    };
+    let inputs = pin_map(configs, CONFIG_INPUT_MAP)?;
+    let outputs = pin_map(configs, CONFIG_OUTPUT_MAP)?;
+    spec.inputs = Some(inputs.into_keys().collect());
+    spec.outputs = Some(outputs.into_keys().collect());
+    Ok(())
+}
+
+/// Read a map of exposed pin -> (interior agent id, pin), sorted by the exposed pin.
+fn pin_map(
+    configs: &AgentConfigs,
+    key: &str,
+) -> Result<FnvIndexMap<String, (String, String)>, AgentError> {
+    let Ok(value) = configs.get(key) else {
+        return Ok(FnvIndexMap::default());
+    };
+    let Some(map) = value.as_object() else {
+        return Err(AgentError::InvalidConfig(format!(
+            "{} must be an object",
+            key
+        )));
+    };
+    let mut pins = FnvIndexMap::default();
+    for (exposed, target) in map {
+        let (Some(agent_id), Some(pin)) = (target.get_str("agent"), target.get_str("pin")) else {
+            return Err(AgentError::InvalidConfig(format!(
+                "{}.{} needs agent and pin",
+                key, exposed
+            )));
+        };
+        pins.insert(exposed.clone(), (agent_id.to_string(), pin.to_string()));
+    }
+    pins.sort_keys();
+    Ok(pins)
+}
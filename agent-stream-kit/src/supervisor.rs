@@ -0,0 +1,388 @@
+use std::collections::VecDeque;
+use std::ops::Not;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::agent::{Agent, AgentMessage};
+use crate::askit::ASKit;
+use crate::mailbox::MailboxReceiver;
+use crate::trace;
+
+/// Whether a crashed agent is restarted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Leave the agent stopped.
+    #[default]
+    Never,
+
+    /// Restart the agent with exponential backoff.
+    OnFailure,
+}
+
+/// Supervision strategy of an agent.
+///
+/// An agent crashes when its `start` fails or when `start` or `process` panics.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SupervisorSpec {
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    /// Maximum number of restarts within `window_ms`.
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+
+    /// Delay before the first restart. It doubles on every restart within the window.
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+
+    /// Stop the whole stream when the agent is not restarted anymore.
+    #[serde(default, skip_serializing_if = "<&bool>::not")]
+    pub escalate: bool,
+}
+
+fn default_max_restarts() -> u32 {
+    3
+}
+
+fn default_window_ms() -> u64 {
+    60_000
+}
+
+fn default_backoff_ms() -> u64 {
+    100
+}
+
+fn default_max_backoff_ms() -> u64 {
+    10_000
+}
+
+impl Default for SupervisorSpec {
+    fn default() -> Self {
+        Self {
+            restart: RestartPolicy::default(),
+            max_restarts: default_max_restarts(),
+            window_ms: default_window_ms(),
+            backoff_ms: default_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            escalate: false,
+        }
+    }
+}
+
+impl SupervisorSpec {
+    pub fn on_failure() -> Self {
+        Self {
+            restart: RestartPolicy::OnFailure,
+            ..Default::default()
+        }
+    }
+
+    pub fn max_restarts(mut self, max_restarts: u32, window: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.window_ms = window.as_millis() as u64;
+        self
+    }
+
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff_ms = initial.as_millis() as u64;
+        self.max_backoff_ms = max.as_millis() as u64;
+        self
+    }
+
+    pub fn escalate(mut self) -> Self {
+        self.escalate = true;
+        self
+    }
+}
+
+struct RestartTracker {
+    spec: SupervisorSpec,
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartTracker {
+    fn new(spec: SupervisorSpec) -> Self {
+        Self {
+            spec,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Returns the delay before the next restart, or `None` when the agent should not be restarted.
+    fn next_backoff(&mut self) -> Option<Duration> {
+        if self.spec.restart == RestartPolicy::Never {
+            return None;
+        }
+
+        let now = Instant::now();
+        let window = Duration::from_millis(self.spec.window_ms);
+        while let Some(t) = self.restarts.front() {
+            if now.duration_since(*t) > window {
+                self.restarts.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.restarts.len() >= self.spec.max_restarts as usize {
+            return None;
+        }
+
+        let exp = (self.restarts.len() as u32).min(16);
+        let backoff = self
+            .spec
+            .backoff_ms
+            .saturating_mul(1 << exp)
+            .min(self.spec.max_backoff_ms);
+        self.restarts.push_back(now);
+        Some(Duration::from_millis(backoff))
+    }
+}
+
+enum Exit {
+    Stopped,
+    Crashed(String),
+}
+
+/// Run an agent: start it, process its mailbox, and restart it on crashes according to the supervisor spec.
+pub(crate) async fn run_agent(
+    askit: ASKit,
+    agent_id: String,
+    agent: Arc<AsyncMutex<Box<dyn Agent>>>,
+    mut rx: MailboxReceiver,
+    supervisor: Option<SupervisorSpec>,
+) {
+    let mut tracker = RestartTracker::new(supervisor.unwrap_or_default());
+    let mut restarts = 0;
+    // kept across restarts, so that a restarted agent stays paused
+    let mut paused = false;
+
+    loop {
+        let exit = match start_guarded(&agent).await {
+            Ok(()) => {
+                if restarts == 0 {
+                    askit.emit_agent_started(agent_id.clone());
+                } else {
+                    askit.emit_agent_restarted(agent_id.clone(), restarts);
+                }
+                if paused && let Err(e) = agent.lock().await.pause().await {
+                    log::error!("Pause Error {}: {}", agent_id, e);
+                }
+                process_messages(&askit, &agent_id, &agent, &mut rx, &mut paused).await
+            }
+            Err(message) => Exit::Crashed(message),
+        };
+
+        let Exit::Crashed(message) = exit else {
+            return;
+        };
+
+        log::error!("Agent {} crashed: {}", agent_id, message);
+        askit.emit_agent_crashed(agent_id.clone(), message);
+
+        // reset the agent so that it can be started again
+        if let Err(e) = agent.lock().await.stop().await {
+            log::error!("Failed to stop crashed agent {}: {}", agent_id, e);
+        }
+
+        let Some(delay) = tracker.next_backoff() else {
+            break;
+        };
+        tokio::time::sleep(delay).await;
+
+        if !askit.is_agent_mailbox(&agent_id, &rx) {
+            // stopped while waiting
+            return;
+        }
+        restarts += 1;
+        log::info!("Restarting agent {} (attempt {})", agent_id, restarts);
+    }
+
+    // give up
+    askit.remove_agent_mailbox(&agent_id, &rx);
+    rx.close();
+
+    if tracker.spec.escalate {
+        let stream_id = agent.lock().await.stream_id().to_string();
+        if stream_id.is_empty() {
+            return;
+        }
+        log::warn!(
+            "Stopping stream {} because agent {} gave up",
+            stream_id,
+            agent_id
+        );
+        if let Err(e) = askit.stop_agent_stream(&stream_id).await {
+            log::error!("Failed to stop stream {}: {}", stream_id, e);
+        }
+    }
+}
+
+async fn start_guarded(agent: &Arc<AsyncMutex<Box<dyn Agent>>>) -> Result<(), String> {
+    let result = AssertUnwindSafe(async { agent.lock().await.start().await })
+        .catch_unwind()
+        .await;
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(panic) => Err(panic_message(panic)),
+    }
+}
+
+async fn process_messages(
+    askit: &ASKit,
+    agent_id: &str,
+    agent: &Arc<AsyncMutex<Box<dyn Agent>>>,
+    rx: &mut MailboxReceiver,
+    paused: &mut bool,
+) -> Exit {
+    loop {
+        // inputs stay in the queue while paused
+        let message = if *paused {
+            rx.recv_control().await
+        } else {
+            rx.recv().await
+        };
+        let Some(message) = message else {
+            break;
+        };
+        match message {
+            AgentMessage::Input { ctx, pin, value } => {
+                let span = match askit.span_exporter() {
+                    Some(exporter) => {
+                        Some(trace::start_process_span(exporter, agent, &ctx, &pin).await)
+                    }
+                    None => None,
+                };
+                let ctx = match &span {
+                    Some(span) => span.enter(&ctx),
+                    None => ctx,
+                };
+                let start = Instant::now();
+                let result =
+                    AssertUnwindSafe(async { agent.lock().await.process(ctx, pin, value).await })
+                        .catch_unwind()
+                        .await;
+                askit.observe_process(agent_id, start.elapsed(), !matches!(result, Ok(Ok(()))));
+                if let Some(span) = span {
+                    span.end(match &result {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(e.to_string()),
+                        Err(_) => Some("panic".to_string()),
+                    });
+                }
+                match result {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        log::error!("Process Error {}: {}", agent_id, e);
+                    }
+                    Err(panic) => {
+                        return Exit::Crashed(panic_message(panic));
+                    }
+                }
+            }
+            AgentMessage::Config { key, value } => {
+                agent
+                    .lock()
+                    .await
+                    .set_config(key, value)
+                    .unwrap_or_else(|e| {
+                        log::error!("Config Error {}: {}", agent_id, e);
+                    });
+            }
+            AgentMessage::Configs { configs } => {
+                agent.lock().await.set_configs(configs).unwrap_or_else(|e| {
+                    log::error!("Configs Error {}: {}", agent_id, e);
+                });
+            }
+            AgentMessage::Pause => {
+                if *paused {
+                    continue;
+                }
+                match agent.lock().await.pause().await {
+                    Ok(()) => {
+                        *paused = true;
+                        rx.set_paused(true);
+                        askit.emit_agent_paused(agent_id.to_string());
+                    }
+                    Err(e) => {
+                        log::error!("Pause Error {}: {}", agent_id, e);
+                    }
+                }
+            }
+            AgentMessage::Resume => {
+                if !*paused {
+                    continue;
+                }
+                match agent.lock().await.resume().await {
+                    Ok(()) => {
+                        *paused = false;
+                        rx.set_paused(false);
+                        askit.emit_agent_resumed(agent_id.to_string());
+                    }
+                    Err(e) => {
+                        log::error!("Resume Error {}: {}", agent_id, e);
+                    }
+                }
+            }
+            AgentMessage::Stop => {
+                rx.close();
+                break;
+            }
+        }
+    }
+    Exit::Stopped
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        format!("panic: {}", s)
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        format!("panic: {}", s)
+    } else {
+        "panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_restarts_by_default() {
+        let mut tracker = RestartTracker::new(SupervisorSpec::default());
+        assert!(tracker.next_backoff().is_none());
+    }
+
+    #[test]
+    fn backoff_grows_until_max_restarts() {
+        let spec = SupervisorSpec::on_failure()
+            .max_restarts(3, Duration::from_secs(60))
+            .backoff(Duration::from_millis(100), Duration::from_millis(250));
+        let mut tracker = RestartTracker::new(spec);
+        assert_eq!(tracker.next_backoff(), Some(Duration::from_millis(100)));
+        assert_eq!(tracker.next_backoff(), Some(Duration::from_millis(200)));
+        assert_eq!(tracker.next_backoff(), Some(Duration::from_millis(250)));
+        assert_eq!(tracker.next_backoff(), None);
+    }
+
+    #[test]
+    fn deserialize_with_defaults() {
+        let spec: SupervisorSpec =
+            serde_json::from_str(r#"{"restart":"on_failure","escalate":true}"#).unwrap();
+        assert_eq!(spec.restart, RestartPolicy::OnFailure);
+        assert_eq!(spec.max_restarts, 3);
+        assert!(spec.escalate);
+    }
+}
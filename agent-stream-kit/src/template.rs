@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+use crate::FnvIndexMap;
+use crate::error::AgentError;
+use crate::spec::AgentStreamSpec;
+use crate::validation::value_matches_type;
+use crate::value::AgentValue;
+
+/// Key of the stream extension which declares the parameters of a template.
+pub const TEMPLATE_PARAMETERS_KEY: &str = "parameters";
+
+pub type TemplateParams = FnvIndexMap<String, TemplateParam>;
+
+/// A parameter declared by a stream template.
+///
+/// ```json
+/// "parameters": {
+///     "customer": { "type": "string", "description": "Customer name" },
+///     "limit": { "type": "integer", "default": 10 }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TemplateParam {
+    /// Type of the value, with the same names as config types.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none", default)]
+    pub type_: Option<String>,
+
+    /// Value used when the parameter is not supplied. Without a default the parameter is required.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub default: Option<AgentValue>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub description: Option<String>,
+}
+
+impl AgentStreamSpec {
+    /// Parameters declared in the `parameters` extension.
+    pub fn template_params(&self) -> Result<TemplateParams, AgentError> {
+        let Some(value) = self.extensions.get(TEMPLATE_PARAMETERS_KEY) else {
+            return Ok(TemplateParams::default());
+        };
+        serde_json::from_value(value.clone())
+            .map_err(|e| AgentError::InvalidTemplate(format!("parameters: {}", e)))
+    }
+
+    /// Produce a concrete stream spec by substituting `${param}` in the agent configs.
+    ///
+    /// A config value which is exactly `${param}` is replaced by the parameter value as is,
+    /// keeping its type. Otherwise each `${param}` in a string is replaced by the value as text.
+    /// `$${` is written as a literal `${`.
+    pub fn instantiate(
+        &self,
+        params: &FnvIndexMap<String, AgentValue>,
+    ) -> Result<AgentStreamSpec, AgentError> {
+        let values = resolve_params(&self.template_params()?, params)?;
+
+        let mut spec = self.clone();
+        spec.extensions.shift_remove(TEMPLATE_PARAMETERS_KEY);
+        for agent in spec.agents.iter_mut() {
+            let Some(configs) = agent.configs.take() else {
+                continue;
+            };
+            let mut new_configs = configs.clone();
+            for (key, value) in &configs {
+                let value = substitute(value, &values).map_err(|e| {
+                    AgentError::InvalidTemplate(format!("{}.{}: {}", agent.id, key, e))
+                })?;
+                new_configs.set(key.clone(), value);
+            }
+            agent.configs = Some(new_configs);
+        }
+        Ok(spec)
+    }
+}
+
+/// Check the supplied params against the declared ones and fill in the defaults.
+fn resolve_params(
+    declared: &TemplateParams,
+    params: &FnvIndexMap<String, AgentValue>,
+) -> Result<FnvIndexMap<String, AgentValue>, AgentError> {
+    if let Some(name) = params.keys().find(|name| !declared.contains_key(*name)) {
+        return Err(AgentError::InvalidTemplate(format!(
+            "unknown parameter \"{}\"",
+            name
+        )));
+    }
+
+    let mut values = FnvIndexMap::default();
+    for (name, param) in declared {
+        let Some(value) = params.get(name).or(param.default.as_ref()) else {
+            return Err(AgentError::InvalidTemplate(format!(
+                "parameter \"{}\" is required",
+                name
+            )));
+        };
+        if let Some(type_) = &param.type_
+            && !value_matches_type(value, type_)
+        {
+            return Err(AgentError::InvalidTemplate(format!(
+                "parameter \"{}\" is not of type {}",
+                name, type_
+            )));
+        }
+        values.insert(name.clone(), value.clone());
+    }
+    Ok(values)
+}
+
+fn substitute(
+    value: &AgentValue,
+    params: &FnvIndexMap<String, AgentValue>,
+) -> Result<AgentValue, String> {
+    match value {
+        AgentValue::String(s) => {
+            if let Some(name) = s.strip_prefix("${").and_then(|s| s.strip_suffix('}'))
+                && let Some(value) = params.get(name)
+            {
+                return Ok(value.clone());
+            }
+            Ok(AgentValue::string(substitute_str(s, params)?))
+        }
+        AgentValue::Array(arr) => {
+            let arr = arr
+                .iter()
+                .map(|v| substitute(v, params))
+                .collect::<Result<_, _>>()?;
+            Ok(AgentValue::Array(arr))
+        }
+        AgentValue::Object(obj) => {
+            let obj = obj
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), substitute(v, params)?)))
+                .collect::<Result<_, String>>()?;
+            Ok(AgentValue::Object(obj))
+        }
+        _ => Ok(value.clone()),
+    }
+}
+
+fn substitute_str(s: &str, params: &FnvIndexMap<String, AgentValue>) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let Some(end) = after.find('}') else {
+                return Err(format!("unclosed parameter reference in \"{}\"", s));
+            };
+            let name = &after[..end];
+            let Some(value) = params.get(name) else {
+                return Err(format!("unknown parameter \"{}\"", name));
+            };
+            match value.as_str() {
+                Some(text) => out.push_str(text),
+                None => out.push_str(&value.to_json().to_string()),
+            }
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(entries: &[(&str, AgentValue)]) -> FnvIndexMap<String, AgentValue> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_substitute_str() {
+        let p = params(&[
+            ("name", AgentValue::string("acme")),
+            ("n", AgentValue::integer(3)),
+        ]);
+        assert_eq!(
+            substitute_str("hello ${name} x${n}", &p).unwrap(),
+            "hello acme x3"
+        );
+        assert_eq!(
+            substitute_str("$5 and $${name}", &p).unwrap(),
+            "$5 and ${name}"
+        );
+        assert!(substitute_str("${name", &p).is_err());
+        assert!(substitute_str("${other}", &p).is_err());
+    }
+
+    #[test]
+    fn test_substitute_keeps_type() {
+        let p = params(&[("n", AgentValue::integer(3))]);
+        assert_eq!(
+            substitute(&AgentValue::string("${n}"), &p).unwrap(),
+            AgentValue::integer(3)
+        );
+        assert_eq!(
+            substitute(&AgentValue::string("n=${n}"), &p).unwrap(),
+            AgentValue::string("n=3")
+        );
+    }
+}
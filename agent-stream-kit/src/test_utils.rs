@@ -31,9 +31,7 @@ pub async fn setup_askit() -> ASKit {
 
 /// Load and start an agent stream from a file.
 pub async fn load_and_start_stream(askit: &ASKit, path: &str) -> Result<String, AgentError> {
-    let stream_json = std::fs::read_to_string(path)
-        .map_err(|e| AgentError::IoError(format!("Failed to read stream file: {}", e)))?;
-    let spec = AgentStreamSpec::from_json(&stream_json)?;
+    let spec = AgentStreamSpec::from_file(path)?;
     let name = Path::new(path)
         .file_stem()
         .and_then(|s| s.to_str())
@@ -50,6 +48,9 @@ type BoardReceiver = Arc<AsyncMutex<mpsc::UnboundedReceiver<(String, AgentValue)
 
 thread_local! {
     static BOARD_RX: RefCell<Option<BoardReceiver>> = RefCell::new(None);
+
+    // the ASKit of the board receiver, to address variables
+    static ASKIT: RefCell<Option<ASKit>> = const { RefCell::new(None) };
 }
 
 pub fn subscribe_board_observer(askit: &ASKit) -> Result<(), AgentError> {
@@ -64,6 +65,9 @@ pub fn subscribe_board_observer(askit: &ASKit) -> Result<(), AgentError> {
     BOARD_RX.with(|slot| {
         *slot.borrow_mut() = Some(Arc::new(AsyncMutex::new(board_event_rx)));
     });
+    ASKIT.with(|slot| {
+        *slot.borrow_mut() = Some(askit.clone());
+    });
     Ok(())
 }
 
@@ -101,12 +105,17 @@ pub async fn expect_board_value(
     }
 }
 
+/// Expect the next board value to be written to the variable, as seen from the agents of
+/// the stream. See `ASKit::var_board_name`.
 pub async fn expect_var_value(
     flow_id: &str,
     var_name: &str,
     expected_value: &AgentValue,
 ) -> Result<(), AgentError> {
-    let expected_name = format!("%{}/{}", flow_id, var_name);
+    let askit = ASKIT
+        .with(|slot| slot.borrow().clone())
+        .ok_or_else(|| AgentError::SendMessageFailed("board receiver not initialized".into()))?;
+    let expected_name = askit.var_board_name(flow_id, var_name)?;
     expect_board_value(&expected_name, expected_value).await
 }
 
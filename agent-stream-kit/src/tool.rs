@@ -26,6 +26,7 @@ const CONFIG_TOOLS: &str = "tools";
 const CONFIG_TOOL_NAME: &str = "name";
 const CONFIG_TOOL_DESCRIPTION: &str = "description";
 const CONFIG_TOOL_PARAMETERS: &str = "parameters";
+const CONFIG_STREAM_LOCAL: &str = "stream_local";
 
 #[derive(Clone, Debug)]
 pub struct ToolInfo {
@@ -75,36 +76,54 @@ impl ToolEntry {
     }
 }
 
-struct ToolRegistry {
+#[derive(Default)]
+pub(crate) struct ToolRegistry {
     tools: HashMap<String, ToolEntry>,
 }
 
 impl ToolRegistry {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             tools: HashMap::new(),
         }
     }
 
-    fn register_tool<T: Tool + Send + Sync + 'static>(&mut self, tool: T) {
+    pub(crate) fn register_tool<T: Tool + Send + Sync + 'static>(&mut self, tool: T) {
         let name = tool.info().name.to_string();
         let entry = ToolEntry::new(tool);
         self.tools.insert(name, entry);
     }
 
-    fn unregister_tool(&mut self, name: &str) {
+    pub(crate) fn unregister_tool(&mut self, name: &str) {
         self.tools.remove(name);
     }
 
-    fn get_tool(&self, name: &str) -> Option<Arc<Box<dyn Tool + Send + Sync>>> {
+    pub(crate) fn get_tool(&self, name: &str) -> Option<Arc<Box<dyn Tool + Send + Sync>>> {
         self.tools.get(name).map(|entry| entry.tool.clone())
     }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Tool infos whose names match the regex set, or all of them when `reg_set` is `None`.
+    pub(crate) fn tool_infos(&self, reg_set: Option<&RegexSet>) -> Vec<ToolInfo> {
+        self.tools
+            .values()
+            .filter(|entry| reg_set.is_none_or(|r| r.is_match(&entry.info.name)))
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
 }
 
 // Global registry instance.
 static TOOL_REGISTRY: OnceLock<RwLock<ToolRegistry>> = OnceLock::new();
 
-fn registry() -> &'static RwLock<ToolRegistry> {
+pub(crate) fn registry() -> &'static RwLock<ToolRegistry> {
     TOOL_REGISTRY.get_or_init(|| RwLock::new(ToolRegistry::new()))
 }
 
@@ -120,38 +139,24 @@ pub fn unregister_tool(name: &str) {
 
 /// List all registered tool infos.
 pub fn list_tool_infos() -> Vec<ToolInfo> {
-    registry()
-        .read()
-        .unwrap()
-        .tools
-        .values()
-        .map(|entry| entry.info.clone())
-        .collect()
+    registry().read().unwrap().tool_infos(None)
 }
 
 /// List registerd tool infos filtered by patterns.
 pub fn list_tool_infos_patterns(patterns: &str) -> Result<Vec<ToolInfo>, regex::Error> {
+    let reg_set = patterns_to_regex_set(patterns)?;
+    Ok(registry().read().unwrap().tool_infos(Some(&reg_set)))
+}
+
+/// Build a regex set from newline separated patterns.
+pub(crate) fn patterns_to_regex_set(patterns: &str) -> Result<RegexSet, regex::Error> {
     // Split patterns by newline and trim whitespace
     let patterns = patterns
         .lines()
         .map(|line| line.trim())
         .filter(|line| !line.is_empty())
         .collect::<Vec<&str>>();
-    let reg_set = RegexSet::new(&patterns)?;
-    let tool_names = registry()
-        .read()
-        .unwrap()
-        .tools
-        .values()
-        .filter_map(|entry| {
-            if reg_set.is_match(&entry.info.name) {
-                Some(entry.info.clone())
-            } else {
-                None
-            }
-        })
-        .collect();
-    Ok(tool_names)
+    RegexSet::new(&patterns)
 }
 
 /// Get a tool by name.
@@ -165,22 +170,42 @@ pub async fn call_tool(
     name: &str,
     args: AgentValue,
 ) -> Result<AgentValue, AgentError> {
-    let tool = {
-        let guard = registry().read().unwrap();
-        guard.get_tool(name)
-    };
+    call_tool_with(ctx, name, args, get_tool).await
+}
+
+pub async fn call_tools(
+    ctx: &AgentContext,
+    tool_calls: &Vector<ToolCall>,
+) -> Result<Vector<Message>, AgentError> {
+    call_tools_with(ctx, tool_calls, get_tool).await
+}
 
-    let Some(tool) = tool else {
+/// Call a tool looked up by `get_tool`.
+pub(crate) async fn call_tool_with<F>(
+    ctx: AgentContext,
+    name: &str,
+    args: AgentValue,
+    get_tool: F,
+) -> Result<AgentValue, AgentError>
+where
+    F: Fn(&str) -> Option<Arc<Box<dyn Tool + Send + Sync>>>,
+{
+    let Some(tool) = get_tool(name) else {
         return Err(AgentError::Other(format!("Tool '{}' not found", name)));
     };
 
     tool.call(ctx, args).await
 }
 
-pub async fn call_tools(
+/// Call tools looked up by `get_tool`, and returns their responses as tool messages.
+pub(crate) async fn call_tools_with<F>(
     ctx: &AgentContext,
     tool_calls: &Vector<ToolCall>,
-) -> Result<Vector<Message>, AgentError> {
+    get_tool: F,
+) -> Result<Vector<Message>, AgentError>
+where
+    F: Fn(&str) -> Option<Arc<Box<dyn Tool + Send + Sync>>>,
+{
     if tool_calls.is_empty() {
         return Ok(vector![]);
     };
@@ -191,7 +216,8 @@ pub async fn call_tools(
             AgentValue::from_json(call.function.parameters.clone()).map_err(|e| {
                 AgentError::InvalidValue(format!("Failed to parse tool call parameters: {}", e))
             })?;
-        let tool_resp = call_tool(ctx.clone(), call.function.name.as_str(), args).await?;
+        let tool_resp =
+            call_tool_with(ctx.clone(), call.function.name.as_str(), args, &get_tool).await?;
         resp_messages.push(Message::tool(
             call.function.name.clone(),
             tool_resp.to_json().to_string(),
@@ -233,11 +259,13 @@ impl AsAgent for ListToolsAgent {
             ));
         };
 
+        let stream_id = Some(self.stream_id());
         let tools = if !patterns.is_empty() {
-            list_tool_infos_patterns(patterns)
+            self.askit()
+                .list_tool_infos_patterns(stream_id, patterns)
                 .map_err(|e| AgentError::InvalidValue(format!("Invalid regex patterns: {}", e)))?
         } else {
-            list_tool_infos()
+            self.askit().list_tool_infos(stream_id)
         };
         let tools = tools
             .into_iter()
@@ -251,6 +279,10 @@ impl AsAgent for ListToolsAgent {
     }
 }
 
+/// Registers the stream as a tool, called through its `tool_in` output and `tool_out` input.
+///
+/// The tool is registered with this instance, or with the stream when stream local. An agent
+/// fails to start when a tool of the same name is already registered there.
 #[askit_agent(
     title="Stream Tool",
     category=CATEGORY,
@@ -259,13 +291,18 @@ impl AsAgent for ListToolsAgent {
     string_config(name=CONFIG_TOOL_NAME),
     text_config(name=CONFIG_TOOL_DESCRIPTION),
     object_config(name=CONFIG_TOOL_PARAMETERS),
+    boolean_config(name=CONFIG_STREAM_LOCAL),
 )]
 pub struct StreamToolAgent {
     data: AgentData,
     name: String,
     description: String,
     parameters: Option<serde_json::Value>,
+    stream_local: bool,
     pending: Arc<Mutex<HashMap<usize, oneshot::Sender<AgentValue>>>>,
+
+    // (stream id if stream local, tool name) of the registered tool
+    registered: Option<(Option<String>, String)>,
 }
 
 impl StreamToolAgent {
@@ -281,6 +318,40 @@ impl StreamToolAgent {
 
         Ok(rx)
     }
+
+    fn register_stream_tool(&mut self) -> Result<(), AgentError> {
+        let agent_handle = self
+            .askit()
+            .get_agent(self.id())
+            .ok_or_else(|| AgentError::AgentNotFound(self.id().to_string()))?;
+        let tool = StreamTool::new(
+            self.name.clone(),
+            self.description.clone(),
+            self.parameters.clone(),
+            agent_handle,
+        );
+        let stream_id = self.stream_local.then(|| self.stream_id().to_string());
+        let old = self
+            .registered
+            .as_ref()
+            .map(|(stream_id, name)| (stream_id.as_deref(), name.as_str()));
+        self.askit()
+            .replace_unique_tool(old, stream_id.as_deref(), tool)?;
+        self.registered = Some((stream_id, self.name.clone()));
+        Ok(())
+    }
+
+    fn unregister_stream_tool(&mut self) {
+        match self.registered.take() {
+            Some((Some(stream_id), name)) => {
+                self.askit().unregister_stream_tool(&stream_id, &name);
+            }
+            Some((None, name)) => {
+                self.askit().unregister_tool(&name);
+            }
+            None => {}
+        }
+    }
 }
 
 #[async_trait]
@@ -300,48 +371,59 @@ impl AsAgent for StreamToolAgent {
             .as_ref()
             .and_then(|c| c.get(CONFIG_TOOL_PARAMETERS).ok())
             .and_then(|v| serde_json::to_value(v).ok());
+        let stream_local = configs
+            .as_ref()
+            .map(|c| c.get_bool_or_default(CONFIG_STREAM_LOCAL))
+            .unwrap_or_default();
         Ok(Self {
             data: AgentData::new(askit, id, spec),
             name,
             description,
             parameters,
+            stream_local,
             pending: Arc::new(Mutex::new(HashMap::new())),
+            registered: None,
         })
     }
 
     fn configs_changed(&mut self) -> Result<(), AgentError> {
-        self.name = self.configs()?.get_string_or_default(CONFIG_TOOL_NAME);
-        self.description = self
-            .configs()?
-            .get_string_or_default(CONFIG_TOOL_DESCRIPTION);
-        self.parameters = self
-            .configs()?
+        let configs = self.configs()?;
+        let name = configs.get_string_or_default(CONFIG_TOOL_NAME);
+        let description = configs.get_string_or_default(CONFIG_TOOL_DESCRIPTION);
+        let parameters = configs
             .get(CONFIG_TOOL_PARAMETERS)
             .ok()
             .and_then(|v| serde_json::to_value(v).ok());
+        let stream_local = configs.get_bool_or_default(CONFIG_STREAM_LOCAL);
+        let previous = (
+            std::mem::replace(&mut self.name, name),
+            std::mem::replace(&mut self.description, description),
+            std::mem::replace(&mut self.parameters, parameters),
+            std::mem::replace(&mut self.stream_local, stream_local),
+        );
 
-        // TODO: update registered tool info
+        // replace the registered tool, or keep the old one when the new one cannot be registered
+        if self.registered.is_some()
+            && let Err(e) = self.register_stream_tool()
+        {
+            (
+                self.name,
+                self.description,
+                self.parameters,
+                self.stream_local,
+            ) = previous;
+            return Err(e);
+        }
 
         Ok(())
     }
 
     async fn start(&mut self) -> Result<(), AgentError> {
-        let agent_handle = self
-            .askit()
-            .get_agent(self.id())
-            .ok_or_else(|| AgentError::AgentNotFound(self.id().to_string()))?;
-        let tool = StreamTool::new(
-            self.name.clone(),
-            self.description.clone(),
-            self.parameters.clone(),
-            agent_handle,
-        );
-        register_tool(tool);
-        Ok(())
+        self.register_stream_tool()
     }
 
     async fn stop(&mut self) -> Result<(), AgentError> {
-        unregister_tool(&self.name);
+        self.unregister_stream_tool();
         self.pending.lock().unwrap().clear();
         Ok(())
     }
@@ -451,7 +533,9 @@ impl AsAgent for CallToolMessageAgent {
         // Filter tools
         let config_tools = self.configs()?.get_string_or_default(CONFIG_TOOLS);
         if !config_tools.is_empty() {
-            let tools = list_tool_infos_patterns(&config_tools)
+            let tools = self
+                .askit()
+                .list_tool_infos_patterns(Some(self.stream_id()), &config_tools)
                 .map_err(|e| AgentError::InvalidValue(format!("Invalid regex patterns: {}", e)))?;
             // FIXME: cache allowed tool names
             let allowed_tool_names: HashSet<String> = tools.into_iter().map(|t| t.name).collect();
@@ -462,7 +546,7 @@ impl AsAgent for CallToolMessageAgent {
                 .collect();
         }
 
-        let resp_messages = call_tools(&ctx, &tool_calls).await?;
+        let resp_messages = self.call_tools(&ctx, &tool_calls).await?;
         for resp_msg in resp_messages {
             self.output(ctx.clone(), PIN_MESSAGE, AgentValue::message(resp_msg))
                 .await?;
@@ -504,7 +588,9 @@ impl AsAgent for CallToolAgent {
         })?;
         let tool_parameters = obj.get("parameters").cloned().unwrap_or(AgentValue::unit());
 
-        let resp = call_tool(ctx.clone(), tool_name, tool_parameters).await?;
+        let resp = self
+            .call_tool(ctx.clone(), tool_name, tool_parameters)
+            .await?;
         self.output(ctx, PIN_VALUE, resp).await?;
 
         Ok(())
@@ -0,0 +1,511 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::FnvIndexMap;
+use crate::agent::Agent;
+use crate::askit::ASKit;
+use crate::async_trait;
+use crate::context::AgentContext;
+use crate::error::AgentError;
+use crate::tool::{Tool, ToolInfo};
+use crate::value::AgentValue;
+
+pub const ATTR_CONTEXT_ID: &str = "askit.context_id";
+pub const ATTR_AGENT_ID: &str = "askit.agent_id";
+pub const ATTR_DEF_NAME: &str = "askit.def_name";
+pub const ATTR_PIN: &str = "askit.pin";
+pub const ATTR_STREAM_ID: &str = "askit.stream_id";
+pub const ATTR_BOARD: &str = "askit.board";
+pub const ATTR_TOOL: &str = "askit.tool";
+
+// reported as the `service.name` and the instrumentation scope
+const SERVICE_NAME: &str = "agent-stream-kit";
+
+/// Identifies a span, and the trace it belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+impl SpanContext {
+    // a span starting a new trace
+    fn root() -> Self {
+        Self {
+            trace_id: ((random_id() as u128) << 64) | random_id() as u128,
+            span_id: random_id(),
+        }
+    }
+
+    fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: random_id(),
+        }
+    }
+
+    /// Trace id in the W3C / OTLP form, 32 lowercase hex digits.
+    pub fn trace_id_hex(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    /// Span id in the W3C / OTLP form, 16 lowercase hex digits.
+    pub fn span_id_hex(&self) -> String {
+        format!("{:016x}", self.span_id)
+    }
+}
+
+/// A finished span: an agent processing an input, a tool call, or an external board write.
+#[derive(Clone, Debug)]
+pub struct AgentSpan {
+    pub name: String,
+
+    pub context: SpanContext,
+
+    /// Span which delivered the input, if any.
+    pub parent_span_id: Option<u64>,
+
+    pub start_time: SystemTime,
+
+    pub end_time: SystemTime,
+
+    /// `ATTR_*` keys -> values.
+    pub attributes: FnvIndexMap<String, String>,
+
+    /// Error returned by the agent or the tool.
+    pub error: Option<String>,
+}
+
+/// Receives the spans of an ASKit, set with `ASKit::set_span_exporter`.
+///
+/// `export` is called from the agent tasks as each span ends, so it should not block for long.
+pub trait SpanExporter: Send + Sync {
+    fn export(&self, span: AgentSpan);
+}
+
+/// Appends spans to a file as JSON lines, each line an OTLP `TracesData` in the JSON encoding,
+/// as written by the file exporter of the OpenTelemetry Collector.
+pub struct JsonFileSpanExporter {
+    file: Mutex<File>,
+}
+
+impl JsonFileSpanExporter {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                AgentError::IoError(format!("Failed to open {}: {}", path.display(), e))
+            })?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl SpanExporter for JsonFileSpanExporter {
+    fn export(&self, span: AgentSpan) {
+        let line = otlp_traces_json(&[span]).to_string();
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            log::error!("Failed to write span: {}", e);
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+const OTLP_BATCH_SIZE: usize = 512;
+
+#[cfg(feature = "otlp")]
+const OTLP_BATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[cfg(feature = "otlp")]
+const OTLP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Sends spans to an OTLP/HTTP endpoint, such as a local OpenTelemetry Collector,
+/// in the JSON encoding.
+///
+/// Spans are sent in batches from a background thread. Dropping the exporter sends
+/// the remaining spans.
+#[cfg(feature = "otlp")]
+pub struct OtlpSpanExporter {
+    tx: Mutex<Option<std::sync::mpsc::Sender<AgentSpan>>>,
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "otlp")]
+impl OtlpSpanExporter {
+    /// `endpoint` is the base URL of the collector, such as `http://localhost:4318`.
+    /// Spans are posted to `{endpoint}/v1/traces`.
+    pub fn new(endpoint: &str) -> Self {
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || otlp_export_loop(url, rx));
+        Self {
+            tx: Mutex::new(Some(tx)),
+            handle: Some(handle),
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+impl SpanExporter for OtlpSpanExporter {
+    fn export(&self, span: AgentSpan) {
+        if let Some(tx) = self.tx.lock().unwrap().as_ref() {
+            let _ = tx.send(span);
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+impl Drop for OtlpSpanExporter {
+    fn drop(&mut self) {
+        // closing the channel makes the thread send the rest and exit
+        self.tx.lock().unwrap().take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_export_loop(url: String, rx: std::sync::mpsc::Receiver<AgentSpan>) {
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Instant;
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(OTLP_TIMEOUT))
+        .build()
+        .into();
+    let mut batch = Vec::new();
+    let mut last_sent = Instant::now();
+    loop {
+        let closed = match rx.recv_timeout(OTLP_BATCH_INTERVAL) {
+            Ok(span) => {
+                batch.push(span);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        let due = batch.len() >= OTLP_BATCH_SIZE || last_sent.elapsed() >= OTLP_BATCH_INTERVAL;
+        if !batch.is_empty() && (due || closed) {
+            let body = otlp_traces_json(&batch).to_string();
+            if let Err(e) = agent
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .send(body.as_str())
+            {
+                log::error!("Failed to send {} spans to {}: {}", batch.len(), url, e);
+            }
+            batch.clear();
+            last_sent = Instant::now();
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+/// OTLP `TracesData` of the spans, in the JSON encoding.
+pub fn otlp_traces_json(spans: &[AgentSpan]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(otlp_span_json).collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [otlp_attribute("service.name", SERVICE_NAME)],
+            },
+            "scopeSpans": [{
+                "scope": {
+                    "name": SERVICE_NAME,
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn otlp_span_json(span: &AgentSpan) -> Value {
+    let attributes: Vec<Value> = span
+        .attributes
+        .iter()
+        .map(|(key, value)| otlp_attribute(key, value))
+        .collect();
+    let status = match &span.error {
+        // STATUS_CODE_ERROR
+        Some(message) => json!({ "code": 2, "message": message }),
+        None => json!({}),
+    };
+    json!({
+        "traceId": span.context.trace_id_hex(),
+        "spanId": span.context.span_id_hex(),
+        "parentSpanId": span.parent_span_id.map(|id| format!("{:016x}", id)).unwrap_or_default(),
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": unix_nanos(span.start_time).to_string(),
+        "endTimeUnixNano": unix_nanos(span.end_time).to_string(),
+        "attributes": attributes,
+        "status": status,
+    })
+}
+
+fn otlp_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+// Random non-zero ids without a dependency on a random number generator.
+fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish().max(1)
+}
+
+/// A span which has not ended yet.
+pub(crate) struct ActiveSpan {
+    exporter: Arc<dyn SpanExporter>,
+    span: AgentSpan,
+}
+
+impl ActiveSpan {
+    /// Start a span, as a child of the span of the context if it has one.
+    pub(crate) fn start(
+        exporter: Arc<dyn SpanExporter>,
+        name: String,
+        ctx: &AgentContext,
+        attributes: FnvIndexMap<String, String>,
+    ) -> Self {
+        let parent = ctx.span();
+        let mut attributes = attributes;
+        attributes.insert(ATTR_CONTEXT_ID.into(), ctx.id().to_string());
+        let now = SystemTime::now();
+        Self {
+            exporter,
+            span: AgentSpan {
+                name,
+                context: parent.map(|p| p.child()).unwrap_or_else(SpanContext::root),
+                parent_span_id: parent.map(|p| p.span_id),
+                start_time: now,
+                end_time: now,
+                attributes,
+                error: None,
+            },
+        }
+    }
+
+    /// The context handled in this span.
+    pub(crate) fn enter(&self, ctx: &AgentContext) -> AgentContext {
+        ctx.with_span(self.span.context)
+    }
+
+    pub(crate) fn end(mut self, error: Option<String>) {
+        self.span.end_time = SystemTime::now();
+        self.span.error = error;
+        self.exporter.export(self.span);
+    }
+}
+
+/// Start the span of an agent processing an input.
+pub(crate) async fn start_process_span(
+    exporter: Arc<dyn SpanExporter>,
+    agent: &Arc<AsyncMutex<Box<dyn Agent>>>,
+    ctx: &AgentContext,
+    pin: &str,
+) -> ActiveSpan {
+    let (agent_id, def_name, stream_id) = {
+        let agent = agent.lock().await;
+        (
+            agent.id().to_string(),
+            agent.def_name().to_string(),
+            agent.stream_id().to_string(),
+        )
+    };
+    let mut attributes = FnvIndexMap::default();
+    attributes.insert(ATTR_AGENT_ID.into(), agent_id);
+    attributes.insert(ATTR_DEF_NAME.into(), def_name.clone());
+    attributes.insert(ATTR_PIN.into(), pin.to_string());
+    attributes.insert(ATTR_STREAM_ID.into(), stream_id);
+    ActiveSpan::start(exporter, format!("process {}", def_name), ctx, attributes)
+}
+
+impl ASKit {
+    /// Trace the agents of this ASKit, sending the spans to the exporter.
+    ///
+    /// Each `process` call is a span, a child of the span which sent the input. Values written
+    /// with `write_board_value` or `write_var_value` start a new trace, and tool calls through
+    /// `call_tool` and `call_tools` are spans of their own.
+    pub fn set_span_exporter(&self, exporter: impl SpanExporter + 'static) {
+        *self.span_exporter.write().unwrap() = Some(Arc::new(exporter));
+    }
+
+    /// Stop tracing.
+    pub fn remove_span_exporter(&self) {
+        self.span_exporter.write().unwrap().take();
+    }
+
+    pub(crate) fn span_exporter(&self) -> Option<Arc<dyn SpanExporter>> {
+        self.span_exporter.read().unwrap().clone()
+    }
+
+    /// Record an external write to the board as the root span of the context.
+    pub(crate) fn trace_board_write(&self, name: &str, ctx: AgentContext) -> AgentContext {
+        let Some(exporter) = self.span_exporter() else {
+            return ctx;
+        };
+        let mut attributes = FnvIndexMap::default();
+        attributes.insert(ATTR_BOARD.into(), name.to_string());
+        let span = ActiveSpan::start(exporter, format!("board {}", name), &ctx, attributes);
+        let ctx = span.enter(&ctx);
+        span.end(None);
+        ctx
+    }
+}
+
+/// A tool whose calls are traced.
+struct TracedTool {
+    tool: Arc<Box<dyn Tool + Send + Sync>>,
+    exporter: Arc<dyn SpanExporter>,
+}
+
+#[async_trait]
+impl Tool for TracedTool {
+    fn info(&self) -> &ToolInfo {
+        self.tool.info()
+    }
+
+    async fn call(&self, ctx: AgentContext, args: AgentValue) -> Result<AgentValue, AgentError> {
+        let name = self.tool.info().name.clone();
+        let mut attributes = FnvIndexMap::default();
+        attributes.insert(ATTR_TOOL.into(), name.clone());
+        let span = ActiveSpan::start(
+            self.exporter.clone(),
+            format!("tool {}", name),
+            &ctx,
+            attributes,
+        );
+        let result = self.tool.call(span.enter(&ctx), args).await;
+        span.end(result.as_ref().err().map(|e| e.to_string()));
+        result
+    }
+}
+
+pub(crate) fn traced_tool(
+    tool: Arc<Box<dyn Tool + Send + Sync>>,
+    exporter: Arc<dyn SpanExporter>,
+) -> Arc<Box<dyn Tool + Send + Sync>> {
+    Arc::new(Box::new(TracedTool { tool, exporter }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_ids() {
+        let root = SpanContext::root();
+        let child = root.child();
+        assert_eq!(child.trace_id, root.trace_id);
+        assert_ne!(child.span_id, root.span_id);
+        assert_eq!(root.trace_id_hex().len(), 32);
+        assert_eq!(root.span_id_hex().len(), 16);
+    }
+
+    #[test]
+    fn test_otlp_json() {
+        let root = SpanContext::root();
+        let span = AgentSpan {
+            name: "process test".into(),
+            context: root.child(),
+            parent_span_id: Some(root.span_id),
+            start_time: UNIX_EPOCH,
+            end_time: UNIX_EPOCH + std::time::Duration::from_millis(5),
+            attributes: [(ATTR_PIN.to_string(), "in".to_string())]
+                .into_iter()
+                .collect(),
+            error: Some("failed".into()),
+        };
+        let json = otlp_traces_json(&[span]);
+        let span = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], root.trace_id_hex());
+        assert_eq!(span["parentSpanId"], root.span_id_hex());
+        assert_eq!(span["endTimeUnixNano"], "5000000");
+        assert_eq!(span["attributes"][0]["key"], ATTR_PIN);
+        assert_eq!(span["attributes"][0]["value"]["stringValue"], "in");
+        assert_eq!(span["status"]["code"], 2);
+    }
+
+    #[cfg(feature = "otlp")]
+    #[test]
+    fn test_otlp_exporter_posts_spans() {
+        use std::io::{BufRead, BufReader, Read};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((key, value)) = line.split_once(':')
+                    && key.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream)
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (request_line, body)
+        });
+
+        let exporter = OtlpSpanExporter::new(&endpoint);
+        let root = SpanContext::root();
+        exporter.export(AgentSpan {
+            name: "board in".into(),
+            context: root,
+            parent_span_id: None,
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: Default::default(),
+            error: None,
+        });
+        // sends the rest
+        drop(exporter);
+
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with("POST /v1/traces "));
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        let span = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], root.trace_id_hex());
+        assert_eq!(span["name"], "board in");
+    }
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+
+use crate::FnvIndexSet;
+use crate::askit::ASKit;
+use crate::definition::{AgentConfigSpecs, AgentDefinition};
+use crate::error::AgentError;
+use crate::spec::{AgentSpec, AgentStreamSpec, ChannelSpec};
+use crate::value::AgentValue;
+
+// prefix of target handles which set a config value instead of sending an input
+const CONFIG_HANDLE_PREFIX: &str = "config:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticLevel {
+    /// The stream cannot be loaded as written.
+    Error,
+
+    /// The stream can be loaded, but part of it is likely to be ignored.
+    Warning,
+}
+
+/// A problem found by `AgentStreamSpec::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationDiagnostic {
+    pub level: DiagnosticLevel,
+
+    pub message: String,
+
+    /// Id of the agent the problem was found in.
+    pub agent_id: Option<String>,
+
+    /// The channel the problem was found in.
+    pub channel: Option<ChannelSpec>,
+}
+
+impl ValidationDiagnostic {
+    fn agent_error(agent: &AgentSpec, message: String) -> Self {
+        Self {
+            level: DiagnosticLevel::Error,
+            message,
+            agent_id: Some(agent.id.clone()),
+            channel: None,
+        }
+    }
+
+    fn agent_warning(agent: &AgentSpec, message: String) -> Self {
+        Self {
+            level: DiagnosticLevel::Warning,
+            message,
+            agent_id: Some(agent.id.clone()),
+            channel: None,
+        }
+    }
+
+    fn channel_error(channel: &ChannelSpec, message: String) -> Self {
+        Self {
+            level: DiagnosticLevel::Error,
+            message,
+            agent_id: None,
+            channel: Some(channel.clone()),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == DiagnosticLevel::Error
+    }
+}
+
+impl fmt::Display for ValidationDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            DiagnosticLevel::Error => write!(f, "error: ")?,
+            DiagnosticLevel::Warning => write!(f, "warning: ")?,
+        }
+        if let Some(agent_id) = &self.agent_id {
+            write!(f, "agent {}: ", agent_id)?;
+        }
+        if let Some(c) = &self.channel {
+            write!(
+                f,
+                "channel {}:{} -> {}:{}: ",
+                c.source, c.source_handle, c.target, c.target_handle
+            )?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl AgentStreamSpec {
+    /// Check the spec against the agent definitions registered in the ASKit.
+    ///
+    /// Returns all problems found, in the order of agents and then channels.
+    /// An empty list means that the stream loads without anything being skipped.
+    pub fn validate(&self, askit: &ASKit) -> Vec<ValidationDiagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let mut ids = FnvIndexSet::default();
+        for agent in &self.agents {
+            if !ids.insert(agent.id.as_str()) {
+                diagnostics.push(ValidationDiagnostic::agent_error(
+                    agent,
+                    "duplicate agent id".to_string(),
+                ));
+            }
+            let def = askit.get_agent_definition(&agent.def_name);
+            if def.is_none() {
+                diagnostics.push(ValidationDiagnostic::agent_error(
+                    agent,
+                    format!("unknown agent definition \"{}\"", agent.def_name),
+                ));
+            }
+            // configs of older versions are checked after they are upgraded on load
+            match def.as_ref().map(|def| def.check_version(agent)) {
+                Some(Err(e)) => {
+                    diagnostics.push(ValidationDiagnostic::agent_error(agent, e.to_string()));
+                }
+                Some(Ok(Some(_))) => {}
+                _ => validate_configs(agent, def.as_ref(), &mut diagnostics),
+            }
+            if let Some(def) = &def {
+                validate_required_inputs(self, agent, def, &mut diagnostics);
+            }
+        }
+
+        for channel in &self.channels {
+            validate_channel(self, askit, channel, &mut diagnostics);
+        }
+
+        diagnostics
+    }
+
+    /// Fail with `InvalidStreamSpec` when `validate` reports errors. Warnings are logged.
+    pub(crate) fn check(&self, askit: &ASKit, name: &str) -> Result<(), AgentError> {
+        let diagnostics = self.validate(askit);
+        let errors: Vec<String> = diagnostics
+            .iter()
+            .filter(|d| d.is_error())
+            .map(|d| d.to_string())
+            .collect();
+        if !errors.is_empty() {
+            return Err(AgentError::InvalidStreamSpec(errors.join("; ")));
+        }
+        for d in &diagnostics {
+            log::warn!("{}: {}", name, d);
+        }
+        Ok(())
+    }
+}
+
+fn config_specs<'a>(
+    agent: &'a AgentSpec,
+    def: Option<&'a AgentDefinition>,
+) -> Option<&'a AgentConfigSpecs> {
+    agent
+        .config_specs
+        .as_ref()
+        .or_else(|| def.and_then(|def| def.configs.as_ref()))
+}
+
+fn validate_configs(
+    agent: &AgentSpec,
+    def: Option<&AgentDefinition>,
+    diagnostics: &mut Vec<ValidationDiagnostic>,
+) {
+    let Some(configs) = &agent.configs else {
+        return;
+    };
+    // without a definition nothing is known about the configs
+    let Some(specs) = config_specs(agent, def) else {
+        if def.is_some() {
+            for (key, _) in configs {
+                diagnostics.push(ValidationDiagnostic::agent_warning(
+                    agent,
+                    format!("unknown config \"{}\"", key),
+                ));
+            }
+        }
+        return;
+    };
+    for (key, value) in configs {
+        let Some(spec) = specs.get(key) else {
+            diagnostics.push(ValidationDiagnostic::agent_warning(
+                agent,
+                format!("unknown config \"{}\"", key),
+            ));
+            continue;
+        };
+        if let Some(type_) = &spec.type_
+            && !value_matches_type(value, type_)
+        {
+            diagnostics.push(ValidationDiagnostic::agent_error(
+                agent,
+                format!("config \"{}\" is not of type {}", key, type_),
+            ));
+        }
+    }
+}
+
+/// Whether the value can be used for a config of the given type.
+///
+/// Custom types are not known here, so any value is accepted for them.
+pub(crate) fn value_matches_type(value: &AgentValue, type_: &str) -> bool {
+    match type_ {
+        "unit" => value.is_unit(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_integer(),
+        "number" => value.is_number() || value.is_integer(),
+        "string" | "text" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn validate_required_inputs(
+    spec: &AgentStreamSpec,
+    agent: &AgentSpec,
+    def: &AgentDefinition,
+    diagnostics: &mut Vec<ValidationDiagnostic>,
+) {
+    let Some(pins) = &def.input_pins else {
+        return;
+    };
+    for (name, pin) in pins {
+        if pin.required
+            && !spec
+                .channels
+                .iter()
+                .any(|c| c.target == agent.id && &c.target_handle == name)
+        {
+            diagnostics.push(ValidationDiagnostic::agent_warning(
+                agent,
+                format!("required input \"{}\" is not connected", name),
+            ));
+        }
+    }
+}
+
+fn validate_channel(
+    spec: &AgentStreamSpec,
+    askit: &ASKit,
+    channel: &ChannelSpec,
+    diagnostics: &mut Vec<ValidationDiagnostic>,
+) {
+    let source = spec.agents.iter().find(|a| a.id == channel.source);
+    let target = spec.agents.iter().find(|a| a.id == channel.target);
+    if source.is_none() {
+        diagnostics.push(ValidationDiagnostic::channel_error(
+            channel,
+            format!("source agent {} not found", channel.source),
+        ));
+    }
+    if target.is_none() {
+        diagnostics.push(ValidationDiagnostic::channel_error(
+            channel,
+            format!("target agent {} not found", channel.target),
+        ));
+    }
+
+    if channel.source_handle.is_empty() {
+        diagnostics.push(ValidationDiagnostic::channel_error(
+            channel,
+            "source handle is empty".to_string(),
+        ));
+    } else if let Some(source) = source
+        && let Some(def) = askit.get_agent_definition(&source.def_name)
+    {
+        let outputs = source.outputs.as_ref().or(def.outputs.as_ref());
+        if !outputs.is_some_and(|outputs| outputs.contains(&channel.source_handle)) {
+            diagnostics.push(ValidationDiagnostic::channel_error(
+                channel,
+                format!(
+                    "source handle \"{}\" is not an output",
+                    channel.source_handle
+                ),
+            ));
+        }
+    }
+
+    if channel.target_handle.is_empty() {
+        diagnostics.push(ValidationDiagnostic::channel_error(
+            channel,
+            "target handle is empty".to_string(),
+        ));
+    } else if let Some(target) = target
+        && let Some(def) = askit.get_agent_definition(&target.def_name)
+    {
+        if let Some(key) = channel.target_handle.strip_prefix(CONFIG_HANDLE_PREFIX) {
+            let specs = config_specs(target, Some(&def));
+            if !specs.is_some_and(|specs| specs.contains_key(key)) {
+                diagnostics.push(ValidationDiagnostic::channel_error(
+                    channel,
+                    format!(
+                        "target handle \"{}\" is not a config",
+                        channel.target_handle
+                    ),
+                ));
+            }
+        } else {
+            let inputs = target.inputs.as_ref().or(def.inputs.as_ref());
+            if !inputs.is_some_and(|inputs| inputs.contains(&channel.target_handle)) {
+                diagnostics.push(ValidationDiagnostic::channel_error(
+                    channel,
+                    format!(
+                        "target handle \"{}\" is not an input",
+                        channel.target_handle
+                    ),
+                ));
+            }
+        }
+    }
+
+    if let Some(source) = source
+        && let Some(target) = target
+        && let Some(source_def) = askit.get_agent_definition(&source.def_name)
+        && let Some(target_def) = askit.get_agent_definition(&target.def_name)
+    {
+        let source_type = source_def.output_pin_type(&channel.source_handle);
+        let target_type = target_def.input_pin_type(&channel.target_handle);
+        if !target_type.accepts(source_type) {
+            diagnostics.push(ValidationDiagnostic::channel_error(
+                channel,
+                format!(
+                    "pin types are incompatible: {} -> {}",
+                    source_type, target_type
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_matches_type() {
+        assert!(value_matches_type(&AgentValue::integer(1), "integer"));
+        assert!(!value_matches_type(&AgentValue::number(1.0), "integer"));
+        assert!(value_matches_type(&AgentValue::integer(1), "number"));
+        assert!(value_matches_type(&AgentValue::string("a"), "text"));
+        assert!(!value_matches_type(&AgentValue::boolean(true), "string"));
+        assert!(value_matches_type(
+            &AgentValue::boolean(true),
+            "my_custom_type"
+        ));
+    }
+}
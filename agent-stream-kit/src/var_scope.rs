@@ -0,0 +1,417 @@
+use crate::FnvIndexMap;
+use crate::agent::Agent;
+use crate::askit::ASKit;
+use crate::context::AgentContext;
+use crate::error::AgentError;
+use crate::value::AgentValue;
+
+/// Prefix of a variable name in the global scope, shared by all streams.
+pub const GLOBAL_VAR_PREFIX: &str = "global:";
+
+/// Prefix of a variable name in the scope of the stream, which reaches out of subflows.
+pub const STREAM_VAR_PREFIX: &str = "stream:";
+
+/// Where a variable lives.
+///
+/// Variables are boards named after their scope, so that they keep their names when streams
+/// are reloaded under new ids:
+///
+/// - `Global`: `%/{name}`
+/// - `Stream`: `%{stream name}:/{name}`
+/// - `Subflow`: `%{stream name}:{subflow id}/{name}`, with the ids of nested subflows
+///   joined with `:`
+///
+/// Stream names cannot contain `:`, nor start with `/`, so the scope of a board name is
+/// never confused with the name of the variable.
+///
+/// The vars of an agent are in the scope of its stream, or of the subflow it is inside of,
+/// unless the name starts with `global:` or `stream:`. A variable without a value in its scope
+/// is looked up in the scopes enclosing it, so `Var->` agents emit the values written to
+/// the nearest scope with one. The vars of an `AgentContext` are not in any scope, and only
+/// `ASKit::lookup_var_value` looks at them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VarScope {
+    Global,
+
+    /// Shared by the streams of the name.
+    Stream(String),
+
+    /// Local to a subflow agent while its stream is loaded, given by the name of the stream
+    /// and the ids of the subflow agents, outermost first.
+    Subflow(String, Vec<String>),
+}
+
+impl VarScope {
+    /// The name of the board holding the variable of the scope.
+    pub fn board_name(&self, name: &str) -> String {
+        match self {
+            VarScope::Global => format!("%/{}", name),
+            VarScope::Stream(stream) => format!("%{}:/{}", stream, name),
+            VarScope::Subflow(stream, path) => format!("%{}:{}/{}", stream, path.join(":"), name),
+        }
+    }
+
+    /// The scope enclosing this one, None for the global scope.
+    pub fn parent(&self) -> Option<VarScope> {
+        match self {
+            VarScope::Global => None,
+            VarScope::Stream(_) => Some(VarScope::Global),
+            VarScope::Subflow(stream, path) if path.len() > 1 => Some(VarScope::Subflow(
+                stream.clone(),
+                path[..path.len() - 1].to_vec(),
+            )),
+            VarScope::Subflow(stream, _) => Some(VarScope::Stream(stream.clone())),
+        }
+    }
+
+    /// The names of the boards the variable is looked up in from this scope, innermost first.
+    pub fn lookup_board_names(&self, name: &str) -> Vec<String> {
+        let (mut scope, name) = self.resolve(name);
+        let mut names = vec![scope.board_name(name)];
+        while let Some(parent) = scope.parent() {
+            names.push(parent.board_name(name));
+            scope = parent;
+        }
+        names
+    }
+
+    /// The scope a variable name refers to from this scope, with the name without its prefix.
+    pub fn resolve<'a>(&self, name: &'a str) -> (VarScope, &'a str) {
+        if let Some(name) = name.strip_prefix(GLOBAL_VAR_PREFIX) {
+            return (VarScope::Global, name);
+        }
+        if let Some(name) = name.strip_prefix(STREAM_VAR_PREFIX) {
+            let scope = match self {
+                VarScope::Subflow(stream, _) => VarScope::Stream(stream.clone()),
+                scope => scope.clone(),
+            };
+            return (scope, name);
+        }
+        (self.clone(), name)
+    }
+}
+
+fn has_scope_prefix(name: &str) -> bool {
+    name.starts_with(GLOBAL_VAR_PREFIX) || name.starts_with(STREAM_VAR_PREFIX)
+}
+
+/// Map the ids of the subflow agents in the name of a subflow variable board.
+pub(crate) fn map_subflow_ids(name: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    let Some((scope, var)) = name.strip_prefix('%').and_then(|n| n.split_once(':')) else {
+        return name.to_string();
+    };
+    // stream names cannot contain `:` nor start with `/`, and agent ids cannot contain `/`
+    if scope.is_empty() || scope.starts_with('/') {
+        return name.to_string();
+    }
+    let Some((path, var)) = var.split_once('/') else {
+        return name.to_string();
+    };
+    if path.is_empty() {
+        return name.to_string();
+    }
+    let path: Vec<String> = path
+        .split(':')
+        .map(|id| f(id).unwrap_or_else(|| id.to_string()))
+        .collect();
+    format!("%{}:{}/{}", scope, path.join(":"), var)
+}
+
+impl ASKit {
+    /// The scope of the vars of the agents in the stream.
+    ///
+    /// `{stream id}/{subflow id}` gives the scope of the interior agents of the subflow agent,
+    /// with the ids of nested subflow agents appended the same way.
+    pub fn var_scope(&self, stream_id: &str) -> Result<VarScope, AgentError> {
+        let mut ids = stream_id.split('/');
+        let id = ids.next().unwrap_or_default();
+        let path: Vec<String> = ids.map(str::to_string).collect();
+        self.scope_of(id, &path)
+    }
+
+    /// The scope of the vars of the agent, the scope of its stream or of the subflow agent
+    /// it is inside of.
+    pub fn agent_var_scope(&self, agent: &dyn Agent) -> Result<VarScope, AgentError> {
+        self.scope_of(agent.stream_id(), agent.subflow_path())
+    }
+
+    fn scope_of(&self, id: &str, subflow_path: &[String]) -> Result<VarScope, AgentError> {
+        let name = {
+            // not `streams`, which the stream is taken out of while it is started or stopped
+            let stream_names = self.stream_names.lock().unwrap();
+            let Some(name) = stream_names.get(id) else {
+                return Err(AgentError::StreamNotFound(id.to_string()));
+            };
+            name.clone()
+        };
+        if subflow_path.is_empty() {
+            Ok(VarScope::Stream(name))
+        } else {
+            Ok(VarScope::Subflow(name, subflow_path.to_vec()))
+        }
+    }
+
+    /// The name of the board holding the variable, as seen from the agents of the stream.
+    pub fn var_board_name(&self, stream_id: &str, name: &str) -> Result<String, AgentError> {
+        let (scope, name) = self.var_scope(stream_id)?.resolve(name);
+        Ok(scope.board_name(name))
+    }
+
+    /// Write a value to the variable of the scope.
+    pub async fn write_scoped_var_value(
+        &self,
+        scope: &VarScope,
+        name: &str,
+        value: AgentValue,
+    ) -> Result<(), AgentError> {
+        self.write_board_value(scope.board_name(name), value).await
+    }
+
+    /// The last value written to the variable of the scope.
+    pub fn read_scoped_var_value(&self, scope: &VarScope, name: &str) -> Option<AgentValue> {
+        self.read_board_value(&scope.board_name(name))
+    }
+
+    /// Look up the variable as seen from the agents of the stream handling the context.
+    /// See `lookup_scoped_var_value`.
+    pub fn lookup_var_value(
+        &self,
+        stream_id: &str,
+        ctx: &AgentContext,
+        name: &str,
+    ) -> Option<AgentValue> {
+        let scope = self.var_scope(stream_id).ok()?;
+        self.lookup_scoped_var_value(&scope, ctx, name)
+    }
+
+    /// Look up the variable as seen from the scope handling the context.
+    ///
+    /// A name without a scope prefix is looked up in the vars of the context first. Then,
+    /// the scope of the name and the scopes enclosing it are searched from the innermost out,
+    /// so a subflow sees the vars of its stream and the global vars it does not shadow.
+    pub fn lookup_scoped_var_value(
+        &self,
+        scope: &VarScope,
+        ctx: &AgentContext,
+        name: &str,
+    ) -> Option<AgentValue> {
+        if !has_scope_prefix(name)
+            && let Some(value) = ctx.get_var(name)
+        {
+            return Some(value.clone());
+        }
+        scope
+            .lookup_board_names(name)
+            .iter()
+            .find_map(|board| self.read_board_value(board))
+    }
+
+    /// Subscribe the `Var->` agent to the boards its variable is looked up in, and return
+    /// their names, innermost first.
+    pub(crate) fn subscribe_var(
+        &self,
+        agent_id: &str,
+        scope: &VarScope,
+        name: &str,
+    ) -> Vec<String> {
+        let boards = scope.lookup_board_names(name);
+        {
+            let mut board_out_agents = self.board_out_agents.lock().unwrap();
+            for board in &boards {
+                board_out_agents
+                    .entry(board.clone())
+                    .or_default()
+                    .push(agent_id.to_string());
+            }
+        }
+        let mut var_out_boards = self.var_out_boards.lock().unwrap();
+        var_out_boards.insert(agent_id.to_string(), boards.clone());
+        boards
+    }
+
+    pub(crate) fn unsubscribe_var(&self, agent_id: &str) {
+        let Some(boards) = self.var_out_boards.lock().unwrap().swap_remove(agent_id) else {
+            return;
+        };
+        let mut board_out_agents = self.board_out_agents.lock().unwrap();
+        for board in boards {
+            if let Some(nodes) = board_out_agents.get_mut(&board) {
+                nodes.retain(|x| x != agent_id);
+            }
+        }
+    }
+
+    /// Whether the value written to the board is shadowed, for the agent, by the value of
+    /// a var in an inner scope. Always false for agents other than `Var->` ones.
+    pub(crate) fn is_var_shadowed(&self, agent_id: &str, board: &str) -> bool {
+        let Some(boards) = self.var_out_boards.lock().unwrap().get(agent_id).cloned() else {
+            return false;
+        };
+        boards
+            .iter()
+            .take_while(|b| *b != board)
+            .any(|b| self.read_board_value(b).is_some())
+    }
+
+    /// The value of the innermost of the boards with one, if it is retained.
+    pub(crate) fn retained_var_value(&self, boards: &[String]) -> Option<AgentValue> {
+        let board = boards
+            .iter()
+            .find(|board| self.read_board_value(board).is_some())?;
+        self.retained_value(board)
+    }
+
+    /// Move the vars of the stream, and the `Var->` agents reading them, to the new name.
+    ///
+    /// The vars of the new name which the stream does not have are kept.
+    pub(crate) fn rename_stream_vars(&self, old_name: &str, new_name: &str) {
+        let old_prefix = format!("%{}:", old_name);
+        let new_prefix = format!("%{}:", new_name);
+        let rename = |board: &str| {
+            board
+                .strip_prefix(&old_prefix)
+                .map(|rest| format!("{}{}", new_prefix, rest))
+        };
+
+        let renamed_values: Vec<(String, String, AgentValue)> = {
+            let mut board_value = self.board_value.lock().unwrap();
+            let renamed = rename_keys(&mut board_value, rename);
+            renamed
+                .into_iter()
+                .filter_map(|(old, new)| {
+                    let value = board_value.get(&new).cloned()?;
+                    Some((old, new, value))
+                })
+                .collect()
+        };
+        {
+            let mut histories = self.board_histories.lock().unwrap();
+            rename_keys(&mut histories, rename);
+        }
+        for boards in [&self.retained_boards, &self.persistent_boards] {
+            let mut boards = boards.lock().unwrap();
+            let renamed: Vec<(String, String)> = boards
+                .iter()
+                .filter_map(|board| Some((board.clone(), rename(board)?)))
+                .collect();
+            for (old, new) in renamed {
+                boards.shift_remove(&old);
+                boards.insert(new);
+            }
+        }
+        for (old, new, value) in renamed_values {
+            if self.is_board_persistent(&new) {
+                self.write_board_store(&old, None);
+                self.write_board_store(&new, Some(&value));
+            }
+        }
+
+        // Board-> agents set to the old board names keep them
+        let mut var_out_boards = self.var_out_boards.lock().unwrap();
+        let mut board_out_agents = self.board_out_agents.lock().unwrap();
+        for (agent_id, boards) in var_out_boards.iter_mut() {
+            for board in boards.iter_mut() {
+                let Some(new) = rename(board) else {
+                    continue;
+                };
+                if let Some(nodes) = board_out_agents.get_mut(board.as_str()) {
+                    nodes.retain(|x| x != agent_id);
+                }
+                board_out_agents
+                    .entry(new.clone())
+                    .or_default()
+                    .push(agent_id.clone());
+                *board = new;
+            }
+        }
+    }
+}
+
+// Rename the keys the function gives new names to, replacing the entries of the new names,
+// and return the renamed keys as (old, new).
+fn rename_keys<V>(
+    map: &mut FnvIndexMap<String, V>,
+    rename: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    let renamed: Vec<(String, String)> = map
+        .keys()
+        .filter_map(|key| Some((key.clone(), rename(key)?)))
+        .collect();
+    for (old, new) in &renamed {
+        if let Some(value) = map.shift_remove(old) {
+            map.insert(new.clone(), value);
+        }
+    }
+    renamed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subflow(path: &[&str]) -> VarScope {
+        VarScope::Subflow("main".into(), path.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn test_board_name() {
+        assert_eq!(VarScope::Global.board_name("n"), "%/n");
+        assert_eq!(
+            VarScope::Stream("dir/main".into()).board_name("n"),
+            "%dir/main:/n"
+        );
+        assert_eq!(subflow(&["3", "7"]).board_name("n"), "%main:3:7/n");
+    }
+
+    #[test]
+    fn test_parent() {
+        assert_eq!(subflow(&["3", "7"]).parent(), Some(subflow(&["3"])));
+        assert_eq!(
+            subflow(&["3"]).parent(),
+            Some(VarScope::Stream("main".into()))
+        );
+        assert_eq!(
+            VarScope::Stream("main".into()).parent(),
+            Some(VarScope::Global)
+        );
+        assert_eq!(VarScope::Global.parent(), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let scope = subflow(&["3"]);
+        assert_eq!(scope.resolve("n"), (scope.clone(), "n"));
+        assert_eq!(
+            scope.resolve("stream:n"),
+            (VarScope::Stream("main".into()), "n")
+        );
+        assert_eq!(scope.resolve("global:n"), (VarScope::Global, "n"));
+        assert_eq!(
+            VarScope::Global.resolve("stream:n"),
+            (VarScope::Global, "n")
+        );
+    }
+
+    #[test]
+    fn test_lookup_board_names() {
+        assert_eq!(
+            subflow(&["3", "7"]).lookup_board_names("n"),
+            ["%main:3:7/n", "%main:3/n", "%main:/n", "%/n"]
+        );
+        assert_eq!(
+            subflow(&["3"]).lookup_board_names("stream:n"),
+            ["%main:/n", "%/n"]
+        );
+        assert_eq!(subflow(&["3"]).lookup_board_names("global:n"), ["%/n"]);
+    }
+
+    #[test]
+    fn test_map_subflow_ids() {
+        let f = |id: &str| (id == "3").then(|| "30".to_string());
+        assert_eq!(map_subflow_ids("%main:3:7/n", f), "%main:30:7/n");
+        assert_eq!(map_subflow_ids("%main:3/a/b", f), "%main:30/a/b");
+        assert_eq!(map_subflow_ids("%main:/3", f), "%main:/3");
+        assert_eq!(map_subflow_ids("%/a:3/n", f), "%/a:3/n");
+        assert_eq!(map_subflow_ids("sensors:3/n", f), "sensors:3/n");
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use agent_stream_kit::{ASKit, ASKitEvent, AgentValue, test_utils};
+use tokio::sync::mpsc;
+
+/// Integers written to any board, with the board name.
+pub fn counts(askit: &ASKit) -> mpsc::UnboundedReceiver<(String, i64)> {
+    askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) => value.as_i64().map(|v| (name, v)),
+        _ => None,
+    })
+}
+
+/// The next count, or None when nothing comes within 200ms.
+pub async fn next_count(rx: &mut mpsc::UnboundedReceiver<(String, i64)>) -> Option<(String, i64)> {
+    tokio::time::timeout(Duration::from_millis(200), rx.recv())
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Write a unit to the board.
+pub async fn tick(askit: &ASKit, board: &str) {
+    askit
+        .write_board_value(board.into(), AgentValue::unit())
+        .await
+        .unwrap();
+}
+
+/// Write the value to the board, and wait until it is written.
+pub async fn write_board(askit: &ASKit, name: &str, value: AgentValue) {
+    askit
+        .write_board_value(name.into(), value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value(name, &value).await.unwrap();
+}
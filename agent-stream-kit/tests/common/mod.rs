@@ -1,3 +1,5 @@
 extern crate agent_stream_kit as askit;
 
 pub mod agents;
+pub mod boards;
+pub mod streams;
@@ -0,0 +1,44 @@
+use agent_stream_kit::{ASKit, AgentStreamSpec, AgentValue, ChannelSpec};
+
+use super::agents::CounterAgent;
+
+pub const BOARD_IN_DEF: &str = "agent_stream_kit::board_agent::BoardInAgent";
+pub const BOARD_OUT_DEF: &str = "agent_stream_kit::board_agent::BoardOutAgent";
+pub const VAR_IN_DEF: &str = "agent_stream_kit::board_agent::VarInAgent";
+pub const VAR_OUT_DEF: &str = "agent_stream_kit::board_agent::VarOutAgent";
+
+/// Board `in_board` -> Counter -> Board `out_board`
+pub fn counter_stream(askit: &ASKit, in_board: &str, out_board: &str) -> AgentStreamSpec {
+    let mut spec = AgentStreamSpec::default();
+
+    let mut board_out = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    board_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(in_board));
+    let counter = askit.new_agent_spec(CounterAgent::DEF_NAME).unwrap();
+    let mut board_in = askit.new_agent_spec(BOARD_IN_DEF).unwrap();
+    board_in
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(out_board));
+
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: counter.id.clone(),
+        target_handle: "in".into(),
+    });
+    spec.add_channel(ChannelSpec {
+        source: counter.id.clone(),
+        source_handle: "count".into(),
+        target: board_in.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(board_out);
+    spec.add_agent(counter);
+    spec.add_agent(board_in);
+    spec
+}
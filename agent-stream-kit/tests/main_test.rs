@@ -3,10 +3,33 @@
 pub mod common;
 
 mod suites {
+    mod apply_test;
     mod askit_test;
+    mod board_history_test;
+    mod board_pattern_test;
+    mod board_store_test;
     mod board_test;
     mod counter_test;
+    mod graph_test;
+    mod mailbox_test;
+    mod metrics_test;
+    mod migration_test;
+    mod pause_test;
+    mod pin_test;
+    mod replay_test;
+    mod retain_test;
+    mod server_test;
+    mod shutdown_test;
+    mod stream_dir_test;
     mod stream_test;
+    mod subflow_test;
+    mod supervisor_test;
+    mod template_test;
+    mod tool_test;
+    mod trace_test;
+    mod validation_test;
     mod var_disabled_test;
+    mod var_scope_test;
     mod var_test;
+    mod version_test;
 }
@@ -0,0 +1,225 @@
+extern crate agent_stream_kit as askit;
+
+use askit::{
+    ASKit, Agent, AgentData, AgentError, AgentSpec, AgentStreamSpec, AgentValue, AsAgent,
+    ChannelSpec, askit_agent, async_trait, test_utils,
+};
+
+use crate::common;
+use crate::common::boards::{counts, next_count, tick};
+
+const COUNTER_DEF: &str = common::agents::CounterAgent::DEF_NAME;
+
+/// Rejects a negative `n`.
+#[askit_agent(
+    title = "Non Negative",
+    category = "Test/Apply",
+    inputs = ["in"],
+    integer_config(name = "n", default = 0)
+)]
+pub struct NonNegativeAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for NonNegativeAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    fn configs_changed(&mut self) -> Result<(), AgentError> {
+        if self.configs()?.get_integer("n")? < 0 {
+            return Err(AgentError::InvalidConfig("n is negative".into()));
+        }
+        Ok(())
+    }
+}
+
+fn agent_id(spec: &AgentStreamSpec, def_name: &str) -> String {
+    spec.agents
+        .iter()
+        .find(|a| a.def_name == def_name)
+        .unwrap()
+        .id
+        .clone()
+}
+
+#[tokio::test]
+async fn test_apply_config_change_keeps_state() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    let mut rx = counts(&askit);
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    for count in 1..=2 {
+        tick(&askit, "in").await;
+        assert_eq!(next_count(&mut rx).await, Some(("out".into(), count)));
+    }
+
+    let mut spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    let board_in_id = agent_id(&spec, common::streams::BOARD_IN_DEF);
+    spec.agents
+        .iter_mut()
+        .find(|a| a.id == board_in_id)
+        .unwrap()
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("out2"));
+    let diff = askit.apply_stream_spec(&stream_id, spec).await.unwrap();
+    assert_eq!(diff.updated_configs.len(), 1);
+    assert_eq!(diff.updated_configs[0].0, board_in_id);
+    assert!(diff.replaced_agents.is_empty());
+    assert!(diff.added_channels.is_empty());
+
+    // the counter was not restarted
+    tick(&askit, "in").await;
+    assert_eq!(next_count(&mut rx).await, Some(("out2".into(), 3)));
+
+    // applying the same spec again changes nothing
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    assert!(
+        askit
+            .apply_stream_spec(&stream_id, spec)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_apply_adds_removes_and_replaces_agents() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    let mut rx = counts(&askit);
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    for count in 1..=2 {
+        tick(&askit, "in").await;
+        assert_eq!(next_count(&mut rx).await, Some(("out".into(), count)));
+    }
+
+    // replace the counter, and add a second output board
+    let mut spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    let counter_id = agent_id(&spec, COUNTER_DEF);
+    spec.agents
+        .iter_mut()
+        .find(|a| a.id == counter_id)
+        .unwrap()
+        .queue_capacity = Some(8);
+    let mut extra = askit.new_agent_spec(common::streams::BOARD_IN_DEF).unwrap();
+    extra.id = "extra".into();
+    extra
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("out_extra"));
+    spec.add_agent(extra);
+    spec.add_channel(ChannelSpec {
+        source: counter_id.clone(),
+        source_handle: "count".into(),
+        target: "extra".into(),
+        target_handle: "value".into(),
+    });
+    let diff = askit.apply_stream_spec(&stream_id, spec).await.unwrap();
+    assert_eq!(diff.replaced_agents.len(), 1);
+    assert_eq!(diff.added_agents.len(), 1);
+    let extra_id = diff.added_agents[0].id.clone();
+    assert_ne!(extra_id, "extra");
+    assert_eq!(diff.added_channels.len(), 1);
+    assert_eq!(diff.added_channels[0].target, extra_id);
+
+    // the new counter starts from scratch, and feeds both boards
+    tick(&askit, "in").await;
+    let mut outputs = vec![
+        next_count(&mut rx).await.unwrap(),
+        next_count(&mut rx).await.unwrap(),
+    ];
+    outputs.sort();
+    assert_eq!(outputs, vec![("out".into(), 1), ("out_extra".into(), 1)]);
+
+    // remove the added board again
+    let mut spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    spec.agents.retain(|a| a.id != extra_id);
+    spec.channels.retain(|c| c.target != extra_id);
+    let diff = askit.apply_stream_spec(&stream_id, spec).await.unwrap();
+    assert_eq!(diff.removed_agents, vec![extra_id.clone()]);
+    assert_eq!(diff.removed_channels.len(), 1);
+    assert!(askit.get_agent(&extra_id).is_none());
+
+    tick(&askit, "in").await;
+    assert_eq!(next_count(&mut rx).await, Some(("out".into(), 2)));
+    assert_eq!(next_count(&mut rx).await, None);
+
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    assert_eq!(spec.agents.len(), 3);
+    assert_eq!(spec.channels.len(), 2);
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_apply_invalid_spec_changes_nothing() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    let before = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+
+    let mut spec = before.clone();
+    spec.add_channel(ChannelSpec {
+        source: agent_id(&spec, COUNTER_DEF),
+        source_handle: "count".into(),
+        target: "no_such_agent".into(),
+        target_handle: "value".into(),
+    });
+    assert!(askit.apply_stream_spec(&stream_id, spec).await.is_err());
+    assert!(
+        askit
+            .apply_stream_spec("no_such_stream", before.clone())
+            .await
+            .is_err()
+    );
+
+    let after = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    assert!(before.diff(&after).is_empty());
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_apply_failing_configs_changes_nothing() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = common::streams::counter_stream(&askit, "in", "out");
+    spec.add_agent(askit.new_agent_spec(NonNegativeAgent::DEF_NAME).unwrap());
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    let before = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+
+    // the counter is updated before the other agent rejects its configs
+    let mut spec = before.clone();
+    for agent in spec.agents.iter_mut() {
+        let configs = agent.configs.as_mut().unwrap();
+        if agent.def_name == COUNTER_DEF {
+            configs.set("initial_count".into(), AgentValue::integer(5));
+        } else if agent.def_name == NonNegativeAgent::DEF_NAME {
+            configs.set("n".into(), AgentValue::integer(-1));
+        }
+    }
+    spec.agents
+        .retain(|a| a.def_name != common::streams::BOARD_IN_DEF);
+    let err = askit.apply_stream_spec(&stream_id, spec).await.unwrap_err();
+    assert!(matches!(err, AgentError::InvalidConfig(_)));
+
+    let after = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    assert!(before.diff(&after).is_empty());
+
+    askit.quit();
+}
@@ -11,16 +11,34 @@ fn test_init() {
     let askit = ASKit::init().unwrap();
 
     let defs = askit.get_agent_definitions();
-    assert_eq!(defs.len(), 6);
+    assert_eq!(defs.len(), 24);
     let mut keys: Vec<_> = defs.keys().cloned().collect();
     keys.sort();
     let expected = vec![
+        "agent_stream_kit::board_agent::BoardHistoryAgent",
         "agent_stream_kit::board_agent::BoardInAgent",
         "agent_stream_kit::board_agent::BoardOutAgent",
         "agent_stream_kit::board_agent::VarInAgent",
         "agent_stream_kit::board_agent::VarOutAgent",
+        "agent_stream_kit::subflow::SubflowAgent",
         "agent_stream_kit::test_utils::TestProbeAgent",
+        "agent_stream_kit::tool::CallToolAgent",
+        "agent_stream_kit::tool::CallToolMessageAgent",
+        "agent_stream_kit::tool::ListToolsAgent",
+        "agent_stream_kit::tool::StreamToolAgent",
         "main_test::common::agents::CounterAgent",
+        "main_test::suites::apply_test::NonNegativeAgent",
+        "main_test::suites::board_pattern_test::BoardNameAgent",
+        "main_test::suites::mailbox_test::DefaultQueueAgent",
+        "main_test::suites::mailbox_test::SmallQueueAgent",
+        "main_test::suites::migration_test::LabelAgent",
+        "main_test::suites::pin_test::ImageSourceAgent",
+        "main_test::suites::pin_test::MessageSinkAgent",
+        "main_test::suites::shutdown_test::SlowAgent",
+        "main_test::suites::supervisor_test::FlakyAgent",
+        "main_test::suites::supervisor_test::PanicAgent",
+        "main_test::suites::version_test::FixedAgent",
+        "main_test::suites::version_test::ThresholdAgent",
     ];
     assert_eq!(keys, expected);
 
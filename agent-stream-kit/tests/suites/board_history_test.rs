@@ -0,0 +1,161 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use std::sync::Arc;
+
+use askit::{
+    AgentStreamSpec, AgentValue, BoardRetention, BoardStore, ChannelSpec, MemoryBoardStore,
+    test_utils,
+};
+
+use crate::common::boards::write_board;
+use crate::common::streams::{BOARD_IN_DEF, BOARD_OUT_DEF};
+
+const BOARD_HISTORY_DEF: &str = "agent_stream_kit::board_agent::BoardHistoryAgent";
+
+#[tokio::test]
+async fn test_board_history() {
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_retention("temp", BoardRetention::last(3));
+    assert_eq!(askit.board_retention("temp"), Some(BoardRetention::last(3)));
+    for i in 1..=4 {
+        write_board(&askit, "temp", AgentValue::integer(i)).await;
+    }
+
+    let history = askit.read_board_history("temp");
+    let values: Vec<i64> = history.iter().filter_map(|e| e.value.as_i64()).collect();
+    assert_eq!(values, vec![2, 3, 4]);
+    assert!(history.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    assert_eq!(
+        askit.read_board_window("temp", Duration::from_secs(60)),
+        history
+    );
+
+    // boards without a policy keep no history
+    assert!(askit.read_board_history("other").is_empty());
+
+    askit.remove_board_retention("temp");
+    assert!(askit.read_board_history("temp").is_empty());
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_board_ttl() {
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_retention(
+        "status",
+        BoardRetention::expire_after(Duration::from_millis(50)),
+    );
+    write_board(&askit, "status", AgentValue::string("ok")).await;
+    assert_eq!(
+        askit.read_board_value("status"),
+        Some(AgentValue::string("ok"))
+    );
+    assert_eq!(askit.list_boards(), vec!["status"]);
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert_eq!(askit.read_board_value("status"), None);
+    assert!(askit.list_boards().is_empty());
+
+    // written again
+    write_board(&askit, "status", AgentValue::string("ok")).await;
+    assert!(askit.read_board_value("status").is_some());
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_expired_board_is_removed_from_store() {
+    let askit = test_utils::setup_askit().await;
+    let store = Arc::new(MemoryBoardStore::new());
+    askit.set_board_store(store.clone()).unwrap();
+    askit.set_board_persistent("expiring", true).unwrap();
+    askit.set_board_retention(
+        "expiring",
+        BoardRetention::expire_after(Duration::from_millis(50)),
+    );
+    write_board(&askit, "expiring", AgentValue::string("ok")).await;
+    askit.flush_board_store();
+    assert_eq!(store.load().unwrap().len(), 1);
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert_eq!(askit.read_board_value("expiring"), None);
+    askit.flush_board_store();
+    assert!(store.load().unwrap().is_empty());
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_restored_board_expires() {
+    let askit = test_utils::setup_askit().await;
+    // written before a restart, at an unknown time
+    let store = MemoryBoardStore::new();
+    store.save("restored", &AgentValue::string("ok")).unwrap();
+    askit.set_board_store(store).unwrap();
+    askit.set_board_retention(
+        "restored",
+        BoardRetention::expire_after(Duration::from_millis(50)),
+    );
+    assert_eq!(
+        askit.read_board_value("restored"),
+        Some(AgentValue::string("ok"))
+    );
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert_eq!(askit.read_board_value("restored"), None);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_board_history_agent() {
+    let askit = test_utils::setup_askit().await;
+
+    // Board `trigger` -> Board History `temp` -> Board `recent`
+    let mut spec = AgentStreamSpec::default();
+    let mut trigger = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    trigger
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("trigger"));
+    let mut history = askit.new_agent_spec(BOARD_HISTORY_DEF).unwrap();
+    history
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("temp"));
+    let mut recent = askit.new_agent_spec(BOARD_IN_DEF).unwrap();
+    recent
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("recent"));
+    spec.add_channel(ChannelSpec {
+        source: trigger.id.clone(),
+        source_handle: "value".into(),
+        target: history.id.clone(),
+        target_handle: "trigger".into(),
+    });
+    spec.add_channel(ChannelSpec {
+        source: history.id.clone(),
+        source_handle: "values".into(),
+        target: recent.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(trigger);
+    spec.add_agent(history);
+    spec.add_agent(recent);
+    let stream_id = askit.add_agent_stream("history".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    askit.set_board_retention("temp", BoardRetention::last(2));
+    for i in 1..=3 {
+        write_board(&askit, "temp", AgentValue::integer(i)).await;
+    }
+    write_board(&askit, "trigger", AgentValue::unit()).await;
+    let expected = AgentValue::from_json(serde_json::json!([2, 3])).unwrap();
+    test_utils::expect_board_value("recent", &expected)
+        .await
+        .unwrap();
+    askit.quit();
+}
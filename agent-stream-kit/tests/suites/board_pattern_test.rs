@@ -0,0 +1,251 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{
+    ASKit, ASKitEvent, AgentContext, AgentData, AgentError, AgentOutput, AgentSpec,
+    AgentStreamSpec, AgentValue, AsAgent, BOARD_NAME_VAR, ChannelSpec, askit_agent, async_trait,
+    test_utils,
+};
+
+use crate::common::streams::{BOARD_IN_DEF, BOARD_OUT_DEF};
+
+/// Outputs the name of the board the value was written to.
+#[askit_agent(
+    title = "Board Name",
+    category = "Test/Board",
+    inputs = ["in"],
+    outputs = ["name"]
+)]
+pub struct BoardNameAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for BoardNameAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        let name = ctx
+            .get_var(BOARD_NAME_VAR)
+            .cloned()
+            .unwrap_or_else(AgentValue::unit);
+        self.output(ctx, "name", name).await
+    }
+}
+
+/// Board `name` -> Board Name -> Board `out_board`, with `name` read as a pattern or not
+fn board_name_stream(askit: &ASKit, name: &str, pattern: bool, out_board: &str) -> AgentStreamSpec {
+    let mut spec = AgentStreamSpec::default();
+
+    let mut board_out = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    let configs = board_out.configs.as_mut().unwrap();
+    configs.set("name".into(), AgentValue::string(name));
+    configs.set("pattern".into(), AgentValue::boolean(pattern));
+    let board_name = askit.new_agent_spec(BoardNameAgent::DEF_NAME).unwrap();
+    let mut board_in = askit.new_agent_spec(BOARD_IN_DEF).unwrap();
+    board_in
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(out_board));
+
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: board_name.id.clone(),
+        target_handle: "in".into(),
+    });
+    spec.add_channel(ChannelSpec {
+        source: board_name.id.clone(),
+        source_handle: "name".into(),
+        target: board_in.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(board_out);
+    spec.add_agent(board_name);
+    spec.add_agent(board_in);
+    spec
+}
+
+#[tokio::test]
+async fn test_wildcard_board_out() {
+    let askit = test_utils::setup_askit().await;
+    let spec = board_name_stream(&askit, "sensors/*", true, "out");
+    let stream_id = askit.add_agent_stream("pattern".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    for name in ["sensors/temp", "sensors/humidity"] {
+        askit
+            .write_board_value(name.into(), AgentValue::integer(1))
+            .await
+            .unwrap();
+        test_utils::expect_board_value(name, &AgentValue::integer(1))
+            .await
+            .unwrap();
+        test_utils::expect_board_value("out", &AgentValue::string(name))
+            .await
+            .unwrap();
+    }
+
+    // one segment only
+    askit
+        .write_board_value("sensors/room1/temp".into(), AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("sensors/room1/temp", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    assert!(
+        test_utils::recv_board_with_timeout(Duration::from_millis(200))
+            .await
+            .is_err()
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_exact_board_out_has_no_board_name() {
+    let askit = test_utils::setup_askit().await;
+    let spec = board_name_stream(&askit, "sensors/temp", false, "out");
+    let stream_id = askit.add_agent_stream("exact".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    askit
+        .write_board_value("sensors/temp".into(), AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("sensors/temp", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &AgentValue::unit())
+        .await
+        .unwrap();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_board_out_matches_name_with_wildcard_exactly() {
+    let askit = test_utils::setup_askit().await;
+    let spec = board_name_stream(&askit, "alerts/*", false, "out");
+    let stream_id = askit.add_agent_stream("exact".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    // not matched as a wildcard
+    askit
+        .write_board_value("alerts/fire".into(), AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("alerts/fire", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    assert!(
+        test_utils::recv_board_with_timeout(Duration::from_millis(200))
+            .await
+            .is_err()
+    );
+
+    askit
+        .write_board_value("alerts/*".into(), AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("alerts/*", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &AgentValue::unit())
+        .await
+        .unwrap();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_board_out_configured_before_start_emits_once() {
+    let askit = test_utils::setup_askit().await;
+    let spec = board_name_stream(&askit, "sensors/temp", false, "out");
+    let stream_id = askit.add_agent_stream("configured".into(), spec).unwrap();
+    let board_out = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents[0]
+        .clone();
+    let mut configs = board_out.configs.unwrap();
+    configs.set("name".into(), AgentValue::string("sensors/*"));
+    configs.set("pattern".into(), AgentValue::boolean(true));
+    askit
+        .set_agent_configs(board_out.id, configs)
+        .await
+        .unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    askit
+        .write_board_value("sensors/temp".into(), AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("sensors/temp", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &AgentValue::string("sensors/temp"))
+        .await
+        .unwrap();
+    // not emitted twice
+    assert!(
+        test_utils::recv_board_with_timeout(Duration::from_millis(200))
+            .await
+            .is_err()
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_subscribe_board() {
+    let askit = test_utils::setup_askit().await;
+    let mut rx = askit.subscribe_board(r"re:sensors/\d+").unwrap();
+
+    for name in ["sensors/x", "sensors/12"] {
+        askit
+            .write_board_value(name.into(), AgentValue::integer(1))
+            .await
+            .unwrap();
+    }
+    let (name, value) = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(name, "sensors/12");
+    assert_eq!(value, AgentValue::integer(1));
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_invalid_board_pattern() {
+    let askit = test_utils::setup_askit().await;
+    assert!(matches!(
+        askit.subscribe_board("sensors/#/temp"),
+        Err(AgentError::InvalidBoardPattern(_))
+    ));
+
+    // the Board-> agent crashes on start
+    let mut crashes = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentCrashed(_, message) => Some(message),
+        _ => None,
+    });
+    let spec = board_name_stream(&askit, "re:(", true, "out");
+    let stream_id = askit.add_agent_stream("invalid".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    let message = tokio::time::timeout(Duration::from_secs(1), crashes.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(message.contains("re:("), "{}", message);
+    askit.quit();
+}
@@ -0,0 +1,128 @@
+extern crate agent_stream_kit as askit;
+
+use std::sync::Arc;
+
+use askit::{AgentValue, BoardStore, JsonFileBoardStore, MemoryBoardStore, test_utils};
+
+#[tokio::test]
+async fn test_read_and_list_boards() {
+    let askit = test_utils::setup_askit().await;
+    assert_eq!(askit.read_board_value("a"), None);
+
+    for (name, value) in [("a", 1), ("b", 2), ("a", 3)] {
+        let value = AgentValue::integer(value);
+        askit
+            .write_board_value(name.into(), value.clone())
+            .await
+            .unwrap();
+        test_utils::expect_board_value(name, &value).await.unwrap();
+    }
+    assert_eq!(askit.read_board_value("a"), Some(AgentValue::integer(3)));
+    assert_eq!(askit.list_boards(), vec!["a", "b"]);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_persistent_board_is_restored() {
+    let store = Arc::new(MemoryBoardStore::new());
+
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_store(store.clone()).unwrap();
+    askit.set_board_persistent("kept", true).unwrap();
+    for name in ["kept", "lost"] {
+        let value = AgentValue::string(name);
+        askit
+            .write_board_value(name.into(), value.clone())
+            .await
+            .unwrap();
+        test_utils::expect_board_value(name, &value).await.unwrap();
+    }
+    askit.flush_board_store();
+    askit.quit();
+    assert_eq!(
+        store.load().unwrap(),
+        vec![("kept".to_string(), AgentValue::string("kept"))]
+    );
+
+    // restarted
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_store(store.clone()).unwrap();
+    assert_eq!(
+        askit.read_board_value("kept"),
+        Some(AgentValue::string("kept"))
+    );
+    assert_eq!(askit.read_board_value("lost"), None);
+    assert!(askit.is_board_persistent("kept"));
+
+    // no longer persistent
+    askit.set_board_persistent("kept", false).unwrap();
+    askit.flush_board_store();
+    assert!(store.load().unwrap().is_empty());
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_persistent_var_is_restored_by_stream_name() {
+    let store = Arc::new(MemoryBoardStore::new());
+
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_store(store.clone()).unwrap();
+    let stream_id = askit.new_agent_stream("settings").unwrap();
+    askit.set_var_persistent(&stream_id, "limit", true).unwrap();
+    let value = AgentValue::integer(10);
+    askit
+        .write_var_value(&stream_id, "limit", value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_var_value(&stream_id, "limit", &value)
+        .await
+        .unwrap();
+    askit.flush_board_store();
+    askit.quit();
+    assert_eq!(
+        store.load().unwrap(),
+        vec![("%settings:/limit".to_string(), value.clone())]
+    );
+
+    // restarted, with the stream loaded under a new id
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_store(store.clone()).unwrap();
+    let new_stream_id = askit.new_agent_stream("settings").unwrap();
+    assert_ne!(new_stream_id, stream_id);
+    assert_eq!(
+        askit.read_var_value(&new_stream_id, "limit"),
+        Some(value.clone())
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_json_file_store_survives_restart() {
+    let path = std::env::temp_dir().join(format!("askit_board_store_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let askit = test_utils::setup_askit().await;
+    askit
+        .set_board_store(JsonFileBoardStore::open(&path).unwrap())
+        .unwrap();
+    askit.set_board_persistent("state", true).unwrap();
+    let value = AgentValue::from_json(serde_json::json!({ "on": true, "level": 3 })).unwrap();
+    askit
+        .write_board_value("state".into(), value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value("state", &value)
+        .await
+        .unwrap();
+    askit.flush_board_store();
+    askit.quit();
+
+    let askit = test_utils::setup_askit().await;
+    askit
+        .set_board_store(JsonFileBoardStore::open(&path).unwrap())
+        .unwrap();
+    assert_eq!(askit.read_board_value("state"), Some(value));
+    askit.quit();
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,86 @@
+extern crate agent_stream_kit as askit;
+
+use askit::{AgentValue, test_utils};
+
+use crate::common;
+
+#[tokio::test]
+async fn test_to_dot() {
+    let askit = test_utils::setup_askit().await;
+    // the count is written back to the board the counter reads
+    let spec = common::streams::counter_stream(&askit, "loop", "loop");
+    let ids: Vec<&str> = spec.agents.iter().map(|a| a.id.as_str()).collect();
+
+    let dot = spec.to_dot(&askit);
+    assert!(dot.starts_with("digraph {\n"));
+    assert!(dot.ends_with("}\n"));
+    let lines: Vec<&str> = dot.lines().map(str::trim).collect();
+    for line in [
+        format!(
+            r#""{}" [label="Board->\nname: loop\npattern: false"];"#,
+            ids[0]
+        ),
+        format!(r#""{}" [label="Counter\ninitial_count: 1"];"#, ids[1]),
+        format!(r#""{}" [label="->Board\nname: loop"];"#, ids[2]),
+        format!(r#""{}" -> "{}" [label="value -> in"];"#, ids[0], ids[1]),
+        format!(r#""{}" -> "{}" [label="count -> value"];"#, ids[1], ids[2]),
+        format!(
+            r#""{}" -> "{}" [label="loop", style=dashed];"#,
+            ids[2], ids[0]
+        ),
+    ] {
+        assert!(lines.contains(&line.as_str()), "{} not in\n{}", line, dot);
+    }
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_to_mermaid() {
+    let askit = test_utils::setup_askit().await;
+    let spec = common::streams::counter_stream(&askit, "loop", "loop");
+
+    let mermaid = spec.to_mermaid(&askit);
+    assert_eq!(
+        mermaid,
+        concat!(
+            "flowchart LR\n",
+            "  a0[\"Board-><br/>name: loop<br/>pattern: false\"]\n",
+            "  a1[\"Counter<br/>initial_count: 1\"]\n",
+            "  a2[\"->Board<br/>name: loop\"]\n",
+            "  a0 -->|\"value -> in\"| a1\n",
+            "  a1 -->|\"count -> value\"| a2\n",
+            "  a2 -.->|\"loop\"| a0\n",
+        )
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_graph_links_only_matching_names() {
+    let askit = test_utils::setup_askit().await;
+    // `out` is written, but nothing reads it
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+
+    assert!(!spec.to_dot(&askit).contains("style=dashed"));
+    assert!(!spec.to_mermaid(&askit).contains("-.->"));
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_graph_links_board_patterns() {
+    let askit = test_utils::setup_askit().await;
+    // `loop/count` is read back through the pattern
+    let mut spec = common::streams::counter_stream(&askit, "loop/*", "loop/count");
+    assert!(!spec.to_mermaid(&askit).contains("-.->"));
+    spec.agents[0]
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("pattern".into(), AgentValue::boolean(true));
+
+    assert!(
+        spec.to_mermaid(&askit)
+            .contains("  a2 -.->|\"loop/count\"| a0\n")
+    );
+    askit.quit();
+}
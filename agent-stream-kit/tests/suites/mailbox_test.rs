@@ -0,0 +1,216 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{
+    ASKit, ASKitEvent, AgentData, AgentError, AgentSpec, AgentStreamSpec, AgentValue, AsAgent,
+    ChannelSpec, DeliveryPolicy, askit_agent, async_trait, test_utils,
+};
+
+use crate::common::streams::BOARD_OUT_DEF;
+
+/// Holds two inputs by definition, and drops the ones after.
+#[askit_agent(
+    title = "Small Queue",
+    category = "Test/Mailbox",
+    inputs = ["in"],
+    queue_capacity = 2,
+    delivery_policy = DeliveryPolicy::DropNewest
+)]
+pub struct SmallQueueAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for SmallQueueAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+}
+
+/// Uses the default queue.
+#[askit_agent(title = "Default Queue", category = "Test/Mailbox", inputs = ["in"])]
+pub struct DefaultQueueAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for DefaultQueueAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Input {
+    Queued,
+    Dropped,
+}
+
+/// Start Board `board` -> the agent, with the agent paused so that its inputs stay queued,
+/// and write `n` values to the board. Returns what became of each input.
+async fn feed_paused(askit: &ASKit, board: &str, mut agent: AgentSpec, n: usize) -> Vec<Input> {
+    let mut spec = AgentStreamSpec::default();
+    let mut board_out = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    board_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(board));
+    agent.id = "agent".into();
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: agent.id.clone(),
+        target_handle: "in".into(),
+    });
+    let def_name = agent.def_name.clone();
+    spec.add_agent(board_out);
+    spec.add_agent(agent);
+
+    let stream_id = askit.add_agent_stream(board.into(), spec).unwrap();
+    let agent_id = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents
+        .iter()
+        .find(|a| a.def_name == def_name)
+        .unwrap()
+        .id
+        .clone();
+    let id = agent_id.clone();
+    let mut events = askit.subscribe_to_event(move |event| match event {
+        ASKitEvent::AgentPaused(a) if a == id => Some(None),
+        ASKitEvent::AgentIn(a, _) if a == id => Some(Some(Input::Queued)),
+        ASKitEvent::AgentInDropped(a, _) if a == id => Some(Some(Input::Dropped)),
+        _ => None,
+    });
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    askit.pause_agent(&agent_id).await.unwrap();
+    // wait until the agent has processed the pause
+    while events.recv().await.unwrap().is_some() {}
+
+    for i in 0..n {
+        askit
+            .write_board_value(board.into(), AgentValue::integer(i as i64))
+            .await
+            .unwrap();
+    }
+    let mut inputs = Vec::new();
+    for _ in 0..n {
+        let input = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        inputs.push(input);
+    }
+    // no input is reported twice
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), events.recv())
+            .await
+            .is_err()
+    );
+    inputs
+}
+
+#[tokio::test]
+async fn test_definition_queue() {
+    let askit = test_utils::setup_askit().await;
+    let agent = askit.new_agent_spec(SmallQueueAgent::DEF_NAME).unwrap();
+    assert_eq!(
+        feed_paused(&askit, "def", agent, 3).await,
+        vec![Input::Queued, Input::Queued, Input::Dropped]
+    );
+
+    // the dropped input is not counted as queued
+    let metrics = askit.metrics();
+    let agent = metrics
+        .agents
+        .values()
+        .find(|agent| agent.def_name == SmallQueueAgent::DEF_NAME)
+        .unwrap();
+    assert_eq!(agent.inputs["in"], 2);
+    assert_eq!(agent.dropped_inputs, 1);
+    assert_eq!(metrics.streams[&agent.stream_id].dropped_inputs, 1);
+    assert!(metrics.to_prometheus().contains(&format!(
+        "askit_stream_dropped_inputs_total{{stream_id=\"{}\",stream_name=\"def\"}} 1\n",
+        agent.stream_id
+    )));
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_spec_queue_overrides_definition() {
+    let askit = test_utils::setup_askit().await;
+    let mut agent = askit.new_agent_spec(SmallQueueAgent::DEF_NAME).unwrap();
+    agent.queue_capacity = Some(1);
+    assert_eq!(
+        feed_paused(&askit, "spec", agent, 3).await,
+        vec![Input::Queued, Input::Dropped, Input::Dropped]
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_default_queue() {
+    let askit = test_utils::setup_askit().await;
+    let mut agent = askit.new_agent_spec(DefaultQueueAgent::DEF_NAME).unwrap();
+    // the capacity is the default
+    agent.delivery_policy = Some(DeliveryPolicy::DropNewest);
+    assert_eq!(
+        feed_paused(&askit, "default", agent, 3).await,
+        vec![Input::Queued, Input::Queued, Input::Queued]
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_block_holds_while_paused() {
+    let askit = test_utils::setup_askit().await;
+    let mut agent = askit.new_agent_spec(DefaultQueueAgent::DEF_NAME).unwrap();
+    // a paused agent with a full queue keeps its inputs up to the default pause capacity,
+    // without holding up other agents
+    agent.queue_capacity = Some(1);
+    assert_eq!(
+        feed_paused(&askit, "block", agent, 3).await,
+        vec![Input::Queued, Input::Queued, Input::Queued]
+    );
+
+    let metrics = askit.metrics();
+    let agent = metrics
+        .agents
+        .values()
+        .find(|agent| agent.def_name == DefaultQueueAgent::DEF_NAME)
+        .unwrap();
+    assert_eq!(agent.inputs["in"], 3);
+    assert_eq!(agent.dropped_inputs, 0);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_block_drops_past_pause_capacity() {
+    let askit = test_utils::setup_askit().await;
+    let mut agent = askit.new_agent_spec(DefaultQueueAgent::DEF_NAME).unwrap();
+    agent.queue_capacity = Some(1);
+    agent.pause_capacity = Some(2);
+    assert_eq!(
+        feed_paused(&askit, "pause_capacity", agent, 4).await,
+        vec![Input::Queued, Input::Queued, Input::Dropped, Input::Dropped]
+    );
+
+    let metrics = askit.metrics();
+    let agent = metrics
+        .agents
+        .values()
+        .find(|agent| agent.def_name == DefaultQueueAgent::DEF_NAME)
+        .unwrap();
+    assert_eq!(agent.inputs["in"], 2);
+    assert_eq!(agent.dropped_inputs, 2);
+    askit.quit();
+}
@@ -0,0 +1,147 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::tool::{Tool, ToolInfo};
+use askit::{ASKitEvent, AgentContext, AgentError, AgentValue, async_trait, test_utils};
+
+use crate::common;
+use crate::common::agents::CounterAgent;
+
+struct EchoTool {
+    info: ToolInfo,
+}
+
+#[async_trait]
+impl Tool for EchoTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, _ctx: AgentContext, args: AgentValue) -> Result<AgentValue, AgentError> {
+        if args.is_unit() {
+            return Err(AgentError::InvalidValue("no args".into()));
+        }
+        Ok(args)
+    }
+}
+
+#[tokio::test]
+async fn test_agent_and_stream_metrics() {
+    let askit = test_utils::setup_askit().await;
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let mut outputs = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "out" => value.as_i64(),
+        _ => None,
+    });
+    for count in 1..=3 {
+        askit
+            .write_board_value("in".into(), AgentValue::unit())
+            .await
+            .unwrap();
+        let output = tokio::time::timeout(Duration::from_millis(200), outputs.recv())
+            .await
+            .unwrap();
+        assert_eq!(output, Some(count));
+    }
+
+    let metrics = askit.metrics();
+    assert_eq!(metrics.board_writes["in"], 3);
+    assert_eq!(metrics.board_writes["out"], 3);
+    assert_eq!(metrics.main_queue_depth, 0);
+
+    let (counter_id, counter) = metrics
+        .agents
+        .iter()
+        .find(|(_, agent)| agent.def_name == CounterAgent::DEF_NAME)
+        .unwrap();
+    assert_eq!(counter.stream_id, stream_id);
+    assert_eq!(counter.inputs["in"], 3);
+    assert_eq!(counter.outputs["count"], 3);
+    assert_eq!(counter.errors, 0);
+    assert_eq!(counter.process_latency.count, 3);
+    assert_eq!(counter.mailbox_depth, 0);
+
+    let stream = &metrics.streams[&stream_id];
+    assert_eq!(stream.name, "counter");
+    // the counter and the board in agent
+    assert_eq!(stream.inputs, 6);
+
+    let text = metrics.to_prometheus();
+    assert!(text.contains(&format!(
+        "askit_agent_inputs_total{{agent_id=\"{}\",def_name=\"{}\",stream_id=\"{}\",pin=\"in\"}} 3\n",
+        counter_id,
+        CounterAgent::DEF_NAME,
+        stream_id
+    )));
+    assert!(text.contains("askit_board_writes_total{board=\"in\"} 3\n"));
+
+    // the counters of a removed agent go with it
+    askit.remove_agent_stream(&stream_id).await.unwrap();
+    let metrics = askit.metrics();
+    assert!(!metrics.agents.contains_key(counter_id));
+    assert!(!metrics.streams.contains_key(&stream_id));
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_tool_call_metrics() {
+    let askit = test_utils::setup_askit().await;
+    let stream_id = askit.new_agent_stream("tools").unwrap();
+    askit.register_tool(EchoTool {
+        info: ToolInfo {
+            name: "metered_echo".into(),
+            description: String::new(),
+            parameters: None,
+        },
+    });
+
+    let ctx = AgentContext::new();
+    askit
+        .call_tool(ctx.clone(), None, "metered_echo", AgentValue::integer(1))
+        .await
+        .unwrap();
+    askit
+        .call_tool(
+            ctx.clone(),
+            Some(&stream_id),
+            "metered_echo",
+            AgentValue::integer(2),
+        )
+        .await
+        .unwrap();
+    assert!(
+        askit
+            .call_tool(
+                ctx.clone(),
+                Some(&stream_id),
+                "metered_echo",
+                AgentValue::unit()
+            )
+            .await
+            .is_err()
+    );
+
+    let metrics = askit.metrics();
+    let tool = &metrics.tools["metered_echo"];
+    assert_eq!(tool.calls, 3);
+    assert_eq!(tool.errors, 1);
+    assert_eq!(tool.latency.count, 3);
+    let stream_tool = &metrics.streams[&stream_id].tools["metered_echo"];
+    assert_eq!(stream_tool.calls, 2);
+    assert_eq!(stream_tool.errors, 1);
+
+    let text = metrics.to_prometheus();
+    assert!(text.contains("askit_tool_calls_total{tool=\"metered_echo\"} 3\n"));
+    assert!(text.contains("askit_tool_duration_seconds_count{tool=\"metered_echo\"} 3\n"));
+    assert!(text.contains(&format!(
+        "askit_stream_tool_calls_total{{stream_id=\"{}\",stream_name=\"tools\",tool=\"metered_echo\"}} 2\n",
+        stream_id
+    )));
+
+    askit.quit();
+}
@@ -0,0 +1,130 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{
+    ASKit, ASKitEvent, Agent, AgentContext, AgentData, AgentError, AgentOutput, AgentSpec,
+    AgentStreamSpec, AgentValue, AsAgent, STREAM_SPEC_VERSION, askit_agent, async_trait,
+    test_utils,
+};
+use serde_json::json;
+
+use crate::common;
+
+const OLD_DEF: &str = "old_crate::LabelAgent";
+const LABEL_DEF: &str = "main_test::suites::migration_test::LabelAgent";
+
+/// Prefixes the input with a label
+#[askit_agent(
+    title = "Label",
+    category = "Test/Migrations",
+    inputs = ["in"],
+    outputs = ["out"],
+    string_config(name = "prefix"),
+    renamed_from = OLD_DEF,
+    config_renamed(from = "label", to = "prefix"),
+    input_renamed(from = "text", to = "in"),
+    output_renamed(from = "labeled", to = "out"),
+)]
+pub struct LabelAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for LabelAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        ctx: AgentContext,
+        _pin: String,
+        value: AgentValue,
+    ) -> Result<(), AgentError> {
+        let prefix = self.configs()?.get_string_or_default("prefix");
+        let text = value.as_str().unwrap_or_default();
+        self.output(ctx, "out", AgentValue::string(format!("{prefix}{text}")))
+            .await
+    }
+}
+
+/// A spec saved before versions and renames: no `version`, `enabled` flags, and old names.
+fn old_spec() -> String {
+    json!({
+        "agents": [
+            { "id": "a", "def_name": common::streams::BOARD_OUT_DEF, "configs": { "name": "text_in" }, "enabled": true },
+            { "id": "b", "def_name": OLD_DEF, "configs": { "label": "> " }, "inputs": ["text"], "outputs": ["labeled"], "enabled": true },
+            { "id": "c", "def_name": common::streams::BOARD_IN_DEF, "configs": { "name": "text_out" } },
+        ],
+        "channels": [
+            { "source": "a", "source_handle": "value", "target": "b", "target_handle": "text" },
+            { "source": "b", "source_handle": "labeled", "target": "c", "target_handle": "value" },
+        ],
+    })
+    .to_string()
+}
+
+#[tokio::test]
+async fn test_old_spec_is_migrated_on_load() {
+    let askit = test_utils::setup_askit().await;
+    LabelAgent::register(&askit);
+
+    let spec = AgentStreamSpec::from_json(&old_spec()).unwrap();
+    assert_eq!(spec.version, STREAM_SPEC_VERSION);
+    assert!(spec.agents.iter().all(|a| !a.disabled));
+    // the definition renames need the registered definitions
+    assert_eq!(spec.agents[1].def_name, OLD_DEF);
+    assert!(!spec.validate(&askit).is_empty());
+
+    let stream_id = askit.add_agent_stream_strict("old".into(), spec).unwrap();
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    let label = spec
+        .agents
+        .iter()
+        .find(|a| a.def_name == LABEL_DEF)
+        .unwrap();
+    let configs = label.configs.as_ref().unwrap();
+    assert_eq!(configs.get_string("prefix").unwrap(), "> ");
+    assert!(!configs.contains_key("label"));
+    assert_eq!(label.inputs.as_deref().unwrap(), ["in"]);
+    assert_eq!(label.outputs.as_deref().unwrap(), ["out"]);
+    let handles: Vec<(&str, &str)> = spec
+        .channels
+        .iter()
+        .map(|c| (c.source_handle.as_str(), c.target_handle.as_str()))
+        .collect();
+    assert_eq!(handles, vec![("value", "in"), ("out", "value")]);
+
+    // saved again with the current version
+    let saved: serde_json::Value = serde_json::from_str(&spec.to_json().unwrap()).unwrap();
+    assert_eq!(saved["version"], STREAM_SPEC_VERSION);
+    assert!(saved["agents"][1].get("enabled").is_none());
+
+    let mut outputs = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "text_out" => {
+            value.as_str().map(|s| s.to_string())
+        }
+        _ => None,
+    });
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    askit
+        .write_board_value("text_in".into(), AgentValue::string("hello"))
+        .await
+        .unwrap();
+    let output = tokio::time::timeout(Duration::from_millis(200), outputs.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(output, "> hello");
+
+    askit.quit();
+}
+
+#[test]
+fn test_newer_spec_version_is_rejected() {
+    let spec = json!({ "version": STREAM_SPEC_VERSION + 1, "agents": [], "channels": [] });
+    assert!(AgentStreamSpec::from_json(&spec.to_string()).is_err());
+}
@@ -0,0 +1,84 @@
+extern crate agent_stream_kit as askit;
+
+use askit::{ASKitEvent, AgentStatus, AgentValue, test_utils};
+
+use crate::common;
+use crate::common::boards::{counts, next_count};
+
+const COUNTER_DEF: &str = common::agents::CounterAgent::DEF_NAME;
+
+#[tokio::test]
+async fn test_pause_and_resume_stream() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "pause_in", "pause_out");
+    let stream_id = askit.add_agent_stream("pause".into(), spec).unwrap();
+    let counter_id = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents
+        .iter()
+        .find(|a| a.def_name == COUNTER_DEF)
+        .unwrap()
+        .id
+        .clone();
+    let mut rx = counts(&askit);
+    let mut paused_events = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentPaused(id) => Some(id),
+        _ => None,
+    });
+
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    askit
+        .write_board_value("pause_in".into(), AgentValue::unit())
+        .await
+        .unwrap();
+    assert_eq!(next_count(&mut rx).await, Some(("pause_out".into(), 1)));
+
+    askit.pause_agent_stream(&stream_id).await.unwrap();
+    assert!(askit.get_agent_stream_info(&stream_id).unwrap().paused);
+    // wait until the counter has processed the pause
+    while paused_events.recv().await.unwrap() != counter_id {}
+    let counter = askit.get_agent(&counter_id).unwrap();
+    assert_eq!(counter.lock().await.status(), &AgentStatus::Paused);
+
+    for _ in 0..2 {
+        askit
+            .write_board_value("pause_in".into(), AgentValue::unit())
+            .await
+            .unwrap();
+    }
+    assert_eq!(next_count(&mut rx).await, None);
+
+    // held inputs are processed on resume, and the count is kept
+    askit.resume_agent_stream(&stream_id).await.unwrap();
+    assert!(!askit.get_agent_stream_info(&stream_id).unwrap().paused);
+    assert_eq!(next_count(&mut rx).await, Some(("pause_out".into(), 2)));
+    assert_eq!(next_count(&mut rx).await, Some(("pause_out".into(), 3)));
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_pause_agent_not_running() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "pause_in", "pause_out");
+    let stream_id = askit.add_agent_stream("pause".into(), spec).unwrap();
+    let agent_id = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents[0]
+        .id
+        .clone();
+
+    assert!(askit.pause_agent(&agent_id).await.is_err());
+
+    // pausing a stream which is not running does nothing
+    askit.pause_agent_stream(&stream_id).await.unwrap();
+    assert!(!askit.get_agent_stream_info(&stream_id).unwrap().paused);
+
+    askit.quit();
+}
@@ -0,0 +1,233 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{
+    ASKit, ASKitEvent, AgentContext, AgentData, AgentError, AgentSpec, AgentStreamSpec, AgentValue,
+    AsAgent, ChannelSpec, PinType, askit_agent, async_trait, test_utils,
+};
+use serde_json::json;
+
+#[askit_agent(
+    title = "Image Source",
+    category = "Test/Pins",
+    output_pin(name = "image", type_ = PinType::Image),
+    output_pin(name = "count", type_ = PinType::Integer),
+)]
+pub struct ImageSourceAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for ImageSourceAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+}
+
+#[askit_agent(
+    title = "Message Sink",
+    category = "Test/Pins",
+    inputs = ["any"],
+    input_pin(name = "message", type_ = PinType::Message, required),
+    input_pin(name = "number", type_ = PinType::Number),
+)]
+pub struct MessageSinkAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for MessageSinkAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+fn channel(source: &str, source_handle: &str, target: &str, target_handle: &str) -> ChannelSpec {
+    ChannelSpec {
+        source: source.into(),
+        source_handle: source_handle.into(),
+        target: target.into(),
+        target_handle: target_handle.into(),
+    }
+}
+
+#[tokio::test]
+async fn test_add_channel_checks_pin_types() {
+    let askit = test_utils::setup_askit().await;
+
+    let stream_id = askit
+        .add_agent_stream("pins".into(), AgentStreamSpec::default())
+        .unwrap();
+    let source = askit
+        .add_agent(
+            stream_id.clone(),
+            askit.new_agent_spec(ImageSourceAgent::DEF_NAME).unwrap(),
+        )
+        .unwrap();
+    let sink = askit
+        .add_agent(
+            stream_id.clone(),
+            askit.new_agent_spec(MessageSinkAgent::DEF_NAME).unwrap(),
+        )
+        .unwrap();
+
+    let err = askit
+        .add_channel(&stream_id, channel(&source, "image", &sink, "message"))
+        .unwrap_err();
+    assert!(matches!(err, AgentError::IncompatiblePinTypes(_, _)));
+    assert_eq!(
+        err.to_string(),
+        "Incompatible pin types: image (image) -> message (message)"
+    );
+    assert!(
+        askit
+            .get_agent_stream_spec(&stream_id)
+            .await
+            .unwrap()
+            .channels
+            .is_empty()
+    );
+
+    // untyped pins and integers into numbers are accepted
+    askit
+        .add_channel(&stream_id, channel(&source, "image", &sink, "any"))
+        .unwrap();
+    askit
+        .add_channel(&stream_id, channel(&source, "count", &sink, "number"))
+        .unwrap();
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_add_agents_and_channels_checks_pin_types() {
+    let askit = test_utils::setup_askit().await;
+
+    let stream_id = askit
+        .add_agent_stream("pins".into(), AgentStreamSpec::default())
+        .unwrap();
+    let source = askit.new_agent_spec(ImageSourceAgent::DEF_NAME).unwrap();
+    let sink = askit.new_agent_spec(MessageSinkAgent::DEF_NAME).unwrap();
+    let channels = vec![channel(&source.id, "image", &sink.id, "message")];
+
+    let err = askit
+        .add_agents_and_channels(&stream_id, &vec![source, sink], &channels)
+        .unwrap_err();
+    assert!(matches!(err, AgentError::IncompatiblePinTypes(_, _)));
+    // nothing is added
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    assert!(spec.agents.is_empty());
+    assert!(spec.channels.is_empty());
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_add_agent_stream_strict_checks_pin_types() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = AgentStreamSpec::default();
+    let source = askit.new_agent_spec(ImageSourceAgent::DEF_NAME).unwrap();
+    let sink = askit.new_agent_spec(MessageSinkAgent::DEF_NAME).unwrap();
+    spec.add_channel(channel(&source.id, "image", &sink.id, "message"));
+    spec.add_agent(source);
+    spec.add_agent(sink);
+
+    let err = askit
+        .add_agent_stream_strict("pins".into(), spec)
+        .unwrap_err();
+    assert!(matches!(err, AgentError::InvalidStreamSpec(_)));
+    assert!(askit.get_agent_stream_infos().is_empty());
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_validate_pin_types() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = AgentStreamSpec::default();
+    let source = askit.new_agent_spec(ImageSourceAgent::DEF_NAME).unwrap();
+    let sink = askit.new_agent_spec(MessageSinkAgent::DEF_NAME).unwrap();
+    spec.add_channel(channel(&source.id, "count", &sink.id, "number"));
+    spec.add_agent(source);
+    spec.add_agent(sink);
+
+    let diagnostics = spec.validate(&askit);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(!diagnostics[0].is_error());
+    assert_eq!(
+        diagnostics[0].message,
+        "required input \"message\" is not connected"
+    );
+
+    spec.channels[0].source_handle = "image".into();
+    spec.channels[0].target_handle = "message".into();
+    let diagnostics = spec.validate(&askit);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].is_error());
+    assert_eq!(
+        diagnostics[0].message,
+        "pin types are incompatible: image -> message"
+    );
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_subflow_checks_interior_pin_types() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut errors = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentError(_, message) => Some(message),
+        _ => None,
+    });
+    let mut subflow = askit
+        .new_agent_spec("agent_stream_kit::subflow::SubflowAgent")
+        .unwrap();
+    let interior = json!({
+        "agents": [
+            { "id": "source", "def_name": ImageSourceAgent::DEF_NAME },
+            { "id": "sink", "def_name": MessageSinkAgent::DEF_NAME },
+        ],
+        "channels": [
+            { "source": "source", "source_handle": "image", "target": "sink", "target_handle": "message" },
+        ],
+    });
+    let output_map = json!({ "out": { "agent": "source", "pin": "count" } });
+    let configs = subflow.configs.as_mut().unwrap();
+    configs.set("spec".into(), AgentValue::from_json(interior).unwrap());
+    configs.set(
+        "output_map".into(),
+        AgentValue::from_json(output_map).unwrap(),
+    );
+    let mut spec = AgentStreamSpec::default();
+    spec.add_agent(subflow);
+    let stream_id = askit.add_agent_stream("pins".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let message = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        message,
+        "Incompatible pin types: image (image) -> message (message)"
+    );
+
+    askit.quit();
+}
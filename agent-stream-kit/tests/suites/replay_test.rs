@@ -0,0 +1,146 @@
+extern crate agent_stream_kit as askit;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use askit::{ASKitEvent, AgentValue, RecordedEvent, Replayer, test_utils};
+
+use crate::common;
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("askit_{}_{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Run the counter stream, writing `in` three times while recording.
+async fn record_counter(path: &PathBuf) {
+    let askit = test_utils::setup_askit().await;
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let mut outputs = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "out" => value.as_i64(),
+        _ => None,
+    });
+    askit.start_recording(path).await.unwrap();
+    assert!(askit.is_recording());
+    for count in 1..=3 {
+        askit
+            .write_board_value("in".into(), AgentValue::unit())
+            .await
+            .unwrap();
+        let output = tokio::time::timeout(Duration::from_millis(200), outputs.recv())
+            .await
+            .unwrap();
+        assert_eq!(output, Some(count));
+    }
+    askit.stop_recording();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_record_stream_run() {
+    let path = temp_file("record");
+    record_counter(&path).await;
+
+    let events = RecordedEvent::read_file(&path).unwrap();
+    let RecordedEvent::Stream { name, running, .. } = &events[0] else {
+        panic!("expected the stream first, got {:?}", events[0]);
+    };
+    assert_eq!(name, "counter");
+    assert!(running);
+
+    let inputs: Vec<&RecordedEvent> = events.iter().filter(|e| e.is_external()).collect();
+    assert_eq!(inputs.len(), 3);
+
+    // the input, the count, and the board written from the count share a context
+    let ctx_id = inputs[0].ctx().unwrap().id();
+    let flow: Vec<String> = events
+        .iter()
+        .filter(|e| e.ctx().is_some_and(|c| c.id() == ctx_id))
+        .map(|e| match e {
+            RecordedEvent::AgentOut { pin, value, .. } => format!("{}={:?}", pin, value.as_i64()),
+            RecordedEvent::BoardOut { name, .. } => name.clone(),
+            RecordedEvent::Stream { .. } => unreachable!(),
+        })
+        .collect();
+    assert_eq!(flow, vec!["in", "count=Some(1)", "out"]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_replay_matches_recording() {
+    let path = temp_file("replay");
+    record_counter(&path).await;
+
+    let askit = test_utils::setup_askit().await;
+    let report = Replayer::from_file(&path)
+        .unwrap()
+        .replay(&askit)
+        .await
+        .unwrap();
+    assert_eq!(report.inputs, 3);
+    assert!(report.is_match(), "{:?}", report.mismatches);
+    assert!(!askit.is_recording());
+    askit.quit();
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_replay_reports_mismatches() {
+    let path = temp_file("replay_mismatch");
+    record_counter(&path).await;
+
+    // pretend that the second count was different in production
+    let mut events = RecordedEvent::read_file(&path).unwrap();
+    let second = events
+        .iter_mut()
+        .filter_map(|e| match e {
+            RecordedEvent::AgentOut { value, .. } => Some(value),
+            _ => None,
+        })
+        .nth(1)
+        .unwrap();
+    *second = AgentValue::integer(5);
+
+    let askit = test_utils::setup_askit().await;
+    let report = Replayer::new(events).replay(&askit).await.unwrap();
+    assert_eq!(report.inputs, 3);
+    assert_eq!(report.mismatches.len(), 1);
+    let mismatch = &report.mismatches[0];
+    let count = |events: &[RecordedEvent]| {
+        events.iter().find_map(|e| match e {
+            RecordedEvent::AgentOut { value, .. } => value.as_i64(),
+            _ => None,
+        })
+    };
+    assert_eq!(count(&mismatch.expected), Some(5));
+    assert_eq!(count(&mismatch.actual), Some(2));
+    askit.quit();
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_shutdown_writes_recording() {
+    let path = temp_file("record_shutdown");
+    let askit = test_utils::setup_askit().await;
+    askit.start_recording(&path).await.unwrap();
+    askit
+        .write_board_value("in".into(), AgentValue::unit())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let report = askit.shutdown(Duration::from_secs(1)).await;
+    assert!(report.is_clean());
+    assert!(!askit.is_recording());
+    let events = RecordedEvent::read_file(&path).unwrap();
+    assert_eq!(events.iter().filter(|e| e.is_external()).count(), 1);
+
+    let _ = std::fs::remove_file(&path);
+}
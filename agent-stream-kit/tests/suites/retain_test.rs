@@ -0,0 +1,115 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{AgentStreamSpec, AgentValue, ChannelSpec, test_utils};
+
+use crate::common;
+use crate::common::boards::write_board;
+use crate::common::streams::{BOARD_IN_DEF, VAR_OUT_DEF};
+
+#[tokio::test]
+async fn test_retained_board_is_emitted_on_start() {
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_retained("in", true);
+    assert!(askit.is_board_retained("in"));
+    write_board(&askit, "in", AgentValue::string("before")).await;
+
+    // started after the value was written
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    test_utils::expect_board_value("out", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_unretained_board_is_not_emitted_on_start() {
+    let askit = test_utils::setup_askit().await;
+    write_board(&askit, "in", AgentValue::string("before")).await;
+
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    assert!(
+        test_utils::recv_board_with_timeout(Duration::from_millis(200))
+            .await
+            .is_err()
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_retained_board_is_emitted_on_name_change() {
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_retained("in", true);
+    write_board(&askit, "in", AgentValue::string("before")).await;
+
+    let spec = common::streams::counter_stream(&askit, "other", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    // the ids are renewed when the stream is added
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    let board_out_id = spec.agents[0].id.clone();
+
+    let mut configs = askit
+        .get_agent_spec(&board_out_id)
+        .await
+        .unwrap()
+        .configs
+        .unwrap();
+    configs.set("name".into(), AgentValue::string("in"));
+    askit
+        .set_agent_configs(board_out_id, configs)
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &AgentValue::integer(1))
+        .await
+        .unwrap();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_retained_var_is_emitted_on_start() {
+    let askit = test_utils::setup_askit().await;
+
+    // Var `count` -> Board `out`
+    let mut spec = AgentStreamSpec::default();
+    let mut var_out = askit.new_agent_spec(VAR_OUT_DEF).unwrap();
+    var_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("count"));
+    let mut board_in = askit.new_agent_spec(BOARD_IN_DEF).unwrap();
+    board_in
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("out"));
+    spec.add_channel(ChannelSpec {
+        source: var_out.id.clone(),
+        source_handle: "value".into(),
+        target: board_in.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(var_out);
+    spec.add_agent(board_in);
+    let stream_id = askit.add_agent_stream("vars".into(), spec).unwrap();
+
+    askit.set_var_retained(&stream_id, "count", true).unwrap();
+    let value = AgentValue::integer(7);
+    askit
+        .write_var_value(&stream_id, "count", value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_var_value(&stream_id, "count", &value)
+        .await
+        .unwrap();
+
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    test_utils::expect_board_value("out", &value).await.unwrap();
+    askit.quit();
+}
@@ -0,0 +1,222 @@
+#![cfg(feature = "server")]
+
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::axum::Router;
+use askit::axum::body::{Body, to_bytes};
+use askit::axum::http::{Request, StatusCode};
+use askit::{ASKit, server, test_utils};
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio_tungstenite::tungstenite::Message;
+use tower::ServiceExt;
+
+use crate::common;
+use crate::common::agents::CounterAgent;
+
+async fn request(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut builder = Request::builder().method(method).uri(uri);
+    let body = match body {
+        Some(body) => {
+            builder = builder.header("content-type", "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = app
+        .clone()
+        .oneshot(builder.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let value = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+    };
+    (status, value)
+}
+
+async fn add_counter_stream(askit: &ASKit, app: &Router) -> String {
+    let spec = common::streams::counter_stream(askit, "in", "out");
+    let (status, body) = request(
+        app,
+        "POST",
+        "/streams",
+        Some(json!({ "name": "counter", "spec": spec })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    body["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_rest_api() {
+    let askit = test_utils::setup_askit().await;
+    let app = server::router(askit.clone());
+
+    let (status, defs) = request(&app, "GET", "/definitions", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(defs.get(CounterAgent::DEF_NAME).is_some());
+
+    let stream_id = add_counter_stream(&askit, &app).await;
+    let (_, streams) = request(&app, "GET", "/streams", None).await;
+    assert_eq!(streams[0]["id"], stream_id);
+    assert_eq!(streams[0]["running"], false);
+
+    let (status, _) = request(&app, "POST", &format!("/streams/{}/start", stream_id), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(askit.get_agent_stream_info(&stream_id).unwrap().running);
+
+    let (status, spec) = request(&app, "GET", &format!("/streams/{}/spec", stream_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let agents = spec["agents"].as_array().unwrap();
+    assert_eq!(agents.len(), 3);
+    let counter_id = agents
+        .iter()
+        .find(|a| a["def_name"] == CounterAgent::DEF_NAME)
+        .unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, _) = request(
+        &app,
+        "PATCH",
+        &format!("/agents/{}/spec", counter_id),
+        Some(json!({ "note": "counts" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, agent) = request(&app, "GET", &format!("/agents/{}/spec", counter_id), None).await;
+    assert_eq!(agent["note"], "counts");
+
+    let (status, _) = request(
+        &app,
+        "PUT",
+        &format!("/configs/{}", CounterAgent::DEF_NAME),
+        Some(json!({ "limit": 3 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, configs) = request(&app, "GET", "/configs", None).await;
+    assert_eq!(configs[CounterAgent::DEF_NAME]["limit"], 3);
+
+    let mut outputs = askit.subscribe_to_event(|event| match event {
+        askit::ASKitEvent::Board(name, value) if name == "out" => value.as_i64(),
+        _ => None,
+    });
+    let (status, _) = request(&app, "PUT", "/boards/in", Some(json!(null))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let output = tokio::time::timeout(Duration::from_millis(200), outputs.recv())
+        .await
+        .unwrap();
+    assert_eq!(output, Some(1));
+    let (_, boards) = request(&app, "GET", "/boards", None).await;
+    assert_eq!(boards, json!(["in", "out"]));
+    let (status, value) = request(&app, "GET", "/boards/out", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(value, json!(1));
+    let (status, _) = request(&app, "GET", "/boards/unknown", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, history) = request(&app, "GET", "/boards/out/history", None).await;
+    assert_eq!(history, json!([]));
+
+    let (status, metrics) = request(&app, "GET", "/metrics", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        metrics
+            .as_str()
+            .unwrap()
+            .contains("askit_board_writes_total{board=\"in\"} 1")
+    );
+
+    let (status, _) = request(&app, "DELETE", &format!("/streams/{}", stream_id), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = request(&app, "GET", &format!("/streams/{}", stream_id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"].as_str().unwrap().contains(&stream_id));
+    let (status, _) = request(
+        &app,
+        "GET",
+        &format!("/streams/{}/vars/unknown", stream_id),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = request(&app, "POST", "/streams/unknown/start", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_websocket_events_and_writes() {
+    let askit = test_utils::setup_askit().await;
+    let app = server::router(askit.clone());
+    add_counter_stream(&askit, &app).await;
+    let stream_id = askit.get_agent_stream_infos()[0].id.clone();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(askit.clone(), listener));
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/events", addr))
+        .await
+        .unwrap();
+    // the subscription starts after the upgrade
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    ws.send(Message::text(
+        json!({ "type": "write_board", "name": "in", "value": null }).to_string(),
+    ))
+    .await
+    .unwrap();
+
+    let board = tokio::time::timeout(Duration::from_secs(1), async {
+        while let Some(message) = ws.next().await {
+            let Message::Text(text) = message.unwrap() else {
+                continue;
+            };
+            let event: Value = serde_json::from_str(&text).unwrap();
+            if event["type"] == "board" && event["name"] == "out" {
+                return event;
+            }
+        }
+        panic!("closed");
+    })
+    .await
+    .unwrap();
+    assert_eq!(board["value"], 1);
+
+    ws.send(Message::text(json!({ "type": "unknown" }).to_string()))
+        .await
+        .unwrap();
+    let error = tokio::time::timeout(Duration::from_secs(1), async {
+        while let Some(message) = ws.next().await {
+            let Message::Text(text) = message.unwrap() else {
+                continue;
+            };
+            let event: Value = serde_json::from_str(&text).unwrap();
+            if event["type"] == "error" {
+                return event;
+            }
+        }
+        panic!("closed");
+    })
+    .await
+    .unwrap();
+    assert!(error["message"].is_string());
+
+    askit.quit();
+}
@@ -0,0 +1,277 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::tool::{Tool, ToolInfo};
+use askit::{
+    ASKit, ASKitEvent, AgentContext, AgentData, AgentError, AgentSpec, AgentStreamSpec, AgentValue,
+    AsAgent, ChannelSpec, askit_agent, async_trait, test_utils,
+};
+use serde_json::json;
+
+use crate::common;
+
+/// Takes a while to process each input.
+#[askit_agent(title = "Slow", category = "Test/Shutdown", inputs = ["in"])]
+pub struct SlowAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for SlowAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        Ok(())
+    }
+}
+
+const CALL_TOOL_DEF: &str = "agent_stream_kit::tool::CallToolAgent";
+
+/// Returns the value after a while.
+struct DelayTool {
+    info: ToolInfo,
+    value: AgentValue,
+}
+
+impl DelayTool {
+    fn new(name: &str, value: AgentValue) -> Self {
+        Self {
+            info: ToolInfo {
+                name: name.to_string(),
+                description: String::new(),
+                parameters: None,
+            },
+            value,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for DelayTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, _ctx: AgentContext, _args: AgentValue) -> Result<AgentValue, AgentError> {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(self.value.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_messages() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "shutdown_in", "shutdown_out");
+    let stream_id = askit.add_agent_stream("shutdown".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let mut counts = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "shutdown_out" => value.as_i64(),
+        _ => None,
+    });
+
+    for _ in 0..5 {
+        askit
+            .write_board_value("shutdown_in".into(), AgentValue::unit())
+            .await
+            .unwrap();
+    }
+
+    let report = askit.shutdown(Duration::from_secs(2)).await;
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.stopped_agents, 3);
+    assert!(!askit.get_agent_stream_info(&stream_id).unwrap().running);
+
+    // every input went through the graph before the agents were stopped
+    let mut last = None;
+    while let Ok(count) = counts.try_recv() {
+        last = Some(count);
+    }
+    assert_eq!(last, Some(5));
+
+    assert!(matches!(
+        askit
+            .write_board_value("shutdown_in".into(), AgentValue::unit())
+            .await,
+        Err(AgentError::ShuttingDown)
+    ));
+    assert!(matches!(
+        askit.start_agent_stream(&stream_id).await,
+        Err(AgentError::ShuttingDown)
+    ));
+    let spec = common::streams::counter_stream(&askit, "shutdown_in", "shutdown_out");
+    assert!(matches!(
+        askit.add_agent_stream("shutdown2".into(), spec),
+        Err(AgentError::ShuttingDown)
+    ));
+    assert!(matches!(
+        askit
+            .call_tool(AgentContext::new(), None, "any_tool", AgentValue::unit())
+            .await,
+        Err(AgentError::ShuttingDown)
+    ));
+}
+
+#[tokio::test]
+async fn test_shutdown_reports_abandoned_inputs() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = AgentStreamSpec::default();
+    let mut board_out = askit
+        .new_agent_spec(common::streams::BOARD_OUT_DEF)
+        .unwrap();
+    board_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("slow_in"));
+    let slow = askit.new_agent_spec(SlowAgent::DEF_NAME).unwrap();
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: slow.id.clone(),
+        target_handle: "in".into(),
+    });
+    spec.add_agent(board_out);
+    spec.add_agent(slow);
+    let stream_id = askit.add_agent_stream("slow".into(), spec).unwrap();
+    let slow_id = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents
+        .iter()
+        .find(|a| a.def_name == SlowAgent::DEF_NAME)
+        .unwrap()
+        .id
+        .clone();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    for _ in 0..3 {
+        askit
+            .write_board_value("slow_in".into(), AgentValue::unit())
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let report = askit.shutdown(Duration::from_millis(50)).await;
+    assert!(!report.drained);
+    assert!(!report.is_clean());
+    assert!(report.pending_inputs.get(&slow_id).is_some_and(|n| *n > 0));
+}
+
+#[tokio::test]
+async fn test_shutdown_reports_inputs_of_paused_agents() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "paused_in", "paused_out");
+    let stream_id = askit.add_agent_stream("paused".into(), spec).unwrap();
+    let counter_id = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents
+        .iter()
+        .find(|a| a.def_name == common::agents::CounterAgent::DEF_NAME)
+        .unwrap()
+        .id
+        .clone();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    askit.pause_agent_stream(&stream_id).await.unwrap();
+    for _ in 0..2 {
+        askit
+            .write_board_value("paused_in".into(), AgentValue::unit())
+            .await
+            .unwrap();
+    }
+
+    // the held inputs do not hold up the drain until the timeout
+    let report = tokio::time::timeout(
+        Duration::from_secs(1),
+        askit.shutdown(Duration::from_secs(5)),
+    )
+    .await
+    .unwrap();
+    assert!(report.drained);
+    assert!(!report.is_clean());
+    assert_eq!(report.pending_inputs.get(&counter_id), Some(&2));
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_tool_calls() {
+    let askit = test_utils::setup_askit().await;
+    // the first tool returns the call of the second, which starts while draining
+    askit.register_tool(DelayTool::new(
+        "first_tool",
+        AgentValue::from_json(json!({ "name": "second_tool" })).unwrap(),
+    ));
+    askit.register_tool(DelayTool::new("second_tool", AgentValue::string("done")));
+
+    // Board `tools_in` -> Call Tool -> Call Tool -> Board `tools_out`
+    let mut spec = AgentStreamSpec::default();
+    let mut board_out = askit
+        .new_agent_spec(common::streams::BOARD_OUT_DEF)
+        .unwrap();
+    board_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("tools_in"));
+    let first = askit.new_agent_spec(CALL_TOOL_DEF).unwrap();
+    let second = askit.new_agent_spec(CALL_TOOL_DEF).unwrap();
+    let mut board_in = askit.new_agent_spec(common::streams::BOARD_IN_DEF).unwrap();
+    board_in
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("tools_out"));
+    for (source, source_handle, target, target_handle) in [
+        (&board_out, "value", &first, "tool_call"),
+        (&first, "value", &second, "tool_call"),
+        (&second, "value", &board_in, "value"),
+    ] {
+        spec.add_channel(ChannelSpec {
+            source: source.id.clone(),
+            source_handle: source_handle.into(),
+            target: target.id.clone(),
+            target_handle: target_handle.into(),
+        });
+    }
+    spec.add_agent(board_out);
+    spec.add_agent(first);
+    spec.add_agent(second);
+    spec.add_agent(board_in);
+    let stream_id = askit.add_agent_stream("tools".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let mut outputs = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "tools_out" => {
+            value.as_str().map(|s| s.to_string())
+        }
+        _ => None,
+    });
+    askit
+        .write_board_value(
+            "tools_in".into(),
+            AgentValue::from_json(json!({ "name": "first_tool" })).unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let report = askit.shutdown(Duration::from_secs(2)).await;
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(outputs.try_recv().ok().as_deref(), Some("done"));
+}
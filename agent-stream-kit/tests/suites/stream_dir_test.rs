@@ -0,0 +1,234 @@
+extern crate agent_stream_kit as askit;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use askit::{ASKitEvent, AgentStreamSpec, AgentValue, StreamDirectory, test_utils};
+
+use crate::common;
+use crate::common::boards::{counts, next_count, tick};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("askit_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_stream(path: &Path, spec: &AgentStreamSpec) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, spec.to_json().unwrap()).unwrap();
+}
+
+fn set_out_board(spec: &mut AgentStreamSpec, board: &str) {
+    spec.agents
+        .iter_mut()
+        .find(|a| a.def_name == common::streams::BOARD_IN_DEF)
+        .unwrap()
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(board));
+}
+
+#[tokio::test]
+async fn test_stream_directory_sync() {
+    let askit = test_utils::setup_askit().await;
+    let dir = temp_dir("stream_dir_sync");
+
+    let mut counter = common::streams::counter_stream(&askit, "a_in", "a_out");
+    write_stream(&dir.join("counter.json"), &counter);
+    write_stream(
+        &dir.join("nested/counter.json"),
+        &common::streams::counter_stream(&askit, "b_in", "b_out"),
+    );
+    std::fs::write(dir.join("notes.txt"), "not a stream").unwrap();
+
+    let stream_dir = StreamDirectory::new(askit.clone(), &dir);
+    stream_dir.sync().await.unwrap();
+
+    let counter_id = stream_dir.stream_id("counter").await.unwrap();
+    let nested_id = stream_dir.stream_id("nested/counter").await.unwrap();
+    let mut names: Vec<String> = askit
+        .get_agent_stream_infos()
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["counter", "nested/counter"]);
+    assert!(askit.get_agent_stream_info(&counter_id).unwrap().running);
+
+    let mut rx = counts(&askit);
+    tick(&askit, "a_in").await;
+    assert_eq!(next_count(&mut rx).await, Some(("a_out".into(), 1)));
+
+    // a changed file is applied to the running stream
+    set_out_board(&mut counter, "a_out2");
+    write_stream(&dir.join("counter.json"), &counter);
+    stream_dir.sync().await.unwrap();
+    assert_eq!(stream_dir.stream_id("counter").await.unwrap(), counter_id);
+    tick(&askit, "a_in").await;
+    assert_eq!(next_count(&mut rx).await, Some(("a_out2".into(), 2)));
+
+    // a deleted file removes its stream
+    std::fs::remove_file(dir.join("nested/counter.json")).unwrap();
+    stream_dir.sync().await.unwrap();
+    assert!(stream_dir.stream_id("nested/counter").await.is_none());
+    assert!(askit.get_agent_stream_info(&nested_id).is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_stream_directory_errors_are_events() {
+    let askit = test_utils::setup_askit().await;
+    let dir = temp_dir("stream_dir_errors");
+
+    let mut errors = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::StreamFileError(path, _) => Some(path),
+        _ => None,
+    });
+
+    std::fs::write(dir.join("broken.json"), "{").unwrap();
+    write_stream(
+        &dir.join("counter.json"),
+        &common::streams::counter_stream(&askit, "in", "out"),
+    );
+    let stream_dir = StreamDirectory::new(askit.clone(), &dir).auto_start(false);
+    stream_dir.sync().await.unwrap();
+
+    let path = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(path.ends_with("broken.json"));
+    assert!(stream_dir.stream_id("broken").await.is_none());
+
+    // the other files are loaded anyway
+    let counter_id = stream_dir.stream_id("counter").await.unwrap();
+    assert!(!askit.get_agent_stream_info(&counter_id).unwrap().running);
+
+    assert!(
+        StreamDirectory::new(askit.clone(), dir.join("no_such_dir"))
+            .sync()
+            .await
+            .is_err()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+    askit.quit();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stream_directory_unreadable_subdirectory() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let askit = test_utils::setup_askit().await;
+    let dir = temp_dir("stream_dir_unreadable");
+    let locked = dir.join("locked");
+
+    write_stream(
+        &dir.join("counter.json"),
+        &common::streams::counter_stream(&askit, "a_in", "a_out"),
+    );
+    write_stream(
+        &locked.join("counter.json"),
+        &common::streams::counter_stream(&askit, "b_in", "b_out"),
+    );
+    let stream_dir = StreamDirectory::new(askit.clone(), &dir);
+    stream_dir.sync().await.unwrap();
+
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    if std::fs::read_dir(&locked).is_err() {
+        // not when the permissions do not apply, such as to root
+        let mut errors = askit.subscribe_to_event(|event| match event {
+            ASKitEvent::StreamFileError(path, _) => Some(path),
+            _ => None,
+        });
+        stream_dir.sync().await.unwrap();
+        let path = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(path.ends_with("locked"));
+        // the streams of the files in it are kept
+        assert!(stream_dir.stream_id("locked/counter").await.is_some());
+        assert!(stream_dir.stream_id("counter").await.is_some());
+    }
+
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    askit.quit();
+}
+
+#[cfg(feature = "watch")]
+#[tokio::test]
+async fn test_stream_directory_watch() {
+    let askit = test_utils::setup_askit().await;
+    let dir = temp_dir("stream_dir_watch");
+
+    let stream_dir = StreamDirectory::new(askit.clone(), &dir);
+    let watcher = stream_dir.watch().await.unwrap();
+
+    let wait_for = async |loaded: bool| {
+        for _ in 0..50 {
+            if stream_dir.stream_id("counter").await.is_some() == loaded {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    };
+
+    write_stream(
+        &dir.join("counter.json"),
+        &common::streams::counter_stream(&askit, "in", "out"),
+    );
+    assert!(wait_for(true).await);
+
+    std::fs::remove_file(dir.join("counter.json")).unwrap();
+    assert!(wait_for(false).await);
+    assert!(askit.get_agent_stream_infos().is_empty());
+
+    drop(watcher);
+    let _ = std::fs::remove_dir_all(&dir);
+    askit.quit();
+}
+
+#[cfg(feature = "yaml")]
+#[tokio::test]
+async fn test_stream_directory_yaml() {
+    let askit = test_utils::setup_askit().await;
+    let dir = temp_dir("stream_dir_yaml");
+
+    let spec = common::streams::counter_stream(&askit, "in", "out");
+    std::fs::write(dir.join("counter.yaml"), spec.to_yaml().unwrap()).unwrap();
+    // the same stream name twice
+    std::fs::write(dir.join("counter.json"), spec.to_json().unwrap()).unwrap();
+
+    let mut errors = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::StreamFileError(path, message) => Some((path, message)),
+        _ => None,
+    });
+    let stream_dir = StreamDirectory::new(askit.clone(), &dir);
+    stream_dir.sync().await.unwrap();
+
+    let counter_id = stream_dir.stream_id("counter").await.unwrap();
+    assert_eq!(askit.get_agent_stream_infos().len(), 1);
+    let (path, message) = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(path.ends_with("counter.yaml"));
+    assert_eq!(message, "Agent stream counter already exists");
+
+    let mut rx = counts(&askit);
+    tick(&askit, "in").await;
+    assert_eq!(next_count(&mut rx).await, Some(("out".into(), 1)));
+    assert!(askit.get_agent_stream_info(&counter_id).unwrap().running);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    askit.quit();
+}
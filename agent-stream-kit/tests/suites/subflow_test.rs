@@ -0,0 +1,323 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{ASKit, ASKitEvent, AgentSpec, AgentStreamSpec, AgentValue, ChannelSpec, test_utils};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::common;
+use crate::common::boards::{counts, next_count};
+use crate::common::streams::{VAR_IN_DEF, VAR_OUT_DEF};
+
+const SUBFLOW_DEF: &str = "agent_stream_kit::subflow::SubflowAgent";
+const COUNTER_DEF: &str = common::agents::CounterAgent::DEF_NAME;
+
+/// Counter -> Var `n` -> Var `n`, with `in` and `out` exposed.
+fn interior_spec() -> AgentValue {
+    AgentValue::from_json(json!({
+        "agents": [
+            { "id": "counter", "def_name": COUNTER_DEF, "configs": { "initial_count": 1 } },
+            { "id": "var_in", "def_name": VAR_IN_DEF, "configs": { "name": "n" } },
+            { "id": "var_out", "def_name": VAR_OUT_DEF, "configs": { "name": "n" } },
+        ],
+        "channels": [
+            { "source": "counter", "source_handle": "count", "target": "var_in", "target_handle": "value" },
+        ],
+    }))
+    .unwrap()
+}
+
+fn pin_maps(input: (&str, &str), output: (&str, &str)) -> (AgentValue, AgentValue) {
+    (
+        AgentValue::from_json(json!({ "in": { "agent": input.0, "pin": input.1 } })).unwrap(),
+        AgentValue::from_json(json!({ "out": { "agent": output.0, "pin": output.1 } })).unwrap(),
+    )
+}
+
+fn subflow_spec(
+    askit: &ASKit,
+    stream: Option<&str>,
+    spec: Option<AgentValue>,
+    input_map: AgentValue,
+    output_map: AgentValue,
+) -> AgentSpec {
+    let mut subflow = askit.new_agent_spec(SUBFLOW_DEF).unwrap();
+    let configs = subflow.configs.as_mut().unwrap();
+    if let Some(stream) = stream {
+        configs.set("stream".into(), AgentValue::string(stream));
+    }
+    if let Some(spec) = spec {
+        configs.set("spec".into(), spec);
+    }
+    configs.set("input_map".into(), input_map);
+    configs.set("output_map".into(), output_map);
+    subflow
+}
+
+fn board_agent(askit: &ASKit, def_name: &str, board: &str) -> AgentSpec {
+    let mut agent = askit.new_agent_spec(def_name).unwrap();
+    agent
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(board));
+    agent
+}
+
+/// Board `{prefix}_in` -> subflow -> Board `{prefix}_out`
+fn wire(spec: &mut AgentStreamSpec, askit: &ASKit, subflow: AgentSpec, prefix: &str) {
+    let board_out = board_agent(
+        askit,
+        common::streams::BOARD_OUT_DEF,
+        &format!("{prefix}_in"),
+    );
+    let board_in = board_agent(
+        askit,
+        common::streams::BOARD_IN_DEF,
+        &format!("{prefix}_out"),
+    );
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: subflow.id.clone(),
+        target_handle: "in".into(),
+    });
+    spec.add_channel(ChannelSpec {
+        source: subflow.id.clone(),
+        source_handle: "out".into(),
+        target: board_in.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(board_out);
+    spec.add_agent(subflow);
+    spec.add_agent(board_in);
+}
+
+/// The counts written to the `_out` boards until nothing comes.
+async fn out_counts(rx: &mut mpsc::UnboundedReceiver<(String, i64)>) -> Vec<(String, i64)> {
+    let mut outs = Vec::new();
+    while let Some((name, count)) = next_count(rx).await {
+        if name.ends_with("_out") {
+            outs.push((name, count));
+        }
+    }
+    outs
+}
+
+#[tokio::test]
+async fn test_subflow_inline_spec_scopes_vars() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = AgentStreamSpec::default();
+    for prefix in ["a", "b"] {
+        let (input_map, output_map) = pin_maps(("counter", "in"), ("var_out", "value"));
+        let mut subflow = subflow_spec(&askit, None, Some(interior_spec()), input_map, output_map);
+        // exposed pins, as an editor would write them
+        subflow.inputs = Some(vec!["in".into()]);
+        subflow.outputs = Some(vec!["out".into()]);
+        wire(&mut spec, &askit, subflow, prefix);
+    }
+    assert!(spec.validate(&askit).is_empty());
+    let stream_id = askit.add_agent_stream("subflows".into(), spec).unwrap();
+    let mut rx = counts(&askit);
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    for board in ["a_in", "a_in", "b_in"] {
+        askit
+            .write_board_value(board.into(), AgentValue::unit())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(
+        out_counts(&mut rx).await,
+        [
+            ("a_out".into(), 1),
+            ("a_out".into(), 2),
+            ("b_out".into(), 1)
+        ]
+    );
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_subflow_var_falls_back_to_stream_var() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = AgentStreamSpec::default();
+    let (input_map, output_map) = pin_maps(("counter", "in"), ("var_out", "value"));
+    let mut subflow = subflow_spec(&askit, None, Some(interior_spec()), input_map, output_map);
+    subflow.inputs = Some(vec!["in".into()]);
+    subflow.outputs = Some(vec!["out".into()]);
+    wire(&mut spec, &askit, subflow, "a");
+    let stream_id = askit.add_agent_stream("subflows".into(), spec).unwrap();
+    let mut rx = counts(&askit);
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    // the interior agents are started by the subflow agent
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // the subflow var has no value yet
+    askit
+        .write_var_value(&stream_id, "n", AgentValue::integer(5))
+        .await
+        .unwrap();
+    assert_eq!(out_counts(&mut rx).await, [("a_out".into(), 5)]);
+
+    askit
+        .write_board_value("a_in".into(), AgentValue::unit())
+        .await
+        .unwrap();
+    assert_eq!(out_counts(&mut rx).await, [("a_out".into(), 1)]);
+
+    // shadowed by the subflow var
+    askit
+        .write_var_value(&stream_id, "n", AgentValue::integer(6))
+        .await
+        .unwrap();
+    assert_eq!(out_counts(&mut rx).await, []);
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_subflow_stream_by_name_restarts_fresh() {
+    let askit = test_utils::setup_askit().await;
+
+    let interior: AgentStreamSpec = serde_json::from_value(interior_spec().to_json()).unwrap();
+    let interior_id = askit
+        .add_agent_stream("counter_subflow".into(), interior)
+        .unwrap();
+    let interior = askit.get_agent_stream_spec(&interior_id).await.unwrap();
+    let id_of = |def_name: &str| {
+        interior
+            .agents
+            .iter()
+            .find(|a| a.def_name == def_name)
+            .unwrap()
+            .id
+            .clone()
+    };
+    let (input_map, output_map) =
+        pin_maps((&id_of(COUNTER_DEF), "in"), (&id_of(COUNTER_DEF), "count"));
+
+    let mut spec = AgentStreamSpec::default();
+    let subflow = subflow_spec(&askit, Some("counter_subflow"), None, input_map, output_map);
+    wire(&mut spec, &askit, subflow, "c");
+    let stream_id = askit.add_agent_stream("parent".into(), spec).unwrap();
+    let subflow = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents
+        .into_iter()
+        .find(|a| a.def_name == SUBFLOW_DEF)
+        .unwrap();
+    assert_eq!(subflow.inputs.as_deref().unwrap(), ["in"]);
+    assert_eq!(subflow.outputs.as_deref().unwrap(), ["out"]);
+    let mut rx = counts(&askit);
+
+    for expected in [1, 2] {
+        askit.start_agent_stream(&stream_id).await.unwrap();
+        for _ in 0..expected {
+            askit
+                .write_board_value("c_in".into(), AgentValue::unit())
+                .await
+                .unwrap();
+        }
+        let outs: Vec<(String, i64)> = (1..=expected).map(|n| ("c_out".into(), n)).collect();
+        assert_eq!(out_counts(&mut rx).await, outs);
+        // a new copy of the interior stream is created on every start
+        askit.stop_agent_stream(&stream_id).await.unwrap();
+    }
+
+    // the referenced stream itself is left untouched
+    assert!(!askit.get_agent_stream_info(&interior_id).unwrap().running);
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_subflow_unknown_stream_fails_to_start() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut errors = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentError(_, message) => Some(message),
+        _ => None,
+    });
+    let (input_map, output_map) = pin_maps(("counter", "in"), ("counter", "count"));
+    let mut spec = AgentStreamSpec::default();
+    spec.add_agent(subflow_spec(
+        &askit,
+        Some("no_such_stream"),
+        None,
+        input_map,
+        output_map,
+    ));
+    let stream_id = askit.add_agent_stream("broken".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let message = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message, "Agent stream no_such_stream not found");
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_subflow_embedding_own_stream_fails_to_start() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut errors = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentError(_, message) => Some(message),
+        _ => None,
+    });
+    let (input_map, output_map) = pin_maps(("counter", "in"), ("counter", "count"));
+    let mut spec = AgentStreamSpec::default();
+    spec.add_agent(subflow_spec(
+        &askit,
+        Some("selfish"),
+        None,
+        input_map,
+        output_map,
+    ));
+    let stream_id = askit.add_agent_stream("selfish".into(), spec).unwrap();
+    let subflow_id = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents[0]
+        .id
+        .clone();
+    let expected = "Configuration error: Subflow cannot embed the stream selfish it is inside of";
+
+    // while the stream is taken out of the streams to be started
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    let message = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message, expected);
+
+    // and on its own, with the stream in place
+    askit.stop_agent(&subflow_id).await.unwrap();
+    askit.start_agent(&subflow_id).await.unwrap();
+    let message = tokio::time::timeout(Duration::from_millis(200), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message, expected);
+
+    tokio::time::timeout(
+        Duration::from_millis(500),
+        askit.stop_agent_stream(&stream_id),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    askit.quit();
+}
@@ -0,0 +1,254 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{
+    ASKit, ASKitEvent, Agent, AgentContext, AgentData, AgentError, AgentSpec, AgentStreamSpec,
+    AgentValue, AsAgent, ChannelSpec, SupervisorSpec, askit_agent, async_trait, test_utils,
+};
+use tokio::sync::mpsc;
+
+const BOARD_OUT_DEF: &str = "agent_stream_kit::board_agent::BoardOutAgent";
+const SUBFLOW_DEF: &str = "agent_stream_kit::subflow::SubflowAgent";
+
+/// Fails to start until it has been started `failures` times.
+#[askit_agent(
+    title = "Flaky",
+    category = "Test/Supervisor",
+    integer_config(name = "failures", default = 0)
+)]
+pub struct FlakyAgent {
+    data: AgentData,
+    starts: i64,
+}
+
+#[async_trait]
+impl AsAgent for FlakyAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+            starts: 0,
+        })
+    }
+
+    async fn start(&mut self) -> Result<(), AgentError> {
+        self.starts += 1;
+        let failures = self.configs()?.get_integer_or_default("failures");
+        if self.starts <= failures {
+            return Err(AgentError::Other("flaky".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Panics on every input.
+#[askit_agent(title = "Panic", category = "Test/Supervisor", inputs = ["in"])]
+pub struct PanicAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for PanicAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        panic!("boom");
+    }
+}
+
+fn lifecycle_events(askit: &ASKit, agent_id: String) -> mpsc::UnboundedReceiver<ASKitEvent> {
+    askit.subscribe_to_event(move |event| match &event {
+        ASKitEvent::AgentStarted(id)
+        | ASKitEvent::AgentCrashed(id, _)
+        | ASKitEvent::AgentRestarted(id, _)
+            if *id == agent_id =>
+        {
+            Some(event)
+        }
+        _ => None,
+    })
+}
+
+async fn next_event(rx: &mut mpsc::UnboundedReceiver<ASKitEvent>) -> ASKitEvent {
+    tokio::time::timeout(Duration::from_secs(2), rx.recv())
+        .await
+        .expect("timeout")
+        .expect("closed")
+}
+
+fn flaky_spec(askit: &ASKit, failures: i64, supervisor: SupervisorSpec) -> AgentSpec {
+    let mut spec = askit.new_agent_spec(FlakyAgent::DEF_NAME).unwrap();
+    spec.configs
+        .as_mut()
+        .unwrap()
+        .set("failures".into(), AgentValue::integer(failures));
+    spec.supervisor = Some(supervisor);
+    spec
+}
+
+#[tokio::test]
+async fn test_restart_on_failure() {
+    let askit = test_utils::setup_askit().await;
+
+    let supervisor =
+        SupervisorSpec::on_failure().backoff(Duration::from_millis(10), Duration::from_millis(50));
+    let stream_id = askit.new_agent_stream("restart").unwrap();
+    let agent_id = askit
+        .add_agent(stream_id.clone(), flaky_spec(&askit, 2, supervisor))
+        .unwrap();
+
+    let mut events = lifecycle_events(&askit, agent_id.clone());
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentCrashed(..)
+    ));
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentCrashed(..)
+    ));
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentRestarted(_, 2)
+    ));
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_give_up_and_escalate() {
+    let askit = test_utils::setup_askit().await;
+
+    let supervisor = SupervisorSpec::on_failure()
+        .max_restarts(1, Duration::from_secs(60))
+        .backoff(Duration::from_millis(10), Duration::from_millis(10))
+        .escalate();
+    let stream_id = askit.new_agent_stream("escalate").unwrap();
+    let agent_id = askit
+        .add_agent(stream_id.clone(), flaky_spec(&askit, 5, supervisor))
+        .unwrap();
+
+    let mut events = lifecycle_events(&askit, agent_id.clone());
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentCrashed(..)
+    ));
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentCrashed(..)
+    ));
+
+    for _ in 0..100 {
+        if !askit.get_agent_stream_info(&stream_id).unwrap().running {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!askit.get_agent_stream_info(&stream_id).unwrap().running);
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_escalate_from_subflow() {
+    let askit = test_utils::setup_askit().await;
+
+    let supervisor = SupervisorSpec::on_failure()
+        .max_restarts(1, Duration::from_secs(60))
+        .backoff(Duration::from_millis(10), Duration::from_millis(10))
+        .escalate();
+    let mut interior = AgentStreamSpec::default();
+    interior.add_agent(flaky_spec(&askit, 5, supervisor));
+    let mut subflow = askit.new_agent_spec(SUBFLOW_DEF).unwrap();
+    subflow.configs.as_mut().unwrap().set(
+        "spec".into(),
+        AgentValue::from_json(serde_json::to_value(&interior).unwrap()).unwrap(),
+    );
+    let mut spec = AgentStreamSpec::default();
+    spec.add_agent(subflow);
+    let stream_id = askit
+        .add_agent_stream("escalate_subflow".into(), spec)
+        .unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    // the interior agent stops the stream of the subflow agent
+    for _ in 0..100 {
+        if !askit.get_agent_stream_info(&stream_id).unwrap().running {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!askit.get_agent_stream_info(&stream_id).unwrap().running);
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_restart_after_panic() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut stream_spec = AgentStreamSpec::default();
+    let mut board_spec = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    board_spec
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("panic_in"));
+    let mut panic_spec = askit.new_agent_spec(PanicAgent::DEF_NAME).unwrap();
+    panic_spec.supervisor = Some(
+        SupervisorSpec::on_failure().backoff(Duration::from_millis(10), Duration::from_millis(10)),
+    );
+    stream_spec.add_channel(ChannelSpec {
+        source: board_spec.id.clone(),
+        source_handle: "value".into(),
+        target: panic_spec.id.clone(),
+        target_handle: "in".into(),
+    });
+    stream_spec.add_agent(board_spec);
+    stream_spec.add_agent(panic_spec);
+
+    let stream_id = askit.add_agent_stream("panic".into(), stream_spec).unwrap();
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    let agent_id = spec
+        .agents
+        .iter()
+        .find(|a| a.def_name == PanicAgent::DEF_NAME)
+        .unwrap()
+        .id
+        .clone();
+
+    let mut events = lifecycle_events(&askit, agent_id.clone());
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentStarted(_)
+    ));
+
+    askit
+        .write_board_value("panic_in".into(), AgentValue::unit())
+        .await
+        .unwrap();
+
+    match next_event(&mut events).await {
+        ASKitEvent::AgentCrashed(_, message) => assert!(message.contains("boom")),
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(matches!(
+        next_event(&mut events).await,
+        ASKitEvent::AgentRestarted(_, 1)
+    ));
+
+    askit.quit();
+}
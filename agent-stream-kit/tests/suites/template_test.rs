@@ -0,0 +1,152 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::{ASKit, ASKitEvent, AgentError, AgentStreamSpec, AgentValue, FnvIndexMap, test_utils};
+use serde_json::json;
+
+use crate::common;
+
+const COUNTER_DEF: &str = common::agents::CounterAgent::DEF_NAME;
+
+/// Board `${customer}_in` -> Counter starting at `${start}` -> Board `${customer}_out`
+fn counter_template(askit: &ASKit) -> AgentStreamSpec {
+    let mut spec = common::streams::counter_stream(askit, "${customer}_in", "${customer}_out");
+    spec.agents
+        .iter_mut()
+        .find(|a| a.def_name == COUNTER_DEF)
+        .unwrap()
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("initial_count".into(), AgentValue::string("${start}"));
+    spec.extensions.insert(
+        "parameters".into(),
+        json!({
+            "customer": { "type": "string" },
+            "start": { "type": "integer", "default": 1 },
+        }),
+    );
+    spec
+}
+
+fn params(entries: &[(&str, AgentValue)]) -> FnvIndexMap<String, AgentValue> {
+    entries
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_instantiate_stream_template() {
+    let askit = test_utils::setup_askit().await;
+
+    askit
+        .add_stream_template("counter", counter_template(&askit))
+        .unwrap();
+    assert_eq!(askit.get_stream_template_names(), vec!["counter"]);
+
+    let mut counts = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name.ends_with("_out") => {
+            value.as_i64().map(|v| (name, v))
+        }
+        _ => None,
+    });
+
+    let acme_id = askit
+        .instantiate_stream_template(
+            "counter",
+            &params(&[
+                ("customer", AgentValue::string("acme")),
+                ("start", AgentValue::integer(10)),
+            ]),
+        )
+        .unwrap();
+    let globex_id = askit
+        .instantiate_stream_template(
+            "counter",
+            &params(&[("customer", AgentValue::string("globex"))]),
+        )
+        .unwrap();
+
+    let acme = askit.get_agent_stream_spec(&acme_id).await.unwrap();
+    assert!(!acme.extensions.contains_key("parameters"));
+    assert!(acme.validate(&askit).is_empty());
+    for (stream_id, start) in [(&acme_id, 10), (&globex_id, 1)] {
+        let spec = askit.get_agent_stream_spec(stream_id).await.unwrap();
+        let counter = spec
+            .agents
+            .iter()
+            .find(|a| a.def_name == COUNTER_DEF)
+            .unwrap();
+        assert_eq!(
+            counter
+                .configs
+                .as_ref()
+                .unwrap()
+                .get_integer("initial_count")
+                .unwrap(),
+            start
+        );
+    }
+    assert_eq!(
+        askit.get_agent_stream_info(&acme_id).unwrap().name,
+        "counter"
+    );
+    assert_eq!(
+        askit.get_agent_stream_info(&globex_id).unwrap().name,
+        "counter2"
+    );
+
+    askit.start_agent_stream(&acme_id).await.unwrap();
+    askit.start_agent_stream(&globex_id).await.unwrap();
+    for board in ["acme_in", "globex_in"] {
+        askit
+            .write_board_value(board.into(), AgentValue::unit())
+            .await
+            .unwrap();
+        let count = tokio::time::timeout(Duration::from_millis(200), counts.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(count, (board.replace("_in", "_out"), 1));
+    }
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_instantiate_stream_template_invalid_params() {
+    let askit = test_utils::setup_askit().await;
+
+    askit
+        .add_stream_template("counter", counter_template(&askit))
+        .unwrap();
+
+    for (params, message) in [
+        (params(&[]), "parameter \"customer\" is required"),
+        (
+            params(&[("customer", AgentValue::integer(1))]),
+            "parameter \"customer\" is not of type string",
+        ),
+        (
+            params(&[
+                ("customer", AgentValue::string("acme")),
+                ("region", AgentValue::string("eu")),
+            ]),
+            "unknown parameter \"region\"",
+        ),
+    ] {
+        match askit.instantiate_stream_template("counter", &params) {
+            Err(AgentError::InvalidTemplate(m)) => assert_eq!(m, message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    assert!(matches!(
+        askit.instantiate_stream_template("no_such_template", &params(&[])),
+        Err(AgentError::TemplateNotFound(_))
+    ));
+    assert!(askit.get_agent_stream_infos().is_empty());
+
+    askit.quit();
+}
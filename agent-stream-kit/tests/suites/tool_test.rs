@@ -0,0 +1,332 @@
+extern crate agent_stream_kit as askit;
+
+use std::time::Duration;
+
+use askit::tool::{self, Tool, ToolInfo};
+use askit::{
+    ASKit, ASKitEvent, AgentContext, AgentError, AgentStreamSpec, AgentValue, ChannelSpec,
+    async_trait, test_utils,
+};
+use serde_json::json;
+
+use crate::common::streams::{BOARD_IN_DEF, BOARD_OUT_DEF};
+
+const STREAM_TOOL_DEF: &str = "agent_stream_kit::tool::StreamToolAgent";
+const CALL_TOOL_DEF: &str = "agent_stream_kit::tool::CallToolAgent";
+const SUBFLOW_DEF: &str = "agent_stream_kit::subflow::SubflowAgent";
+
+struct ConstTool {
+    info: ToolInfo,
+    value: &'static str,
+}
+
+impl ConstTool {
+    fn new(name: &str, value: &'static str) -> Self {
+        Self {
+            info: ToolInfo {
+                name: name.to_string(),
+                description: String::new(),
+                parameters: None,
+            },
+            value,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ConstTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, _ctx: AgentContext, _args: AgentValue) -> Result<AgentValue, AgentError> {
+        Ok(AgentValue::string(self.value))
+    }
+}
+
+async fn call(askit: &ASKit, stream_id: Option<&str>, name: &str) -> Option<String> {
+    askit
+        .call_tool(AgentContext::new(), stream_id, name, AgentValue::unit())
+        .await
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+}
+
+#[tokio::test]
+async fn test_instance_tools_are_isolated() {
+    let askit1 = ASKit::init().unwrap();
+    let askit2 = ASKit::init().unwrap();
+
+    askit1.register_tool(ConstTool::new("isolated", "one"));
+    askit2.register_tool(ConstTool::new("isolated", "two"));
+
+    assert_eq!(
+        call(&askit1, None, "isolated").await.as_deref(),
+        Some("one")
+    );
+    assert_eq!(
+        call(&askit2, None, "isolated").await.as_deref(),
+        Some("two")
+    );
+    assert!(tool::get_tool("isolated").is_none());
+
+    askit1.unregister_tool("isolated");
+    assert!(call(&askit1, None, "isolated").await.is_none());
+    assert_eq!(
+        call(&askit2, None, "isolated").await.as_deref(),
+        Some("two")
+    );
+}
+
+#[tokio::test]
+async fn test_tool_lookup_falls_back_from_stream_to_global() {
+    let askit = ASKit::init().unwrap();
+    let stream1 = askit.new_agent_stream("tools1").unwrap();
+    let stream2 = askit.new_agent_stream("tools2").unwrap();
+
+    tool::register_tool(ConstTool::new("fallback_global", "global"));
+    tool::register_tool(ConstTool::new("fallback_shadowed", "global"));
+    askit.register_tool(ConstTool::new("fallback_shadowed", "instance"));
+    askit.register_stream_tool(&stream1, ConstTool::new("fallback_shadowed", "stream"));
+
+    assert_eq!(
+        call(&askit, Some(&stream1), "fallback_shadowed")
+            .await
+            .as_deref(),
+        Some("stream")
+    );
+    assert_eq!(
+        call(&askit, Some(&stream2), "fallback_shadowed")
+            .await
+            .as_deref(),
+        Some("instance")
+    );
+    assert_eq!(
+        call(&askit, Some(&stream1), "fallback_global")
+            .await
+            .as_deref(),
+        Some("global")
+    );
+
+    let infos = askit
+        .list_tool_infos_patterns(Some(&stream1), "^fallback_")
+        .unwrap();
+    assert_eq!(infos.len(), 2);
+
+    // stream tools are removed with the stream
+    askit.remove_agent_stream(&stream1).await.unwrap();
+    assert_eq!(
+        call(&askit, Some(&stream1), "fallback_shadowed")
+            .await
+            .as_deref(),
+        Some("instance")
+    );
+
+    tool::unregister_tool("fallback_global");
+    tool::unregister_tool("fallback_shadowed");
+}
+
+#[tokio::test]
+async fn test_tool_lookup_from_subflow() {
+    let askit = test_utils::setup_askit().await;
+
+    // Board `subflow_tool_in` -> subflow of Call Tool -> Board `subflow_tool_out`
+    let mut spec = AgentStreamSpec::default();
+    let mut subflow = askit.new_agent_spec(SUBFLOW_DEF).unwrap();
+    let configs = subflow.configs.as_mut().unwrap();
+    for (key, value) in [
+        (
+            "spec",
+            json!({ "agents": [{ "id": "call", "def_name": CALL_TOOL_DEF }], "channels": [] }),
+        ),
+        (
+            "input_map",
+            json!({ "in": { "agent": "call", "pin": "tool_call" } }),
+        ),
+        (
+            "output_map",
+            json!({ "out": { "agent": "call", "pin": "value" } }),
+        ),
+    ] {
+        configs.set(key.into(), AgentValue::from_json(value).unwrap());
+    }
+    subflow.inputs = Some(vec!["in".into()]);
+    subflow.outputs = Some(vec!["out".into()]);
+    let mut board_out = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    board_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("subflow_tool_in"));
+    let mut board_in = askit.new_agent_spec(BOARD_IN_DEF).unwrap();
+    board_in
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("subflow_tool_out"));
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: subflow.id.clone(),
+        target_handle: "in".into(),
+    });
+    spec.add_channel(ChannelSpec {
+        source: subflow.id.clone(),
+        source_handle: "out".into(),
+        target: board_in.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(board_out);
+    spec.add_agent(subflow);
+    spec.add_agent(board_in);
+
+    let stream_id = askit
+        .add_agent_stream("subflow_tools".into(), spec)
+        .unwrap();
+    askit.register_stream_tool(&stream_id, ConstTool::new("subflow_tool", "stream"));
+    let mut results = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "subflow_tool_out" => {
+            value.as_str().map(|s| s.to_string())
+        }
+        _ => None,
+    });
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    // the interior agents are started by the subflow agent
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    askit
+        .write_board_value(
+            "subflow_tool_in".into(),
+            AgentValue::from_json(json!({ "name": "subflow_tool" })).unwrap(),
+        )
+        .await
+        .unwrap();
+    let result = tokio::time::timeout(Duration::from_secs(1), results.recv())
+        .await
+        .unwrap();
+    assert_eq!(result.as_deref(), Some("stream"));
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_stream_tool_agents_reject_duplicate_names() {
+    let askit = test_utils::setup_askit().await;
+    let mut spec = AgentStreamSpec::default();
+    for _ in 0..2 {
+        let mut agent = askit.new_agent_spec(STREAM_TOOL_DEF).unwrap();
+        agent
+            .configs
+            .as_mut()
+            .unwrap()
+            .set("name".into(), AgentValue::string("duplicate_tool"));
+        spec.add_agent(agent);
+    }
+    let mut crashes = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentCrashed(agent_id, message) => Some((agent_id, message)),
+        _ => None,
+    });
+    let stream_id = askit.add_agent_stream("tools".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    // the second agent fails to register the tool
+    let (agent_id, message) = tokio::time::timeout(Duration::from_secs(1), crashes.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(message.contains("duplicate_tool"), "{}", message);
+
+    // and stopping it leaves the tool of the first one
+    askit.stop_agent(&agent_id).await.unwrap();
+    let names: Vec<String> = askit
+        .list_tool_infos(None)
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    assert!(names.contains(&"duplicate_tool".to_string()));
+
+    askit.stop_agent_stream(&stream_id).await.unwrap();
+    assert!(
+        askit
+            .list_tool_infos(None)
+            .iter()
+            .all(|info| info.name != "duplicate_tool")
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_stream_tool_rename_to_used_name_keeps_old_tool() {
+    let askit = test_utils::setup_askit().await;
+    let mut spec = AgentStreamSpec::default();
+    for name in ["kept_tool", "renamed_tool"] {
+        let mut agent = askit.new_agent_spec(STREAM_TOOL_DEF).unwrap();
+        agent
+            .configs
+            .as_mut()
+            .unwrap()
+            .set("name".into(), AgentValue::string(name));
+        spec.add_agent(agent);
+    }
+    let stream_id = askit.add_agent_stream("tools".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let agent = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents[1]
+        .clone();
+    let mut configs = agent.configs.unwrap();
+    configs.set("name".into(), AgentValue::string("kept_tool"));
+    askit.set_agent_configs(agent.id, configs).await.unwrap();
+
+    // the configs are applied by the agent, which fails to register the new name
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // both tools are still registered
+    let mut names: Vec<String> = askit
+        .list_tool_infos(None)
+        .into_iter()
+        .map(|info| info.name)
+        .filter(|name| name.ends_with("_tool"))
+        .collect();
+    names.sort();
+    assert_eq!(names, ["kept_tool", "renamed_tool"]);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_stream_tool_rename_replaces_tool() {
+    let askit = test_utils::setup_askit().await;
+    let mut spec = AgentStreamSpec::default();
+    let mut agent = askit.new_agent_spec(STREAM_TOOL_DEF).unwrap();
+    agent
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("old_tool"));
+    spec.add_agent(agent);
+    let stream_id = askit.add_agent_stream("tools".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let agent = askit
+        .get_agent_stream_spec(&stream_id)
+        .await
+        .unwrap()
+        .agents[0]
+        .clone();
+    let mut configs = agent.configs.unwrap();
+    configs.set("name".into(), AgentValue::string("new_tool"));
+    askit.set_agent_configs(agent.id, configs).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // only the new name is registered
+    let names: Vec<String> = askit
+        .list_tool_infos(None)
+        .into_iter()
+        .map(|info| info.name)
+        .filter(|name| name.ends_with("_tool"))
+        .collect();
+    assert_eq!(names, ["new_tool"]);
+    askit.quit();
+}
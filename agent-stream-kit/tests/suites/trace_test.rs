@@ -0,0 +1,191 @@
+extern crate agent_stream_kit as askit;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use askit::tool::{Tool, ToolInfo};
+use askit::trace::{
+    ATTR_AGENT_ID, ATTR_BOARD, ATTR_CONTEXT_ID, ATTR_DEF_NAME, ATTR_PIN, ATTR_STREAM_ID, ATTR_TOOL,
+    AgentSpan, JsonFileSpanExporter, SpanExporter,
+};
+use askit::{ASKit, ASKitEvent, AgentContext, AgentError, AgentValue, async_trait, test_utils};
+
+use crate::common;
+use crate::common::agents::CounterAgent;
+use crate::common::streams::BOARD_IN_DEF;
+
+/// Keeps the spans in memory.
+#[derive(Clone, Default)]
+struct CollectingExporter {
+    spans: Arc<Mutex<Vec<AgentSpan>>>,
+}
+
+impl CollectingExporter {
+    /// Wait until `count` spans have ended.
+    async fn wait_for(&self, count: usize) -> Vec<AgentSpan> {
+        for _ in 0..100 {
+            let spans = self.spans.lock().unwrap().clone();
+            if spans.len() >= count {
+                return spans;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!(
+            "expected {} spans, got {:?}",
+            count,
+            self.spans.lock().unwrap()
+        );
+    }
+}
+
+impl SpanExporter for CollectingExporter {
+    fn export(&self, span: AgentSpan) {
+        self.spans.lock().unwrap().push(span);
+    }
+}
+
+struct FailingTool {
+    info: ToolInfo,
+}
+
+#[async_trait]
+impl Tool for FailingTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, _ctx: AgentContext, _args: AgentValue) -> Result<AgentValue, AgentError> {
+        Err(AgentError::Other("unavailable".into()))
+    }
+}
+
+fn find<'a>(spans: &'a [AgentSpan], name: &str) -> &'a AgentSpan {
+    spans
+        .iter()
+        .find(|s| s.name == name)
+        .unwrap_or_else(|| panic!("no span {} in {:?}", name, spans))
+}
+
+async fn run_counter(askit: &ASKit) -> String {
+    let spec = common::streams::counter_stream(askit, "in", "out");
+    let stream_id = askit.add_agent_stream("counter".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+
+    let mut outputs = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::Board(name, value) if name == "out" => value.as_i64(),
+        _ => None,
+    });
+    askit
+        .write_board_value("in".into(), AgentValue::unit())
+        .await
+        .unwrap();
+    let output = tokio::time::timeout(Duration::from_millis(200), outputs.recv())
+        .await
+        .unwrap();
+    assert_eq!(output, Some(1));
+    stream_id
+}
+
+#[tokio::test]
+async fn test_process_spans_follow_the_context() {
+    let askit = test_utils::setup_askit().await;
+    let exporter = CollectingExporter::default();
+    askit.set_span_exporter(exporter.clone());
+
+    let stream_id = run_counter(&askit).await;
+    let spans = exporter.wait_for(3).await;
+
+    // the board out agent sends the board value on without processing it
+    let board = find(&spans, "board in");
+    let counter = find(&spans, &format!("process {}", CounterAgent::DEF_NAME));
+    let board_in = find(&spans, &format!("process {}", BOARD_IN_DEF));
+
+    assert_eq!(board.parent_span_id, None);
+    assert_eq!(board.attributes[ATTR_BOARD], "in");
+    assert_eq!(counter.parent_span_id, Some(board.context.span_id));
+    assert_eq!(board_in.parent_span_id, Some(counter.context.span_id));
+
+    let ctx_id = &board.attributes[ATTR_CONTEXT_ID];
+    for span in [counter, board_in] {
+        assert_eq!(span.context.trace_id, board.context.trace_id);
+        assert_eq!(&span.attributes[ATTR_CONTEXT_ID], ctx_id);
+        assert_eq!(span.attributes[ATTR_STREAM_ID], stream_id);
+        assert!(span.error.is_none());
+    }
+    assert_eq!(counter.attributes[ATTR_DEF_NAME], CounterAgent::DEF_NAME);
+    assert_eq!(counter.attributes[ATTR_PIN], "in");
+    assert!(!counter.attributes[ATTR_AGENT_ID].is_empty());
+    assert!(counter.start_time >= board.start_time);
+    assert!(counter.end_time >= counter.start_time);
+
+    // nothing is traced after the exporter is removed
+    askit.remove_span_exporter();
+    askit
+        .write_board_value("in".into(), AgentValue::unit())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(exporter.spans.lock().unwrap().len(), spans.len());
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_tool_calls_are_spans() {
+    let askit = test_utils::setup_askit().await;
+    askit.register_tool(FailingTool {
+        info: ToolInfo {
+            name: "traced_failing".into(),
+            description: String::new(),
+            parameters: None,
+        },
+    });
+    let exporter = CollectingExporter::default();
+    askit.set_span_exporter(exporter.clone());
+
+    let ctx = AgentContext::new();
+    let result = askit
+        .call_tool(ctx.clone(), None, "traced_failing", AgentValue::unit())
+        .await;
+    assert!(result.is_err());
+
+    let spans = exporter.wait_for(1).await;
+    let span = find(&spans, "tool traced_failing");
+    assert_eq!(span.parent_span_id, None);
+    assert_eq!(span.attributes[ATTR_TOOL], "traced_failing");
+    assert_eq!(span.attributes[ATTR_CONTEXT_ID], ctx.id().to_string());
+    assert!(span.error.as_ref().unwrap().contains("unavailable"));
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_json_file_exporter() {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("askit_trace_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let askit = test_utils::setup_askit().await;
+    askit.set_span_exporter(JsonFileSpanExporter::new(&path).unwrap());
+    run_counter(&askit).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    askit.quit();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_json::Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    let spans: Vec<&serde_json::Value> = lines
+        .iter()
+        .map(|line| &line["resourceSpans"][0]["scopeSpans"][0]["spans"][0])
+        .collect();
+    let trace_id = &spans[0]["traceId"];
+    assert_eq!(trace_id.as_str().unwrap().len(), 32);
+    assert!(spans.iter().all(|span| &span["traceId"] == trace_id));
+    assert!(spans.iter().any(|span| span["name"] == "board in"));
+
+    let _ = std::fs::remove_file(&path);
+}
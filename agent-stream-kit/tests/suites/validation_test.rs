@@ -0,0 +1,183 @@
+extern crate agent_stream_kit as askit;
+
+use askit::{
+    AgentError, AgentSpec, AgentStreamSpec, AgentValue, ChannelSpec, DiagnosticLevel,
+    ValidationDiagnostic, test_utils,
+};
+
+use crate::common;
+
+const COUNTER_DEF: &str = common::agents::CounterAgent::DEF_NAME;
+
+fn messages(diagnostics: &[ValidationDiagnostic]) -> Vec<(DiagnosticLevel, &str)> {
+    diagnostics
+        .iter()
+        .map(|d| (d.level, d.message.as_str()))
+        .collect()
+}
+
+fn counter_id(spec: &AgentStreamSpec) -> String {
+    spec.agents
+        .iter()
+        .find(|a| a.def_name == COUNTER_DEF)
+        .unwrap()
+        .id
+        .clone()
+}
+
+#[tokio::test]
+async fn test_validate_valid_stream() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "valid_in", "valid_out");
+    assert!(spec.validate(&askit).is_empty());
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_validate_agents() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = common::streams::counter_stream(&askit, "agents_in", "agents_out");
+    let counter_id = counter_id(&spec);
+    {
+        let counter = spec.agents.iter_mut().find(|a| a.id == counter_id).unwrap();
+        let configs = counter.configs.as_mut().unwrap();
+        configs.set("initial_count".into(), AgentValue::string("one"));
+        configs.set("no_such_config".into(), AgentValue::integer(1));
+    }
+    let mut duplicate = askit.new_agent_spec(COUNTER_DEF).unwrap();
+    duplicate.id = counter_id.clone();
+    spec.add_agent(duplicate);
+    let mut unknown = askit.new_agent_spec(COUNTER_DEF).unwrap();
+    unknown.def_name = "no_such_agent".into();
+    let unknown_id = unknown.id.clone();
+    spec.add_agent(unknown);
+
+    let diagnostics = spec.validate(&askit);
+    assert_eq!(
+        messages(&diagnostics),
+        vec![
+            (
+                DiagnosticLevel::Error,
+                "config \"initial_count\" is not of type integer"
+            ),
+            (
+                DiagnosticLevel::Warning,
+                "unknown config \"no_such_config\""
+            ),
+            (DiagnosticLevel::Error, "duplicate agent id"),
+            (
+                DiagnosticLevel::Error,
+                "unknown agent definition \"no_such_agent\""
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics[0].agent_id.as_deref(),
+        Some(counter_id.as_str())
+    );
+    assert_eq!(
+        diagnostics[3].agent_id.as_deref(),
+        Some(unknown_id.as_str())
+    );
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_validate_channels() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = common::streams::counter_stream(&askit, "channels_in", "channels_out");
+    let counter_id = counter_id(&spec);
+    let board_out_id = spec.agents[0].id.clone();
+    for (source_handle, target, target_handle) in [
+        ("no_such_output", counter_id.as_str(), "in"),
+        ("value", counter_id.as_str(), "no_such_input"),
+        ("value", counter_id.as_str(), "config:initial_count"),
+        ("value", counter_id.as_str(), "config:no_such_config"),
+        ("value", "no_such_agent", "in"),
+    ] {
+        spec.add_channel(ChannelSpec {
+            source: board_out_id.clone(),
+            source_handle: source_handle.into(),
+            target: target.into(),
+            target_handle: target_handle.into(),
+        });
+    }
+
+    let diagnostics = spec.validate(&askit);
+    assert_eq!(
+        messages(&diagnostics),
+        vec![
+            (
+                DiagnosticLevel::Error,
+                "source handle \"no_such_output\" is not an output"
+            ),
+            (
+                DiagnosticLevel::Error,
+                "target handle \"no_such_input\" is not an input"
+            ),
+            (
+                DiagnosticLevel::Error,
+                "target handle \"config:no_such_config\" is not a config"
+            ),
+            (
+                DiagnosticLevel::Error,
+                "target agent no_such_agent not found"
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics[0].channel.as_ref().unwrap().source_handle,
+        "no_such_output"
+    );
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_validate_spec_inputs_override_definition() {
+    let askit = test_utils::setup_askit().await;
+
+    let mut spec = common::streams::counter_stream(&askit, "override_in", "override_out");
+    let counter_id = counter_id(&spec);
+    let counter: &mut AgentSpec = spec.agents.iter_mut().find(|a| a.id == counter_id).unwrap();
+    counter.inputs = Some(vec!["in".into(), "extra".into()]);
+    let board_out_id = spec.agents[0].id.clone();
+    spec.add_channel(ChannelSpec {
+        source: board_out_id,
+        source_handle: "value".into(),
+        target: counter_id,
+        target_handle: "extra".into(),
+    });
+
+    assert!(spec.validate(&askit).is_empty());
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_add_agent_stream_strict() {
+    let askit = test_utils::setup_askit().await;
+
+    let spec = common::streams::counter_stream(&askit, "strict_in", "strict_out");
+    let mut broken = spec.clone();
+    broken.channels[0].target_handle = "no_such_input".into();
+
+    let err = askit
+        .add_agent_stream_strict("broken".into(), broken)
+        .unwrap_err();
+    assert!(matches!(err, AgentError::InvalidStreamSpec(_)));
+    assert!(err.to_string().contains("no_such_input"));
+    assert!(askit.get_agent_stream_infos().is_empty());
+
+    askit
+        .add_agent_stream_strict("strict".into(), spec)
+        .unwrap();
+    assert_eq!(askit.get_agent_stream_infos().len(), 1);
+
+    askit.quit();
+}
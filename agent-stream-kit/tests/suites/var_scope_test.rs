@@ -0,0 +1,278 @@
+extern crate agent_stream_kit as askit;
+
+use std::sync::Arc;
+
+use askit::{
+    ASKit, AgentContext, AgentError, AgentStreamSpec, AgentValue, BoardStore, ChannelSpec,
+    MemoryBoardStore, VarScope, test_utils,
+};
+
+use crate::common::boards::{counts, next_count};
+use crate::common::streams::{BOARD_IN_DEF, BOARD_OUT_DEF, VAR_IN_DEF, VAR_OUT_DEF};
+
+/// `source` agent of `source_name` -> `target` agent of `target_name`
+fn pipe_stream(
+    askit: &ASKit,
+    (source, source_name): (&str, &str),
+    (target, target_name): (&str, &str),
+) -> AgentStreamSpec {
+    let mut spec = AgentStreamSpec::default();
+    let mut source = askit.new_agent_spec(source).unwrap();
+    source
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(source_name));
+    let mut target = askit.new_agent_spec(target).unwrap();
+    target
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string(target_name));
+    spec.add_channel(ChannelSpec {
+        source: source.id.clone(),
+        source_handle: "value".into(),
+        target: target.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(source);
+    spec.add_agent(target);
+    spec
+}
+
+#[tokio::test]
+async fn test_vars_are_addressed_by_stream_name() {
+    let askit = test_utils::setup_askit().await;
+    let stream_id = askit.new_agent_stream("settings").unwrap();
+    let value = AgentValue::integer(3);
+    askit
+        .write_var_value(&stream_id, "limit", value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value("%settings:/limit", &value)
+        .await
+        .unwrap();
+
+    // reloaded under a new id
+    askit.remove_agent_stream(&stream_id).await.unwrap();
+    let new_stream_id = askit.new_agent_stream("settings").unwrap();
+    assert_ne!(new_stream_id, stream_id);
+    assert_eq!(askit.read_var_value(&new_stream_id, "limit"), Some(value));
+    assert_eq!(
+        askit.read_scoped_var_value(&VarScope::Stream("settings".into()), "limit"),
+        Some(AgentValue::integer(3))
+    );
+
+    // unknown stream
+    assert!(
+        askit
+            .write_var_value("unknown", "limit", AgentValue::integer(1))
+            .await
+            .is_err()
+    );
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_stream_names() {
+    let askit = test_utils::setup_askit().await;
+    let main_id = askit
+        .add_agent_stream("main".into(), AgentStreamSpec::default())
+        .unwrap();
+    // the strict add rejects a used name, since the streams would share their vars
+    assert!(matches!(
+        askit.add_agent_stream_strict("main".into(), AgentStreamSpec::default()),
+        Err(AgentError::DuplicateStreamName(name)) if name == "main"
+    ));
+    assert_eq!(askit.get_agent_stream_infos().len(), 1);
+    askit
+        .add_agent_stream("main".into(), AgentStreamSpec::default())
+        .unwrap();
+    assert_eq!(askit.get_agent_stream_infos().len(), 2);
+
+    let stream_id = askit.new_agent_stream(" main ").unwrap();
+    assert_eq!(
+        askit.var_scope(&stream_id).unwrap(),
+        VarScope::Stream("main2".into())
+    );
+    assert_eq!(askit.unique_stream_name(" main2 "), "main22");
+
+    // renaming a stream to its own name keeps it
+    assert_eq!(
+        askit.rename_agent_stream(&stream_id, "main2").unwrap(),
+        "main2"
+    );
+    assert_eq!(
+        askit.rename_agent_stream(&main_id, "main2").unwrap(),
+        "main22"
+    );
+
+    // the scope would be mistaken for a subflow
+    assert!(matches!(
+        askit.add_agent_stream("main:3".into(), AgentStreamSpec::default()),
+        Err(AgentError::InvalidStreamName(_))
+    ));
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_global_var_is_shared_by_streams() {
+    let askit = test_utils::setup_askit().await;
+    let writer = pipe_stream(&askit, (BOARD_OUT_DEF, "in"), (VAR_IN_DEF, "global:shared"));
+    let reader = pipe_stream(
+        &askit,
+        (VAR_OUT_DEF, "global:shared"),
+        (BOARD_IN_DEF, "out"),
+    );
+    for (name, spec) in [("writer", writer), ("reader", reader)] {
+        let stream_id = askit.add_agent_stream(name.into(), spec).unwrap();
+        askit.start_agent_stream(&stream_id).await.unwrap();
+    }
+
+    let value = AgentValue::string("hello");
+    askit
+        .write_board_value("in".into(), value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value("in", &value).await.unwrap();
+    test_utils::expect_board_value("%/shared", &value)
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &value).await.unwrap();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_stream_var_is_not_shared() {
+    let askit = test_utils::setup_askit().await;
+    let writer = pipe_stream(&askit, (BOARD_OUT_DEF, "in"), (VAR_IN_DEF, "stream:local"));
+    let writer_id = askit.add_agent_stream("writer".into(), writer).unwrap();
+    askit.start_agent_stream(&writer_id).await.unwrap();
+
+    let value = AgentValue::integer(1);
+    askit
+        .write_board_value("in".into(), value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value("in", &value).await.unwrap();
+    test_utils::expect_var_value(&writer_id, "local", &value)
+        .await
+        .unwrap();
+    assert_eq!(askit.read_board_value("%writer:/local"), Some(value));
+    assert_eq!(askit.read_board_value("%/local"), None);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_var_out_falls_back_to_enclosing_scopes() {
+    let askit = test_utils::setup_askit().await;
+    let spec = pipe_stream(&askit, (VAR_OUT_DEF, "n"), (BOARD_IN_DEF, "out"));
+    let stream_id = askit.add_agent_stream("main".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    let mut rx = counts(&askit);
+
+    // no stream var yet
+    askit
+        .write_board_value("%/n".into(), AgentValue::integer(1))
+        .await
+        .unwrap();
+    assert_eq!(next_count(&mut rx).await, Some(("%/n".into(), 1)));
+    assert_eq!(next_count(&mut rx).await, Some(("out".into(), 1)));
+
+    askit
+        .write_var_value(&stream_id, "n", AgentValue::integer(2))
+        .await
+        .unwrap();
+    assert_eq!(next_count(&mut rx).await, Some(("%main:/n".into(), 2)));
+    assert_eq!(next_count(&mut rx).await, Some(("out".into(), 2)));
+
+    // shadowed by the stream var
+    askit
+        .write_board_value("%/n".into(), AgentValue::integer(3))
+        .await
+        .unwrap();
+    assert_eq!(next_count(&mut rx).await, Some(("%/n".into(), 3)));
+    assert_eq!(next_count(&mut rx).await, None);
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_rename_stream_moves_vars() {
+    let store = Arc::new(MemoryBoardStore::new());
+    let askit = test_utils::setup_askit().await;
+    askit.set_board_store(store.clone()).unwrap();
+    let spec = pipe_stream(&askit, (VAR_OUT_DEF, "n"), (BOARD_IN_DEF, "out"));
+    let stream_id = askit.add_agent_stream("main".into(), spec).unwrap();
+    askit.start_agent_stream(&stream_id).await.unwrap();
+    askit.set_var_persistent(&stream_id, "n", true).unwrap();
+    askit.set_var_retained(&stream_id, "n", true).unwrap();
+    let value = AgentValue::integer(1);
+    askit
+        .write_var_value(&stream_id, "n", value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value("%main:/n", &value)
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &value).await.unwrap();
+
+    askit.rename_agent_stream(&stream_id, "renamed").unwrap();
+    assert_eq!(askit.read_board_value("%main:/n"), None);
+    assert_eq!(askit.read_board_value("%renamed:/n"), Some(value.clone()));
+    assert!(askit.is_board_persistent("%renamed:/n"));
+    assert!(askit.is_board_retained("%renamed:/n"));
+    askit.flush_board_store();
+    assert_eq!(
+        store.load().unwrap(),
+        vec![("%renamed:/n".to_string(), value)]
+    );
+
+    // the running Var-> agent reads the var under the new name
+    let value = AgentValue::integer(2);
+    askit
+        .write_var_value(&stream_id, "n", value.clone())
+        .await
+        .unwrap();
+    test_utils::expect_board_value("%renamed:/n", &value)
+        .await
+        .unwrap();
+    test_utils::expect_board_value("out", &value).await.unwrap();
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_lookup_var_value() {
+    let askit = test_utils::setup_askit().await;
+    let stream_id = askit.new_agent_stream("main").unwrap();
+    // as seen from inside the subflow agent `9`
+    let interior_id = format!("{}/9", stream_id);
+    let subflow = askit.var_scope(&interior_id).unwrap();
+    assert_eq!(subflow, VarScope::Subflow("main".into(), vec!["9".into()]));
+
+    let lookup = |ctx: &AgentContext, name: &str| askit.lookup_var_value(&interior_id, ctx, name);
+    let ctx = AgentContext::new();
+    assert_eq!(lookup(&ctx, "n"), None);
+
+    // each scope shadows the ones enclosing it
+    for (i, scope) in [VarScope::Global, VarScope::Stream("main".into()), subflow]
+        .iter()
+        .enumerate()
+    {
+        let value = AgentValue::integer(i as i64);
+        askit
+            .write_scoped_var_value(scope, "n", value.clone())
+            .await
+            .unwrap();
+        test_utils::expect_board_value(&scope.board_name("n"), &value)
+            .await
+            .unwrap();
+        assert_eq!(lookup(&ctx, "n"), Some(AgentValue::integer(i as i64)));
+    }
+    let ctx = ctx.with_var("n".into(), AgentValue::integer(3));
+    assert_eq!(lookup(&ctx, "n"), Some(AgentValue::integer(3)));
+
+    // prefixed names skip the context and the inner scopes
+    assert_eq!(lookup(&ctx, "stream:n"), Some(AgentValue::integer(1)));
+    assert_eq!(lookup(&ctx, "global:n"), Some(AgentValue::integer(0)));
+    askit.quit();
+}
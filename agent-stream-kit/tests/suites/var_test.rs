@@ -1,6 +1,8 @@
 extern crate agent_stream_kit as askit;
 
-use askit::{AgentValue, test_utils};
+use std::time::Duration;
+
+use askit::{ASKitEvent, AgentValue, test_utils};
 use serial_test::serial;
 
 #[serial(board_group)]
@@ -28,3 +30,44 @@ async fn test_var_routing() {
 
     askit.quit();
 }
+
+#[serial(board_group)]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_var_agents_start_while_stream_starts() {
+    let askit = test_utils::setup_askit().await;
+    let mut events = askit.subscribe_to_event(|event| match event {
+        ASKitEvent::AgentStarted(_) | ASKitEvent::AgentCrashed(_, _) => Some(event),
+        _ => None,
+    });
+
+    // the Var agents start on their own tasks, while their stream is being started
+    for i in 0..20 {
+        let var_stream_id =
+            test_utils::load_and_start_stream(&askit, "tests/streams/Core_Var.json")
+                .await
+                .unwrap();
+        for _ in 0..2 {
+            let event = tokio::time::timeout(Duration::from_secs(2), events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(matches!(event, ASKitEvent::AgentStarted(_)), "{:?}", event);
+        }
+
+        let value = AgentValue::integer(i);
+        askit
+            .write_var_value(&var_stream_id, "var1", value.clone())
+            .await
+            .unwrap();
+        test_utils::expect_var_value(&var_stream_id, "var1", &value)
+            .await
+            .unwrap();
+        test_utils::expect_var_value(&var_stream_id, "var2", &value)
+            .await
+            .unwrap();
+
+        askit.remove_agent_stream(&var_stream_id).await.unwrap();
+    }
+
+    askit.quit();
+}
@@ -0,0 +1,170 @@
+extern crate agent_stream_kit as askit;
+
+use askit::{
+    ASKit, AgentConfigs, AgentContext, AgentData, AgentError, AgentSpec, AgentStreamSpec,
+    AgentValue, AsAgent, agent_new, askit_agent, async_trait, semver::Version, test_utils,
+};
+
+/// Version 1 took a percentage, version 2 takes a ratio.
+fn upgrade_threshold(from: &Version, configs: &mut AgentConfigs) -> Result<(), AgentError> {
+    if from.major < 2
+        && let Some(percent) = configs.remove("percent")
+    {
+        let percent = percent
+            .as_f64()
+            .ok_or_else(|| AgentError::InvalidConfig("percent".into()))?;
+        configs.set("ratio".into(), AgentValue::number(percent / 100.0));
+    }
+    Ok(())
+}
+
+#[askit_agent(
+    title = "Threshold",
+    category = "Test/Versions",
+    version = "2.0.0",
+    upgrade = upgrade_threshold,
+    inputs = ["in"],
+    number_config(name = "ratio", default = 0.5),
+)]
+pub struct ThresholdAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for ThresholdAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+/// A definition without an upgrade hook.
+#[askit_agent(title = "Fixed", category = "Test/Versions", version = "1.0.0")]
+pub struct FixedAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for FixedAgent {
+    fn new(askit: ASKit, id: String, spec: AgentSpec) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+async fn setup() -> ASKit {
+    let askit = test_utils::setup_askit().await;
+    ThresholdAgent::register(&askit);
+    FixedAgent::register(&askit);
+    askit
+}
+
+fn spec_with_version(askit: &ASKit, def_name: &str, version: Option<&str>) -> AgentSpec {
+    let mut spec = askit.new_agent_spec(def_name).unwrap();
+    spec.def_version = version.map(|v| v.to_string());
+    spec
+}
+
+#[tokio::test]
+async fn test_spec_records_definition_version() {
+    let askit = setup().await;
+
+    let spec = askit.new_agent_spec(ThresholdAgent::DEF_NAME).unwrap();
+    assert_eq!(spec.def_version.as_deref(), Some("2.0.0"));
+
+    // the same major version, and specs from before versioning, load as they are
+    for version in [Some("2.3.1"), None] {
+        let spec = spec_with_version(&askit, ThresholdAgent::DEF_NAME, version);
+        let agent = agent_new(askit.clone(), "a".into(), spec).unwrap();
+        assert_eq!(agent.spec().def_version.as_deref(), Some("2.0.0"));
+    }
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_older_major_version_is_upgraded() {
+    let askit = setup().await;
+
+    let mut agent = spec_with_version(&askit, ThresholdAgent::DEF_NAME, Some("1.4.0"));
+    let configs = agent.configs.as_mut().unwrap();
+    configs.remove("ratio");
+    configs.set("percent".into(), AgentValue::integer(30));
+    let mut spec = AgentStreamSpec::default();
+    spec.add_agent(agent);
+
+    assert!(spec.validate(&askit).is_empty());
+    let stream_id = askit.add_agent_stream_strict("old".into(), spec).unwrap();
+
+    let spec = askit.get_agent_stream_spec(&stream_id).await.unwrap();
+    let agent = &spec.agents[0];
+    assert_eq!(agent.def_version.as_deref(), Some("2.0.0"));
+    let configs = agent.configs.as_ref().unwrap();
+    assert_eq!(configs.get_number("ratio").unwrap(), 0.3);
+    assert!(!configs.contains_key("percent"));
+
+    askit.quit();
+}
+
+#[tokio::test]
+async fn test_incompatible_version_is_refused() {
+    let askit = setup().await;
+
+    // no upgrade hook
+    let spec = spec_with_version(&askit, FixedAgent::DEF_NAME, Some("0.9.0"));
+    let err = agent_new(askit.clone(), "a".into(), spec.clone())
+        .err()
+        .unwrap();
+    assert!(matches!(err, AgentError::IncompatibleVersion(..)));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Agent definition \"{}\" is version 1.0.0, but the spec was created with version 0.9.0",
+            FixedAgent::DEF_NAME
+        )
+    );
+
+    let mut stream = AgentStreamSpec::default();
+    stream.add_agent(spec);
+    assert!(stream.validate(&askit)[0].is_error());
+    assert!(
+        askit
+            .add_agent_stream_strict("fixed".into(), stream)
+            .is_err()
+    );
+
+    // newer versions cannot be upgraded
+    let spec = spec_with_version(&askit, ThresholdAgent::DEF_NAME, Some("3.0.0"));
+    assert!(matches!(
+        agent_new(askit.clone(), "a".into(), spec),
+        Err(AgentError::IncompatibleVersion(..))
+    ));
+
+    let spec = spec_with_version(&askit, ThresholdAgent::DEF_NAME, Some("two"));
+    assert!(matches!(
+        agent_new(askit.clone(), "a".into(), spec),
+        Err(AgentError::InvalidVersion(_))
+    ));
+
+    askit.quit();
+}
@@ -0,0 +1,327 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use agent_stream_kit::mcp;
+use agent_stream_kit::{ASKit, ASKitEvent, AgentError, AgentStreamSpec, AgentValue};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Run agent streams from stream files.
+#[derive(Parser)]
+#[command(name = "askit", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the agent definitions as JSON.
+    Defs,
+
+    /// Check stream files against the agent definitions.
+    ///
+    /// Exits with an error status when any file has errors.
+    Validate {
+        /// Stream files, in any supported format.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Run stream files, printing board values to stdout as JSON lines.
+    ///
+    /// With `--input`, runs until stdin ends. Otherwise runs until interrupted.
+    Run {
+        /// Stream files, in any supported format.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Register the tools of the MCP servers in the file, such as `mcp.json`.
+        #[arg(long, value_name = "FILE")]
+        mcp: Option<PathBuf>,
+
+        /// Write each line of stdin to the board.
+        #[arg(long, value_name = "BOARD")]
+        input: Option<String>,
+
+        /// Parse stdin lines as JSON, instead of writing them as strings.
+        #[arg(long, requires = "input")]
+        json: bool,
+
+        /// Print only the values of the board. Defaults to all boards except variables.
+        #[arg(long = "output", value_name = "BOARD")]
+        outputs: Vec<String>,
+
+        /// Seconds to wait for the streams to settle when quitting.
+        #[arg(long, value_name = "SECS", default_value_t = 10)]
+        timeout: u64,
+    },
+
+    /// Print the agents and channels of a stream file.
+    Graph {
+        /// A stream file, in any supported format.
+        file: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = GraphFormat::Json)]
+        format: GraphFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    Json,
+
+    /// Graphviz DOT.
+    Dot,
+
+    /// Mermaid flowchart.
+    Mermaid,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Defs => defs(),
+        Command::Validate { files } => validate(&files),
+        Command::Run {
+            files,
+            mcp,
+            input,
+            json,
+            outputs,
+            timeout,
+        } => {
+            let options = RunOptions {
+                mcp,
+                input,
+                json,
+                outputs,
+                timeout: Duration::from_secs(timeout),
+            };
+            run(&files, options).await
+        }
+        Command::Graph { file, format } => graph(&file, format),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn defs() -> Result<ExitCode, AgentError> {
+    let askit = ASKit::init()?;
+    print_json(&json!(askit.get_agent_definitions()))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(files: &[PathBuf]) -> Result<ExitCode, AgentError> {
+    let askit = ASKit::init()?;
+    let mut code = ExitCode::SUCCESS;
+    for file in files {
+        let spec = match load_spec(&askit, file) {
+            Ok(spec) => spec,
+            Err(e) => {
+                println!("{}: error: {}", file.display(), e);
+                code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        let diagnostics = spec.validate(&askit);
+        if diagnostics.is_empty() {
+            println!("{}: ok", file.display());
+        }
+        for diagnostic in &diagnostics {
+            println!("{}: {}", file.display(), diagnostic);
+            if diagnostic.is_error() {
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    Ok(code)
+}
+
+struct RunOptions {
+    mcp: Option<PathBuf>,
+    input: Option<String>,
+    json: bool,
+    outputs: Vec<String>,
+    timeout: Duration,
+}
+
+async fn run(files: &[PathBuf], options: RunOptions) -> Result<ExitCode, AgentError> {
+    let askit = ASKit::init()?;
+    askit.ready().await?;
+
+    if let Some(path) = &options.mcp {
+        let tools = mcp::register_tools_from_mcp_json_into(&askit, path).await?;
+        log::info!("Registered {} MCP tools", tools.len());
+    }
+
+    let mut events = askit.subscribe();
+    for file in files {
+        let spec = load_spec(&askit, file)?;
+        let name = askit.unique_stream_name(&stream_name(file));
+        let stream_id = askit.add_agent_stream_strict(name, spec)?;
+        askit.start_agent_stream(&stream_id).await?;
+    }
+
+    match &options.input {
+        Some(board) => {
+            let mut lines = BufReader::new(tokio::io::stdin()).lines();
+            loop {
+                tokio::select! {
+                    line = lines.next_line() => {
+                        let line = line.map_err(|e| AgentError::IoError(e.to_string()))?;
+                        let Some(line) = line else {
+                            break;
+                        };
+                        let value = match line_value(&line, options.json) {
+                            Ok(value) => value,
+                            Err(e) => {
+                                log::error!("Skipping input line: {}", e);
+                                continue;
+                            }
+                        };
+                        askit.write_board_value(board.clone(), value).await?;
+                    }
+                    event = events.recv() => {
+                        if !print_event(event, &options.outputs)? {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        None => loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => break,
+                event = events.recv() => {
+                    if !print_event(event, &options.outputs)? {
+                        break;
+                    }
+                }
+            }
+        },
+    }
+
+    let report = askit.shutdown(options.timeout).await;
+    // the values written while shutting down
+    loop {
+        let event = match events.try_recv() {
+            Ok(event) => Ok(event),
+            Err(broadcast::error::TryRecvError::Lagged(n)) => Err(RecvError::Lagged(n)),
+            Err(_) => break,
+        };
+        print_event(event, &options.outputs)?;
+    }
+    if !report.is_clean() {
+        log::warn!("Streams did not shut down cleanly: {:?}", report);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn graph(file: &Path, format: GraphFormat) -> Result<ExitCode, AgentError> {
+    let askit = ASKit::init()?;
+    let spec = load_spec(&askit, file)?;
+    match format {
+        GraphFormat::Json => {}
+        GraphFormat::Dot => {
+            print!("{}", spec.to_dot(&askit));
+            return Ok(ExitCode::SUCCESS);
+        }
+        GraphFormat::Mermaid => {
+            print!("{}", spec.to_mermaid(&askit));
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+    let agents: Vec<Value> = spec
+        .agents
+        .iter()
+        .map(|agent| {
+            let title = askit
+                .get_agent_definition(&agent.def_name)
+                .and_then(|def| def.title);
+            json!({
+                "id": agent.id,
+                "def_name": agent.def_name,
+                "title": title,
+                "configs": agent.configs,
+            })
+        })
+        .collect();
+    print_json(&json!({
+        "name": stream_name(file),
+        "agents": agents,
+        "channels": spec.channels,
+    }))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+// a stream file, migrated to the current definitions
+fn load_spec(askit: &ASKit, file: &Path) -> Result<AgentStreamSpec, AgentError> {
+    let mut spec = AgentStreamSpec::from_file(file)?;
+    askit.migrate_stream_spec(&mut spec);
+    Ok(spec)
+}
+
+fn stream_name(file: &Path) -> String {
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn line_value(line: &str, json: bool) -> Result<AgentValue, AgentError> {
+    if !json {
+        return Ok(AgentValue::string(line));
+    }
+    let value: Value =
+        serde_json::from_str(line).map_err(|e| AgentError::JsonParseError(e.to_string()))?;
+    AgentValue::from_json(value)
+}
+
+// Print a board value as a JSON line. Returns false when there will be no more events.
+fn print_event(
+    event: Result<ASKitEvent, RecvError>,
+    outputs: &[String],
+) -> Result<bool, AgentError> {
+    let event = match event {
+        Ok(event) => event,
+        Err(RecvError::Lagged(n)) => {
+            log::warn!("Skipped {} events", n);
+            return Ok(true);
+        }
+        Err(RecvError::Closed) => return Ok(false),
+    };
+    let ASKitEvent::Board(name, value) = event else {
+        return Ok(true);
+    };
+    let printed = if outputs.is_empty() {
+        !name.starts_with('%')
+    } else {
+        outputs.contains(&name)
+    };
+    if printed {
+        println!(
+            "{}",
+            serde_json::to_string(&json!({ "board": name, "value": value }))
+                .map_err(|e| AgentError::SerializationError(e.to_string()))?
+        );
+    }
+    Ok(true)
+}
+
+fn print_json(value: &Value) -> Result<(), AgentError> {
+    let s = serde_json::to_string_pretty(value)
+        .map_err(|e| AgentError::SerializationError(e.to_string()))?;
+    println!("{}", s);
+    Ok(())
+}
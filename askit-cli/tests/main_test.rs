@@ -0,0 +1,3 @@
+mod suites {
+    mod cli_test;
+}
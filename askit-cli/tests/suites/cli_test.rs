@@ -0,0 +1,180 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use agent_stream_kit::{ASKit, AgentStreamSpec, AgentValue, ChannelSpec};
+use serde_json::Value;
+
+const BOARD_IN_DEF: &str = "agent_stream_kit::board_agent::BoardInAgent";
+const BOARD_OUT_DEF: &str = "agent_stream_kit::board_agent::BoardOutAgent";
+
+fn askit() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_askit"))
+}
+
+fn temp_file(name: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("askit_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+/// A stream copying the board `in` to the board `out`.
+fn relay_stream() -> String {
+    let askit = ASKit::init().unwrap();
+    let mut spec = AgentStreamSpec::default();
+    let mut board_out = askit.new_agent_spec(BOARD_OUT_DEF).unwrap();
+    board_out
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("in"));
+    let mut board_in = askit.new_agent_spec(BOARD_IN_DEF).unwrap();
+    board_in
+        .configs
+        .as_mut()
+        .unwrap()
+        .set("name".into(), AgentValue::string("out"));
+    spec.add_channel(ChannelSpec {
+        source: board_out.id.clone(),
+        source_handle: "value".into(),
+        target: board_in.id.clone(),
+        target_handle: "value".into(),
+    });
+    spec.add_agent(board_out);
+    spec.add_agent(board_in);
+    spec.to_json().unwrap()
+}
+
+fn run_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = askit()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout_lines(output: &Output) -> Vec<Value> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_defs() {
+    let output = askit().arg("defs").output().unwrap();
+    assert!(output.status.success());
+    let defs: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(defs[BOARD_IN_DEF]["name"], BOARD_IN_DEF);
+}
+
+#[test]
+fn test_run_pipes_stdin_into_a_board() {
+    let path = temp_file("relay.json", &relay_stream());
+    let output = run_with_stdin(
+        &[
+            "run",
+            path.to_str().unwrap(),
+            "--input",
+            "in",
+            "--output",
+            "out",
+        ],
+        "hello\nworld\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let lines = stdout_lines(&output);
+    let values: Vec<&Value> = lines.iter().map(|line| &line["value"]).collect();
+    assert_eq!(values, ["hello", "world"]);
+    assert!(lines.iter().all(|line| line["board"] == "out"));
+
+    // as JSON, printing every board
+    let output = run_with_stdin(
+        &["run", path.to_str().unwrap(), "--input", "in", "--json"],
+        "{\"n\": 1}\n",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let lines = stdout_lines(&output);
+    let boards: Vec<&Value> = lines.iter().map(|line| &line["board"]).collect();
+    assert_eq!(boards, ["in", "out"]);
+    assert_eq!(lines[1]["value"]["n"], 1);
+}
+
+#[test]
+fn test_validate() {
+    let valid = temp_file("valid.json", &relay_stream());
+    let output = askit()
+        .args(["validate", valid.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with(": ok\n"));
+
+    let invalid = temp_file(
+        "invalid.json",
+        &relay_stream().replace(BOARD_IN_DEF, "missing::Agent"),
+    );
+    let output = askit()
+        .args([
+            "validate",
+            valid.to_str().unwrap(),
+            invalid.to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("unknown agent definition \"missing::Agent\""));
+}
+
+#[test]
+fn test_graph() {
+    let path = temp_file("graph.json", &relay_stream());
+    let output = askit()
+        .args(["graph", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let graph: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(graph["name"], "graph");
+    assert_eq!(graph["agents"].as_array().unwrap().len(), 2);
+    assert_eq!(graph["agents"][0]["def_name"], BOARD_OUT_DEF);
+    assert_eq!(graph["channels"][0]["target_handle"], "value");
+}
+
+#[test]
+fn test_graph_formats() {
+    let path = temp_file("graph_formats.json", &relay_stream());
+    let output = askit()
+        .args(["graph", "--format", "dot", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let dot = String::from_utf8_lossy(&output.stdout);
+    assert!(dot.starts_with("digraph {"), "{}", dot);
+    assert!(dot.contains(r#"[label="value -> value"]"#), "{}", dot);
+
+    let output = askit()
+        .args(["graph", "--format", "mermaid", path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let mermaid = String::from_utf8_lossy(&output.stdout);
+    assert!(mermaid.starts_with("flowchart LR\n"), "{}", mermaid);
+    assert!(
+        mermaid.contains(r#"a0 -->|"value -> value"| a1"#),
+        "{}",
+        mermaid
+    );
+}
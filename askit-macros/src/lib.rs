@@ -23,13 +23,21 @@ pub fn askit(attr: TokenStream, item: TokenStream) -> TokenStream {
 /// ```rust,ignore
 /// #[askit_agent(
 ///     title = "Add Int",
+///     version = "2.1.0",
+///     upgrade = upgrade_adder_configs,
 ///     category = "Utils",
 ///     inputs = ["int"],
 ///     outputs = ["int"],
+///     input_pin(name = "int", type_ = PinType::Integer, required),
 ///     integer_config(
 ///         name = "n",
 ///         default = 1,
-///     )
+///     ),
+///     queue_capacity = 16,
+///     delivery_policy = DeliveryPolicy::DropOldest,
+///     pause_capacity = 64,
+///     renamed_from = "my_crate::IntAdderAgent",
+///     config_renamed(from = "step", to = "n"),
 /// )]
 /// struct AdderAgent { /* ... */ }
 /// ```
@@ -47,14 +55,29 @@ pub fn askit_agent(attr: TokenStream, item: TokenStream) -> TokenStream {
 struct AgentArgs {
     kind: Option<Expr>,
     name: Option<Expr>,
+    version: Option<Expr>,
+    upgrade: Option<Expr>,
     title: Option<Expr>,
     hide_title: bool,
     description: Option<Expr>,
     category: Option<Expr>,
     inputs: Vec<Expr>,
     outputs: Vec<Expr>,
+    input_pins: Vec<PinArgs>,
+    output_pins: Vec<PinArgs>,
     configs: Vec<ConfigSpec>,
     global_configs: Vec<ConfigSpec>,
+    queue_capacity: Option<Expr>,
+    delivery_policy: Option<Expr>,
+    pause_capacity: Option<Expr>,
+    migrations: Vec<proc_macro2::TokenStream>,
+}
+
+struct PinArgs {
+    name: Expr,
+    type_: Option<Expr>,
+    description: Option<Expr>,
+    required: bool,
 }
 
 #[derive(Default)]
@@ -115,14 +138,22 @@ fn expand_askit_agent(
     let mut parsed = AgentArgs {
         kind: None,
         name: None,
+        version: None,
+        upgrade: None,
         title: None,
         hide_title: false,
         description: None,
         category: None,
         inputs: Vec::new(),
         outputs: Vec::new(),
+        input_pins: Vec::new(),
+        output_pins: Vec::new(),
         configs: Vec::new(),
         global_configs: Vec::new(),
+        queue_capacity: None,
+        delivery_policy: None,
+        pause_capacity: None,
+        migrations: Vec::new(),
     };
 
     for meta in args {
@@ -133,6 +164,12 @@ fn expand_askit_agent(
             Meta::NameValue(nv) if nv.path.is_ident("name") => {
                 parsed.name = Some(nv.value);
             }
+            Meta::NameValue(nv) if nv.path.is_ident("version") => {
+                parsed.version = Some(nv.value);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("upgrade") => {
+                parsed.upgrade = Some(nv.value);
+            }
             Meta::NameValue(nv) if nv.path.is_ident("title") => {
                 parsed.title = Some(nv.value);
             }
@@ -145,6 +182,28 @@ fn expand_askit_agent(
             Meta::NameValue(nv) if nv.path.is_ident("category") => {
                 parsed.category = Some(nv.value);
             }
+            Meta::NameValue(nv) if nv.path.is_ident("queue_capacity") => {
+                parsed.queue_capacity = Some(nv.value);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("delivery_policy") => {
+                parsed.delivery_policy = Some(nv.value);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("pause_capacity") => {
+                parsed.pause_capacity = Some(nv.value);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("renamed_from") => {
+                let name = nv.value;
+                parsed.migrations.push(quote! { .renamed_from(#name) });
+            }
+            Meta::List(ml) if ml.path.is_ident("config_renamed") => {
+                parsed.migrations.push(parse_rename("config_renamed", ml)?);
+            }
+            Meta::List(ml) if ml.path.is_ident("input_renamed") => {
+                parsed.migrations.push(parse_rename("input_renamed", ml)?);
+            }
+            Meta::List(ml) if ml.path.is_ident("output_renamed") => {
+                parsed.migrations.push(parse_rename("output_renamed", ml)?);
+            }
             Meta::NameValue(nv) if nv.path.is_ident("inputs") => {
                 parsed.inputs = parse_expr_array(nv.value)?;
             }
@@ -157,6 +216,12 @@ fn expand_askit_agent(
             Meta::List(ml) if ml.path.is_ident("outputs") => {
                 parsed.outputs = collect_exprs(ml)?;
             }
+            Meta::List(ml) if ml.path.is_ident("input_pin") => {
+                parsed.input_pins.push(parse_pin(ml)?);
+            }
+            Meta::List(ml) if ml.path.is_ident("output_pin") => {
+                parsed.output_pins.push(parse_pin(ml)?);
+            }
             Meta::List(ml) if ml.path.is_ident("string_config") => {
                 parsed
                     .configs
@@ -305,6 +370,30 @@ fn expand_askit_agent(
         quote! { .outputs(vec![#(#values),*]) }
     };
 
+    let input_pin_calls = parsed
+        .input_pins
+        .into_iter()
+        .map(|pin| pin_call("input_pin_with", pin));
+    let output_pin_calls = parsed
+        .output_pins
+        .into_iter()
+        .map(|pin| pin_call("output_pin_with", pin));
+
+    let queue_capacity = parsed
+        .queue_capacity
+        .map(|c| quote! { .queue_capacity(#c) });
+    let delivery_policy = parsed
+        .delivery_policy
+        .map(|p| quote! { .delivery_policy(#p) });
+    let pause_capacity = parsed
+        .pause_capacity
+        .map(|c| quote! { .pause_capacity(#c) });
+
+    let version = parsed.version.map(|v| quote! { .version(#v) });
+    let upgrade = parsed.upgrade.map(|f| quote! { .upgrade(#f) });
+
+    let migrations = parsed.migrations;
+
     let config_calls = parsed
         .configs
         .into_iter()
@@ -909,12 +998,20 @@ fn expand_askit_agent(
             #name_tokens,
             Some(::agent_stream_kit::new_agent_boxed::<#ident>),
         )
+        #version
+        #upgrade
         #title
         #hide_title
         #description
         #category
         #inputs
         #outputs
+        #(#input_pin_calls)*
+        #(#output_pin_calls)*
+        #queue_capacity
+        #delivery_policy
+        #pause_capacity
+        #(#migrations)*
         #(#config_calls)*
         #(#global_config_calls)*
     };
@@ -1041,6 +1138,101 @@ fn parse_custom_config(list: MetaList) -> syn::Result<CustomConfig> {
     })
 }
 
+fn parse_rename(method: &str, list: MetaList) -> syn::Result<proc_macro2::TokenStream> {
+    let mut from = None;
+    let mut to = None;
+    let nested = list.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated)?;
+
+    for meta in nested {
+        match meta {
+            Meta::NameValue(nv) if nv.path.is_ident("from") => {
+                from = Some(nv.value.clone());
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("to") => {
+                to = Some(nv.value.clone());
+            }
+            other => {
+                return Err(syn::Error::new_spanned(
+                    other,
+                    format!("{} supports from, to", method),
+                ));
+            }
+        }
+    }
+
+    let from = from.ok_or_else(|| syn::Error::new(list.span(), "rename missing `from`"))?;
+    let to = to.ok_or_else(|| syn::Error::new(list.span(), "rename missing `to`"))?;
+    let method_ident = format_ident!("{}", method);
+    Ok(quote! { .#method_ident(#from, #to) })
+}
+
+fn parse_pin(list: MetaList) -> syn::Result<PinArgs> {
+    let mut name = None;
+    let mut type_ = None;
+    let mut description = None;
+    let mut required = false;
+    let nested = list.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated)?;
+
+    for meta in nested {
+        match meta {
+            Meta::NameValue(nv) if nv.path.is_ident("name") => {
+                name = Some(nv.value.clone());
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("type_") => {
+                type_ = Some(nv.value.clone());
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("description") => {
+                description = Some(nv.value.clone());
+            }
+            Meta::Path(p) if p.is_ident("required") => {
+                required = true;
+            }
+            other => {
+                return Err(syn::Error::new_spanned(
+                    other,
+                    "input_pin/output_pin supports name, type_, description, required",
+                ));
+            }
+        }
+    }
+
+    let name = name.ok_or_else(|| syn::Error::new(list.span(), "pin missing `name`"))?;
+    Ok(PinArgs {
+        name,
+        type_,
+        description,
+        required,
+    })
+}
+
+fn pin_call(method: &str, pin: PinArgs) -> proc_macro2::TokenStream {
+    let PinArgs {
+        name,
+        type_,
+        description,
+        required,
+    } = pin;
+    let type_ = type_
+        .map(|t| quote! { #t })
+        .unwrap_or_else(|| quote! { ::agent_stream_kit::PinType::Any });
+    let description = description.map(|d| quote! { let pin = pin.description(#d); });
+    let required = if required {
+        quote! { let pin = pin.required(); }
+    } else {
+        quote! {}
+    };
+    let method_ident = format_ident!("{}", method);
+
+    quote! {
+        .#method_ident(#name, #type_, |pin| {
+            let pin = pin;
+            #description
+            #required
+            pin
+        })
+    }
+}
+
 fn collect_exprs(list: MetaList) -> syn::Result<Vec<Expr>> {
     let values = list.parse_args_with(Punctuated::<Expr, Comma>::parse_terminated)?;
     Ok(values.into_iter().collect())
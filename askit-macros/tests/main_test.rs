@@ -4,5 +4,9 @@ mod suites {
     pub mod configs;
     pub mod default_kind;
     pub mod default_name;
+    pub mod delivery;
+    pub mod migrations;
+    pub mod pins;
     pub mod string_literal_names;
+    pub mod versions;
 }
@@ -0,0 +1,46 @@
+use agent_stream_kit::{
+    AgentContext, AgentData, AgentError, AgentSpec, AgentValue, AsAgent, DeliveryPolicy,
+    askit_agent, async_trait,
+};
+
+#[askit_agent(
+    title = "Sensor",
+    category = "Tests",
+    inputs = ["reading"],
+    queue_capacity = 4,
+    delivery_policy = DeliveryPolicy::CoalesceLatest,
+    pause_capacity = 16,
+)]
+struct SensorAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for SensorAgent {
+    fn new(
+        askit: agent_stream_kit::ASKit,
+        id: String,
+        spec: AgentSpec,
+    ) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn delivery_options_are_set() {
+    let def = SensorAgent::agent_definition();
+    assert_eq!(def.queue_capacity, Some(4));
+    assert_eq!(def.delivery_policy, Some(DeliveryPolicy::CoalesceLatest));
+    assert_eq!(def.pause_capacity, Some(16));
+}
@@ -0,0 +1,54 @@
+use agent_stream_kit::{
+    AgentContext, AgentData, AgentError, AgentSpec, AgentValue, AsAgent, askit_agent, async_trait,
+};
+
+#[askit_agent(
+    title = "Greeter",
+    category = "Tests",
+    inputs = ["name"],
+    outputs = ["greeting"],
+    string_config(name = "template", default = "Hello, {}"),
+    renamed_from = "tests::HelloAgent",
+    renamed_from = "tests::OldHelloAgent",
+    config_renamed(from = "format", to = "template"),
+    input_renamed(from = "in", to = "name"),
+    output_renamed(from = "out", to = "greeting"),
+)]
+struct GreeterAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for GreeterAgent {
+    fn new(
+        askit: agent_stream_kit::ASKit,
+        id: String,
+        spec: AgentSpec,
+    ) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn migrations_are_declared() {
+    let def = GreeterAgent::agent_definition();
+    let migrations = def.migrations.unwrap();
+    assert_eq!(
+        migrations.renamed_from,
+        ["tests::HelloAgent", "tests::OldHelloAgent"]
+    );
+    assert_eq!(migrations.configs["format"], "template");
+    assert_eq!(migrations.inputs["in"], "name");
+    assert_eq!(migrations.outputs["out"], "greeting");
+}
@@ -0,0 +1,59 @@
+use agent_stream_kit::{
+    AgentContext, AgentData, AgentError, AgentSpec, AgentValue, AsAgent, PinType, askit_agent,
+    async_trait,
+};
+
+#[askit_agent(
+    title = "Captioner",
+    category = "Tests",
+    inputs = ["image", "hint"],
+    input_pin(name = "image", type_ = PinType::Image, description = "image to caption", required),
+    input_pin(name = "options", type_ = PinType::Object),
+    output_pin(name = "captions", type_ = PinType::array(PinType::Message)),
+    output_pin(name = "raw"),
+)]
+struct CaptionerAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for CaptionerAgent {
+    fn new(
+        askit: agent_stream_kit::ASKit,
+        id: String,
+        spec: AgentSpec,
+    ) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn pins_are_declared() {
+    let def = CaptionerAgent::agent_definition();
+    assert_eq!(def.inputs.as_deref().unwrap(), ["image", "hint", "options"]);
+    assert_eq!(def.outputs.as_deref().unwrap(), ["captions", "raw"]);
+
+    let image = &def.input_pins.as_ref().unwrap()["image"];
+    assert_eq!(image.type_, Some(PinType::Image));
+    assert_eq!(image.description.as_deref(), Some("image to caption"));
+    assert!(image.required);
+    assert_eq!(def.input_pin_type("options"), &PinType::Object);
+    assert_eq!(def.input_pin_type("hint"), &PinType::Any);
+
+    assert_eq!(
+        def.output_pin_type("captions"),
+        &PinType::array(PinType::Message)
+    );
+    assert_eq!(def.output_pin_type("raw"), &PinType::Any);
+}
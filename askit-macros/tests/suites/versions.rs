@@ -0,0 +1,49 @@
+use agent_stream_kit::{
+    AgentConfigs, AgentContext, AgentData, AgentError, AgentSpec, AgentValue, AsAgent, askit_agent,
+    async_trait, semver::Version,
+};
+
+fn upgrade_counter(_from: &Version, _configs: &mut AgentConfigs) -> Result<(), AgentError> {
+    Ok(())
+}
+
+#[askit_agent(
+    title = "Counter",
+    category = "Tests",
+    version = "2.1.0",
+    upgrade = upgrade_counter,
+    outputs = ["count"],
+)]
+struct CounterAgent {
+    data: AgentData,
+}
+
+#[async_trait]
+impl AsAgent for CounterAgent {
+    fn new(
+        askit: agent_stream_kit::ASKit,
+        id: String,
+        spec: AgentSpec,
+    ) -> Result<Self, AgentError> {
+        Ok(Self {
+            data: AgentData::new(askit, id, spec),
+        })
+    }
+
+    async fn process(
+        &mut self,
+        _ctx: AgentContext,
+        _pin: String,
+        _value: AgentValue,
+    ) -> Result<(), AgentError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn version_is_declared() {
+    let def = CounterAgent::agent_definition();
+    assert_eq!(def.version.as_deref(), Some("2.1.0"));
+    assert!(def.upgrade.is_some());
+    assert_eq!(def.to_spec().def_version.as_deref(), Some("2.1.0"));
+}